@@ -8,7 +8,11 @@
 #![deny(clippy::all)]
 #![warn(rust_2018_idioms)]
 
-use std::{collections::HashMap, ops::Deref};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+use std::str::FromStr;
 
 use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
 use url::Url;
@@ -54,6 +58,17 @@ kind = "stderr"
 # prot = "tls" # or prot = "tcp"
 # host = "localhost"
 # port = 23456
+
+## A pre-opened directory, whose files are encrypted on the host
+# [[dirs]]
+# host = "/var/lib/app"
+# guest = "data"
+# encryption = "platform"
+
+## Resource limits of the Keep
+# [limits]
+# memory = "512MiB"
+# threads = 8
 "#;
 
 const fn default_tcp_port() -> u16 {
@@ -69,6 +84,7 @@ fn default_addr() -> String {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 /// Name assigned to a file descriptor
 ///
 /// This is used to export the `FD_NAMES` environment variable,
@@ -135,6 +151,7 @@ impl<'de> Deserialize<'de> for FileName {
 /// let config: Config = toml::from_str(CONFIG).unwrap();
 /// ```
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Config {
     /// An optional Steward URL
     #[serde(default)]
@@ -151,6 +168,14 @@ pub struct Config {
     /// The environment variables to provide to the application
     #[serde(default)]
     pub env: HashMap<String, String>,
+
+    /// The array of pre-opened directories
+    #[serde(default)]
+    pub dirs: Vec<Dir>,
+
+    /// The resource limits of the Keep
+    #[serde(default)]
+    pub limits: Limits,
 }
 
 impl Default for Config {
@@ -166,12 +191,175 @@ impl Default for Config {
             args: vec![],
             files,
             steward: None, // TODO: Default to a deployed Steward instance
+            dirs: vec![],
+            limits: Default::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Check the configuration for semantic problems
+    ///
+    /// A configuration, which deserializes successfully, may still be unusable
+    /// at runtime, e.g. because two file descriptors share a name or two
+    /// listen sockets bind the same port.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate toml;
+    /// use enarx_config::{Config, Problem};
+    /// const CONFIG: &str = r#"
+    /// [[files]]
+    /// name = "listen"
+    /// kind = "listen"
+    /// prot = "tls"
+    /// port = 12345
+    ///
+    /// [[files]]
+    /// name = "listen"
+    /// kind = "null"
+    /// "#;
+    ///
+    /// let config: Config = toml::from_str(CONFIG).unwrap();
+    /// assert_eq!(
+    ///     config.problems(),
+    ///     vec![Problem::DuplicateName("listen".into())]
+    /// );
+    /// ```
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems = vec![];
+
+        if let Some(steward) = &self.steward {
+            if steward.scheme() != "https" {
+                problems.push(Problem::StewardScheme(steward.scheme().into()));
+            }
+        }
+
+        let mut names = HashSet::new();
+        for file in &self.files {
+            if !names.insert(file.name()) {
+                problems.push(Problem::DuplicateName(file.name().into()));
+            }
+        }
+
+        let listen = self
+            .files
+            .iter()
+            .filter_map(|file| match file {
+                File::Listen(ListenFile::Tls { name, addr, port })
+                | File::Listen(ListenFile::Tcp { name, addr, port }) => {
+                    Some((name.deref(), addr.as_str(), *port))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for (i, (first, addr, port)) in listen.iter().enumerate() {
+            let wildcard = |addr: &str| matches!(addr, "::" | "0.0.0.0");
+            for (second, other, _) in listen[i + 1..].iter().filter(|(_, other, p)| {
+                p == port && (addr == other || wildcard(addr) || wildcard(other))
+            }) {
+                problems.push(Problem::PortCollision {
+                    first: (*first).into(),
+                    second: (*second).into(),
+                    addr: if wildcard(addr) { other } else { addr }.to_string(),
+                    port: *port,
+                });
+            }
+        }
+
+        for file in &self.files {
+            match file {
+                File::Connect(ConnectFile::Tls { host, .. })
+                | File::Connect(ConnectFile::Tcp { host, .. })
+                    if host.trim().is_empty() =>
+                {
+                    problems.push(Problem::MissingHost(file.name().into()))
+                }
+                _ => {}
+            }
+        }
+
+        let mut guests = HashSet::new();
+        for dir in &self.dirs {
+            if !guests.insert(dir.guest.as_str()) {
+                problems.push(Problem::DuplicateDir(dir.guest.clone()));
+            }
+        }
+
+        problems
+    }
+
+    /// The JSON Schema of the configuration, for editor integration
+    #[cfg(feature = "schema")]
+    pub fn schema() -> schemars::schema::RootSchema {
+        schemars::schema_for!(Config)
+    }
+}
+
+/// A semantic problem in a [`Config`]
+///
+/// See [`Config::problems`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The Steward URL uses a scheme other than `https`
+    StewardScheme(String),
+
+    /// More than one file descriptor has the same name
+    DuplicateName(String),
+
+    /// Two listen sockets bind the same address and port
+    PortCollision {
+        /// Name of the first listen socket
+        first: String,
+
+        /// Name of the second listen socket
+        second: String,
+
+        /// Address both sockets listen on
+        addr: String,
+
+        /// Port both sockets listen on
+        port: u16,
+    },
+
+    /// A connect socket has an empty host
+    MissingHost(String),
+
+    /// More than one directory is pre-opened at the same guest path
+    DuplicateDir(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StewardScheme(scheme) => write!(
+                f,
+                "steward URL scheme `{scheme}` is not supported, only `https` can be used"
+            ),
+            Self::DuplicateName(name) => {
+                write!(f, "file descriptor name `{name}` is used more than once")
+            }
+            Self::PortCollision {
+                first,
+                second,
+                addr,
+                port,
+            } => write!(
+                f,
+                "listen sockets `{first}` and `{second}` both bind to `{addr}` port {port}"
+            ),
+            Self::MissingHost(name) => write!(f, "connect socket `{name}` has no host"),
+            Self::DuplicateDir(guest) => {
+                write!(f, "directory `{guest}` is pre-opened more than once")
+            }
         }
     }
 }
 
 /// `/dev/null` file descriptor
 #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(deny_unknown_fields)]
 pub struct NullFile {
     /// Name assigned to the file descriptor
@@ -180,6 +368,7 @@ pub struct NullFile {
 
 /// Standard I/O file descriptor
 #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(deny_unknown_fields)]
 pub struct StdioFile {
     /// Name assigned to the file descriptor
@@ -188,6 +377,7 @@ pub struct StdioFile {
 
 /// File descriptor of a listen socket
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "prot", deny_unknown_fields)]
 pub enum ListenFile {
     /// TLS listen socket
@@ -223,6 +413,7 @@ pub enum ListenFile {
 
 /// File descriptor of a stream socket
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "prot", deny_unknown_fields)]
 pub enum ConnectFile {
     /// TLS stream socket
@@ -256,6 +447,7 @@ pub enum ConnectFile {
 
 /// Parameters for a pre-opened file descriptor
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "kind", deny_unknown_fields)]
 pub enum File {
     /// File descriptor of `/dev/null`
@@ -299,6 +491,122 @@ impl File {
     }
 }
 
+/// Encryption of the files in a pre-opened directory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Encryption {
+    /// Files are stored in plaintext
+    #[default]
+    #[serde(rename = "none")]
+    None,
+
+    /// Files are sealed with a key derived from the platform sealing key of the Keep
+    ///
+    /// Each file is split into chunks encrypted with AES-256-GCM and authenticated as a whole.
+    /// A manifest in the directory records the latest version of every file to detect rollback.
+    #[serde(rename = "platform")]
+    Platform,
+}
+
+/// A pre-opened directory
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct Dir {
+    /// Path of the directory on the host
+    pub host: String,
+
+    /// Path the directory is pre-opened at for the application
+    pub guest: String,
+
+    /// Encryption of the files in the directory
+    #[serde(default)]
+    pub encryption: Encryption,
+}
+
+/// The resource limits of a Keep
+///
+/// The host refuses the Keep memory and threads beyond the limits.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    /// The maximum memory of the Keep
+    #[cfg_attr(feature = "schema", schemars(with = "Option<MemorySizeSchema>"))]
+    pub memory: Option<MemorySize>,
+
+    /// The maximum number of threads of the Keep
+    pub threads: Option<NonZeroUsize>,
+}
+
+/// A size of memory in bytes
+///
+/// It is given as a number of bytes or as a string with one of the suffixes
+/// `K`, `M`, `G` or `T` and their binary `KiB`, `MiB`, `GiB` and `TiB` forms,
+/// all of which are powers of 1024, e.g. `"512MiB"`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct MemorySize(pub u64);
+
+/// The forms a [`MemorySize`] can be given in
+#[cfg(feature = "schema")]
+#[derive(schemars::JsonSchema)]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum MemorySizeSchema {
+    Bytes(u64),
+    Text(String),
+}
+
+impl FromStr for MemorySize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, suffix) = s.split_at(split);
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("invalid memory size `{s}`"))?;
+        let shift = match suffix.trim_start() {
+            "" | "B" => 0,
+            "K" | "KiB" => 10,
+            "M" | "MiB" => 20,
+            "G" | "GiB" => 30,
+            "T" | "TiB" => 40,
+            suffix => return Err(format!("invalid memory size suffix `{suffix}`")),
+        };
+        number
+            .checked_mul(1 << shift)
+            .map(Self)
+            .ok_or_else(|| format!("memory size `{s}` is too large"))
+    }
+}
+
+impl fmt::Display for MemorySize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for MemorySize {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Size {
+            Bytes(u64),
+            Text(String),
+        }
+
+        match Size::deserialize(deserializer)? {
+            Size::Bytes(bytes) => Ok(Self(bytes)),
+            Size::Text(text) => text.parse().map_err(D::Error::custom),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -326,6 +634,19 @@ mod test {
         kind = "connect"
         host = "example.com"
         prot = "tls"
+
+        [[dirs]]
+        host = "/var/lib/app"
+        guest = "data"
+        encryption = "platform"
+
+        [[dirs]]
+        host = "/tmp"
+        guest = "tmp"
+
+        [limits]
+        memory = "512MiB"
+        threads = 4
     "#;
 
     #[test]
@@ -352,9 +673,48 @@ mod test {
             ]
         );
 
+        assert_eq!(
+            cfg.dirs,
+            vec![
+                Dir {
+                    host: "/var/lib/app".into(),
+                    guest: "data".into(),
+                    encryption: Encryption::Platform,
+                },
+                Dir {
+                    host: "/tmp".into(),
+                    guest: "tmp".into(),
+                    encryption: Encryption::None,
+                },
+            ]
+        );
+
+        assert_eq!(
+            cfg.limits,
+            Limits {
+                memory: Some(MemorySize(512 << 20)),
+                threads: NonZeroUsize::new(4),
+            }
+        );
+
         let _cfg_str = toml::to_string(&cfg).unwrap();
     }
 
+    #[test]
+    fn memory_size() {
+        assert_eq!("4096".parse(), Ok(MemorySize(4096)));
+        assert_eq!("64K".parse(), Ok(MemorySize(64 << 10)));
+        assert_eq!("2 GiB".parse(), Ok(MemorySize(2 << 30)));
+        assert_eq!("1T".parse(), Ok(MemorySize(1 << 40)));
+        assert!("".parse::<MemorySize>().is_err());
+        assert!("12PiB".parse::<MemorySize>().is_err());
+        assert!("18446744073709551615K".parse::<MemorySize>().is_err());
+
+        let limits: Limits = toml::from_str("memory = 1048576").unwrap();
+        assert_eq!(limits.memory, Some(MemorySize(1 << 20)));
+        assert!(toml::from_str::<Limits>("threads = 0").is_err());
+    }
+
     #[test]
     fn names() {
         let cfg: Config = toml::from_str(CONFIG).unwrap();
@@ -381,6 +741,73 @@ mod test {
         );
     }
 
+    #[test]
+    fn problems() {
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(cfg.problems(), vec![]);
+
+        const INVALID: &str = r#"
+        steward = "http://attest.example.com"
+
+        [[files]]
+        name = "a"
+        kind = "listen"
+        prot = "tls"
+        port = 8443
+
+        [[files]]
+        name = "b"
+        kind = "listen"
+        prot = "tcp"
+        addr = "127.0.0.1"
+        port = 8443
+
+        [[files]]
+        name = "c"
+        kind = "listen"
+        prot = "tcp"
+        addr = "127.0.0.2"
+        port = 8443
+
+        [[files]]
+        name = "a"
+        kind = "connect"
+        prot = "tls"
+        host = ""
+
+        [[dirs]]
+        host = "/a"
+        guest = "data"
+
+        [[dirs]]
+        host = "/b"
+        guest = "data"
+        "#;
+
+        let cfg: Config = toml::from_str(INVALID).unwrap();
+        assert_eq!(
+            cfg.problems(),
+            vec![
+                Problem::StewardScheme("http".into()),
+                Problem::DuplicateName("a".into()),
+                Problem::PortCollision {
+                    first: "a".into(),
+                    second: "b".into(),
+                    addr: "127.0.0.1".into(),
+                    port: 8443,
+                },
+                Problem::PortCollision {
+                    first: "a".into(),
+                    second: "c".into(),
+                    addr: "127.0.0.2".into(),
+                    port: 8443,
+                },
+                Problem::MissingHost("a".into()),
+                Problem::DuplicateDir("data".into()),
+            ]
+        );
+    }
+
     #[test]
     fn check_template() {
         let cfg_str = CONFIG_TEMPLATE
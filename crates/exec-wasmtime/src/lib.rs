@@ -37,10 +37,36 @@ pub struct Args {
     pub package: Package,
 }
 
+/// The outcome of establishing the keep identity
+///
+/// On Unix, this is sent TOML-encoded to the host on file descriptor 3,
+/// after the arguments have been read.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Attestation {
+    /// The keep attested to a Steward and received a certificate chain
+    Steward {
+        /// URL of the Steward
+        url: String,
+    },
+
+    /// No Steward was configured, so a self-signed certificate is used
+    SelfSigned,
+
+    /// Attestation to the Steward failed
+    Failed {
+        /// URL of the Steward
+        url: String,
+
+        /// The error chain
+        error: String,
+    },
+}
+
 /// Execute package
 #[instrument]
 pub fn execute_package(pkg: Package) -> anyhow::Result<()> {
-    Runtime::execute(pkg).map(|_| ())
+    Runtime::execute(pkg, |_| ()).map(|_| ())
 }
 
 /// Execute with arguments read from file descriptor 3.
@@ -59,16 +85,14 @@ pub fn execute() -> anyhow::Result<()> {
 
     // This is the FD of a Unix socket on which the host will send the TOML-encoded execution arguments
     // and shutdown the write half of it immediately after.
-    // TODO: Use the write half of the socket to write logs/errors to the host
+    // The write half is used to report the `Attestation` outcome back to the host.
+    // TODO: Use the write half of the socket to write logs to the host
     let mut host = unsafe { UnixStream::from_raw_fd(3) };
 
     let mut args = String::new();
     host.read_to_string(&mut args)
         .context("failed to read arguments")?;
 
-    // The FD is managed by the host or its parent.
-    forget(host);
-
     let Args {
         log_level,
         #[cfg(feature = "bench")]
@@ -111,11 +135,29 @@ pub fn execute() -> anyhow::Result<()> {
     let registry = registry().with(fmt_layer);
     #[cfg(feature = "bench")]
     let registry = registry.with(flame_layer);
-    {
+    let report = |attestation: Attestation| {
+        use std::io::Write;
+
+        // The host may not care about the outcome, so failing to report it is not fatal.
+        if let Err(e) = toml::to_vec(&attestation)
+            .context("failed to encode attestation outcome")
+            .and_then(|buf| {
+                (&host)
+                    .write_all(&buf)
+                    .context("failed to write attestation outcome")
+            })
+        {
+            tracing::debug!("{e:#}");
+        }
+    };
+    let ret = {
         let _guard = registry.set_default();
-        execute_package(package)?;
-    }
-    Ok(())
+        Runtime::execute(package, report).map(|_| ())
+    };
+
+    // The FD is managed by the host or its parent.
+    forget(host);
+    ret
 }
 
 #[cfg(test)]
@@ -170,10 +212,13 @@ mod test {
         file.rewind().context("failed to rewind file")?;
         #[cfg(unix)]
         let file = file.into_raw_fd();
-        Runtime::execute(Package::Local {
-            wasm: file,
-            conf: None,
-        })
+        Runtime::execute(
+            Package::Local {
+                wasm: file,
+                conf: None,
+            },
+            |attestation| assert_eq!(attestation, Attestation::SelfSigned),
+        )
     }
 
     #[test]
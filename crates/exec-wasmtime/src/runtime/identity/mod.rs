@@ -135,6 +135,17 @@ pub fn generate() -> anyhow::Result<(Zeroizing<Vec<u8>>, Vec<u8>)> {
     Ok((raw, req))
 }
 
+/// Returns the sealing key of the platform
+#[instrument]
+pub fn sealing_key() -> anyhow::Result<Zeroizing<Vec<u8>>> {
+    let platform = Platform::get().context("failed to query platform")?;
+    let key = Zeroizing::new(platform.key().context("failed to get the sealing key")?);
+    if key.is_empty() {
+        bail!("{:?} does not provide a sealing key", platform.technology());
+    }
+    Ok(key)
+}
+
 #[instrument(skip(csr))]
 pub fn steward(url: &Url, csr: impl AsRef<[u8]>) -> anyhow::Result<Vec<Vec<u8>>> {
     if url.scheme() != "https" {
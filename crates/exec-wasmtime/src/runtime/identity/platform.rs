@@ -135,7 +135,6 @@ impl Platform {
         self.technology
     }
 
-    #[allow(dead_code)]
     pub fn key(&self) -> Result<Vec<u8>> {
         let mut buf = vec![0; self.key_size];
 
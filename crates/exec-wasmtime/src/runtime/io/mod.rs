@@ -3,6 +3,7 @@
 //! I/O functionality for keeps
 
 pub mod null;
+pub mod sealed;
 
 use wasi_common::WasiFile;
 use wasmtime_wasi::preview1::types::Rights;
@@ -0,0 +1,431 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! The on-disk format of sealed files.
+//!
+//! A sealed file consists of a header followed by the plaintext split into chunks of
+//! [`CHUNK_SIZE`] bytes, each encrypted with AES-256-GCM under a fresh random nonce:
+//!
+//! ```text
+//! header: magic (8) | file id (16) | generation (8) | length (8) | mac (32)
+//! chunk:  nonce (12) | ciphertext (up to CHUNK_SIZE) | tag (16)
+//! ```
+//!
+//! Each chunk is bound to its file id and position via the associated data. The header MAC covers
+//! the header fields and the tags of all chunks, so chunks can be neither swapped nor replaced with
+//! an older version. The generation is incremented on every commit and checked against the
+//! [`Manifest`](super::manifest::Manifest) of the directory to detect rollback of whole files.
+
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::os::unix::fs::FileExt;
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::hkdf::Prk;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// The magic bytes at the start of every sealed file.
+const MAGIC: [u8; 8] = *b"ENXSEAL1";
+
+/// The number of plaintext bytes per chunk.
+pub const CHUNK_SIZE: usize = 4096;
+
+const ID_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+const MAC_LEN: usize = 32;
+
+/// The length of the authenticated header fields.
+const FIELDS_LEN: usize = MAGIC.len() + ID_LEN + 8 + 8;
+
+/// The length of the header.
+pub const HEADER_LEN: usize = FIELDS_LEN + MAC_LEN;
+
+/// The number of bytes a chunk takes up on disk in addition to its plaintext.
+const OVERHEAD: usize = NONCE_LEN + TAG_LEN;
+
+/// The identifier of a sealed file.
+pub type Id = [u8; ID_LEN];
+
+fn corrupted(what: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("sealed file {what}"))
+}
+
+/// Returns the plaintext length of a sealed file, which takes up `size` bytes on disk.
+pub fn plaintext_len(size: u64) -> u64 {
+    let body = size.saturating_sub(HEADER_LEN as _);
+    let stored = (CHUNK_SIZE + OVERHEAD) as u64;
+    let rest = body % stored;
+    body / stored * CHUNK_SIZE as u64 + rest.saturating_sub(OVERHEAD as _)
+}
+
+/// Keys of a single sealed file.
+struct Keys {
+    aead: LessSafeKey,
+    mac: hmac::Key,
+}
+
+impl Keys {
+    fn derive(prk: &Prk, id: &Id) -> io::Result<Self> {
+        let expand_err = |_| io::Error::new(io::ErrorKind::Other, "failed to derive file keys");
+        let aead = UnboundKey::from(
+            prk.expand(&[b"aead", id], &AES_256_GCM)
+                .map_err(expand_err)?,
+        );
+        let mac = hmac::Key::from(
+            prk.expand(&[b"mac", id], hmac::HMAC_SHA256)
+                .map_err(expand_err)?,
+        );
+        Ok(Self {
+            aead: LessSafeKey::new(aead),
+            mac,
+        })
+    }
+}
+
+/// An open sealed file.
+pub struct SealedFile {
+    file: File,
+    keys: Keys,
+    id: Id,
+    generation: u64,
+    len: u64,
+    /// Tags of all chunks in order
+    tags: Vec<[u8; TAG_LEN]>,
+}
+
+impl SealedFile {
+    /// Initializes `file` as an empty sealed file with keys derived from `prk`.
+    pub fn create(file: File, prk: &Prk) -> io::Result<Self> {
+        let mut id = [0; ID_LEN];
+        SystemRandom::new()
+            .fill(&mut id)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to generate file id"))?;
+        let mut sealed = Self {
+            keys: Keys::derive(prk, &id)?,
+            file,
+            id,
+            generation: 0,
+            len: 0,
+            tags: vec![],
+        };
+        sealed.file.set_len(0)?;
+        sealed.commit()?;
+        Ok(sealed)
+    }
+
+    /// Opens the sealed `file` with keys derived from `prk` and verifies its integrity.
+    pub fn open(file: File, prk: &Prk) -> io::Result<Self> {
+        let mut header = [0; HEADER_LEN];
+        file.read_exact_at(&mut header, 0)
+            .map_err(|_| corrupted("header is truncated"))?;
+        let (fields, mac) = header.split_at(FIELDS_LEN);
+        let (magic, fields) = fields.split_at(MAGIC.len());
+        if magic != MAGIC {
+            return Err(corrupted("has an unknown format"));
+        }
+        let (id, fields) = fields.split_at(ID_LEN);
+        let (generation, len) = fields.split_at(8);
+
+        let id: Id = id.try_into().unwrap();
+        let len = u64::from_le_bytes(len.try_into().unwrap());
+        let mut sealed = Self {
+            keys: Keys::derive(prk, &id)?,
+            file,
+            id,
+            generation: u64::from_le_bytes(generation.try_into().unwrap()),
+            len,
+            tags: vec![],
+        };
+
+        let chunks = sealed.chunks();
+        for index in 0..chunks {
+            let (offset, stored) = sealed.location(index);
+            let mut tag = [0; TAG_LEN];
+            sealed
+                .file
+                .read_exact_at(&mut tag, offset + stored as u64 - TAG_LEN as u64)
+                .map_err(|_| corrupted("is truncated"))?;
+            sealed.tags.push(tag);
+        }
+        hmac::verify(&sealed.keys.mac, &sealed.authenticated(), mac)
+            .map_err(|_| corrupted("failed to authenticate"))?;
+        Ok(sealed)
+    }
+
+    /// Returns the identifier of the file.
+    pub fn id(&self) -> &Id {
+        &self.id
+    }
+
+    /// Returns the generation of the file, which is incremented on every commit.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the plaintext length of the file.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns the underlying file.
+    pub fn inner(&self) -> &File {
+        &self.file
+    }
+
+    /// Returns the number of chunks of the file.
+    fn chunks(&self) -> u64 {
+        (self.len + CHUNK_SIZE as u64 - 1) / CHUNK_SIZE as u64
+    }
+
+    /// Returns the plaintext length of chunk `index`.
+    fn chunk_len(&self, index: u64) -> usize {
+        let start = index * CHUNK_SIZE as u64;
+        (self.len - start).min(CHUNK_SIZE as _) as _
+    }
+
+    /// Returns the offset of chunk `index` on disk.
+    fn offset(index: u64) -> u64 {
+        HEADER_LEN as u64 + index * (CHUNK_SIZE + OVERHEAD) as u64
+    }
+
+    /// Returns the offset on disk and stored length of chunk `index`.
+    fn location(&self, index: u64) -> (u64, usize) {
+        (Self::offset(index), self.chunk_len(index) + OVERHEAD)
+    }
+
+    /// Returns the associated data of chunk `index`.
+    fn aad(&self, index: u64) -> [u8; ID_LEN + 8] {
+        let mut aad = [0; ID_LEN + 8];
+        aad[..ID_LEN].copy_from_slice(&self.id);
+        aad[ID_LEN..].copy_from_slice(&index.to_le_bytes());
+        aad
+    }
+
+    /// Returns the header fields followed by the tags of all chunks.
+    fn authenticated(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(FIELDS_LEN + self.tags.len() * TAG_LEN);
+        data.extend(MAGIC);
+        data.extend(self.id);
+        data.extend(self.generation.to_le_bytes());
+        data.extend(self.len.to_le_bytes());
+        self.tags.iter().for_each(|tag| data.extend(tag));
+        data
+    }
+
+    /// Reads and decrypts chunk `index`.
+    fn read_chunk(&self, index: u64) -> io::Result<Vec<u8>> {
+        let (offset, stored) = self.location(index);
+        let mut buf = vec![0; stored];
+        self.file
+            .read_exact_at(&mut buf, offset)
+            .map_err(|_| corrupted("is truncated"))?;
+        if buf[stored - TAG_LEN..] != self.tags[index as usize] {
+            return Err(corrupted("was modified"));
+        }
+        let nonce = Nonce::try_assume_unique_for_key(&buf[..NONCE_LEN]).unwrap();
+        let len = self
+            .keys
+            .aead
+            .open_in_place(nonce, Aad::from(self.aad(index)), &mut buf[NONCE_LEN..])
+            .map_err(|_| corrupted("failed to decrypt"))?
+            .len();
+        buf.drain(..NONCE_LEN);
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// Encrypts and writes `plaintext` as chunk `index`.
+    fn write_chunk(&mut self, index: u64, plaintext: &[u8]) -> io::Result<()> {
+        let mut buf = vec![0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut buf)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to generate nonce"))?;
+        let nonce = Nonce::try_assume_unique_for_key(&buf).unwrap();
+        buf.extend(plaintext);
+        let tag = self
+            .keys
+            .aead
+            .seal_in_place_separate_tag(nonce, Aad::from(self.aad(index)), &mut buf[NONCE_LEN..])
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to encrypt"))?;
+        buf.extend(tag.as_ref());
+
+        self.file.write_all_at(&buf, Self::offset(index))?;
+        let tag = tag.as_ref().try_into().unwrap();
+        match self.tags.get_mut(index as usize) {
+            Some(old) => *old = tag,
+            None => self.tags.push(tag),
+        }
+        Ok(())
+    }
+
+    /// Reads up to `buf.len()` bytes at `offset` and returns the number of bytes read.
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let mut read = 0;
+        while read < buf.len() {
+            let pos = offset + read as u64;
+            if pos >= self.len {
+                break;
+            }
+            let index = pos / CHUNK_SIZE as u64;
+            let chunk = self.read_chunk(index)?;
+            let start = (pos % CHUNK_SIZE as u64) as usize;
+            let n = (chunk.len() - start).min(buf.len() - read);
+            buf[read..read + n].copy_from_slice(&chunk[start..start + n]);
+            read += n;
+        }
+        Ok(read)
+    }
+
+    /// Writes `buf` at `offset`, filling any gap after the current end with zeros.
+    ///
+    /// The write is only durable and verifiable after the next [`commit`](Self::commit).
+    pub fn write_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+        if offset > self.len {
+            self.set_len(offset)?;
+        }
+        let mut written = 0;
+        while written < buf.len() {
+            let pos = offset + written as u64;
+            let index = pos / CHUNK_SIZE as u64;
+            let start = (pos % CHUNK_SIZE as u64) as usize;
+            let n = (CHUNK_SIZE - start).min(buf.len() - written);
+
+            let mut chunk = if index < self.chunks() {
+                self.read_chunk(index)?
+            } else {
+                vec![]
+            };
+            if chunk.len() < start + n {
+                chunk.resize(start + n, 0);
+            }
+            chunk[start..start + n].copy_from_slice(&buf[written..written + n]);
+
+            self.len = self.len.max(pos + n as u64);
+            self.write_chunk(index, &chunk)?;
+            written += n;
+        }
+        Ok(())
+    }
+
+    /// Truncates or extends the file with zeros to `len` bytes.
+    pub fn set_len(&mut self, len: u64) -> io::Result<()> {
+        if len < self.len {
+            let index = len / CHUNK_SIZE as u64;
+            let rest = (len % CHUNK_SIZE as u64) as usize;
+            let mut last = if rest > 0 {
+                self.read_chunk(index)?
+            } else {
+                vec![]
+            };
+            self.len = len;
+            self.tags.truncate(self.chunks() as _);
+            if rest > 0 {
+                last.truncate(rest);
+                self.write_chunk(index, &last)?;
+            }
+            self.file.set_len(Self::offset(self.chunks()))?;
+        } else {
+            while self.len < len {
+                let pos = self.len;
+                let start = (pos % CHUNK_SIZE as u64) as usize;
+                let n = ((CHUNK_SIZE - start) as u64).min(len - pos) as usize;
+                self.write_at(&vec![0; n], pos)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Increments the generation and writes the authenticated header.
+    pub fn commit(&mut self) -> io::Result<()> {
+        self.generation += 1;
+        let fields = self.authenticated();
+        let mac = hmac::sign(&self.keys.mac, &fields);
+
+        let mut header = [0; HEADER_LEN];
+        header[..FIELDS_LEN].copy_from_slice(&fields[..FIELDS_LEN]);
+        header[FIELDS_LEN..].copy_from_slice(mac.as_ref());
+        self.file.write_all_at(&header, 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use ring::hkdf::{Salt, HKDF_SHA256};
+
+    fn prk() -> Prk {
+        Salt::new(HKDF_SHA256, b"test").extract(b"secret")
+    }
+
+    #[test]
+    fn roundtrip() {
+        let file = tempfile::tempfile().unwrap();
+        let mut sealed = SealedFile::create(file.try_clone().unwrap(), &prk()).unwrap();
+
+        let data: Vec<u8> = (0..3 * CHUNK_SIZE + 100).map(|i| i as u8).collect();
+        sealed.write_at(&data, 0).unwrap();
+        sealed.write_at(b"hello", CHUNK_SIZE as u64 - 2).unwrap();
+        sealed.commit().unwrap();
+        assert_eq!(sealed.generation(), 2);
+
+        let size = file.metadata().unwrap().len();
+        assert_eq!(plaintext_len(size), data.len() as u64);
+
+        let sealed = SealedFile::open(file, &prk()).unwrap();
+        let mut buf = vec![0; data.len() + 10];
+        assert_eq!(sealed.read_at(&mut buf, 0).unwrap(), data.len());
+        assert_eq!(&buf[CHUNK_SIZE - 2..CHUNK_SIZE + 3], b"hello");
+        assert_eq!(buf[..CHUNK_SIZE - 2], data[..CHUNK_SIZE - 2]);
+        assert_eq!(buf[CHUNK_SIZE + 3..data.len()], data[CHUNK_SIZE + 3..]);
+    }
+
+    #[test]
+    fn set_len() {
+        let file = tempfile::tempfile().unwrap();
+        let mut sealed = SealedFile::create(file.try_clone().unwrap(), &prk()).unwrap();
+
+        sealed.write_at(b"abc", 2 * CHUNK_SIZE as u64).unwrap();
+        sealed.set_len(CHUNK_SIZE as u64 + 1).unwrap();
+        sealed.commit().unwrap();
+
+        let sealed = SealedFile::open(file, &prk()).unwrap();
+        assert_eq!(sealed.len(), CHUNK_SIZE as u64 + 1);
+        let mut buf = vec![0xff; CHUNK_SIZE + 1];
+        assert_eq!(sealed.read_at(&mut buf, 0).unwrap(), CHUNK_SIZE + 1);
+        assert!(buf.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn tampered() {
+        let file = tempfile::tempfile().unwrap();
+        let mut sealed = SealedFile::create(file.try_clone().unwrap(), &prk()).unwrap();
+        sealed.write_at(&[1; 2 * CHUNK_SIZE], 0).unwrap();
+        sealed.commit().unwrap();
+
+        // A wrong key fails to authenticate the header.
+        let other = Salt::new(HKDF_SHA256, b"other").extract(b"secret");
+        assert!(SealedFile::open(file.try_clone().unwrap(), &other).is_err());
+
+        // Swapping two chunks fails to authenticate the header.
+        let stored = CHUNK_SIZE + OVERHEAD;
+        let mut first = vec![0; stored];
+        let mut second = vec![0; stored];
+        file.read_exact_at(&mut first, HEADER_LEN as _).unwrap();
+        file.read_exact_at(&mut second, (HEADER_LEN + stored) as _)
+            .unwrap();
+        file.write_all_at(&second, HEADER_LEN as _).unwrap();
+        file.write_all_at(&first, (HEADER_LEN + stored) as _)
+            .unwrap();
+        assert!(SealedFile::open(file.try_clone().unwrap(), &prk()).is_err());
+
+        // Flipping a ciphertext bit fails to decrypt.
+        file.write_all_at(&first, HEADER_LEN as _).unwrap();
+        file.write_all_at(&second, (HEADER_LEN + stored) as _)
+            .unwrap();
+        let sealed = SealedFile::open(file.try_clone().unwrap(), &prk()).unwrap();
+        file.write_all_at(&[first[NONCE_LEN] ^ 1], (HEADER_LEN + NONCE_LEN) as _)
+            .unwrap();
+        assert!(sealed.read_at(&mut [0; 1], 0).is_err());
+    }
+}
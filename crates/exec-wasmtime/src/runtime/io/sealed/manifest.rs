@@ -0,0 +1,282 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! The rollback manifest of a sealed directory.
+//!
+//! The manifest records the latest generation of every sealed file in the directory tree, so the
+//! host cannot replace a file with an older, validly sealed copy of itself. Removed files are kept
+//! as tombstones, so they cannot be restored either. It also records the path of every file
+//! relative to the root, so the host cannot swap files or make one appear under another path.
+//!
+//! ```text
+//! magic (8) | count (8) | count * (file id (16) | generation (8))
+//!           | count (8) | count * (file id (16) | length (8) | path) | mac (32)
+//! ```
+//!
+//! The manifest itself is authenticated, but it is stored on the host as well. Rolling back the
+//! whole directory together with its manifest can therefore not be detected.
+
+use super::file::Id;
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::io::{self, ErrorKind, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use cap_std::fs::{Dir, OpenOptions};
+use ring::hkdf::Prk;
+use ring::hmac;
+
+/// The name of the manifest in the root of a sealed directory.
+pub const NAME: &str = ".enarx-sealed";
+
+/// The name the manifest is written to before atomically replacing it.
+const TMP_NAME: &str = ".enarx-sealed.tmp";
+
+const MAGIC: [u8; 8] = *b"ENXMANI2";
+
+/// The length of a record of a file id and its generation.
+const RECORD_LEN: usize = 16 + 8;
+
+const MAC_LEN: usize = 32;
+
+/// The generation recorded for removed files.
+const REMOVED: u64 = 0;
+
+fn corrupted(what: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("sealed manifest {what}"))
+}
+
+/// The recorded state of the sealed files.
+#[derive(Default)]
+struct Entries {
+    /// The latest generation of every file, which was ever sealed
+    generations: BTreeMap<Id, u64>,
+    /// The file id at every path relative to the root
+    paths: BTreeMap<PathBuf, Id>,
+}
+
+/// Splits `len` bytes off the front of `data`.
+fn take<'a>(data: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if data.len() < len {
+        return Err(corrupted("is truncated"));
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Ok(head)
+}
+
+fn take_u64(data: &mut &[u8]) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(take(data, 8)?.try_into().unwrap()))
+}
+
+/// The rollback manifest of a sealed directory.
+pub struct Manifest {
+    dir: Dir,
+    key: hmac::Key,
+    entries: Mutex<Entries>,
+}
+
+impl Manifest {
+    /// Loads the manifest from the root `dir` of a sealed directory or starts an empty one,
+    /// if there is none yet.
+    pub fn load(dir: Dir, prk: &Prk) -> io::Result<Self> {
+        let key =
+            hmac::Key::from(prk.expand(&[b"manifest"], hmac::HMAC_SHA256).map_err(|_| {
+                io::Error::new(io::ErrorKind::Other, "failed to derive manifest key")
+            })?);
+
+        let mut data = vec![];
+        match dir.open(NAME) {
+            Ok(mut file) => file.read_to_end(&mut data)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Ok(Self {
+                    dir,
+                    key,
+                    entries: Default::default(),
+                })
+            }
+            Err(e) => return Err(e),
+        };
+
+        if data.len() < MAGIC.len() + MAC_LEN {
+            return Err(corrupted("is truncated"));
+        }
+        let (body, mac) = data.split_at(data.len() - MAC_LEN);
+        hmac::verify(&key, body, mac).map_err(|_| corrupted("failed to authenticate"))?;
+        if body[..MAGIC.len()] != MAGIC {
+            return Err(corrupted("has an unknown format"));
+        }
+
+        let mut body = &body[MAGIC.len()..];
+        let mut entries = Entries::default();
+        for _ in 0..take_u64(&mut body)? {
+            let record = take(&mut body, RECORD_LEN)?;
+            let (id, generation) = record.split_at(16);
+            entries.generations.insert(
+                id.try_into().unwrap(),
+                u64::from_le_bytes(generation.try_into().unwrap()),
+            );
+        }
+        for _ in 0..take_u64(&mut body)? {
+            let id = take(&mut body, 16)?.try_into().unwrap();
+            let len = take_u64(&mut body)?;
+            let len = usize::try_from(len).map_err(|_| corrupted("is truncated"))?;
+            let path = OsStr::from_bytes(take(&mut body, len)?);
+            entries.paths.insert(path.into(), id);
+        }
+        if !body.is_empty() {
+            return Err(corrupted("has trailing data"));
+        }
+
+        Ok(Self {
+            dir,
+            key,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Checks that the file at `path` with `id` and `generation` is the one recorded at `path`
+    /// and not older than recorded.
+    pub fn check(&self, path: &Path, id: &Id, generation: u64) -> io::Result<()> {
+        let entries = self.entries.lock().unwrap();
+        if entries.paths.get(path) != Some(id) {
+            return Err(corrupted("records another file at the path"));
+        }
+        match entries.generations.get(id) {
+            None => Err(corrupted("does not know the file")),
+            Some(&REMOVED) => Err(corrupted("records the file as removed")),
+            Some(&recorded) if generation < recorded => Err(corrupted("records a newer file")),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Records the new file with `id` and `generation` at `path`.
+    pub fn create(&self, path: &Path, id: &Id, generation: u64) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.generations.insert(*id, generation);
+        entries.paths.insert(path.into(), *id);
+        self.store(&entries)
+    }
+
+    /// Records `generation` as the latest generation of the file with `id`.
+    pub fn update(&self, id: &Id, generation: u64) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.generations.insert(*id, generation);
+        self.store(&entries)
+    }
+
+    /// Records the file at `path` as removed, if there is one.
+    pub fn remove(&self, path: &Path) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(id) = entries.paths.remove(path) else {
+            return Ok(());
+        };
+        entries.generations.insert(id, REMOVED);
+        self.store(&entries)
+    }
+
+    /// Records the file or the files below the directory at `from` as moved to `to`, replacing
+    /// the file at `to`, if there is one.
+    pub fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(id) = entries.paths.remove(to) {
+            entries.generations.insert(id, REMOVED);
+        }
+        let moved: Vec<_> = entries
+            .paths
+            .keys()
+            .filter(|path| path.starts_with(from))
+            .cloned()
+            .collect();
+        for path in moved {
+            let id = entries.paths.remove(&path).unwrap();
+            let rest = path.strip_prefix(from).unwrap();
+            let path = match rest.as_os_str().is_empty() {
+                true => to.to_path_buf(),
+                false => to.join(rest),
+            };
+            entries.paths.insert(path, id);
+        }
+        self.store(&entries)
+    }
+
+    fn store(&self, entries: &Entries) -> io::Result<()> {
+        let mut data =
+            Vec::with_capacity(MAGIC.len() + 16 + entries.generations.len() * RECORD_LEN);
+        data.extend(MAGIC);
+        data.extend((entries.generations.len() as u64).to_le_bytes());
+        for (id, generation) in &entries.generations {
+            data.extend(id);
+            data.extend(generation.to_le_bytes());
+        }
+        data.extend((entries.paths.len() as u64).to_le_bytes());
+        for (path, id) in &entries.paths {
+            let path = path.as_os_str().as_bytes();
+            data.extend(id);
+            data.extend((path.len() as u64).to_le_bytes());
+            data.extend(path);
+        }
+        let mac = hmac::sign(&self.key, &data);
+        data.extend(mac.as_ref());
+
+        let mut file = self.dir.open_with(
+            TMP_NAME,
+            OpenOptions::new().write(true).create(true).truncate(true),
+        )?;
+        file.write_all(&data)?;
+        file.sync_data()?;
+        self.dir.rename(TMP_NAME, &self.dir, NAME)
+    }
+}
+
+/// Returns whether `name` is reserved for the manifest.
+pub fn is_reserved(name: &str) -> bool {
+    name == NAME || name == TMP_NAME
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use ring::hkdf::{Salt, HKDF_SHA256};
+
+    fn prk() -> Prk {
+        Salt::new(HKDF_SHA256, b"test").extract(b"secret")
+    }
+
+    #[test]
+    fn rollback() {
+        let tmp = tempfile::tempdir().unwrap();
+        let open = || Dir::open_ambient_dir(tmp.path(), cap_std::ambient_authority()).unwrap();
+
+        let (a, b, c) = (Path::new("a"), Path::new("b"), Path::new("c"));
+        let manifest = Manifest::load(open(), &prk()).unwrap();
+        manifest.create(a, &[1; 16], 1).unwrap();
+        manifest.update(&[1; 16], 3).unwrap();
+        manifest.create(b, &[2; 16], 1).unwrap();
+        manifest.remove(b).unwrap();
+
+        let manifest = Manifest::load(open(), &prk()).unwrap();
+        assert!(manifest.check(a, &[1; 16], 3).is_ok());
+        assert!(manifest.check(a, &[1; 16], 4).is_ok());
+        assert!(manifest.check(a, &[1; 16], 2).is_err());
+        assert!(manifest.check(b, &[2; 16], 1).is_err());
+        assert!(manifest.check(c, &[3; 16], 1).is_err());
+
+        // A file is only valid at its recorded path.
+        assert!(manifest.check(b, &[1; 16], 3).is_err());
+        manifest.rename(a, &c.join("d")).unwrap();
+        let manifest = Manifest::load(open(), &prk()).unwrap();
+        assert!(manifest.check(a, &[1; 16], 3).is_err());
+        assert!(manifest.check(&c.join("d"), &[1; 16], 3).is_ok());
+
+        // Renaming a directory moves the files below it.
+        manifest.rename(c, b).unwrap();
+        assert!(manifest.check(&b.join("d"), &[1; 16], 3).is_ok());
+
+        let other = Salt::new(HKDF_SHA256, b"other").extract(b"secret");
+        assert!(Manifest::load(open(), &other).is_err());
+    }
+}
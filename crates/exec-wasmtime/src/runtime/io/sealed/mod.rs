@@ -0,0 +1,603 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! A pre-opened directory, whose files are transparently encrypted and authenticated.
+//!
+//! Every regular file is stored as a [`SealedFile`](file::SealedFile) with keys derived from the
+//! sealing key of the directory and its own random file id. The [`Manifest`](manifest::Manifest)
+//! in the root of the directory records the path and the latest generation of every file to
+//! detect swapped and rolled back files. A sealed file is therefore only valid at its own path,
+//! not via symbolic links or hard links.
+//!
+//! File names, the directory structure, file sizes rounded to chunks and access patterns are
+//! still visible to the host.
+
+mod file;
+mod manifest;
+
+use self::file::{plaintext_len, SealedFile};
+use self::manifest::Manifest;
+
+use std::any::Any;
+use std::io::{self, ErrorKind, IoSlice, IoSliceMut, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use cap_std::fs::{Dir, OpenOptions, OpenOptionsExt};
+use ring::hkdf::{Prk, Salt, HKDF_SHA256};
+use wasi_common::dir::{OpenResult, ReaddirCursor, ReaddirEntity, WasiDir};
+use wasi_common::file::{FdFlags, FileType, Filestat, OFlags};
+use wasi_common::{Error, ErrorExt, SystemTimeSpec, WasiFile};
+
+/// Converts an I/O error of the sealed file format into a WASI error.
+///
+/// Errors without an OS error code, e.g. failed authentication, are reported as `EIO`
+/// instead of trapping.
+fn error(e: io::Error) -> Error {
+    match e.raw_os_error() {
+        Some(_) => e.into(),
+        None if e.kind() == ErrorKind::NotFound => e.into(),
+        None => Error::io().context(e.to_string()),
+    }
+}
+
+/// Returns whether the last component of `path` is reserved for the manifest.
+fn is_reserved(path: &str) -> bool {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(manifest::is_reserved)
+}
+
+/// A pre-opened directory, whose files are sealed.
+pub struct SealedDir {
+    dir: Dir,
+    /// The path of this directory relative to the root of the sealed directory
+    base: PathBuf,
+    inner: wasi_common::sync::dir::Dir,
+    prk: Arc<Prk>,
+    manifest: Arc<Manifest>,
+}
+
+impl SealedDir {
+    /// Opens the root of a sealed directory with a key derived from `key` and `name`,
+    /// the path the directory is pre-opened at in the guest.
+    pub fn open(dir: Dir, key: &[u8], name: &str) -> io::Result<Self> {
+        let prk = Salt::new(HKDF_SHA256, b"enarx sealed directory")
+            .extract(key)
+            .expand(&[name.as_bytes()], HKDF_SHA256)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to derive directory key"))?
+            .into();
+        let manifest = Manifest::load(dir.try_clone()?, &prk)?;
+        Self::new(dir, PathBuf::new(), Arc::new(prk), Arc::new(manifest))
+    }
+
+    fn new(dir: Dir, base: PathBuf, prk: Arc<Prk>, manifest: Arc<Manifest>) -> io::Result<Self> {
+        Ok(Self {
+            inner: wasi_common::sync::dir::Dir::from_cap_std(dir.try_clone()?),
+            dir,
+            base,
+            prk,
+            manifest,
+        })
+    }
+
+    /// Returns `path` relative to the root of the sealed directory.
+    fn relative(&self, path: &str) -> io::Result<PathBuf> {
+        let mut relative = self.base.clone();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => relative.push(name),
+                Component::CurDir => {}
+                Component::ParentDir if relative.pop() => {}
+                _ => return Err(io::Error::from_raw_os_error(libc::EPERM)),
+            }
+        }
+        Ok(relative)
+    }
+
+    fn open_sealed(
+        &self,
+        symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        write: bool,
+    ) -> io::Result<SealedFile> {
+        let mut opts = OpenOptions::new();
+        opts.read(true);
+        if !symlink_follow {
+            opts.custom_flags(libc::O_NOFOLLOW);
+        }
+
+        // A file is only initialized, if it is created here. Existing files, which fail to
+        // authenticate, are never silently replaced.
+        if oflags.contains(OFlags::CREATE) {
+            match self
+                .dir
+                .open_with(path, opts.clone().write(true).create_new(true))
+            {
+                Ok(file) => {
+                    let sealed = SealedFile::create(file.into_std(), &self.prk)?;
+                    self.manifest.create(
+                        &self.relative(path)?,
+                        sealed.id(),
+                        sealed.generation(),
+                    )?;
+                    return Ok(sealed);
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    if oflags.contains(OFlags::EXCLUSIVE) {
+                        return Err(e);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let truncate = oflags.contains(OFlags::TRUNCATE);
+        let file = self.dir.open_with(path, opts.write(write || truncate))?;
+        let mut sealed = SealedFile::open(file.into_std(), &self.prk)?;
+        self.manifest
+            .check(&self.relative(path)?, sealed.id(), sealed.generation())?;
+        if truncate {
+            sealed.set_len(0)?;
+            sealed.commit()?;
+            self.manifest.update(sealed.id(), sealed.generation())?;
+        }
+        Ok(sealed)
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiDir for SealedDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn open_file(
+        &self,
+        symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        read: bool,
+        write: bool,
+        fdflags: FdFlags,
+    ) -> Result<OpenResult, Error> {
+        if is_reserved(path) {
+            return Err(Error::perm().context("reserved for the sealed manifest"));
+        }
+
+        let meta = if symlink_follow {
+            self.dir.metadata(path)
+        } else {
+            self.dir.symlink_metadata(path)
+        };
+        match meta {
+            Ok(meta) if meta.is_dir() => {
+                if oflags.intersects(OFlags::CREATE | OFlags::EXCLUSIVE | OFlags::TRUNCATE) {
+                    return Err(Error::invalid_argument().context("directory oflags"));
+                }
+                let base = self.relative(path).map_err(error)?;
+                let dir = self.dir.open_dir(path)?;
+                let dir = Self::new(dir, base, self.prk.clone(), self.manifest.clone())?;
+                return Ok(OpenResult::Dir(Box::new(dir)));
+            }
+            Ok(meta) if !meta.is_file() => {
+                // Symbolic links, when not followed, and special files are passed through.
+                return self
+                    .inner
+                    .open_file(symlink_follow, path, oflags, read, write, fdflags)
+                    .await;
+            }
+            _ if oflags.contains(OFlags::DIRECTORY) => {
+                return Err(Error::not_dir().context("expected directory but got file"))
+            }
+            _ => {}
+        }
+
+        let sealed = self
+            .open_sealed(symlink_follow, path, oflags, write)
+            .map_err(error)?;
+        Ok(OpenResult::File(Box::new(File {
+            state: Mutex::new(State {
+                sealed,
+                position: 0,
+            }),
+            manifest: self.manifest.clone(),
+            fdflags,
+        })))
+    }
+
+    async fn create_dir(&self, path: &str) -> Result<(), Error> {
+        self.inner.create_dir(path).await
+    }
+
+    async fn readdir(
+        &self,
+        cursor: ReaddirCursor,
+    ) -> Result<Box<dyn Iterator<Item = Result<ReaddirEntity, Error>> + Send>, Error> {
+        let entries = self.inner.readdir(cursor).await?;
+        Ok(Box::new(entries.filter(
+            |entry| !matches!(entry, Ok(entry) if manifest::is_reserved(&entry.name)),
+        )))
+    }
+
+    async fn symlink(&self, _old_path: &str, _new_path: &str) -> Result<(), Error> {
+        // A sealed file is only valid at its own path.
+        Err(Error::not_supported().context("symbolic links in a sealed directory"))
+    }
+
+    async fn remove_dir(&self, path: &str) -> Result<(), Error> {
+        self.inner.remove_dir(path).await
+    }
+
+    async fn unlink_file(&self, path: &str) -> Result<(), Error> {
+        if is_reserved(path) {
+            return Err(Error::perm().context("reserved for the sealed manifest"));
+        }
+        let relative = self.relative(path).map_err(error)?;
+        self.manifest.remove(&relative).map_err(error)?;
+        self.inner.unlink_file(path).await
+    }
+
+    async fn read_link(&self, path: &str) -> Result<PathBuf, Error> {
+        self.inner.read_link(path).await
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+
+    async fn get_path_filestat(
+        &self,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> Result<Filestat, Error> {
+        let mut stat = self.inner.get_path_filestat(path, follow_symlinks).await?;
+        if stat.filetype == FileType::RegularFile {
+            stat.size = plaintext_len(stat.size);
+        }
+        Ok(stat)
+    }
+
+    async fn rename(
+        &self,
+        path: &str,
+        dest_dir: &dyn WasiDir,
+        dest_path: &str,
+    ) -> Result<(), Error> {
+        let dest_dir = dest_dir
+            .as_any()
+            .downcast_ref::<Self>()
+            .filter(|dest| Arc::ptr_eq(&dest.manifest, &self.manifest))
+            .ok_or_else(|| Error::badf().context("cannot rename out of a sealed directory"))?;
+        if is_reserved(path) || is_reserved(dest_path) {
+            return Err(Error::perm().context("reserved for the sealed manifest"));
+        }
+        let from = self.relative(path).map_err(error)?;
+        let to = dest_dir.relative(dest_path).map_err(error)?;
+        self.inner.rename(path, &dest_dir.inner, dest_path).await?;
+        self.manifest.rename(&from, &to).map_err(error)
+    }
+
+    async fn hard_link(
+        &self,
+        _path: &str,
+        _target_dir: &dyn WasiDir,
+        _target_path: &str,
+    ) -> Result<(), Error> {
+        // A sealed file is only valid at its own path.
+        Err(Error::not_supported().context("hard links in a sealed directory"))
+    }
+
+    async fn set_times(
+        &self,
+        path: &str,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+        follow_symlinks: bool,
+    ) -> Result<(), Error> {
+        self.inner
+            .set_times(path, atime, mtime, follow_symlinks)
+            .await
+    }
+}
+
+struct State {
+    sealed: SealedFile,
+    position: u64,
+}
+
+/// An open file in a [`SealedDir`].
+struct File {
+    state: Mutex<State>,
+    manifest: Arc<Manifest>,
+    fdflags: FdFlags,
+}
+
+impl File {
+    /// Writes `bufs` at `offset` or the end of the file and commits the write.
+    fn write(
+        &self,
+        state: &mut State,
+        bufs: &[IoSlice<'_>],
+        offset: Option<u64>,
+    ) -> io::Result<u64> {
+        let mut offset = offset.unwrap_or(state.sealed.len());
+        let start = offset;
+        for buf in bufs {
+            state.sealed.write_at(buf, offset)?;
+            offset += buf.len() as u64;
+        }
+        state.sealed.commit()?;
+        self.manifest
+            .update(state.sealed.id(), state.sealed.generation())?;
+        Ok(offset - start)
+    }
+
+    fn read(state: &State, bufs: &mut [IoSliceMut<'_>], mut offset: u64) -> io::Result<u64> {
+        let start = offset;
+        for buf in bufs {
+            let n = state.sealed.read_at(buf, offset)?;
+            offset += n as u64;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(offset - start)
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for File {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn datasync(&self) -> Result<(), Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state.sealed.inner().sync_data()?)
+    }
+
+    async fn sync(&self) -> Result<(), Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state.sealed.inner().sync_all()?)
+    }
+
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(self.fdflags)
+    }
+
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        if fdflags.intersects(FdFlags::DSYNC | FdFlags::SYNC | FdFlags::RSYNC) {
+            return Err(Error::invalid_argument().context("cannot set DSYNC, SYNC, or RSYNC flag"));
+        }
+        self.fdflags = fdflags;
+        Ok(())
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        use std::os::unix::fs::MetadataExt;
+
+        let state = self.state.lock().unwrap();
+        let meta = state.sealed.inner().metadata()?;
+        Ok(Filestat {
+            device_id: meta.dev(),
+            inode: meta.ino(),
+            filetype: FileType::RegularFile,
+            nlink: meta.nlink(),
+            size: state.sealed.len(),
+            atim: meta.accessed().ok(),
+            mtim: meta.modified().ok(),
+            ctim: meta.created().ok(),
+        })
+    }
+
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let mut commit = || {
+            state.sealed.set_len(size)?;
+            state.sealed.commit()?;
+            self.manifest
+                .update(state.sealed.id(), state.sealed.generation())
+        };
+        commit().map_err(error)
+    }
+
+    async fn read_vectored<'a>(&self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        let mut state = self.state.lock().unwrap();
+        let n = Self::read(&state, bufs, state.position).map_err(error)?;
+        state.position += n;
+        Ok(n)
+    }
+
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let state = self.state.lock().unwrap();
+        Self::read(&state, bufs, offset).map_err(error)
+    }
+
+    async fn write_vectored<'a>(&self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        let mut state = self.state.lock().unwrap();
+        let offset = match self.fdflags.contains(FdFlags::APPEND) {
+            true => None,
+            false => Some(state.position),
+        };
+        let n = self.write(&mut state, bufs, offset).map_err(error)?;
+        state.position = match offset {
+            Some(offset) => offset + n,
+            None => state.sealed.len(),
+        };
+        Ok(n)
+    }
+
+    async fn write_vectored_at<'a>(&self, bufs: &[IoSlice<'a>], offset: u64) -> Result<u64, Error> {
+        let mut state = self.state.lock().unwrap();
+        self.write(&mut state, bufs, Some(offset)).map_err(error)
+    }
+
+    async fn seek(&self, pos: SeekFrom) -> Result<u64, Error> {
+        let mut state = self.state.lock().unwrap();
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => state.position.checked_add_signed(delta),
+            SeekFrom::End(delta) => state.sealed.len().checked_add_signed(delta),
+        };
+        state.position = position.ok_or_else(|| Error::invalid_argument().context("seek"))?;
+        Ok(state.position)
+    }
+
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        let state = self.state.lock().unwrap();
+        let n = state.sealed.read_at(buf, state.position).map_err(error)?;
+        Ok(n as _)
+    }
+
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state.sealed.len().saturating_sub(state.position))
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    /// Runs a future, which never suspends.
+    fn run<F: Future>(future: F) -> F::Output {
+        const VTABLE: RawWakerVTable =
+            RawWakerVTable::new(|_| RawWaker::new(&(), &VTABLE), |_| {}, |_| {}, |_| {});
+        let waker = unsafe { Waker::from_raw(RawWaker::new(&(), &VTABLE)) };
+        match pin!(future).poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("future suspended"),
+        }
+    }
+
+    fn open(dir: &dyn WasiDir, path: &str, oflags: OFlags) -> Result<Box<dyn WasiFile>, Error> {
+        match run(dir.open_file(false, path, oflags, true, true, FdFlags::empty()))? {
+            OpenResult::File(file) => Ok(file),
+            OpenResult::Dir(_) => panic!("expected a file"),
+        }
+    }
+
+    #[test]
+    fn sealed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ambient = || Dir::open_ambient_dir(tmp.path(), cap_std::ambient_authority()).unwrap();
+        let dir = SealedDir::open(ambient(), b"key", "data").unwrap();
+
+        let file = open(&dir, "file", OFlags::CREATE).unwrap();
+        let data = b"hello, sealed world";
+        assert_eq!(
+            run(file.write_vectored(&[IoSlice::new(data)])).unwrap(),
+            data.len() as u64
+        );
+        drop(file);
+
+        // The host only sees ciphertext.
+        let raw = std::fs::read(tmp.path().join("file")).unwrap();
+        assert!(!raw.windows(data.len()).any(|w| w == data));
+
+        let stat = run(dir.get_path_filestat("file", false)).unwrap();
+        assert_eq!(stat.size, data.len() as u64);
+
+        // The manifest is hidden from the guest.
+        let names = run(dir.readdir(0.into()))
+            .unwrap()
+            .map(|entry| entry.unwrap().name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![".", "..", "file"]);
+        assert!(open(&dir, manifest::NAME, OFlags::empty()).is_err());
+
+        // Reopening with the same key succeeds.
+        let dir = SealedDir::open(ambient(), b"key", "data").unwrap();
+        let file = open(&dir, "file", OFlags::empty()).unwrap();
+        let mut buf = [0; 64];
+        let n = run(file.read_vectored(&mut [IoSliceMut::new(&mut buf)])).unwrap();
+        assert_eq!(&buf[..n as usize], data);
+
+        // A rolled back file is rejected.
+        run(file.write_vectored_at(&[IoSlice::new(b"HELLO")], 0)).unwrap();
+        drop(file);
+        std::fs::write(tmp.path().join("file"), raw).unwrap();
+        assert!(open(&dir, "file", OFlags::empty()).is_err());
+
+        // A different key fails to authenticate the manifest.
+        assert!(SealedDir::open(ambient(), b"other", "data").is_err());
+        assert!(SealedDir::open(ambient(), b"key", "other").is_err());
+    }
+
+    #[test]
+    fn paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ambient = || Dir::open_ambient_dir(tmp.path(), cap_std::ambient_authority()).unwrap();
+        let dir = SealedDir::open(ambient(), b"key", "data").unwrap();
+        run(dir.create_dir("sub")).unwrap();
+
+        for (path, data) in [("a", b"first"), ("sub/b", b"other")] {
+            let file = open(&dir, path, OFlags::CREATE).unwrap();
+            run(file.write_vectored(&[IoSlice::new(data)])).unwrap();
+        }
+
+        // Swapped files are rejected.
+        let (a, b) = (tmp.path().join("a"), tmp.path().join("sub/b"));
+        let (raw_a, raw_b) = (std::fs::read(&a).unwrap(), std::fs::read(&b).unwrap());
+        std::fs::write(&a, &raw_b).unwrap();
+        std::fs::write(&b, &raw_a).unwrap();
+        assert!(open(&dir, "a", OFlags::empty()).is_err());
+        assert!(open(&dir, "sub/b", OFlags::empty()).is_err());
+        std::fs::write(&a, &raw_a).unwrap();
+        std::fs::write(&b, &raw_b).unwrap();
+
+        // Renamed files and directories move along with their paths.
+        run(dir.rename("a", &dir, "sub/c")).unwrap();
+        run(dir.rename("sub", &dir, "moved")).unwrap();
+        let dir = SealedDir::open(ambient(), b"key", "data").unwrap();
+        assert!(open(&dir, "a", OFlags::empty()).is_err());
+        let sub = match run(dir.open_file(
+            false,
+            "moved",
+            OFlags::empty(),
+            true,
+            false,
+            FdFlags::empty(),
+        )) {
+            Ok(OpenResult::Dir(sub)) => sub,
+            _ => panic!("expected a directory"),
+        };
+        for (path, data) in [("c", b"first"), ("b", b"other")] {
+            let file = open(&*sub, path, OFlags::empty()).unwrap();
+            let mut buf = [0; 16];
+            let n = run(file.read_vectored(&mut [IoSliceMut::new(&mut buf)])).unwrap();
+            assert_eq!(&buf[..n as usize], data);
+        }
+
+        // Sealed files are not reachable via other paths.
+        assert!(run(dir.symlink("moved/c", "link")).is_err());
+        std::os::unix::fs::symlink("moved/c", tmp.path().join("link")).unwrap();
+        assert!(open(&dir, "link", OFlags::empty()).is_err());
+        assert!(
+            run(dir.open_file(true, "link", OFlags::empty(), true, false, FdFlags::empty()))
+                .is_err()
+        );
+    }
+}
@@ -4,15 +4,17 @@
 
 mod identity;
 mod io;
+mod signals;
 //mod net;
 
 use self::io::null::Null;
+use self::io::sealed::SealedDir;
 
-use super::{Package, Workload};
+use super::{Attestation, Package, Workload};
 
 use anyhow::{anyhow, bail, Context, Result};
 use cap_std::fs::Dir;
-use enarx_config::{Config, File};
+use enarx_config::{Config, Encryption, File};
 use rawposix::safeposix::dispatcher::lind_syscall_api;
 use std::sync::{atomic::AtomicU64, Arc};
 use wasi_common::sync::WasiCtxBuilder;
@@ -97,6 +99,48 @@ impl LindHost<HostCtx, Option<enarx_config::Config>> for HostCtx {
     }
 }
 
+/// Pre-opens the directories configured in Enarx.toml in `ctx`,
+/// sealing the files of those with encryption enabled.
+///
+/// The runtime runs every module as a lind cage, which does its file I/O through rawposix instead
+/// of WASI and would write the files of a sealed directory in plaintext, so sealed directories
+/// are refused, until rawposix seals them as well.
+fn preopen_dirs(ctx: &wasi_common::WasiCtx, dirs: &[enarx_config::Dir]) -> Result<()> {
+    if let Some(dir) = dirs.iter().find(|dir| dir.encryption != Encryption::None) {
+        bail!(
+            "sealed directory `{}` is not supported for lind cages, which bypass WASI for file I/O",
+            dir.guest
+        );
+    }
+
+    let mut key = None;
+    for enarx_config::Dir {
+        host,
+        guest,
+        encryption,
+    } in dirs
+    {
+        let dir = Dir::open_ambient_dir(host, cap_std::ambient_authority())
+            .with_context(|| format!("failed to open directory `{host}`"))?;
+        let dir: Box<dyn wasi_common::WasiDir> = match encryption {
+            Encryption::None => Box::new(wasi_common::sync::dir::Dir::from_cap_std(dir)),
+            Encryption::Platform => {
+                let key = match &mut key {
+                    Some(key) => key,
+                    None => key.insert(identity::sealing_key()?),
+                };
+                Box::new(
+                    SealedDir::open(dir, key, guest)
+                        .with_context(|| format!("failed to open sealed directory `{host}`"))?,
+                )
+            }
+        };
+        ctx.push_preopened_dir(dir, guest)
+            .with_context(|| format!("failed to pre-open directory `{guest}`"))?;
+    }
+    Ok(())
+}
+
 // The Enarx Wasm runtime
 #[derive(Clone)]
 pub struct Runtime;
@@ -108,7 +152,10 @@ impl Runtime {
     /// and linker, and injects various contexts (WASI, lind-common, lind-multi-process). The
     /// module is instantiated, and the main function is executed via load_main_module. This
     /// function is the primary entry point for initial Wasm execution.
-    pub fn execute(package: Package) -> anyhow::Result<Vec<Val>> {
+    ///
+    /// The outcome of establishing the keep identity is passed to `report`,
+    /// before the workload is started.
+    pub fn execute(package: Package, report: impl FnOnce(Attestation)) -> anyhow::Result<Vec<Val>> {
         let (prvkey, crtreq) =
             identity::generate().context("failed to generate a private key and CSR")?;
 
@@ -119,14 +166,30 @@ impl Runtime {
             args,
             files,
             env,
+            dirs,
         } = enarx_conf.clone().unwrap_or_default();
 
         let certs = if let Some(url) = steward {
             // Obtaining attestation certificates
-            identity::steward(&url, crtreq).context("failed to attest to Steward")?
+            match identity::steward(&url, crtreq).context("failed to attest to Steward") {
+                Ok(certs) => {
+                    report(Attestation::Steward { url: url.into() });
+                    certs
+                }
+                Err(e) => {
+                    report(Attestation::Failed {
+                        url: url.into(),
+                        error: format!("{e:#}"),
+                    });
+                    return Err(e);
+                }
+            }
         } else {
             // Generating a self-signed certificate
-            identity::selfsigned(&prvkey).context("failed to generate self-signed certificates")?
+            let certs = identity::selfsigned(&prvkey)
+                .context("failed to generate self-signed certificates")?;
+            report(Attestation::SelfSigned);
+            certs
         }
         .into_iter()
         .map(rustls::Certificate)
@@ -178,7 +241,9 @@ impl Runtime {
         builder
             .preopened_dir(dir, ".")
             .expect("failed to open current directory");
-        wstore.data_mut().preview1_ctx = Some(builder.build());
+        let ctx = builder.build();
+        preopen_dirs(&ctx, &dirs)?;
+        wstore.data_mut().preview1_ctx = Some(ctx);
 
         // Setup WASI-thread
         trace_span!("link WASI-thread")
@@ -247,6 +312,9 @@ impl Runtime {
             Arc::new(linker.clone()),
         )?));
 
+        // Deliver the signals the host forwards, e.g. on SIGTERM, to the main cage.
+        signals::forward(&mut wstore);
+
         let result = wasmtime_wasi::runtime::with_ambient_tokio_runtime(|| {
             Runtime::load_main_module(
                 &mut wstore,
@@ -309,6 +377,7 @@ impl Runtime {
             args,
             files,
             env,
+            dirs,
         } = enarx_conf.clone().unwrap_or_default();
 
         let mut config = wasmtime::Config::new();
@@ -353,7 +422,9 @@ impl Runtime {
         builder
             .preopened_dir(dir, ".")
             .expect("failed to open current directory");
-        wstore.data_mut().preview1_ctx = Some(builder.build());
+        let ctx = builder.build();
+        preopen_dirs(&ctx, &dirs)?;
+        wstore.data_mut().preview1_ctx = Some(ctx);
 
         // Setup WASI-thread
         trace_span!("link WASI-thread")
@@ -0,0 +1,60 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Signals forwarded by the host into the keep.
+//!
+//! The shim collects the signals the host forwards on any exit to the host. They are polled for
+//! on calls of the main cage into the host, so no thread of the keep waits for them.
+
+use wasmtime::Store;
+use wasmtime_lind_multi_process::CAGE_START_ID;
+
+/// The number of calls into the host between two polls for forwarded signals
+///
+/// Polling is cheap for KVM keeps, but exits the enclave of SGX keeps.
+const POLL_INTERVAL: usize = 64;
+
+/// Returns the next signal the host forwarded to the keep, if any.
+///
+/// Returns `None` as well, if the host does not forward signals, e.g. outside of a keep.
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+fn take() -> Option<i32> {
+    None
+}
+
+/// Returns the next signal the host forwarded to the keep, if any.
+///
+/// Returns `None` as well, if the host does not forward signals, e.g. outside of a keep.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn take() -> Option<i32> {
+    use sallyport::item::enarxcall::SYS_GETSIG;
+    use std::arch::asm;
+
+    let mut rax: isize;
+
+    unsafe {
+        asm!(
+        "syscall",
+        lateout("rax") rax,
+        in("rax") SYS_GETSIG,
+        lateout("rcx") _, // clobbered
+        lateout("r11") _, // clobbered
+        )
+    }
+
+    (rax > 0).then_some(rax as _)
+}
+
+/// Delivers the signals the host forwards to the keep to the main cage running in `store`.
+pub fn forward<T>(store: &mut Store<T>) {
+    let mut calls = 0usize;
+    store.call_hook(move |_, _| {
+        calls = calls.wrapping_add(1);
+        if calls % POLL_INTERVAL == 0 {
+            while let Some(signum) = take() {
+                tracing::debug!("delivering signal {signum} to the main cage");
+                rawposix::interface::lind_send_signal(CAGE_START_ID as u64, signum);
+            }
+        }
+        Ok(())
+    });
+}
@@ -324,6 +324,15 @@ impl<A: Commit, B: Commit, C: Commit, D: Commit> Commit for (A, B, C, D) {
     }
 }
 
+impl<T: Commit, const N: usize> Commit for [T; N] {
+    type Item = [T::Item; N];
+
+    #[inline]
+    fn commit(self, com: &impl Committer) -> Self::Item {
+        self.map(|v| v.commit(com))
+    }
+}
+
 /// Something, for which [`Commit::commit`] is an identity function.
 pub trait CommitPassthrough {}
 
@@ -398,3 +407,12 @@ impl<A: Collect, B: Collect, C: Collect, D: Collect> Collect for (A, B, C, D) {
         )
     }
 }
+
+impl<T: Collect, const N: usize> Collect for [T; N] {
+    type Item = [T::Item; N];
+
+    #[inline]
+    fn collect(self, col: &impl Collector) -> Self::Item {
+        self.map(|v| v.collect(col))
+    }
+}
@@ -11,6 +11,7 @@ use core::ffi::c_int;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Park<'a> {
+    pub key: usize,
     pub expected_val: c_int,
     pub timeout: Option<&'a timespec>,
 }
@@ -18,7 +19,7 @@ pub struct Park<'a> {
 impl<'a> Alloc<'a> for Park<'a> {
     const NUM: Number = Number::Park;
 
-    type Argv = Argv<2>;
+    type Argv = Argv<3>;
     type Ret = c_int;
 
     type Staged = Option<Input<'a, timespec, &'a timespec>>;
@@ -33,7 +34,10 @@ impl<'a> Alloc<'a> for Park<'a> {
         } else {
             (None, NULL)
         };
-        Ok((Argv([self.expected_val as _, timeout_offset]), timeout))
+        Ok((
+            Argv([self.expected_val as _, timeout_offset, self.key]),
+            timeout,
+        ))
     }
 
     fn collect(_: Self::Committed, ret: Result<Self::Ret>, _: &impl Collector) -> Self::Collected {
@@ -78,6 +78,34 @@ impl PassthroughAlloc for BalloonMemory {
     }
 }
 
+/// Return a range of guest memory to the host.
+pub struct DeflateMemory {
+    /// Page size expressed as an exponent of 2.
+    pub size_exponent: usize,
+    /// Number of pages to release.
+    pub pages: usize,
+    /// Guest physical address of the memory to release.
+    pub addr: *mut c_void,
+    /// Set if the memory was private, before it was converted to shared.
+    pub is_private: bool,
+}
+
+impl PassthroughAlloc for DeflateMemory {
+    const NUM: Number = Number::DeflateMemory;
+
+    type Argv = Argv<4>;
+    type Ret = usize;
+
+    fn stage(self) -> Self::Argv {
+        Argv([
+            self.size_exponent,
+            self.pages,
+            self.addr as _,
+            self.is_private as _,
+        ])
+    }
+}
+
 /// Get the size of the SGX Quote
 #[repr(transparent)]
 pub struct GetSgxQuoteSize;
@@ -221,17 +249,21 @@ impl PassthroughAlloc for ModifySgxPageType {
     }
 }
 
-/// Unpark parked threads
-#[repr(transparent)]
-pub struct UnPark;
+/// Unpark threads parked on a key
+pub struct UnPark {
+    /// The key the threads are parked on.
+    pub key: usize,
+    /// The maximum number of threads to unpark.
+    pub count: u32,
+}
 
 impl PassthroughAlloc for UnPark {
     const NUM: Number = Number::UnPark;
 
-    type Argv = Argv<0>;
-    type Ret = ();
+    type Argv = Argv<2>;
+    type Ret = usize;
 
     fn stage(self) -> Self::Argv {
-        Argv([])
+        Argv([self.key, self.count as _])
     }
 }
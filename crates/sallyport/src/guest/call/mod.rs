@@ -47,6 +47,7 @@ pub mod kind {
     impl<AK, BK> Kind for (AK, BK) {}
     impl<AK, BK, CK> Kind for (AK, BK, CK) {}
     impl<AK, BK, CK, DK> Kind for (AK, BK, CK, DK) {}
+    impl<K, const N: usize> Kind for [K; N] {}
 }
 
 /// An [executable](super::Handler::execute) call.
@@ -149,3 +150,19 @@ where
             .map(|((a, b), c, d)| (a, b, c, d))
     }
 }
+
+impl<'a, K, T, const N: usize> Call<'a, [K; N]> for [T; N]
+where
+    K: kind::Kind,
+    T: Call<'a, K>,
+{
+    type Staged = [T::Staged; N];
+    type Committed = [T::Committed; N];
+    type Collected = [T::Collected; N];
+
+    #[inline]
+    fn stage(self, alloc: &mut impl Allocator) -> Result<Self::Staged> {
+        let mut calls = self.into_iter();
+        core::array::try_from_fn(|_| calls.next().unwrap().stage(alloc))
+    }
+}
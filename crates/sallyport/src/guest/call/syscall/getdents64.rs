@@ -0,0 +1,46 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::super::types::Argv;
+use super::Alloc;
+use crate::guest::alloc::{Allocator, Collector, Output};
+use crate::libc::SYS_getdents64;
+use crate::Result;
+
+use core::ffi::{c_int, c_long, c_size_t};
+
+pub struct Getdents64<'a> {
+    pub fd: c_int,
+    pub dirp: &'a mut [u8],
+}
+
+unsafe impl<'a> Alloc<'a> for Getdents64<'a> {
+    const NUM: c_long = SYS_getdents64;
+
+    type Argv = Argv<3>;
+    type Ret = c_size_t;
+
+    type Staged = Output<'a, [u8], &'a mut [u8]>;
+    type Committed = Self::Staged;
+    type Collected = Option<Result<c_size_t>>;
+
+    fn stage(self, alloc: &mut impl Allocator) -> Result<(Self::Argv, Self::Staged)> {
+        // The kernel only ever writes whole entries, so a truncated buffer is fine.
+        let (dirp, _) = Output::stage_slice_max(alloc, self.dirp)?;
+        Ok((Argv([self.fd as _, dirp.offset(), dirp.len()]), dirp))
+    }
+
+    fn collect(
+        dirp: Self::Committed,
+        ret: Result<Self::Ret>,
+        col: &impl Collector,
+    ) -> Self::Collected {
+        match ret {
+            Ok(ret) if ret > dirp.len() => None,
+            res @ Ok(ret) => {
+                unsafe { dirp.collect_range(col, 0..ret) };
+                Some(res)
+            }
+            err => Some(err),
+        }
+    }
+}
@@ -16,20 +16,28 @@ mod epoll_ctl;
 mod epoll_pwait;
 mod epoll_wait;
 mod fcntl;
+mod getdents64;
 mod getsockname;
 mod ioctl;
+mod mkdirat;
 mod nanosleep;
+mod newfstatat;
 mod open;
+mod openat;
 mod passthrough;
 mod poll;
+mod pread64;
+mod pwrite64;
 mod read;
 mod readv;
 mod recv;
 mod recvfrom;
+mod renameat2;
 mod send;
 mod sendto;
 mod setsockopt;
 mod stub;
+mod unlinkat;
 mod write;
 mod writev;
 
@@ -46,20 +54,28 @@ pub use epoll_ctl::*;
 pub use epoll_pwait::EpollPwait;
 pub use epoll_wait::*;
 pub use fcntl::Fcntl;
+pub use getdents64::*;
 pub use getsockname::*;
 pub use ioctl::*;
+pub use mkdirat::*;
 pub use nanosleep::*;
+pub use newfstatat::*;
 pub use open::*;
+pub use openat::*;
 pub use passthrough::*;
 pub use poll::*;
+pub use pread64::*;
+pub use pwrite64::*;
 pub use read::*;
 pub use readv::Readv;
 pub use recv::*;
 pub use recvfrom::*;
+pub use renameat2::*;
 pub use send::*;
 pub use sendto::*;
 pub use setsockopt::*;
 pub use stub::*;
+pub use unlinkat::*;
 pub use write::*;
 pub use writev::Writev;
 
@@ -0,0 +1,56 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::super::types::Argv;
+use super::Alloc;
+use crate::guest::alloc::{Allocator, Collect, Collector, Input, Output};
+use crate::libc::{stat, SYS_newfstatat};
+use crate::Result;
+
+use core::ffi::{c_int, c_long};
+
+pub struct Newfstatat<'a> {
+    pub dirfd: c_int,
+    pub pathname: &'a [u8],
+    pub statbuf: &'a mut stat,
+    pub flags: c_int,
+}
+
+unsafe impl<'a> Alloc<'a> for Newfstatat<'a> {
+    const NUM: c_long = SYS_newfstatat;
+
+    type Argv = Argv<5>;
+    type Ret = ();
+
+    type Staged = (
+        Input<'a, [u8], &'a [u8]>,      // pathname
+        Output<'a, stat, &'a mut stat>, // statbuf
+    );
+    type Committed = ((), Output<'a, stat, &'a mut stat>);
+    type Collected = Result<()>;
+
+    fn stage(self, alloc: &mut impl Allocator) -> Result<(Self::Argv, Self::Staged)> {
+        let pathname = Input::stage_slice(alloc, self.pathname)?;
+        let statbuf = Output::stage(alloc, self.statbuf)?;
+        Ok((
+            Argv([
+                self.dirfd as _,
+                pathname.offset(),
+                pathname.len(),
+                statbuf.offset(),
+                self.flags as _,
+            ]),
+            (pathname, statbuf),
+        ))
+    }
+
+    fn collect(
+        (_, statbuf): Self::Committed,
+        ret: Result<Self::Ret>,
+        col: &impl Collector,
+    ) -> Self::Collected {
+        if ret.is_ok() {
+            statbuf.collect(col);
+        }
+        ret
+    }
+}
@@ -0,0 +1,45 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::super::types::Argv;
+use super::Alloc;
+use crate::guest::alloc::{Allocator, Collector, Input};
+use crate::libc::{mode_t, SYS_openat};
+use crate::Result;
+
+use core::ffi::{c_int, c_long};
+
+pub struct Openat<'a> {
+    pub dirfd: c_int,
+    pub pathname: &'a [u8],
+    pub flags: c_int,
+    pub mode: Option<mode_t>,
+}
+
+unsafe impl<'a> Alloc<'a> for Openat<'a> {
+    const NUM: c_long = SYS_openat;
+
+    type Argv = Argv<5>;
+    type Ret = c_int;
+
+    type Staged = Input<'a, [u8], &'a [u8]>;
+    type Committed = ();
+    type Collected = Result<c_int>;
+
+    fn stage(self, alloc: &mut impl Allocator) -> Result<(Self::Argv, Self::Staged)> {
+        let pathname = Input::stage_slice(alloc, self.pathname)?;
+        Ok((
+            Argv([
+                self.dirfd as _,
+                pathname.offset(),
+                pathname.len(),
+                self.flags as _,
+                self.mode.unwrap_or(0) as _,
+            ]),
+            pathname,
+        ))
+    }
+
+    fn collect(_: Self::Committed, ret: Result<Self::Ret>, _: &impl Collector) -> Self::Collected {
+        ret
+    }
+}
@@ -0,0 +1,47 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::super::types::Argv;
+use super::types::StagedBytesInput;
+use super::Alloc;
+use crate::guest::alloc::{Allocator, Collector, Input};
+use crate::libc::{off_t, SYS_pwrite64};
+use crate::Result;
+
+use core::ffi::{c_int, c_long, c_size_t};
+
+pub struct Pwrite64<'a> {
+    pub fd: c_int,
+    pub buf: &'a [u8],
+    pub offset: off_t,
+}
+
+unsafe impl<'a> Alloc<'a> for Pwrite64<'a> {
+    const NUM: c_long = SYS_pwrite64;
+
+    type Argv = Argv<4>;
+    type Ret = c_size_t;
+
+    type Staged = StagedBytesInput<'a>;
+    type Committed = c_size_t;
+    type Collected = Option<Result<c_size_t>>;
+
+    fn stage(self, alloc: &mut impl Allocator) -> Result<(Self::Argv, Self::Staged)> {
+        let (buf, _) = Input::stage_slice_max(alloc, self.buf)?;
+        Ok((
+            Argv([self.fd as _, buf.offset(), buf.len(), self.offset as _]),
+            StagedBytesInput(buf),
+        ))
+    }
+
+    fn collect(
+        count: Self::Committed,
+        ret: Result<Self::Ret>,
+        _: &impl Collector,
+    ) -> Self::Collected {
+        match ret {
+            Ok(ret) if ret > count => None,
+            res @ Ok(_) => Some(res),
+            err => Some(err),
+        }
+    }
+}
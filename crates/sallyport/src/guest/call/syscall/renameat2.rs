@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::super::types::Argv;
+use super::Alloc;
+use crate::guest::alloc::{Allocator, Collector, Input};
+use crate::libc::SYS_renameat2;
+use crate::Result;
+
+use core::ffi::{c_int, c_long, c_uint};
+
+pub struct Renameat2<'a> {
+    pub olddirfd: c_int,
+    pub oldpath: &'a [u8],
+    pub newdirfd: c_int,
+    pub newpath: &'a [u8],
+    pub flags: c_uint,
+}
+
+unsafe impl<'a> Alloc<'a> for Renameat2<'a> {
+    const NUM: c_long = SYS_renameat2;
+
+    type Argv = Argv<6>;
+    type Ret = ();
+
+    type Staged = (
+        Input<'a, [u8], &'a [u8]>, // oldpath
+        Input<'a, [u8], &'a [u8]>, // newpath
+    );
+    type Committed = ((), ());
+    type Collected = Result<()>;
+
+    /// The argument vector has no room left for the flags, so they are packed into
+    /// the lower 32 bits of the last argument and the length of `newpath` into the upper ones.
+    fn stage(self, alloc: &mut impl Allocator) -> Result<(Self::Argv, Self::Staged)> {
+        let oldpath = Input::stage_slice(alloc, self.oldpath)?;
+        let newpath = Input::stage_slice(alloc, self.newpath)?;
+        Ok((
+            Argv([
+                self.olddirfd as _,
+                oldpath.offset(),
+                oldpath.len(),
+                self.newdirfd as _,
+                newpath.offset(),
+                newpath.len() << 32 | self.flags as usize,
+            ]),
+            (oldpath, newpath),
+        ))
+    }
+
+    fn collect(_: Self::Committed, ret: Result<Self::Ret>, _: &impl Collector) -> Self::Collected {
+        ret
+    }
+}
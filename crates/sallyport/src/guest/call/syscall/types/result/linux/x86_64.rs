@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::libc::EOVERFLOW;
+use crate::libc::{off_t, EOVERFLOW};
 
 use core::ffi::c_int;
 use core::marker::PhantomData;
@@ -47,6 +47,17 @@ impl From<Result<c_int>> for crate::Result<c_int> {
     }
 }
 
+impl From<Result<off_t>> for crate::Result<off_t> {
+    #[inline]
+    fn from(res: Result<off_t>) -> Self {
+        match res.0 {
+            [errno @ ERRNO_START..=usize::MAX, _] => Err(-(errno as c_int)),
+            [ret, _] if ret <= off_t::MAX as usize => Ok(ret as off_t),
+            _ => Err(EOVERFLOW),
+        }
+    }
+}
+
 impl From<Result<usize>> for crate::Result<usize> {
     #[inline]
     fn from(res: Result<usize>) -> Self {
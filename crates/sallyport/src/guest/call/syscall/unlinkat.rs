@@ -0,0 +1,43 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::super::types::Argv;
+use super::Alloc;
+use crate::guest::alloc::{Allocator, Collector, Input};
+use crate::libc::SYS_unlinkat;
+use crate::Result;
+
+use core::ffi::{c_int, c_long};
+
+pub struct Unlinkat<'a> {
+    pub dirfd: c_int,
+    pub pathname: &'a [u8],
+    pub flags: c_int,
+}
+
+unsafe impl<'a> Alloc<'a> for Unlinkat<'a> {
+    const NUM: c_long = SYS_unlinkat;
+
+    type Argv = Argv<4>;
+    type Ret = ();
+
+    type Staged = Input<'a, [u8], &'a [u8]>;
+    type Committed = ();
+    type Collected = Result<()>;
+
+    fn stage(self, alloc: &mut impl Allocator) -> Result<(Self::Argv, Self::Staged)> {
+        let pathname = Input::stage_slice(alloc, self.pathname)?;
+        Ok((
+            Argv([
+                self.dirfd as _,
+                pathname.offset(),
+                pathname.len(),
+                self.flags as _,
+            ]),
+            pathname,
+        ))
+    }
+
+    fn collect(_: Self::Committed, ret: Result<Self::Ret>, _: &impl Collector) -> Self::Collected {
+        ret
+    }
+}
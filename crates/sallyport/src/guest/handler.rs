@@ -15,22 +15,23 @@ use super::alloc::{Alloc, Allocator, Collect, Commit, Committer};
 use super::call::kind;
 use super::syscall::types::{MremapFlags, SockaddrInput, SockaddrOutput, SockoptInput};
 use super::{enarxcall, gdbcall, syscall, Call, Platform, ThreadLocalStorage, SIGRTMAX};
-use crate::item::enarxcall::sgx;
+use crate::item::enarxcall::{sgx, SYS_GETSIG};
 use crate::item::syscall::sigaction;
 use crate::libc::{
     clockid_t, epoll_event, gid_t, mode_t, off_t, pid_t, pollfd, sigset_t, stack_t, stat, timespec,
     uid_t, utsname, CloneFlags, Ioctl, SYS_accept, SYS_accept4, SYS_arch_prctl, SYS_bind, SYS_brk,
     SYS_clock_getres, SYS_clock_gettime, SYS_clone, SYS_close, SYS_connect, SYS_dup, SYS_dup2,
     SYS_dup3, SYS_epoll_create1, SYS_epoll_ctl, SYS_epoll_pwait, SYS_epoll_wait, SYS_eventfd2,
-    SYS_exit, SYS_exit_group, SYS_fcntl, SYS_fstat, SYS_futex, SYS_getegid, SYS_geteuid,
-    SYS_getgid, SYS_getpid, SYS_getrandom, SYS_getsockname, SYS_getuid, SYS_ioctl, SYS_listen,
-    SYS_madvise, SYS_mmap, SYS_mprotect, SYS_mremap, SYS_munmap, SYS_nanosleep, SYS_open,
-    SYS_pipe2, SYS_poll, SYS_read, SYS_readlink, SYS_readv, SYS_recvfrom, SYS_rt_sigaction,
-    SYS_rt_sigprocmask, SYS_sendto, SYS_set_tid_address, SYS_setsockopt, SYS_sigaltstack,
-    SYS_socket, SYS_sync, SYS_uname, SYS_write, SYS_writev, CLOCK_MONOTONIC, EFAULT, EINVAL,
-    ENOSYS, ENOTSUP, FIONBIO, FIONREAD, FUTEX_PRIVATE_FLAG, FUTEX_WAIT, FUTEX_WAIT_BITSET,
-    FUTEX_WAKE, MAP_ANONYMOUS, MAP_PRIVATE, MREMAP_DONTUNMAP, MREMAP_FIXED, MREMAP_MAYMOVE,
-    PROT_EXEC, PROT_READ, PROT_WRITE,
+    SYS_exit, SYS_exit_group, SYS_fcntl, SYS_fdatasync, SYS_fstat, SYS_fsync, SYS_ftruncate,
+    SYS_futex, SYS_getdents64, SYS_getegid, SYS_geteuid, SYS_getgid, SYS_getpid, SYS_getrandom,
+    SYS_getsockname, SYS_getuid, SYS_ioctl, SYS_listen, SYS_lseek, SYS_madvise, SYS_mkdirat,
+    SYS_mmap, SYS_mprotect, SYS_mremap, SYS_munmap, SYS_nanosleep, SYS_newfstatat, SYS_open,
+    SYS_openat, SYS_pipe2, SYS_poll, SYS_pread64, SYS_pwrite64, SYS_read, SYS_readlink, SYS_readv,
+    SYS_recvfrom, SYS_renameat2, SYS_rt_sigaction, SYS_rt_sigprocmask, SYS_sendto,
+    SYS_set_tid_address, SYS_setsockopt, SYS_sigaltstack, SYS_socket, SYS_sync, SYS_uname,
+    SYS_unlinkat, SYS_write, SYS_writev, CLOCK_MONOTONIC, EFAULT, EINVAL, ENOSYS, ENOTSUP, FIONBIO,
+    FIONREAD, FUTEX_PRIVATE_FLAG, FUTEX_WAIT, FUTEX_WAIT_BITSET, FUTEX_WAKE, MAP_ANONYMOUS,
+    MAP_PRIVATE, MREMAP_DONTUNMAP, MREMAP_FIXED, MREMAP_MAYMOVE, PROT_EXEC, PROT_READ, PROT_WRITE,
 };
 use crate::{item, Result};
 
@@ -63,6 +64,10 @@ pub trait Handler {
     /// - [`syscall::Write`]
     /// - [`gdbcall::Read`]
     /// - [`gdbcall::Write`]
+    ///
+    /// Tuples and arrays of calls are executed in a single block with a single exit to the host,
+    /// for example `[syscall::Write; N]` to write `N` buffers at once.
+    /// The exit is synchronous, i.e. this returns once the host executed all of the calls.
     #[inline]
     fn execute<'a, K: kind::Kind, T: Call<'a, K>>(&mut self, call: T) -> Result<T::Collected> {
         let mut alloc = Alloc::new(self.block_mut()).stage();
@@ -146,8 +151,16 @@ pub trait Handler {
     }
 
     /// Executes [`clock_gettime`](https://man7.org/linux/man-pages/man2/clock_gettime.2.html) syscall akin to [`libc::clock_gettime`].
+    ///
+    /// Shims may serve clocks locally, the default asks the host.
     #[inline]
     fn clock_gettime(&mut self, clockid: clockid_t, tp: &mut timespec) -> Result<()> {
+        self.clock_gettime_host(clockid, tp)
+    }
+
+    /// Executes [`clock_gettime`](https://man7.org/linux/man-pages/man2/clock_gettime.2.html) syscall on the host.
+    #[inline]
+    fn clock_gettime_host(&mut self, clockid: clockid_t, tp: &mut timespec) -> Result<()> {
         self.execute(syscall::ClockGettime { clockid, tp })?
     }
 
@@ -272,12 +285,30 @@ pub trait Handler {
         self.execute(syscall::Fcntl { fd, cmd, arg })?
     }
 
+    /// Executes [`fdatasync`](https://man7.org/linux/man-pages/man2/fdatasync.2.html) syscall akin to [`libc::fdatasync`].
+    #[inline]
+    fn fdatasync(&mut self, fd: c_int) -> Result<()> {
+        self.execute(syscall::Fdatasync { fd })?
+    }
+
     /// Executes [`fstat`](https://man7.org/linux/man-pages/man2/fstat.2.html) syscall akin to [`libc::fstat`].
     #[inline]
     fn fstat(&mut self, fd: c_int, statbuf: &mut stat) -> Result<()> {
         self.execute(syscall::Fstat { fd, statbuf })?
     }
 
+    /// Executes [`fsync`](https://man7.org/linux/man-pages/man2/fsync.2.html) syscall akin to [`libc::fsync`].
+    #[inline]
+    fn fsync(&mut self, fd: c_int) -> Result<()> {
+        self.execute(syscall::Fsync { fd })?
+    }
+
+    /// Executes [`ftruncate`](https://man7.org/linux/man-pages/man2/ftruncate.2.html) syscall akin to [`libc::ftruncate`].
+    #[inline]
+    fn ftruncate(&mut self, fd: c_int, length: off_t) -> Result<()> {
+        self.execute(syscall::Ftruncate { fd, length })?
+    }
+
     /// Executes [`futex`](https://man7.org/linux/man-pages/man2/futex.2.html) syscall.
     fn futex(
         &mut self,
@@ -292,6 +323,8 @@ pub trait Handler {
         // if the shims would support multiple processes, which they don't.
         let futex_op = futex_op & !FUTEX_PRIVATE_FLAG;
         let mut expected_park_val: c_int = 0;
+        // Threads are parked on the address of the futex.
+        let key = uaddr as *mut AtomicU32 as usize;
 
         match futex_op {
             FUTEX_WAIT => {
@@ -300,7 +333,9 @@ pub trait Handler {
                         tv_sec: 0,
                         tv_nsec: 0,
                     };
-                    self.clock_gettime(CLOCK_MONOTONIC, &mut cur_time).unwrap();
+                    // The park timeout is a time of the host clock.
+                    self.clock_gettime_host(CLOCK_MONOTONIC, &mut cur_time)
+                        .unwrap();
 
                     let mut timeout = *t;
                     timeout.tv_sec += cur_time.tv_sec;
@@ -310,7 +345,7 @@ pub trait Handler {
                     timeout
                 });
                 while uaddr.load(Ordering::Relaxed) == val {
-                    expected_park_val = self.park(expected_park_val, timeout.as_ref())?;
+                    expected_park_val = self.park(key, expected_park_val, timeout.as_ref())?;
                 }
                 Ok(0)
             }
@@ -320,20 +355,22 @@ pub trait Handler {
                 }
 
                 while uaddr.load(Ordering::Relaxed) == val {
-                    expected_park_val = self.park(expected_park_val, timespec)?;
+                    expected_park_val = self.park(key, expected_park_val, timespec)?;
                 }
                 Ok(0)
             }
-            FUTEX_WAKE => {
-                // TODO: return the number of woken threads: https://github.com/enarx/enarx/issues/2181
-                // This needs extensive book keeping on the futexes and normally nobody cares about the result.
-                // For now return 1 or 0 in the error case.
-                self.unpark().map(|_| 1).or(Ok(0))
-            }
+            FUTEX_WAKE => self.unpark(key, val).map(|woken| woken as _).or(Ok(0)),
             _ => Err(ENOTSUP),
         }
     }
 
+    /// Executes [`getdents64`](https://man7.org/linux/man-pages/man2/getdents64.2.html) syscall.
+    #[inline]
+    fn getdents64(&mut self, fd: c_int, dirp: &mut [u8]) -> Result<c_size_t> {
+        self.execute(syscall::Getdents64 { fd, dirp })?
+            .unwrap_or_else(|| self.attacked())
+    }
+
     /// Executes [`getegid`](https://man7.org/linux/man-pages/man2/getegid.2.html) syscall akin to [`libc::getegid`].
     #[inline]
     fn getegid(&mut self) -> Result<gid_t> {
@@ -392,6 +429,12 @@ pub trait Handler {
         self.execute(syscall::Listen { sockfd, backlog })?
     }
 
+    /// Executes [`lseek`](https://man7.org/linux/man-pages/man2/lseek.2.html) syscall akin to [`libc::lseek`].
+    #[inline]
+    fn lseek(&mut self, fd: c_int, offset: off_t, whence: c_int) -> Result<off_t> {
+        self.execute(syscall::Lseek { fd, offset, whence })?
+    }
+
     /// Executes [`madvise`](https://man7.org/linux/man-pages/man2/madvise.2.html) syscall akin to [`libc::madvise`].
     fn madvise(
         &mut self,
@@ -401,6 +444,18 @@ pub trait Handler {
         advice: c_int,
     ) -> Result<()>;
 
+    /// Executes [`mkdirat`](https://man7.org/linux/man-pages/man2/mkdirat.2.html) syscall akin to [`libc::mkdirat`].
+    ///
+    /// `pathname` argument must contain the trailing nul terminator byte.
+    #[inline]
+    fn mkdirat(&mut self, dirfd: c_int, pathname: &[u8], mode: mode_t) -> Result<()> {
+        self.execute(syscall::Mkdirat {
+            dirfd,
+            pathname,
+            mode,
+        })?
+    }
+
     /// Executes [`mmap`](https://man7.org/linux/man-pages/man2/mmap.2.html) syscall akin to [`libc::mmap`].
     #[allow(clippy::too_many_arguments)]
     fn mmap(
@@ -503,6 +558,25 @@ pub trait Handler {
         self.execute(syscall::Nanosleep { req, rem })?
     }
 
+    /// Executes [`newfstatat`](https://man7.org/linux/man-pages/man2/newfstatat.2.html) syscall akin to [`libc::fstatat`].
+    ///
+    /// `pathname` argument must contain the trailing nul terminator byte.
+    #[inline]
+    fn newfstatat(
+        &mut self,
+        dirfd: c_int,
+        pathname: &[u8],
+        statbuf: &mut stat,
+        flags: c_int,
+    ) -> Result<()> {
+        self.execute(syscall::Newfstatat {
+            dirfd,
+            pathname,
+            statbuf,
+            flags,
+        })?
+    }
+
     /// Executes [`open`](https://man7.org/linux/man-pages/man2/open.2.html) syscall akin to [`libc::open`].
     ///
     /// `pathname` argument must contain the trailing nul terminator byte.
@@ -514,6 +588,25 @@ pub trait Handler {
         })?
     }
 
+    /// Executes [`openat`](https://man7.org/linux/man-pages/man2/openat.2.html) syscall akin to [`libc::openat`].
+    ///
+    /// `pathname` argument must contain the trailing nul terminator byte.
+    #[inline]
+    fn openat(
+        &mut self,
+        dirfd: c_int,
+        pathname: &[u8],
+        flags: c_int,
+        mode: Option<mode_t>,
+    ) -> Result<c_int> {
+        self.execute(syscall::Openat {
+            dirfd,
+            pathname,
+            flags,
+            mode,
+        })?
+    }
+
     /// Executes the pipe2 syscall.
     #[inline]
     fn pipe2(&mut self, pipefd: *mut c_int, flags: c_int) -> Result<c_int> {
@@ -528,6 +621,20 @@ pub trait Handler {
             .unwrap_or_else(|| self.attacked())
     }
 
+    /// Executes [`pread64`](https://man7.org/linux/man-pages/man2/pread64.2.html) syscall akin to [`libc::pread`].
+    #[inline]
+    fn pread64(&mut self, fd: c_int, buf: &mut [u8], offset: off_t) -> Result<c_size_t> {
+        self.execute(syscall::Pread64 { fd, buf, offset })?
+            .unwrap_or_else(|| self.attacked())
+    }
+
+    /// Executes [`pwrite64`](https://man7.org/linux/man-pages/man2/pwrite64.2.html) syscall akin to [`libc::pwrite`].
+    #[inline]
+    fn pwrite64(&mut self, fd: c_int, buf: &[u8], offset: off_t) -> Result<c_size_t> {
+        self.execute(syscall::Pwrite64 { fd, buf, offset })?
+            .unwrap_or_else(|| self.attacked())
+    }
+
     /// Executes [`read`](https://man7.org/linux/man-pages/man2/read.2.html) syscall akin to [`libc::read`].
     #[inline]
     fn read(&mut self, fd: c_int, buf: &mut [u8]) -> Result<c_size_t> {
@@ -583,6 +690,27 @@ pub trait Handler {
         .unwrap_or_else(|| self.attacked())
     }
 
+    /// Executes [`renameat2`](https://man7.org/linux/man-pages/man2/renameat2.2.html) syscall akin to [`libc::renameat2`].
+    ///
+    /// `oldpath` and `newpath` arguments must contain the trailing nul terminator byte.
+    #[inline]
+    fn renameat2(
+        &mut self,
+        olddirfd: c_int,
+        oldpath: &[u8],
+        newdirfd: c_int,
+        newpath: &[u8],
+        flags: c_uint,
+    ) -> Result<()> {
+        self.execute(syscall::Renameat2 {
+            olddirfd,
+            oldpath,
+            newdirfd,
+            newpath,
+            flags,
+        })?
+    }
+
     /// Executes [`rt_sigaction`](https://man7.org/linux/man-pages/man2/rt_sigaction.2.html).
     #[inline]
     fn rt_sigaction(
@@ -698,6 +826,18 @@ pub trait Handler {
         self.execute(syscall::Uname { buf })?
     }
 
+    /// Executes [`unlinkat`](https://man7.org/linux/man-pages/man2/unlinkat.2.html) syscall akin to [`libc::unlinkat`].
+    ///
+    /// `pathname` argument must contain the trailing nul terminator byte.
+    #[inline]
+    fn unlinkat(&mut self, dirfd: c_int, pathname: &[u8], flags: c_int) -> Result<()> {
+        self.execute(syscall::Unlinkat {
+            dirfd,
+            pathname,
+            flags,
+        })?
+    }
+
     /// Executes [`write`](https://man7.org/linux/man-pages/man2/write.2.html) syscall akin to [`libc::write`].
     #[inline]
     fn write(&mut self, fd: c_int, buf: &[u8]) -> Result<c_size_t> {
@@ -735,6 +875,7 @@ pub trait Handler {
         let [num, argv @ ..] = registers;
         #[allow(non_upper_case_globals)]
         match (num as _, argv) {
+            (SYS_GETSIG, ..) => self.get_signal().map(|signum| [signum as _, 0]),
             (SYS_accept, [sockfd, addr, addrlen, ..]) => {
                 let addr = if addr == 0 {
                     None
@@ -835,10 +976,15 @@ pub trait Handler {
             (SYS_fcntl, [fd, cmd, arg, ..]) => self
                 .fcntl(fd as _, cmd as _, arg as _)
                 .map(|ret| [ret as _, 0]),
+            (SYS_fdatasync, [fd, ..]) => self.fdatasync(fd as _).map(|_| [0, 0]),
             (SYS_fstat, [fd, statbuf, ..]) => {
                 let statbuf = platform.validate_mut(statbuf)?;
                 self.fstat(fd as _, statbuf).map(|_| [0, 0])
             }
+            (SYS_fsync, [fd, ..]) => self.fsync(fd as _).map(|_| [0, 0]),
+            (SYS_ftruncate, [fd, length, ..]) => {
+                self.ftruncate(fd as _, length as _).map(|_| [0, 0])
+            }
             (SYS_futex, [uaddr, futex_op, val, timeout, _uaddr2, val3]) => {
                 let futex_op = i32::try_from(futex_op).map_err(|_| EINVAL)?;
                 let timeout = match futex_op & (!FUTEX_PRIVATE_FLAG) {
@@ -858,6 +1004,10 @@ pub trait Handler {
                 self.futex(uaddr, futex_op as _, val as _, timeout, None, val3 as _)
                     .map(|ret| [ret as _, 0])
             }
+            (SYS_getdents64, [fd, dirp, count, ..]) => {
+                let dirp = platform.validate_slice_mut(dirp, count)?;
+                self.getdents64(fd as _, dirp).map(|ret| [ret, 0])
+            }
             (SYS_getegid, ..) => self.getegid().map(|ret| [ret as _, 0]),
             (SYS_geteuid, ..) => self.geteuid().map(|ret| [ret as _, 0]),
             (SYS_getgid, ..) => self.getgid().map(|ret| [ret as _, 0]),
@@ -891,11 +1041,19 @@ pub trait Handler {
             (SYS_listen, [sockfd, backlog, ..]) => {
                 self.listen(sockfd as _, backlog as _).map(|_| [0, 0])
             }
+            (SYS_lseek, [fd, offset, whence, ..]) => self
+                .lseek(fd as _, offset as _, whence as _)
+                .map(|ret| [ret as _, 0]),
             (SYS_madvise, [addr, length, advice, ..]) => {
                 let addr = NonNull::new(addr as _).ok_or(EFAULT)?;
                 self.madvise(platform, addr, length, advice as _)
                     .map(|_| [0, 0])
             }
+            (SYS_mkdirat, [dirfd, pathname, mode, ..]) => {
+                let pathname = platform.validate_str(pathname)?;
+                self.mkdirat(dirfd as _, pathname, mode as _)
+                    .map(|_| [0, 0])
+            }
             (SYS_mmap, [addr, length, prot, flags, fd, offset, ..]) => self
                 .mmap(
                     platform,
@@ -954,12 +1112,24 @@ pub trait Handler {
                 };
                 self.nanosleep(req, rem).map(|_| [0, 0])
             }
+            (SYS_newfstatat, [dirfd, pathname, statbuf, flags, ..]) => {
+                let pathname = platform.validate_str(pathname)?;
+                let statbuf = platform.validate_mut(statbuf)?;
+                self.newfstatat(dirfd as _, pathname, statbuf, flags as _)
+                    .map(|_| [0, 0])
+            }
             (SYS_open, [pathname, flags, mode, ..]) => {
                 let pathname = platform.validate_str(pathname)?;
                 let mode = if mode == 0 { None } else { Some(mode as _) };
                 self.open(pathname, flags as _, mode)
                     .map(|ret| [ret as _, 0])
             }
+            (SYS_openat, [dirfd, pathname, flags, mode, ..]) => {
+                let pathname = platform.validate_str(pathname)?;
+                let mode = if mode == 0 { None } else { Some(mode as _) };
+                self.openat(dirfd as _, pathname, flags as _, mode)
+                    .map(|ret| [ret as _, 0])
+            }
             (SYS_pipe2, [pipefd, flags, ..]) => {
                 let pipefd = platform.validate_mut(pipefd)?;
                 self.pipe2(pipefd, flags as _).map(|ret| [ret as _, 0])
@@ -968,6 +1138,14 @@ pub trait Handler {
                 let fds = platform.validate_slice_mut(fds, nfds)?;
                 self.poll(fds, timeout as _).map(|ret| [ret as _, 0])
             }
+            (SYS_pread64, [fd, buf, count, offset, ..]) => {
+                let buf = platform.validate_slice_mut(buf, count)?;
+                self.pread64(fd as _, buf, offset as _).map(|ret| [ret, 0])
+            }
+            (SYS_pwrite64, [fd, buf, count, offset, ..]) => {
+                let buf = platform.validate_slice(buf, count)?;
+                self.pwrite64(fd as _, buf, offset as _).map(|ret| [ret, 0])
+            }
             (SYS_read, [fd, buf, count, ..]) => {
                 let buf = platform.validate_slice_mut(buf, count)?;
                 self.read(fd as _, buf).map(|ret| [ret, 0])
@@ -991,6 +1169,12 @@ pub trait Handler {
                 }
                 .map(|ret| [ret, 0])
             }
+            (SYS_renameat2, [olddirfd, oldpath, newdirfd, newpath, flags, ..]) => {
+                let oldpath = platform.validate_str(oldpath)?;
+                let newpath = platform.validate_str(newpath)?;
+                self.renameat2(olddirfd as _, oldpath, newdirfd as _, newpath, flags as _)
+                    .map(|_| [0, 0])
+            }
             (SYS_rt_sigaction, [signum, act, oldact, sigsetsize, ..]) => {
                 let act = if act == 0 {
                     None
@@ -1067,6 +1251,11 @@ pub trait Handler {
                 let buf = platform.validate_mut(buf)?;
                 self.uname(buf).map(|_| [0, 0])
             }
+            (SYS_unlinkat, [dirfd, pathname, flags, ..]) => {
+                let pathname = platform.validate_str(pathname)?;
+                self.unlinkat(dirfd as _, pathname, flags as _)
+                    .map(|_| [0, 0])
+            }
             (SYS_write, [fd, buf, count, ..]) => {
                 let buf = platform.validate_slice(buf, count)?;
                 self.write(fd as _, buf).map(|ret| [ret, 0])
@@ -1150,6 +1339,33 @@ pub trait Handler {
         })?
     }
 
+    /// Return a range of guest memory, which is not used anymore, to the host.
+    ///
+    /// The memory can be reclaimed with [`balloon_memory`](Self::balloon_memory) at the same
+    /// address.
+    ///
+    /// # Arguments
+    ///
+    /// - `size_exponent`: Page size expressed as an exponent of 2
+    /// - `pages`: Number of pages to release
+    /// - `addr`: Guest physical address of the memory to release
+    /// - `is_private`: Set if the memory was private, before it was converted to shared
+    #[inline]
+    fn deflate_memory(
+        &mut self,
+        size_exponent: usize,
+        pages: usize,
+        addr: *mut c_void,
+        is_private: bool,
+    ) -> Result<usize> {
+        self.execute(enarxcall::DeflateMemory {
+            size_exponent,
+            pages,
+            addr,
+            is_private,
+        })?
+    }
+
     /// Execute `cpuid` instruction storing the result in `result`.
     #[inline]
     fn cpuid(&mut self, leaf: u32, sub_leaf: u32, result: &mut CpuidResult) -> Result<()> {
@@ -1218,17 +1434,24 @@ pub trait Handler {
     fn new_sallyport(&mut self, addr: NonNull<c_void>, index: usize) -> Result<()> {
         self.execute(enarxcall::NewSallyport { addr, index })?
     }
-    /// Park the current thread
+    /// Park the current thread on `key`
     ///
     /// # Arguments
-    /// expected_val: park the thread, as long as the global parking state has this value
+    /// key: the key to park the thread on, e.g. the address of a futex
+    /// expected_val: park the thread, as long as the parking state of `key` has this value
     /// timeout: the CLOCK_MONOTONIC time, when to timeout the park operation
     ///
     /// # Returns
-    /// the actual value of the global parking state
+    /// the actual value of the parking state of `key`
     #[inline]
-    fn park(&mut self, expected_val: c_int, timeout: Option<&timespec>) -> Result<c_int> {
+    fn park(
+        &mut self,
+        key: usize,
+        expected_val: c_int,
+        timeout: Option<&timespec>,
+    ) -> Result<c_int> {
         self.execute(enarxcall::Park {
+            key,
             expected_val,
             timeout,
         })?
@@ -1258,9 +1481,19 @@ pub trait Handler {
         })?
     }
 
-    /// Unpark all parked threads
+    /// Unpark up to `count` threads parked on `key`
+    ///
+    /// Returns the number of unparked threads.
+    #[inline]
+    fn unpark(&mut self, key: usize, count: u32) -> Result<usize> {
+        self.execute(enarxcall::UnPark { key, count })?
+    }
+
+    /// Take the next signal the host forwarded to the keep, see [`signal`](super::signal)
+    ///
+    /// Returns the number of the signal, or `EAGAIN`, if no signal is pending.
     #[inline]
-    fn unpark(&mut self) -> Result<()> {
-        self.execute(enarxcall::UnPark)?
+    fn get_signal(&mut self) -> Result<c_int> {
+        super::signal::take()
     }
 }
@@ -55,6 +55,8 @@
 pub mod alloc;
 pub mod call;
 
+pub mod signal;
+
 mod handler;
 mod platform;
 mod tls;
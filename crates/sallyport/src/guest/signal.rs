@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Signals the host forwarded to the keep.
+//!
+//! The host posts a forwarded signal in the signal word of the sallyport block on any exit to the
+//! host, see [`split_signal`](crate::item::split_signal). The shim [`collect`]s it, once it gets
+//! control back, and the workload polls for pending signals with the
+//! [`SYS_GETSIG`](crate::item::enarxcall::SYS_GETSIG) syscall, so no thread waits for signals.
+
+use super::SIGRTMAX;
+use crate::item::RESUMED;
+use crate::libc::EAGAIN;
+use crate::Result;
+
+use core::ffi::c_int;
+use core::mem;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// The pending signals, bit `n - 1` standing for signal `n`
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+/// Marks the signal the host posted in the signal word `signal` pending and clears the word.
+///
+/// Anything but a signal number posted by the untrusted host is ignored.
+/// Returns `true`, if the host flagged the keep as [`RESUMED`] from a snapshot.
+#[inline]
+pub fn collect(signal: &mut usize) -> bool {
+    let word = mem::take(signal);
+    let signum = word & !RESUMED;
+    if (1..=SIGRTMAX as usize).contains(&signum) {
+        PENDING.fetch_or(1 << (signum - 1), Ordering::SeqCst);
+    }
+    word & RESUMED != 0
+}
+
+/// Takes the pending signal with the lowest number.
+///
+/// Returns `EAGAIN`, if no signal is pending.
+pub fn take() -> Result<c_int> {
+    let mut pending = PENDING.load(Ordering::SeqCst);
+    while pending != 0 {
+        let lowest = pending & pending.wrapping_neg();
+        match PENDING.compare_exchange_weak(
+            pending,
+            pending & !lowest,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(_) => return Ok(lowest.trailing_zeros() as c_int + 1),
+            Err(current) => pending = current,
+        }
+    }
+    Err(EAGAIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+
+    #[test]
+    fn collect_and_take() {
+        let mut signal = 0;
+        assert!(!collect(&mut signal));
+        assert_eq!(take(), Err(EAGAIN));
+
+        for signum in [SIGTERM, SIGINT, SIGTERM, 0x1000] {
+            signal = signum as _;
+            assert!(!collect(&mut signal));
+            assert_eq!(signal, 0);
+        }
+        signal = RESUMED;
+        assert!(collect(&mut signal));
+        assert_eq!(take(), Ok(SIGINT));
+        assert_eq!(take(), Ok(SIGTERM));
+        assert_eq!(take(), Err(EAGAIN));
+    }
+}
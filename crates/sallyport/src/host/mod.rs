@@ -4,9 +4,12 @@
 
 #[cfg(not(miri))]
 mod enarxcall;
+mod policy;
 #[cfg(not(miri))]
 mod syscall;
 
+pub use policy::{execute_with_policy, Access, Policy};
+
 use crate::item::Item;
 use crate::libc::{EFAULT, EOVERFLOW};
 use crate::Result;
@@ -0,0 +1,422 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host-side policy for guest requests.
+
+use super::{deref, deref_aligned, Execute};
+use crate::item::{self, Item};
+use crate::libc::{self, pollfd, AT_FDCWD, EACCES, EFAULT, F_DUPFD, F_DUPFD_CLOEXEC};
+use crate::{Result, NULL};
+
+use core::ffi::{c_int, c_long};
+use core::slice;
+
+/// A host resource a guest syscall asks to use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access<'a> {
+    /// A file descriptor passed in by the guest.
+    Fd(c_int),
+
+    /// A path without the trailing nul byte, relative to `dirfd` unless absolute.
+    Path { dirfd: c_int, path: &'a [u8] },
+
+    /// A new socket of the given address family.
+    Socket(c_int),
+
+    /// A socket address to bind to.
+    Bind(&'a [u8]),
+
+    /// A socket address to connect or send to.
+    Connect(&'a [u8]),
+}
+
+/// Decides which guest syscalls the host executes.
+pub trait Policy {
+    /// Returns whether syscall `num` may use `access`.
+    fn allows(&self, num: c_long, access: Access<'_>) -> bool;
+
+    /// Called for every file descriptor the host opened on behalf of the guest.
+    fn opened(&self, _fd: c_int) {}
+
+    /// Called for every file descriptor the host closed on behalf of the guest.
+    fn closed(&self, _fd: c_int) {}
+}
+
+/// Validates that `data` contains a nul-terminated path of `len` bytes at `offset`
+/// and returns the path without the nul byte on success.
+#[inline]
+fn path(data: &mut [u8], offset: usize, len: usize) -> Result<&[u8]> {
+    let path = unsafe { deref::<u8>(data, offset, len) }?;
+    match unsafe { slice::from_raw_parts(path, len) } {
+        [path @ .., 0] => Ok(path),
+        _ => Err(EFAULT),
+    }
+}
+
+/// Validates that `data` contains `len` bytes at `offset` and returns them on success.
+#[inline]
+fn bytes(data: &mut [u8], offset: usize, len: usize) -> Result<&[u8]> {
+    let ptr = unsafe { deref::<u8>(data, offset, len) }?;
+    Ok(unsafe { slice::from_raw_parts(ptr, len) })
+}
+
+/// Checks a path relative to `dirfd` against `policy`.
+#[inline]
+fn allows_at(
+    num: c_long,
+    policy: &impl Policy,
+    data: &mut [u8],
+    [dirfd, offset, len]: [usize; 3],
+) -> Result<bool> {
+    let dirfd = dirfd as c_int;
+    if dirfd != AT_FDCWD && !policy.allows(num, Access::Fd(dirfd)) {
+        return Ok(false);
+    }
+    let path = path(data, offset, len)?;
+    Ok(policy.allows(num, Access::Path { dirfd, path }))
+}
+
+/// Returns whether `policy` allows every access of `call`.
+///
+/// Syscalls, which are not listed, are denied, so a syscall added to the host later has to be
+/// checked here, before a keep may use it.
+fn allows(call: &item::Syscall, data: &mut [u8], policy: &impl Policy) -> Result<bool> {
+    let num = call.num as c_long;
+    let fd = |fd: usize| policy.allows(num, Access::Fd(fd as _));
+    let [a0, a1, a2, a3, a4, a5] = call.argv;
+
+    match num {
+        libc::SYS_accept
+        | libc::SYS_accept4
+        | libc::SYS_close
+        | libc::SYS_dup
+        | libc::SYS_epoll_pwait
+        | libc::SYS_epoll_wait
+        | libc::SYS_fcntl
+        | libc::SYS_fdatasync
+        | libc::SYS_fstat
+        | libc::SYS_fsync
+        | libc::SYS_ftruncate
+        | libc::SYS_getdents64
+        | libc::SYS_getsockname
+        | libc::SYS_ioctl
+        | libc::SYS_listen
+        | libc::SYS_lseek
+        | libc::SYS_pread64
+        | libc::SYS_pwrite64
+        | libc::SYS_read
+        | libc::SYS_recvfrom
+        | libc::SYS_setsockopt
+        | libc::SYS_write => Ok(fd(a0)),
+
+        libc::SYS_bind => Ok(fd(a0) && policy.allows(num, Access::Bind(bytes(data, a1, a2)?))),
+        libc::SYS_connect => {
+            Ok(fd(a0) && policy.allows(num, Access::Connect(bytes(data, a1, a2)?)))
+        }
+        libc::SYS_sendto if a4 != NULL => {
+            Ok(fd(a0) && policy.allows(num, Access::Connect(bytes(data, a4, a5)?)))
+        }
+        libc::SYS_sendto => Ok(fd(a0)),
+
+        libc::SYS_dup2 | libc::SYS_dup3 => Ok(fd(a0) && fd(a1)),
+        libc::SYS_epoll_ctl => Ok(fd(a0) && fd(a2)),
+        libc::SYS_poll => {
+            let fds = deref_aligned::<pollfd>(data, a0, a1)?;
+            let fds = unsafe { slice::from_raw_parts(fds, a1) };
+            // Negative file descriptors are ignored by `poll`.
+            Ok(fds.iter().all(|pfd| pfd.fd < 0 || fd(pfd.fd as _)))
+        }
+
+        libc::SYS_socket => Ok(policy.allows(num, Access::Socket(a0 as _))),
+
+        libc::SYS_open => {
+            let path = path(data, a0, a1)?;
+            Ok(policy.allows(
+                num,
+                Access::Path {
+                    dirfd: AT_FDCWD,
+                    path,
+                },
+            ))
+        }
+        libc::SYS_mkdirat | libc::SYS_newfstatat | libc::SYS_openat | libc::SYS_unlinkat => {
+            allows_at(num, policy, data, [a0, a1, a2])
+        }
+        libc::SYS_renameat2 => Ok(allows_at(num, policy, data, [a0, a1, a2])?
+            && allows_at(num, policy, data, [a3, a4, a5 >> 32])?),
+
+        // These do not use any host resource passed in by the guest.
+        libc::SYS_clock_getres
+        | libc::SYS_clock_gettime
+        | libc::SYS_epoll_create1
+        | libc::SYS_eventfd2
+        | libc::SYS_exit
+        | libc::SYS_exit_group
+        | libc::SYS_nanosleep
+        | libc::SYS_sync => Ok(true),
+
+        // This includes `pipe2`, which has the host write the new file descriptors to a guest
+        // pointer outside of the block.
+        _ => Ok(false),
+    }
+}
+
+/// Tells `policy` about the file descriptors `call` opened or closed.
+fn notify(call: &item::Syscall, policy: &impl Policy) {
+    let ret = call.ret[0] as isize;
+    if ret < 0 {
+        return;
+    }
+
+    match call.num as c_long {
+        libc::SYS_accept
+        | libc::SYS_accept4
+        | libc::SYS_dup
+        | libc::SYS_dup2
+        | libc::SYS_dup3
+        | libc::SYS_epoll_create1
+        | libc::SYS_eventfd2
+        | libc::SYS_open
+        | libc::SYS_openat
+        | libc::SYS_socket => policy.opened(ret as _),
+        libc::SYS_fcntl if matches!(call.argv[1] as c_int, F_DUPFD | F_DUPFD_CLOEXEC) => {
+            policy.opened(ret as _)
+        }
+        libc::SYS_close => policy.closed(call.argv[0] as _),
+        _ => {}
+    }
+}
+
+/// Executes the passed `items`, failing each syscall denied by `policy` with `EACCES`
+/// instead of executing it.
+#[inline]
+pub fn execute_with_policy<'a>(
+    items: impl IntoIterator<Item = Item<'a>>,
+    policy: &impl Policy,
+) -> Result<()> {
+    items.into_iter().try_for_each(|item| match item {
+        Item::Syscall(call, data) => {
+            if !allows(call, data, policy)? {
+                call.ret = [-EACCES as usize, 0];
+                return Ok(());
+            }
+            unsafe { Item::Syscall(call, data).execute() }?;
+            notify(call, policy);
+            Ok(())
+        }
+        item => unsafe { item.execute() },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::Syscall;
+    use crate::libc::{
+        SYS_close, SYS_eventfd2, SYS_fcntl, SYS_getpid, SYS_open, SYS_pipe2, SYS_sync, SYS_write,
+        ENOSYS, STDOUT_FILENO,
+    };
+
+    use core::cell::RefCell;
+
+    /// Denies everything but paths below `/allowed` and records the checked accesses.
+    #[derive(Default)]
+    struct TestPolicy {
+        checked: RefCell<Vec<(c_long, String)>>,
+    }
+
+    impl Policy for TestPolicy {
+        fn allows(&self, num: c_long, access: Access<'_>) -> bool {
+            self.checked.borrow_mut().push((num, format!("{access:?}")));
+            matches!(access, Access::Path { path, .. } if path.starts_with(b"/allowed/"))
+        }
+    }
+
+    #[test]
+    fn denied() {
+        let policy = TestPolicy::default();
+
+        let mut write = Syscall {
+            num: SYS_write as _,
+            argv: [STDOUT_FILENO as _, 0, 0, NULL, NULL, NULL],
+            ret: [-ENOSYS as _, 0],
+        };
+        let mut open = Syscall {
+            num: SYS_open as _,
+            argv: [0, 12, 0, 0, NULL, NULL],
+            ret: [-ENOSYS as _, 0],
+        };
+        let mut path = *b"/etc/shadow\0";
+        assert_eq!(
+            execute_with_policy(
+                [
+                    Item::Syscall(&mut write, &mut []),
+                    Item::Syscall(&mut open, &mut path),
+                ],
+                &policy
+            ),
+            Ok(())
+        );
+        assert_eq!(write.ret, [-EACCES as usize, 0]);
+        assert_eq!(open.ret, [-EACCES as usize, 0]);
+        assert_eq!(
+            policy.checked.into_inner(),
+            [
+                (SYS_write, format!("{:?}", Access::Fd(STDOUT_FILENO))),
+                (
+                    SYS_open,
+                    format!(
+                        "{:?}",
+                        Access::Path {
+                            dirfd: AT_FDCWD,
+                            path: b"/etc/shadow"
+                        }
+                    )
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_path() {
+        let mut open = Syscall {
+            num: SYS_open as _,
+            argv: [0, 4, 0, 0, NULL, NULL],
+            ret: [-ENOSYS as _, 0],
+        };
+        let mut path = *b"/etc";
+        assert_eq!(
+            execute_with_policy(
+                [Item::Syscall(&mut open, &mut path)],
+                &TestPolicy::default()
+            ),
+            Err(EFAULT)
+        );
+        assert_eq!(open.ret, [-ENOSYS as usize, 0]);
+    }
+
+    #[test]
+    fn unchecked() {
+        let mut close = Syscall {
+            num: SYS_close as _,
+            argv: [-1isize as _, NULL, NULL, NULL, NULL, NULL],
+            ret: [-ENOSYS as _, 0],
+        };
+        // `sync` does not use any host resource, so it is executed without a check.
+        let mut sync = Syscall {
+            num: SYS_sync as _,
+            argv: [NULL, NULL, NULL, NULL, NULL, NULL],
+            ret: [-ENOSYS as _, 0],
+        };
+        let policy = TestPolicy::default();
+        assert_eq!(
+            execute_with_policy(
+                [
+                    Item::Syscall(&mut close, &mut []),
+                    Item::Syscall(&mut sync, &mut [])
+                ],
+                &policy
+            ),
+            Ok(())
+        );
+        assert_eq!(close.ret, [-EACCES as usize, 0]);
+        if cfg!(not(miri)) {
+            assert_eq!(sync.ret, [0, 0]);
+        }
+        assert_eq!(policy.checked.into_inner().len(), 1);
+    }
+
+    /// Allows only the file descriptors the host opened on behalf of the guest.
+    #[derive(Default)]
+    struct FdPolicy {
+        opened: RefCell<Vec<c_int>>,
+    }
+
+    impl Policy for FdPolicy {
+        fn allows(&self, _num: c_long, access: Access<'_>) -> bool {
+            matches!(access, Access::Fd(fd) if self.opened.borrow().contains(&fd))
+        }
+
+        fn opened(&self, fd: c_int) {
+            self.opened.borrow_mut().push(fd);
+        }
+    }
+
+    #[test]
+    fn denied_by_default() {
+        let mut getpid = Syscall {
+            num: SYS_getpid as _,
+            argv: [NULL, NULL, NULL, NULL, NULL, NULL],
+            ret: [-ENOSYS as _, 0],
+        };
+        // The host must never write to a guest pointer passed in the arguments.
+        let mut fds = [-1 as c_int; 2];
+        let mut pipe2 = Syscall {
+            num: SYS_pipe2 as _,
+            argv: [fds.as_mut_ptr() as _, 0, 0, NULL, NULL, NULL],
+            ret: [-ENOSYS as _, 0],
+        };
+        assert_eq!(
+            execute_with_policy(
+                [
+                    Item::Syscall(&mut getpid, &mut []),
+                    Item::Syscall(&mut pipe2, &mut [])
+                ],
+                &TestPolicy::default()
+            ),
+            Ok(())
+        );
+        assert_eq!(getpid.ret, [-EACCES as usize, 0]);
+        assert_eq!(pipe2.ret, [-EACCES as usize, 0]);
+        assert_eq!(fds, [-1, -1]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn eventfd2_then_write() {
+        let policy = FdPolicy::default();
+
+        let mut eventfd2 = Syscall {
+            num: SYS_eventfd2 as _,
+            argv: [0, 0, NULL, NULL, NULL, NULL],
+            ret: [-ENOSYS as _, 0],
+        };
+        assert_eq!(
+            execute_with_policy([Item::Syscall(&mut eventfd2, &mut [])], &policy),
+            Ok(())
+        );
+        let fd = eventfd2.ret[0] as c_int;
+        assert!(fd >= 0);
+        assert_eq!(policy.opened.borrow().as_slice(), [fd]);
+
+        let mut dup = Syscall {
+            num: SYS_fcntl as _,
+            argv: [fd as _, F_DUPFD_CLOEXEC as _, 0, NULL, NULL, NULL],
+            ret: [-ENOSYS as _, 0],
+        };
+        assert_eq!(
+            execute_with_policy([Item::Syscall(&mut dup, &mut [])], &policy),
+            Ok(())
+        );
+        let dup = dup.ret[0] as c_int;
+        assert!(dup >= 0);
+        assert!(policy.opened.borrow().contains(&dup));
+
+        let mut data = 1u64.to_ne_bytes();
+        for fd in [fd, dup] {
+            let mut write = Syscall {
+                num: SYS_write as _,
+                argv: [fd as _, 0, data.len(), NULL, NULL, NULL],
+                ret: [-ENOSYS as _, 0],
+            };
+            assert_eq!(
+                execute_with_policy([Item::Syscall(&mut write, &mut data)], &policy),
+                Ok(())
+            );
+            assert_eq!(write.ret, [8, 0]);
+        }
+
+        for fd in [fd, dup] {
+            unsafe { ::libc::close(fd) };
+        }
+    }
+}
@@ -162,6 +162,18 @@ fn deref_sockaddr_output(
     }
 }
 
+/// Validates that `data` contains a nul-terminated path of `len` bytes at `offset`
+/// and returns an immutable pointer to the path on success.
+#[inline]
+fn deref_path(data: &mut [u8], offset: usize, len: usize) -> Result<*const u8> {
+    let path = unsafe { deref::<u8>(data, offset, len) }?;
+    if len == 0 || unsafe { *path.add(len - 1) } != 0 {
+        Err(EFAULT)
+    } else {
+        Ok(path)
+    }
+}
+
 pub(super) unsafe fn execute(call: &mut item::Syscall, data: &mut [u8]) -> Result<()> {
     match call {
         item::Syscall {
@@ -413,6 +425,53 @@ pub(super) unsafe fn execute(call: &mut item::Syscall, data: &mut [u8]) -> Resul
         }
         .execute(),
 
+        item::Syscall {
+            num,
+            argv: [fd, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_fdatasync as _ => Syscall {
+            num: libc::SYS_fdatasync,
+            argv: [*fd],
+            ret: [ret],
+        }
+        .execute(),
+
+        item::Syscall {
+            num,
+            argv: [fd, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_fsync as _ => Syscall {
+            num: libc::SYS_fsync,
+            argv: [*fd],
+            ret: [ret],
+        }
+        .execute(),
+
+        item::Syscall {
+            num,
+            argv: [fd, length, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_ftruncate as _ => Syscall {
+            num: libc::SYS_ftruncate,
+            argv: [*fd, *length],
+            ret: [ret],
+        }
+        .execute(),
+
+        item::Syscall {
+            num,
+            argv: [fd, dirp_offset, count, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_getdents64 as _ => {
+            let dirp = deref::<u8>(data, *dirp_offset, *count)?;
+            Syscall {
+                num: libc::SYS_getdents64,
+                argv: [*fd, dirp as _, *count],
+                ret: [ret],
+            }
+            .execute()
+        }
+
         item::Syscall {
             num,
             argv: [sockfd, addr_offset, addrlen_offset, ..],
@@ -456,6 +515,31 @@ pub(super) unsafe fn execute(call: &mut item::Syscall, data: &mut [u8]) -> Resul
         }
         .execute(),
 
+        item::Syscall {
+            num,
+            argv: [fd, offset, whence, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_lseek as _ => Syscall {
+            num: libc::SYS_lseek,
+            argv: [*fd, *offset, *whence],
+            ret: [ret],
+        }
+        .execute(),
+
+        item::Syscall {
+            num,
+            argv: [dirfd, pathname_offset, pathname_len, mode, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_mkdirat as _ => {
+            let pathname = deref_path(data, *pathname_offset, *pathname_len)?;
+            Syscall {
+                num: libc::SYS_mkdirat,
+                argv: [*dirfd, pathname as _, *mode],
+                ret: [ret],
+            }
+            .execute()
+        }
+
         item::Syscall {
             num,
             argv: [req_offset, rem_offset, ..],
@@ -475,6 +559,21 @@ pub(super) unsafe fn execute(call: &mut item::Syscall, data: &mut [u8]) -> Resul
             .execute()
         }
 
+        item::Syscall {
+            num,
+            argv: [dirfd, pathname_offset, pathname_len, statbuf_offset, flags, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_newfstatat as _ => {
+            let pathname = deref_path(data, *pathname_offset, *pathname_len)?;
+            let statbuf = deref_aligned::<libc::stat>(data, *statbuf_offset, 1)?;
+            Syscall {
+                num: libc::SYS_newfstatat,
+                argv: [*dirfd, pathname as _, statbuf as _, *flags],
+                ret: [ret],
+            }
+            .execute()
+        }
+
         item::Syscall {
             num,
             argv: [pathname_offset, pathname_len, flags, mode, ..],
@@ -489,6 +588,20 @@ pub(super) unsafe fn execute(call: &mut item::Syscall, data: &mut [u8]) -> Resul
             .execute()
         }
 
+        item::Syscall {
+            num,
+            argv: [dirfd, pathname_offset, pathname_len, flags, mode, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_openat as _ => {
+            let pathname = deref_path(data, *pathname_offset, *pathname_len)?;
+            Syscall {
+                num: libc::SYS_openat,
+                argv: [*dirfd, pathname as _, *flags, *mode],
+                ret: [ret],
+            }
+            .execute()
+        }
+
         item::Syscall {
             num,
             argv: [fds_offset, nfds, timeout, ..],
@@ -503,6 +616,34 @@ pub(super) unsafe fn execute(call: &mut item::Syscall, data: &mut [u8]) -> Resul
             .execute()
         }
 
+        item::Syscall {
+            num,
+            argv: [fd, buf_offset, count, offset, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_pread64 as _ => {
+            let buf = deref::<u8>(data, *buf_offset, *count)?;
+            Syscall {
+                num: libc::SYS_pread64,
+                argv: [*fd, buf as _, *count, *offset],
+                ret: [ret],
+            }
+            .execute()
+        }
+
+        item::Syscall {
+            num,
+            argv: [fd, buf_offset, count, offset, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_pwrite64 as _ => {
+            let buf = deref::<u8>(data, *buf_offset, *count)?;
+            Syscall {
+                num: libc::SYS_pwrite64,
+                argv: [*fd, buf as _, *count, *offset],
+                ret: [ret],
+            }
+            .execute()
+        }
+
         item::Syscall {
             num,
             argv: [fd, buf_offset, count, ..],
@@ -536,6 +677,23 @@ pub(super) unsafe fn execute(call: &mut item::Syscall, data: &mut [u8]) -> Resul
             .execute();
         }
 
+        item::Syscall {
+            num,
+            argv: [olddirfd, oldpath_offset, oldpath_len, newdirfd, newpath_offset, packed],
+            ret: [ret, ..],
+        } if *num == libc::SYS_renameat2 as _ => {
+            // The guest packs the length of `newpath` and the flags into the last argument.
+            let (newpath_len, flags) = (*packed >> 32, *packed & 0xffff_ffff);
+            let oldpath = deref_path(data, *oldpath_offset, *oldpath_len)?;
+            let newpath = deref_path(data, *newpath_offset, newpath_len)?;
+            Syscall {
+                num: libc::SYS_renameat2,
+                argv: [*olddirfd, oldpath as _, *newdirfd, newpath as _, flags],
+                ret: [ret],
+            }
+            .execute()
+        }
+
         item::Syscall {
             num,
             argv: [sockfd, buf_offset, len, flags, dest_addr_offset, addrlen],
@@ -602,6 +760,20 @@ pub(super) unsafe fn execute(call: &mut item::Syscall, data: &mut [u8]) -> Resul
         }
         .execute(),
 
+        item::Syscall {
+            num,
+            argv: [dirfd, pathname_offset, pathname_len, flags, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_unlinkat as _ => {
+            let pathname = deref_path(data, *pathname_offset, *pathname_len)?;
+            Syscall {
+                num: libc::SYS_unlinkat,
+                argv: [*dirfd, pathname as _, *flags],
+                ret: [ret],
+            }
+            .execute()
+        }
+
         item::Syscall {
             num,
             argv: [fd, buf_offset, count, ..],
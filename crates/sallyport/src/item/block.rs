@@ -38,6 +38,22 @@ impl<'a> Block<'a> {
     }
 }
 
+/// The flag of the signal word of a sallyport block, see [`split_signal`], the host sets,
+/// when it executed the block for a keep resumed from a snapshot.
+pub const RESUMED: usize = 1 << (usize::BITS - 1);
+
+/// Splits a sallyport block into the part holding the items and its last word, which the host
+/// posts a signal forwarded to the keep in on any exit to the host.
+///
+/// The word holds the number of the signal, or 0, if there is none, and the [`RESUMED`] flag.
+/// Returns `None`, if the block is empty.
+#[inline]
+pub fn split_signal(block: &mut [usize]) -> Option<(&mut [usize], &mut usize)> {
+    block
+        .split_last_mut()
+        .map(|(signal, items)| (items, signal))
+}
+
 impl<'a> From<&'a mut [usize]> for Block<'a> {
     #[inline]
     fn from(block: &'a mut [usize]) -> Self {
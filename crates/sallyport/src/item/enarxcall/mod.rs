@@ -19,6 +19,13 @@ pub const SYS_GETATT: i64 = 0xEA01;
 #[allow(dead_code)]
 pub const SYS_GETKEY: i64 = 0xEA02;
 
+/// `get_signal` syscall number used by the shim.
+///
+/// Returns the number of the next signal the host forwarded to the keep, or `-EAGAIN`,
+/// if no signal is pending. It is answered by the shim without exiting to the host.
+#[allow(dead_code)]
+pub const SYS_GETSIG: i64 = 0xEA03;
+
 /// Payload of an [`Item`](super::Item) of [`Kind::Enarxcall`](super::Kind::Enarxcall).
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(C, align(8))]
@@ -75,10 +82,10 @@ pub enum Number {
     /// Trim SGX pages call number.
     SgxModifyPageType = 0x10,
 
-    /// Park the current thread
+    /// Park the current thread on a key
     Park = 0x11,
 
-    /// UnPark all parked threads
+    /// UnPark threads parked on a key
     UnPark = 0x12,
 
     /// Spawn a new thread
@@ -86,6 +93,9 @@ pub enum Number {
 
     /// Register a new sallyport block
     NewSallyport = 0x14,
+
+    /// Memory deflation request call number.
+    DeflateMemory = 0x15,
 }
 
 #[cfg(test)]
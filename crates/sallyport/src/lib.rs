@@ -4,6 +4,7 @@
 #![deny(clippy::all)]
 // TODO: Enable https://github.com/enarx/sallyport/issues/32
 //#![deny(missing_docs)]
+#![feature(array_try_from_fn)]
 #![feature(c_size_t)]
 #![feature(slice_ptr_get)]
 #![feature(slice_ptr_len)]
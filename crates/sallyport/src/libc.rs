@@ -200,6 +200,9 @@ pub const CLONE_NEWUSER: c_uint = 0x10000000;
 pub const CLONE_NEWPID: c_uint = 0x20000000;
 pub const CLONE_NEWNET: c_uint = 0x40000000;
 pub const CLONE_IO: c_uint = 0x80000000;
+pub const AT_FDCWD: c_int = -100;
+pub const AT_REMOVEDIR: c_int = 0x200;
+pub const AT_SYMLINK_NOFOLLOW: c_int = 0x100;
 pub const EACCES: c_int = 13;
 pub const EAGAIN: c_int = 11;
 pub const EBADF: c_int = 9;
@@ -216,6 +219,8 @@ pub const ENOTSUP: c_int = 95;
 pub const ENOTTY: c_int = 25;
 pub const EOVERFLOW: c_int = 75;
 pub const EPERM: c_int = 1;
+pub const F_DUPFD: c_int = 0;
+pub const F_DUPFD_CLOEXEC: c_int = 1030;
 pub const F_GETFD: c_int = 1;
 pub const F_GETFL: c_int = 3;
 pub const F_SETFD: c_int = 2;
@@ -248,12 +253,16 @@ pub const MSG_NOSIGNAL: c_int = 16384;
 pub const O_APPEND: c_int = 1024;
 pub const O_CLOEXEC: c_int = 0x80000;
 pub const O_CREAT: c_int = 64;
+pub const O_DIRECTORY: c_int = 0x10000;
 pub const O_RDONLY: c_int = 0;
 pub const O_RDWR: c_int = 2;
 pub const O_WRONLY: c_int = 1;
 pub const PROT_EXEC: c_int = 4;
 pub const PROT_READ: c_int = 1;
 pub const PROT_WRITE: c_int = 2;
+pub const SEEK_CUR: c_int = 1;
+pub const SEEK_END: c_int = 2;
+pub const SEEK_SET: c_int = 0;
 pub const S_IFIFO: mode_t = 4096;
 pub const SOCK_CLOEXEC: c_int = O_CLOEXEC;
 pub const SOCK_STREAM: c_int = 1;
@@ -284,30 +293,41 @@ pub const SYS_eventfd2: c_long = 290;
 pub const SYS_exit: c_long = 60;
 pub const SYS_exit_group: c_long = 231;
 pub const SYS_fcntl: c_long = 72;
+pub const SYS_fdatasync: c_long = 75;
 pub const SYS_fstat: c_long = 5;
+pub const SYS_fsync: c_long = 74;
+pub const SYS_ftruncate: c_long = 77;
 pub const SYS_futex: c_long = 202;
 pub const SYS_getegid: c_long = 108;
 pub const SYS_geteuid: c_long = 107;
 pub const SYS_getgid: c_long = 104;
 pub const SYS_getpid: c_long = 39;
 pub const SYS_getuid: c_long = 102;
+pub const SYS_getdents64: c_long = 217;
 pub const SYS_getrandom: c_long = 318;
 pub const SYS_getsockname: c_long = 51;
 pub const SYS_ioctl: c_long = 16;
 pub const SYS_listen: c_long = 50;
+pub const SYS_lseek: c_long = 8;
 pub const SYS_madvise: c_long = 28;
+pub const SYS_mkdirat: c_long = 258;
 pub const SYS_mmap: c_long = 9;
 pub const SYS_mprotect: c_long = 10;
 pub const SYS_mremap: c_long = 25;
 pub const SYS_munmap: c_long = 11;
 pub const SYS_nanosleep: c_long = 35;
+pub const SYS_newfstatat: c_long = 262;
 pub const SYS_open: c_long = 2;
+pub const SYS_openat: c_long = 257;
 pub const SYS_poll: c_long = 7;
 pub const SYS_pipe2: c_long = 293;
+pub const SYS_pread64: c_long = 17;
+pub const SYS_pwrite64: c_long = 18;
 pub const SYS_read: c_long = 0;
 pub const SYS_readlink: c_long = 89;
 pub const SYS_readv: c_long = 19;
 pub const SYS_recvfrom: c_long = 45;
+pub const SYS_renameat2: c_long = 316;
 pub const SYS_rt_sigaction: c_long = 13;
 pub const SYS_rt_sigprocmask: c_long = 14;
 pub const SYS_set_tid_address: c_long = 218;
@@ -317,6 +337,7 @@ pub const SYS_sigaltstack: c_long = 131;
 pub const SYS_socket: c_long = 41;
 pub const SYS_sync: c_long = 162;
 pub const SYS_uname: c_long = 63;
+pub const SYS_unlinkat: c_long = 263;
 pub const SYS_write: c_long = 1;
 pub const SYS_writev: c_long = 20;
 pub const TIOCGWINSZ: Ioctl = 0x5413;
@@ -110,7 +110,7 @@ fn park() {
             tv_sec: 0,
             tv_nsec: 0,
         };
-        assert_eq!(handler.park(0, Some(&timeout)), Err(ENOSYS));
+        assert_eq!(handler.park(0x1000, 0, Some(&timeout)), Err(ENOSYS));
     })
 }
 
@@ -127,6 +127,6 @@ fn trim_sgx_pages() {
 #[test]
 fn unpark() {
     run_test(1, [0xff; 16], move |_, _, handler| {
-        assert_eq!(handler.unpark(), Err(ENOSYS));
+        assert_eq!(handler.unpark(0x1000, 1), Err(ENOSYS));
     })
 }
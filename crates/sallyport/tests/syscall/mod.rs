@@ -5,15 +5,18 @@ use super::{recv_udp, run_test, write_tcp};
 use core::ffi::{c_char, c_int};
 use libc::{
     self, in_addr, iovec, pollfd, sockaddr, sockaddr_in, timespec, timeval, utsname, SYS_accept,
-    SYS_accept4, SYS_bind, SYS_clock_getres, SYS_clock_gettime, SYS_close, SYS_fcntl, SYS_fstat,
-    SYS_getegid, SYS_geteuid, SYS_getgid, SYS_getpid, SYS_getrandom, SYS_getsockname, SYS_listen,
-    SYS_mremap, SYS_nanosleep, SYS_open, SYS_poll, SYS_read, SYS_readlink, SYS_readv, SYS_recvfrom,
-    SYS_rt_sigaction, SYS_rt_sigprocmask, SYS_sendto, SYS_set_tid_address, SYS_setsockopt,
-    SYS_sigaltstack, SYS_socket, SYS_uname, SYS_write, SYS_writev, AF_INET, CLOCK_MONOTONIC,
-    CLOCK_REALTIME, EACCES, EBADF, EBADFD, EINVAL, ENOENT, ENOSYS, ENOTSUP, F_GETFD, F_GETFL,
-    F_SETFD, F_SETFL, GRND_RANDOM, MREMAP_DONTUNMAP, MREMAP_FIXED, MREMAP_MAYMOVE, MSG_NOSIGNAL,
-    O_APPEND, O_CREAT, O_RDONLY, O_RDWR, O_WRONLY, SIGCHLD, SIG_BLOCK, SOCK_CLOEXEC, SOCK_STREAM,
-    SOL_SOCKET, SO_RCVTIMEO, SO_REUSEADDR, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO,
+    SYS_accept4, SYS_bind, SYS_clock_getres, SYS_clock_gettime, SYS_close, SYS_fcntl,
+    SYS_fdatasync, SYS_fstat, SYS_fsync, SYS_ftruncate, SYS_getdents64, SYS_getegid, SYS_geteuid,
+    SYS_getgid, SYS_getpid, SYS_getrandom, SYS_getsockname, SYS_listen, SYS_lseek, SYS_mkdirat,
+    SYS_mremap, SYS_nanosleep, SYS_newfstatat, SYS_open, SYS_openat, SYS_poll, SYS_pread64,
+    SYS_pwrite64, SYS_read, SYS_readlink, SYS_readv, SYS_recvfrom, SYS_renameat2, SYS_rt_sigaction,
+    SYS_rt_sigprocmask, SYS_sendto, SYS_set_tid_address, SYS_setsockopt, SYS_sigaltstack,
+    SYS_socket, SYS_uname, SYS_unlinkat, SYS_write, SYS_writev, AF_INET, AT_FDCWD, AT_REMOVEDIR,
+    CLOCK_MONOTONIC, CLOCK_REALTIME, EACCES, EBADF, EBADFD, EEXIST, EINVAL, ENOENT, ENOSYS,
+    ENOTSUP, F_GETFD, F_GETFL, F_SETFD, F_SETFL, GRND_RANDOM, MREMAP_DONTUNMAP, MREMAP_FIXED,
+    MREMAP_MAYMOVE, MSG_NOSIGNAL, O_APPEND, O_CREAT, O_RDONLY, O_RDWR, O_WRONLY, RENAME_NOREPLACE,
+    SEEK_END, SEEK_SET, SIGCHLD, SIG_BLOCK, SOCK_CLOEXEC, SOCK_STREAM, SOL_SOCKET, SO_RCVTIMEO,
+    SO_REUSEADDR, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO,
 };
 use std::env::temp_dir;
 use std::ffi::CString;
@@ -353,6 +356,36 @@ fn fcntl() {
     });
 }
 
+#[test]
+#[serial]
+fn fdatasync() {
+    run_test(2, [0xff; 16], move |i, platform, handler| {
+        let file = File::create(temp_dir().join("sallyport-test-fdatasync")).unwrap();
+        let expected = if cfg!(not(miri)) { Ok(()) } else { Err(ENOSYS) };
+        if i % 2 == 0 {
+            assert_eq!(handler.fdatasync(file.as_raw_fd()), expected);
+            assert_eq!(
+                handler.fdatasync(-1),
+                if cfg!(not(miri)) {
+                    Err(EBADF)
+                } else {
+                    Err(ENOSYS)
+                }
+            );
+        } else {
+            assert_eq!(
+                unsafe {
+                    handler.syscall(
+                        platform,
+                        [SYS_fdatasync as _, file.as_raw_fd() as _, 0, 0, 0, 0, 0],
+                    )
+                },
+                expected.map(|_| [0, 0])
+            );
+        }
+    });
+}
+
 #[test]
 #[serial]
 fn fstat() {
@@ -411,6 +444,106 @@ fn fstat() {
     let _ = file;
 }
 
+#[test]
+#[serial]
+fn fsync() {
+    run_test(2, [0xff; 16], move |i, platform, handler| {
+        let file = File::create(temp_dir().join("sallyport-test-fsync")).unwrap();
+        let expected = if cfg!(not(miri)) { Ok(()) } else { Err(ENOSYS) };
+        if i % 2 == 0 {
+            assert_eq!(handler.fsync(file.as_raw_fd()), expected);
+            assert_eq!(
+                handler.fsync(-1),
+                if cfg!(not(miri)) {
+                    Err(EBADF)
+                } else {
+                    Err(ENOSYS)
+                }
+            );
+        } else {
+            assert_eq!(
+                unsafe {
+                    handler.syscall(
+                        platform,
+                        [SYS_fsync as _, file.as_raw_fd() as _, 0, 0, 0, 0, 0],
+                    )
+                },
+                expected.map(|_| [0, 0])
+            );
+        }
+    });
+}
+
+#[test]
+#[serial]
+fn ftruncate() {
+    run_test(2, [0xff; 16], move |i, platform, handler| {
+        let path = temp_dir().join(format!("sallyport-test-ftruncate-{i}"));
+        write!(&mut File::create(&path).unwrap(), "ftruncate").unwrap();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+
+        let ret = if i % 2 == 0 {
+            handler.ftruncate(file.as_raw_fd(), 4)
+        } else {
+            unsafe {
+                handler.syscall(
+                    platform,
+                    [SYS_ftruncate as _, file.as_raw_fd() as _, 4, 0, 0, 0, 0],
+                )
+            }
+            .map(|ret| assert_eq!(ret, [0, 0]))
+        };
+        if cfg!(not(miri)) {
+            assert_eq!(ret, Ok(()));
+            assert_eq!(file.metadata().unwrap().len(), 4);
+        } else {
+            assert_eq!(ret, Err(ENOSYS));
+        }
+    });
+}
+
+#[test]
+#[serial]
+fn getdents64() {
+    run_test(2, [0xff; 64], move |i, platform, handler| {
+        let path = temp_dir().join(format!("sallyport-test-getdents64-{i}"));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir(&path).unwrap();
+        File::create(path.join("entry")).unwrap();
+        let dir = File::open(&path).unwrap();
+
+        let mut buf = [0u8; 256];
+        let ret = if i % 2 == 0 {
+            handler.getdents64(dir.as_raw_fd(), &mut buf)
+        } else {
+            unsafe {
+                handler.syscall(
+                    platform,
+                    [
+                        SYS_getdents64 as _,
+                        dir.as_raw_fd() as _,
+                        buf.as_mut_ptr() as _,
+                        buf.len(),
+                        0,
+                        0,
+                        0,
+                    ],
+                )
+            }
+            .map(|[ret, _]| ret)
+        };
+        if cfg!(not(miri)) {
+            let len = ret.unwrap();
+            assert!(len > 0);
+            // The directory holds `.`, `..` and `entry`.
+            let names = buf[..len].windows(b"entry\0".len());
+            assert_eq!(names.filter(|w| w == b"entry\0").count(), 1);
+        } else {
+            assert_eq!(ret, Err(ENOSYS));
+        }
+    });
+}
+
 #[test]
 fn getegid() {
     run_test(2, [0xff; 16], move |i, platform, handler| {
@@ -501,6 +634,82 @@ fn getrandom() {
     });
 }
 
+#[test]
+#[serial]
+fn lseek() {
+    run_test(2, [0xff; 16], move |i, platform, handler| {
+        let path = temp_dir().join(format!("sallyport-test-lseek-{i}"));
+        write!(&mut File::create(&path).unwrap(), "lseek").unwrap();
+        let file = File::open(&path).unwrap();
+
+        let ret = if i % 2 == 0 {
+            handler.lseek(file.as_raw_fd(), -2, SEEK_END)
+        } else {
+            unsafe {
+                handler.syscall(
+                    platform,
+                    [
+                        SYS_lseek as _,
+                        file.as_raw_fd() as _,
+                        -2isize as _,
+                        SEEK_END as _,
+                        0,
+                        0,
+                        0,
+                    ],
+                )
+            }
+            .map(|[ret, _]| ret as _)
+        };
+        if cfg!(not(miri)) {
+            assert_eq!(ret, Ok(3));
+            assert_eq!(handler.lseek(file.as_raw_fd(), -1, SEEK_SET), Err(EINVAL));
+        } else {
+            assert_eq!(ret, Err(ENOSYS));
+        }
+    });
+}
+
+#[test]
+#[serial]
+fn mkdirat() {
+    run_test(2, [0xff; 32], move |i, platform, handler| {
+        let path = temp_dir().join(format!("sallyport-test-mkdirat-{i}"));
+        let _ = std::fs::remove_dir_all(&path);
+        let c_path = CString::new(path.as_os_str().to_str().unwrap()).unwrap();
+
+        let ret = if i % 2 == 0 {
+            handler.mkdirat(AT_FDCWD, c_path.as_bytes_with_nul(), 0o755)
+        } else {
+            unsafe {
+                handler.syscall(
+                    platform,
+                    [
+                        SYS_mkdirat as _,
+                        AT_FDCWD as _,
+                        c_path.as_ptr() as _,
+                        0o755,
+                        0,
+                        0,
+                        0,
+                    ],
+                )
+            }
+            .map(|ret| assert_eq!(ret, [0, 0]))
+        };
+        if cfg!(not(miri)) {
+            assert_eq!(ret, Ok(()));
+            assert!(path.is_dir());
+            assert_eq!(
+                handler.mkdirat(AT_FDCWD, c_path.as_bytes_with_nul(), 0o755),
+                Err(EEXIST)
+            );
+        } else {
+            assert_eq!(ret, Err(ENOSYS));
+        }
+    });
+}
+
 #[test]
 fn mremap() {
     let mem = [0u8; 4096];
@@ -645,6 +854,47 @@ fn nanosleep() {
     });
 }
 
+#[test]
+#[serial]
+fn newfstatat() {
+    run_test(2, [0xff; 64], move |i, platform, handler| {
+        let path = temp_dir().join(format!("sallyport-test-newfstatat-{i}"));
+        write!(&mut File::create(&path).unwrap(), "newfstatat").unwrap();
+        let c_path = CString::new(path.as_os_str().to_str().unwrap()).unwrap();
+
+        let mut statbuf: sallyport::libc::stat = unsafe { mem::zeroed() };
+        let ret = if i % 2 == 0 {
+            handler.newfstatat(AT_FDCWD, c_path.as_bytes_with_nul(), &mut statbuf, 0)
+        } else {
+            unsafe {
+                handler.syscall(
+                    platform,
+                    [
+                        SYS_newfstatat as _,
+                        AT_FDCWD as _,
+                        c_path.as_ptr() as _,
+                        &mut statbuf as *mut _ as _,
+                        0,
+                        0,
+                        0,
+                    ],
+                )
+            }
+            .map(|ret| assert_eq!(ret, [0, 0]))
+        };
+        if cfg!(not(miri)) {
+            assert_eq!(ret, Ok(()));
+            assert_eq!(statbuf.st_size, "newfstatat".len() as _);
+            assert_eq!(
+                handler.newfstatat(AT_FDCWD, b"/nonexistent\0", &mut statbuf, 0),
+                Err(ENOENT)
+            );
+        } else {
+            assert_eq!(ret, Err(ENOSYS));
+        }
+    });
+}
+
 #[test]
 #[serial]
 fn open() {
@@ -715,6 +965,54 @@ fn open() {
     });
 }
 
+#[test]
+#[serial]
+fn openat() {
+    run_test(2, [0xff; 32], move |i, platform, handler| {
+        let dir = File::open(temp_dir()).unwrap();
+        let name = format!("sallyport-test-openat-{i}");
+        let _ = std::fs::remove_file(temp_dir().join(&name));
+        let c_name = CString::new(name.clone()).unwrap();
+
+        let ret = if i % 2 == 0 {
+            handler.openat(
+                dir.as_raw_fd(),
+                c_name.as_bytes_with_nul(),
+                O_CREAT | O_WRONLY,
+                Some(0o644),
+            )
+        } else {
+            unsafe {
+                handler.syscall(
+                    platform,
+                    [
+                        SYS_openat as _,
+                        dir.as_raw_fd() as _,
+                        c_name.as_ptr() as _,
+                        (O_CREAT | O_WRONLY) as _,
+                        0o644,
+                        0,
+                        0,
+                    ],
+                )
+            }
+            .map(|[ret, _]| ret as _)
+        };
+        if cfg!(not(miri)) {
+            let fd = ret.unwrap();
+            assert!(fd >= 0);
+            assert_eq!(unsafe { libc::close(fd) }, 0);
+            assert!(temp_dir().join(&name).is_file());
+            assert_eq!(
+                handler.openat(dir.as_raw_fd(), b"nonexistent\0", O_RDONLY, None),
+                Err(ENOENT)
+            );
+        } else {
+            assert_eq!(ret, Err(ENOSYS));
+        }
+    });
+}
+
 #[test]
 #[serial]
 fn poll() {
@@ -764,6 +1062,87 @@ fn poll() {
     });
 }
 
+#[test]
+#[serial]
+fn pread64() {
+    run_test(2, [0xff; 16], move |i, platform, handler| {
+        let path = temp_dir().join(format!("sallyport-test-pread64-{i}"));
+        write!(&mut File::create(&path).unwrap(), "xxpread64").unwrap();
+        let file = File::open(&path).unwrap();
+
+        let mut buf = [0u8; 7];
+        let ret = if i % 2 == 0 {
+            handler.pread64(file.as_raw_fd(), &mut buf, 2)
+        } else {
+            unsafe {
+                handler.syscall(
+                    platform,
+                    [
+                        SYS_pread64 as _,
+                        file.as_raw_fd() as _,
+                        buf.as_mut_ptr() as _,
+                        buf.len(),
+                        2,
+                        0,
+                        0,
+                    ],
+                )
+            }
+            .map(|[ret, _]| ret)
+        };
+        if cfg!(not(miri)) {
+            assert_eq!(ret, Ok(buf.len()));
+            assert_eq!(&buf, b"pread64");
+        } else {
+            assert_eq!(ret, Err(ENOSYS));
+        }
+    });
+}
+
+#[test]
+#[serial]
+fn pwrite64() {
+    run_test(2, [0xff; 16], move |i, platform, handler| {
+        const EXPECTED: &str = "pwrite64";
+        let path = temp_dir().join(format!("sallyport-test-pwrite64-{i}"));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(path)
+            .unwrap();
+
+        let ret = if i % 2 == 0 {
+            handler.pwrite64(file.as_raw_fd(), EXPECTED.as_bytes(), 2)
+        } else {
+            unsafe {
+                handler.syscall(
+                    platform,
+                    [
+                        SYS_pwrite64 as _,
+                        file.as_raw_fd() as _,
+                        EXPECTED.as_ptr() as _,
+                        EXPECTED.len(),
+                        2,
+                        0,
+                        0,
+                    ],
+                )
+            }
+            .map(|[ret, _]| ret)
+        };
+        if cfg!(not(miri)) {
+            assert_eq!(ret, Ok(EXPECTED.len()));
+            let mut got = Vec::new();
+            file.read_to_end(&mut got).unwrap();
+            assert_eq!(got, b"\0\0pwrite64");
+        } else {
+            assert_eq!(ret, Err(ENOSYS));
+        }
+    });
+}
+
 #[test]
 #[serial]
 fn read() {
@@ -1301,6 +1680,65 @@ fn recvfrom() {
     });
 }
 
+#[test]
+#[serial]
+fn renameat2() {
+    run_test(2, [0xff; 64], move |i, platform, handler| {
+        let old = temp_dir().join(format!("sallyport-test-renameat2-old-{i}"));
+        let new = temp_dir().join(format!("sallyport-test-renameat2-new-{i}"));
+        let _ = std::fs::remove_file(&new);
+        File::create(&old).unwrap();
+        let c_old = CString::new(old.as_os_str().to_str().unwrap()).unwrap();
+        let c_new = CString::new(new.as_os_str().to_str().unwrap()).unwrap();
+
+        let ret = if i % 2 == 0 {
+            handler.renameat2(
+                AT_FDCWD,
+                c_old.as_bytes_with_nul(),
+                AT_FDCWD,
+                c_new.as_bytes_with_nul(),
+                RENAME_NOREPLACE,
+            )
+        } else {
+            unsafe {
+                handler.syscall(
+                    platform,
+                    [
+                        SYS_renameat2 as _,
+                        AT_FDCWD as _,
+                        c_old.as_ptr() as _,
+                        AT_FDCWD as _,
+                        c_new.as_ptr() as _,
+                        RENAME_NOREPLACE as _,
+                        0,
+                    ],
+                )
+            }
+            .map(|ret| assert_eq!(ret, [0, 0]))
+        };
+        if cfg!(not(miri)) {
+            assert_eq!(ret, Ok(()));
+            assert!(!old.exists());
+            assert!(new.is_file());
+
+            // `RENAME_NOREPLACE` must make it through the packed argument.
+            File::create(&old).unwrap();
+            assert_eq!(
+                handler.renameat2(
+                    AT_FDCWD,
+                    c_old.as_bytes_with_nul(),
+                    AT_FDCWD,
+                    c_new.as_bytes_with_nul(),
+                    RENAME_NOREPLACE,
+                ),
+                Err(EEXIST)
+            );
+        } else {
+            assert_eq!(ret, Err(ENOSYS));
+        }
+    });
+}
+
 #[test]
 fn rt_sigaction() {
     run_test(2, [0xff; 16], move |i, platform, handler| {
@@ -1616,6 +2054,78 @@ fn uname() {
     });
 }
 
+#[test]
+#[serial]
+fn unlinkat() {
+    run_test(2, [0xff; 32], move |i, platform, handler| {
+        let path = temp_dir().join(format!("sallyport-test-unlinkat-{i}"));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir(&path).unwrap();
+        let c_path = CString::new(path.as_os_str().to_str().unwrap()).unwrap();
+
+        let ret = if i % 2 == 0 {
+            handler.unlinkat(AT_FDCWD, c_path.as_bytes_with_nul(), AT_REMOVEDIR)
+        } else {
+            unsafe {
+                handler.syscall(
+                    platform,
+                    [
+                        SYS_unlinkat as _,
+                        AT_FDCWD as _,
+                        c_path.as_ptr() as _,
+                        AT_REMOVEDIR as _,
+                        0,
+                        0,
+                        0,
+                    ],
+                )
+            }
+            .map(|ret| assert_eq!(ret, [0, 0]))
+        };
+        if cfg!(not(miri)) {
+            assert_eq!(ret, Ok(()));
+            assert!(!path.exists());
+            assert_eq!(
+                handler.unlinkat(AT_FDCWD, c_path.as_bytes_with_nul(), 0),
+                Err(ENOENT)
+            );
+        } else {
+            assert_eq!(ret, Err(ENOSYS));
+        }
+    });
+}
+
+#[test]
+#[serial]
+#[cfg_attr(miri, ignore)]
+fn write_batch() {
+    run_test(1, [0xff; 64], move |_, _, handler| {
+        let path = temp_dir().join("sallyport-test-write-batch");
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(path)
+            .unwrap();
+        let fd = file.as_raw_fd();
+
+        assert_eq!(
+            handler.execute([
+                syscall::Write { fd, buf: b"one " },
+                syscall::Write { fd, buf: b"two " },
+                syscall::Write { fd, buf: b"three" },
+            ]),
+            Ok([Some(Ok(4)), Some(Ok(4)), Some(Ok(5))])
+        );
+
+        let mut got = String::new();
+        file.rewind().unwrap();
+        file.read_to_string(&mut got).unwrap();
+        assert_eq!(got, "one two three");
+    })
+}
+
 #[test]
 #[serial]
 fn write() {
@@ -0,0 +1,359 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! A monotonic clock derived from the time stamp counter
+//!
+//! The clock is calibrated against the monotonic clock of the host, unless the shim knows a
+//! trusted TSC frequency, e.g. from SEV-SNP Secure TSC. Once calibrated, the clock only samples
+//! the host clock periodically, to report the host clock going backwards or jumping.
+//! The time of the clock never goes backwards, whatever the host reports.
+//!
+//! A keep restored from a snapshot [`resume`](TscClock::resume)s its clock, as neither the TSC
+//! nor the host clock continue where they were, when the snapshot was taken.
+
+use core::{fmt, mem};
+
+use spin::Mutex;
+
+/// Nanoseconds per second
+pub const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// The minimum host time between the samples the TSC frequency is calibrated with
+pub const CALIBRATION_NS: u64 = 100_000_000;
+
+/// The time between two cross-checks with the host clock
+pub const CHECK_INTERVAL_NS: u64 = NANOS_PER_SEC;
+
+/// The difference to the host clock tolerated at any time
+pub const JUMP_NS: u64 = 10_000_000;
+
+/// A discrepancy between the clock and the host clock
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Event {
+    /// The host clock went backwards by the given nanoseconds
+    Backwards(u64),
+    /// The host clock jumped away from the clock
+    Jump {
+        /// The time of the clock
+        clock: u64,
+        /// The time of the host clock
+        host: u64,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Backwards(ns) => write!(f, "host clock went backwards by {ns} ns"),
+            Self::Jump { clock, host } if host > clock => {
+                write!(f, "host clock jumped {} ns ahead", host - clock)
+            }
+            Self::Jump { clock, host } => write!(f, "host clock jumped {} ns behind", clock - host),
+        }
+    }
+}
+
+/// A monotonic clock derived from the time stamp counter
+#[derive(Debug)]
+pub struct TscClock {
+    /// The TSC and host time the clock starts at
+    base: Option<(u64, u64)>,
+    /// The rate of the TSC as nanoseconds per number of ticks
+    rate: Option<(u64, u64)>,
+    /// Whether the rate is the trusted frequency of the TSC
+    trusted: bool,
+    /// The TSC and host time of the last sample
+    tsc: u64,
+    host: u64,
+    /// The time of the last cross-check with the host clock
+    checked: u64,
+    /// The last time returned
+    last: u64,
+    /// The difference added to the host times, so a resumed clock continues where it stopped
+    shift: u64,
+    /// Whether the clock was resumed and is to be anchored again at the next sample
+    resumed: bool,
+}
+
+impl TscClock {
+    /// Creates a clock, which is calibrated against the host clock,
+    /// unless the TSC is trusted to tick at `frequency` Hz.
+    pub const fn new(frequency: Option<u64>) -> Self {
+        let rate = match frequency {
+            Some(hz) if hz > 0 => Some((NANOS_PER_SEC, hz)),
+            _ => None,
+        };
+
+        Self {
+            base: None,
+            rate,
+            trusted: rate.is_some(),
+            tsc: 0,
+            host: 0,
+            checked: 0,
+            last: 0,
+            shift: 0,
+            resumed: false,
+        }
+    }
+
+    /// Anchors the clock again at the next sample of the host clock, after the keep was resumed
+    /// with another TSC and host clock, e.g. from a snapshot.
+    ///
+    /// The clock continues from the last time returned, a calibrated rate is calibrated again.
+    pub fn resume(&mut self) {
+        self.base = None;
+        if !self.trusted {
+            self.rate = None;
+        }
+        self.tsc = 0;
+        self.resumed = true;
+    }
+
+    /// Returns `true`, if the clock needs a sample of the host clock at `tsc`,
+    /// because it is not calibrated or due for a cross-check.
+    pub fn needs_sample(&self, tsc: u64) -> bool {
+        match self.time(tsc) {
+            Some(time) => time >= self.checked.saturating_add(CHECK_INTERVAL_NS),
+            None => true,
+        }
+    }
+
+    /// Returns the time in nanoseconds at `tsc`.
+    ///
+    /// Until the clock is calibrated, this is the latest time the host clock was sampled at.
+    pub fn now(&mut self, tsc: u64) -> u64 {
+        if let Some(time) = self.time(tsc) {
+            self.last = self.last.max(time);
+        }
+        self.last
+    }
+
+    /// Adds the sample `host` of the host clock at `tsc`.
+    ///
+    /// Samples taken before the last sample are ignored, as the host clock was sampled outside
+    /// of the lock of the clock and the later sample already accounts for them.
+    ///
+    /// Returns the discrepancy between the clock and the host clock, if any.
+    pub fn sample(&mut self, tsc: u64, host: u64) -> Option<Event> {
+        if self.base.is_none() && mem::take(&mut self.resumed) {
+            self.shift = self.last.wrapping_sub(host);
+        }
+        let host = host.wrapping_add(self.shift);
+
+        let Some((base_tsc, base_host)) = self.base else {
+            self.base = Some((tsc, host));
+            self.tsc = tsc;
+            self.host = host;
+            self.checked = host;
+            self.last = self.last.max(host);
+            return None;
+        };
+
+        if tsc < self.tsc {
+            return None;
+        }
+        if host < self.host {
+            // Cross-check again after the interval, instead of sampling on every call.
+            if let Some(clock) = self.time(tsc) {
+                self.checked = clock;
+            }
+            return Some(Event::Backwards(self.host - host));
+        }
+        self.tsc = tsc;
+        self.host = host;
+
+        if self.rate.is_none() {
+            self.last = self.last.max(host);
+            let (ticks, nanos) = (tsc.saturating_sub(base_tsc), host - base_host);
+            if nanos >= CALIBRATION_NS && ticks > 0 {
+                self.rate = Some((nanos, ticks));
+                self.checked = host;
+            }
+            return None;
+        }
+
+        let clock = self.time(tsc)?;
+        self.checked = clock;
+
+        // Allow for a drift of 0.1% of the TSC against the host clock.
+        let tolerance = JUMP_NS + (clock - base_host) / 1000;
+        (clock.abs_diff(host) > tolerance).then_some(Event::Jump { clock, host })
+    }
+
+    /// Returns the time at `tsc`, if the clock is calibrated.
+    fn time(&self, tsc: u64) -> Option<u64> {
+        let (base_tsc, base_host) = self.base?;
+        let (nanos, ticks) = self.rate?;
+        let elapsed = tsc.saturating_sub(base_tsc) as u128 * nanos as u128 / ticks as u128;
+        Some(base_host.saturating_add(elapsed as u64))
+    }
+}
+
+/// Returns the monotonic time in nanoseconds of `clock`, reading the TSC with `rdtsc`.
+///
+/// The host clock is sampled with `host`, as long as `clock` is not calibrated, and periodically
+/// to cross-check it. The lock of `clock` is not held while sampling, as the host round-trip may
+/// take long, so concurrent samples might be added out of order and the earlier ones are ignored.
+///
+/// Also returns the discrepancy to the host clock found by a cross-check, if any.
+pub fn monotonic<E>(
+    clock: &Mutex<TscClock>,
+    rdtsc: impl Fn() -> u64,
+    host: impl FnOnce() -> Result<u64, E>,
+) -> Result<(u64, Option<Event>), E> {
+    let before = rdtsc();
+    let sample = if clock.lock().needs_sample(before) {
+        let host = host()?;
+        let after = rdtsc();
+        Some((before + after.saturating_sub(before) / 2, host))
+    } else {
+        None
+    };
+
+    let mut clock = clock.lock();
+    let event = sample.and_then(|(tsc, host)| clock.sample(tsc, host));
+    Ok((clock.now(rdtsc()), event))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// TSC ticks per nanosecond of the test clocks
+    const GHZ: u64 = 3;
+
+    #[test]
+    fn calibrate() {
+        let mut clock = TscClock::new(None);
+        assert!(clock.needs_sample(1000));
+        assert_eq!(clock.sample(1000, 5 * NANOS_PER_SEC), None);
+        assert_eq!(clock.now(2000), 5 * NANOS_PER_SEC);
+
+        let tsc = 1000 + CALIBRATION_NS * GHZ;
+        assert!(clock.needs_sample(tsc));
+        assert_eq!(clock.sample(tsc, 5 * NANOS_PER_SEC + CALIBRATION_NS), None);
+        assert!(!clock.needs_sample(tsc + 1000 * GHZ));
+
+        let now = clock.now(tsc + NANOS_PER_SEC * GHZ);
+        assert_eq!(now / 1000, (6 * NANOS_PER_SEC + CALIBRATION_NS) / 1000);
+        assert!(clock.needs_sample(tsc + NANOS_PER_SEC * GHZ));
+    }
+
+    #[test]
+    fn trusted_frequency() {
+        let mut clock = TscClock::new(Some(GHZ * NANOS_PER_SEC));
+        assert_eq!(clock.sample(0, NANOS_PER_SEC), None);
+        assert_eq!(clock.now(GHZ * NANOS_PER_SEC), 2 * NANOS_PER_SEC);
+        assert!(clock.needs_sample(GHZ * NANOS_PER_SEC));
+    }
+
+    #[test]
+    fn monotonic() {
+        let mut clock = TscClock::new(Some(GHZ * NANOS_PER_SEC));
+        clock.sample(1000, NANOS_PER_SEC);
+        assert_eq!(clock.now(1000 + GHZ), NANOS_PER_SEC + 1);
+        assert_eq!(clock.now(1000), NANOS_PER_SEC + 1);
+    }
+
+    #[test]
+    fn backwards() {
+        let mut clock = TscClock::new(Some(GHZ * NANOS_PER_SEC));
+        clock.sample(0, 2 * NANOS_PER_SEC);
+        let event = clock.sample(GHZ * NANOS_PER_SEC, NANOS_PER_SEC);
+        assert_eq!(event, Some(Event::Backwards(NANOS_PER_SEC)));
+        assert_eq!(clock.now(GHZ * NANOS_PER_SEC), 3 * NANOS_PER_SEC);
+    }
+
+    #[test]
+    fn jump() {
+        let mut clock = TscClock::new(Some(GHZ * NANOS_PER_SEC));
+        clock.sample(0, NANOS_PER_SEC);
+        let tsc = GHZ * NANOS_PER_SEC;
+        assert_eq!(clock.sample(tsc, 2 * NANOS_PER_SEC + JUMP_NS / 2), None);
+
+        let (clock_ns, host) = (3 * NANOS_PER_SEC, 13 * NANOS_PER_SEC);
+        let event = clock.sample(2 * tsc, host);
+        assert_eq!(
+            event,
+            Some(Event::Jump {
+                clock: clock_ns,
+                host
+            })
+        );
+        assert_eq!(clock.now(2 * tsc), clock_ns);
+    }
+
+    #[test]
+    fn backwards_checked() {
+        let mut clock = TscClock::new(Some(GHZ * NANOS_PER_SEC));
+        clock.sample(0, 5 * NANOS_PER_SEC);
+        let tsc = GHZ * NANOS_PER_SEC;
+        assert!(clock.needs_sample(tsc));
+        assert_eq!(
+            clock.sample(tsc, NANOS_PER_SEC),
+            Some(Event::Backwards(4 * NANOS_PER_SEC))
+        );
+        assert!(!clock.needs_sample(tsc + GHZ));
+    }
+
+    #[test]
+    fn resume() {
+        let mut clock = TscClock::new(None);
+        clock.sample(0, 100 * NANOS_PER_SEC);
+        clock.sample(CALIBRATION_NS * GHZ, 100 * NANOS_PER_SEC + CALIBRATION_NS);
+        let last = clock.now(NANOS_PER_SEC * GHZ);
+        assert_eq!(last, 101 * NANOS_PER_SEC);
+
+        // Restored on another boot, whose host clock is behind, with a TSC of half the rate.
+        clock.resume();
+        assert!(clock.needs_sample(NANOS_PER_SEC * GHZ));
+        assert_eq!(clock.now(2 * NANOS_PER_SEC * GHZ), last);
+        assert_eq!(clock.sample(0, 7 * NANOS_PER_SEC), None);
+        let (ticks, nanos) = (CALIBRATION_NS * GHZ / 2, CALIBRATION_NS);
+        assert_eq!(clock.sample(ticks, 7 * NANOS_PER_SEC + nanos), None);
+        assert_eq!(clock.now(ticks), last + nanos);
+        assert_eq!(
+            clock.now(ticks + NANOS_PER_SEC * GHZ / 2),
+            last + nanos + NANOS_PER_SEC
+        );
+
+        // Cross-checks compare against the host clock shifted by the resume.
+        let tsc = ticks + NANOS_PER_SEC * GHZ / 2;
+        assert_eq!(clock.sample(tsc, 8 * NANOS_PER_SEC + nanos), None);
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut clock = TscClock::new(Some(GHZ * NANOS_PER_SEC));
+        clock.sample(0, NANOS_PER_SEC);
+        let tsc = GHZ * NANOS_PER_SEC;
+        assert_eq!(clock.sample(2 * tsc, 3 * NANOS_PER_SEC), None);
+        assert_eq!(clock.sample(tsc, 2 * NANOS_PER_SEC - JUMP_NS), None);
+        assert_eq!(clock.host, 3 * NANOS_PER_SEC);
+    }
+
+    #[test]
+    fn sample_outside_lock() {
+        let clock = Mutex::new(TscClock::new(Some(GHZ * NANOS_PER_SEC)));
+        let tsc = core::cell::Cell::new(0);
+        let rdtsc = || {
+            tsc.set(tsc.get() + GHZ);
+            tsc.get()
+        };
+
+        let host = || {
+            assert!(clock.try_lock().is_some());
+            Ok::<_, ()>(NANOS_PER_SEC)
+        };
+        assert_eq!(
+            super::monotonic(&clock, rdtsc, host),
+            Ok((NANOS_PER_SEC + 1, None))
+        );
+
+        let host = || -> Result<u64, ()> { unreachable!() };
+        assert_eq!(
+            super::monotonic(&clock, rdtsc, host),
+            Ok((NANOS_PER_SEC + 3, None))
+        );
+    }
+}
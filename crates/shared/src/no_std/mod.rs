@@ -2,4 +2,5 @@
 
 //! share code between the Enarx components not using std
 
+pub mod clock;
 pub mod cpuid_page;
@@ -6,6 +6,7 @@ use crate::addr::{ShimPhysAddr, ShimVirtAddr, SHIM_VIRT_OFFSET};
 use crate::exec::NEXT_MMAP_RWLOCK;
 use crate::hostcall::HostCall;
 use crate::paging::{ShimPageTable, SHIM_PAGETABLE};
+use crate::snp::ghcb::GHCB;
 use crate::snp::{get_cbit_mask, pvalidate, snp_active, PvalidateSize};
 
 use core::alloc::{GlobalAlloc, Layout};
@@ -22,6 +23,7 @@ use linked_list_allocator::Heap;
 use lset::{Line, Span};
 use primordial::Address;
 use sallyport::guest::Handler;
+use sallyport::libc::{EINVAL, ENOSYS};
 use spin::{Lazy, Mutex, MutexGuard, RwLockWriteGuard};
 use x86_64::instructions::tlb::flush_all;
 use x86_64::structures::paging::mapper::{MapToError, UnmapError};
@@ -32,6 +34,18 @@ use x86_64::{align_down, align_up, PhysAddr, VirtAddr};
 
 const PG_USIZE: usize = Page::<Size4KiB>::SIZE as usize;
 
+/// The size of a huge page, which the host may back guest memory with
+const HUGE_PG_USIZE: usize = Page::<Size2MiB>::SIZE as usize;
+
+/// Free memory kept for new allocations, when memory is returned to the host
+const DEFLATE_KEEP_FREE: usize = 1 << 25; // 32 MiB
+
+/// The smallest range of memory returned to the host
+const DEFLATE_MIN: usize = Page::<Size2MiB>::SIZE as usize;
+
+/// The maximum number of ranges returned to the host at a time
+const MAX_DEFLATED: usize = 64;
+
 /// Frame of the zero page
 pub static ZERO_PAGE_FRAME: Lazy<PhysFrame<Size4KiB>> = Lazy::new(|| {
     let frame = ALLOCATOR.lock().allocate_frame().unwrap();
@@ -75,6 +89,10 @@ pub struct EnarxAllocator {
     max_alloc: usize,
     end_of_mem: PhysAddr,
     allocator: Heap,
+    /// Ranges of the heap returned to the host as shim virtual address and size
+    deflated: [Option<(usize, usize)>; MAX_DEFLATED],
+    /// The host does not support returning memory
+    deflate_unsupported: bool,
 }
 
 impl core::fmt::Debug for EnarxAllocator {
@@ -82,6 +100,7 @@ impl core::fmt::Debug for EnarxAllocator {
         f.debug_struct("EnarxAllocator")
             .field("last_alloc", &self.last_alloc)
             .field("max_alloc", &self.max_alloc)
+            .field("deflated", &self.deflated())
             .finish()
     }
 }
@@ -104,6 +123,26 @@ fn msb(val: usize) -> u32 {
     }
 }
 
+/// Calls a memory enarxcall for `size` bytes at the guest physical address `addr`
+///
+/// `call` is given the exponent of the page size and the number of pages. Memory aligned to huge
+/// pages is passed as huge pages, so the host can back it by huge pages. Hosts, which do not
+/// support huge pages, are asked again with 4 KiB pages.
+fn with_pages(
+    addr: u64,
+    size: usize,
+    mut call: impl FnMut(usize, usize) -> sallyport::Result<usize>,
+) -> sallyport::Result<usize> {
+    let huge_mask = HUGE_PG_USIZE.checked_sub(1).unwrap();
+    if addr & huge_mask as u64 == 0 && size & huge_mask == 0 {
+        match call(21, size.checked_div(HUGE_PG_USIZE).unwrap()) {
+            Err(EINVAL) => {}
+            ret => return ret,
+        }
+    }
+    call(12, size.checked_div(PG_USIZE).unwrap())
+}
+
 /// Struct to hold a lock of the page table and allocator
 ///
 /// For operations needing both the page table and the allocator.
@@ -369,6 +408,8 @@ impl PageTableAllocatorLock<'_> {
         }
 
         flush_all();
+
+        self.allocator.deflate();
         Ok(())
     }
 }
@@ -485,7 +526,8 @@ impl EnarxAllocator {
             Page::<Size4KiB>::SIZE,
         ) as usize;
 
-        let end_of_mem = mem_start + mem_size;
+        // Ballooned memory starts at a huge page, so the host can back it by huge pages.
+        let end_of_mem = (mem_start + mem_size).align_up(HUGE_PG_USIZE as u64);
 
         let mut nmr = NEXT_MMAP_RWLOCK.write();
         *nmr = (*nmr + code_size).align_up(Page::<Size4KiB>::SIZE);
@@ -497,10 +539,17 @@ impl EnarxAllocator {
             max_alloc,
             end_of_mem,
             allocator,
+            deflated: [None; MAX_DEFLATED],
+            deflate_unsupported: false,
         }
     }
 
     fn balloon(&mut self) -> bool {
+        // Memory returned to the host is reclaimed first, so no new memory slot is needed.
+        if self.reclaim() {
+            return true;
+        }
+
         let mut last_size: usize = self.last_alloc;
 
         loop {
@@ -509,14 +558,11 @@ impl EnarxAllocator {
                 .checked_mul(last_size as u64)
                 .unwrap_or(last_size as u64) as _;
             let new_size = new_size.min(self.max_alloc);
-            let num_pages = new_size.checked_div(PG_USIZE).unwrap();
 
-            let ret = HostCall::maint().balloon_memory(
-                12, // 1 << 12 == 4096 == page size
-                num_pages,
-                self.end_of_mem.as_u64() as _,
-                snp_active(),
-            );
+            let addr = self.end_of_mem.as_u64();
+            let ret = with_pages(addr, new_size, |log2, npages| {
+                HostCall::maint().balloon_memory(log2, npages, addr as _, snp_active())
+            });
 
             match ret {
                 Ok(_) => {
@@ -562,6 +608,111 @@ impl EnarxAllocator {
         }
     }
 
+    /// Returns the number of bytes returned to the host.
+    fn deflated(&self) -> usize {
+        self.deflated.iter().flatten().map(|(_, size)| size).sum()
+    }
+
+    /// Return free memory to the host, keeping [`DEFLATE_KEEP_FREE`] bytes for new allocations
+    ///
+    /// The memory is taken out of the heap, so it is never handed out, until it is reclaimed by
+    /// [`Self::balloon`]. The host releases whole memory regions, once all of their memory was
+    /// returned.
+    fn deflate(&mut self) {
+        if self.deflate_unsupported
+            || self.allocator.free() < DEFLATE_KEEP_FREE.checked_mul(2).unwrap()
+        {
+            return;
+        }
+
+        let mut size = 1usize
+            .checked_shl(msb(self
+                .allocator
+                .free()
+                .checked_sub(DEFLATE_KEEP_FREE)
+                .unwrap()))
+            .unwrap()
+            .min(self.max_alloc);
+
+        while size >= DEFLATE_MIN {
+            let Some(slot) = self.deflated.iter().position(Option::is_none) else {
+                return;
+            };
+
+            if self.allocator.free().saturating_sub(size) < DEFLATE_KEEP_FREE {
+                size = size.checked_div(2).unwrap();
+                continue;
+            }
+
+            // Ranges aligned to huge pages can be released from memory backed by huge pages.
+            let layout = Layout::from_size_align(size, DEFLATE_MIN).unwrap();
+            let Ok(ptr) = self.allocator.allocate_first_fit(layout) else {
+                size = size.checked_div(2).unwrap();
+                continue;
+            };
+
+            if let Err(e) = Self::release(ptr, size) {
+                // Only a host without `DeflateMemory` stops deflating for good.
+                if e == ENOSYS {
+                    self.deflate_unsupported = true;
+                }
+                // Safety: the pointer is page aligned and points to allocated pages, which were never used.
+                unsafe { self.dealloc_pages(ptr, size) };
+                return;
+            }
+            self.deflated[slot] = Some((ptr.as_ptr() as usize, size));
+        }
+    }
+
+    /// Return `size` bytes of the heap at `ptr` to the host
+    ///
+    /// On SEV-SNP the pages are converted to shared first, so the host can release them.
+    fn release(ptr: NonNull<u8>, size: usize) -> sallyport::Result<usize> {
+        let virt = VirtAddr::from_ptr(ptr.as_ptr());
+        let npages = size.checked_div(PG_USIZE).unwrap();
+
+        if snp_active() {
+            GHCB.release_memory(virt, npages);
+        }
+
+        let guest = virt.as_u64().checked_sub(SHIM_VIRT_OFFSET).unwrap();
+        let ret = with_pages(guest, size, |log2, npages| {
+            HostCall::maint().deflate_memory(log2, npages, guest as _, snp_active())
+        });
+
+        if ret.is_err() && snp_active() {
+            GHCB.reclaim_memory(virt, npages);
+        }
+        ret
+    }
+
+    /// Reclaim a range of memory returned to the host with [`Self::deflate`]
+    fn reclaim(&mut self) -> bool {
+        let Some(slot) = self.deflated.iter().position(Option::is_some) else {
+            return false;
+        };
+        let (addr, size) = self.deflated[slot].unwrap();
+        let virt = VirtAddr::new(addr as u64);
+        let npages = size.checked_div(PG_USIZE).unwrap();
+
+        let guest = virt.as_u64().checked_sub(SHIM_VIRT_OFFSET).unwrap();
+        let ret = with_pages(guest, size, |log2, npages| {
+            HostCall::maint().balloon_memory(log2, npages, guest as _, snp_active())
+        });
+        if ret.is_err() {
+            return false;
+        }
+
+        if snp_active() {
+            GHCB.reclaim_memory(virt, npages);
+        }
+
+        self.deflated[slot] = None;
+        // Safety: the pointer is page aligned and points to pages taken out of the heap by `deflate`.
+        unsafe { self.dealloc_pages(NonNull::new(addr as *mut u8).unwrap(), size) };
+        true
+    }
+
     fn try_alloc_half(&mut self, mut size: usize) -> (*mut u8, usize) {
         assert!(size >= PG_USIZE);
         loop {
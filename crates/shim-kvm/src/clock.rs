@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! The monotonic clock of the keep, see [`TscClock`]
+
+use crate::snp::snp_active;
+
+use core::arch::x86_64::_rdtsc;
+
+use sallyport::libc::timespec;
+use shared::no_std::clock::{self, Event, TscClock, NANOS_PER_SEC};
+use spin::{Lazy, Mutex};
+use x86_64::registers::model_specific::Msr;
+
+/// The SEV status MSR
+const MSR_SEV_STATUS: u32 = 0xC001_0131;
+
+/// The bit of the SEV status MSR showing Secure TSC is enabled
+const SEV_STATUS_SECURE_TSC: u64 = 1 << 11;
+
+/// The MSR holding the guest TSC frequency in MHz, if Secure TSC is enabled
+const MSR_GUEST_TSC_FREQ: u32 = 0xC001_0134;
+
+static CLOCK: Lazy<Mutex<TscClock>> = Lazy::new(|| Mutex::new(TscClock::new(secure_tsc())));
+
+/// Returns the TSC frequency in Hz, if SEV-SNP Secure TSC is enabled.
+fn secure_tsc() -> Option<u64> {
+    if !snp_active() {
+        return None;
+    }
+
+    // Safety: the SEV MSRs are readable by an SNP guest, the frequency MSR only with Secure TSC.
+    unsafe {
+        if Msr::new(MSR_SEV_STATUS).read() & SEV_STATUS_SECURE_TSC == 0 {
+            return None;
+        }
+        Some((Msr::new(MSR_GUEST_TSC_FREQ).read() & 0x3_FFFF) * 1_000_000)
+    }
+}
+
+/// Anchors the clock again, after the keep was resumed from a snapshot.
+pub fn resume() {
+    CLOCK.lock().resume();
+}
+
+/// Returns the monotonic time, asking `host` for the monotonic time of the host,
+/// as long as the clock is not calibrated, and periodically to cross-check it.
+///
+/// Also returns the discrepancy to the host clock found by a cross-check, if any.
+pub fn monotonic(
+    host: impl FnOnce(&mut timespec) -> sallyport::Result<()>,
+) -> sallyport::Result<(timespec, Option<Event>)> {
+    let host = || -> sallyport::Result<u64> {
+        let mut tp = timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        host(&mut tp)?;
+        Ok((tp.tv_sec as u64)
+            .saturating_mul(NANOS_PER_SEC)
+            .saturating_add(tp.tv_nsec as u64))
+    };
+
+    // Safety: `rdtsc` has no side effects.
+    let (now, event) = clock::monotonic(&CLOCK, || unsafe { _rdtsc() }, host)?;
+    let tp = timespec {
+        tv_sec: (now / NANOS_PER_SEC) as _,
+        tv_nsec: (now % NANOS_PER_SEC) as _,
+    };
+    Ok((tp, event))
+}
@@ -1,28 +1,47 @@
 // SPDX-License-Identifier: Apache-2.0
 
 //! GDB debugging
+//!
+//! The stub exposes every thread stopped in an exception handler as a GDB thread.
 
 #![cfg(feature = "gdb")]
 
 use crate::exec::EXEC_READY;
 use crate::hostcall::HostCall;
 use crate::interrupts::ExtendedInterruptStackFrameValue;
+use crate::snp::snp_active;
 
+use alloc::collections::btree_map::Entry;
+use alloc::collections::BTreeMap;
 use core::arch::asm;
 use core::ffi::c_int;
-use core::sync::atomic::Ordering;
+use core::hint::spin_loop;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 use crate::addr::SHIM_VIRT_OFFSET;
 use crate::exec::EXEC_VIRT_ADDR;
 use crate::paging::SHIM_PAGETABLE;
 use gdbstub::arch::Arch;
-use gdbstub::target::ext::base::singlethread::SingleThreadOps;
-use gdbstub::target::ext::base::singlethread::{GdbInterrupt, ResumeAction, StopReason};
+use gdbstub::common::Tid;
+use gdbstub::target::ext::base::multithread::{
+    GdbInterrupt, MultiThreadOps, ResumeAction, ThreadStopReason,
+};
 use gdbstub::target::ext::base::BaseOps;
+use gdbstub::target::ext::breakpoints::{
+    Breakpoints, BreakpointsOps, HwBreakpoint, HwBreakpointOps, HwWatchpoint, HwWatchpointOps,
+    SwBreakpoint, SwBreakpointOps, WatchKind,
+};
 use gdbstub::target::{Target, TargetError, TargetResult};
 use gdbstub::{DisconnectReason, GdbStubBuilder, GdbStubError};
 use gdbstub_arch::x86::reg::X86_64CoreRegs;
 use sallyport::guest::Handler;
+use sallyport::libc::pid_t;
+use spin::Mutex;
+use x86_64::registers::debug::{
+    BreakpointCondition, BreakpointSize, DebugAddressRegister, DebugAddressRegisterNumber, Dr0,
+    Dr1, Dr2, Dr3, Dr6, Dr6Flags, Dr7, Dr7Flags, Dr7Value,
+};
 use x86_64::registers::rflags::RFlags;
 use x86_64::structures::paging::Translate;
 use x86_64::VirtAddr;
@@ -90,83 +109,231 @@ pub(crate) enum GdbTargetError {
     WriteMemoryOutOfRange(u64),
 }
 
+/// The reason a thread entered a GDB session
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Stop {
+    /// The thread executed `int3`
+    Breakpoint,
+    /// The thread completed a single step
+    Step,
+    /// The thread hit a hardware breakpoint
+    HwBreakpoint,
+    /// The thread accessed memory watched by a hardware watchpoint
+    Watchpoint { kind: WatchKind, addr: u64 },
+    /// The thread accessed unmapped memory
+    PageFault,
+    /// The thread executed an invalid instruction
+    InvalidOpcode,
+}
+
+/// A thread parked in an exception handler
+struct Parked {
+    frame: NonNull<ExtendedInterruptStackFrameValue>,
+    stop: Stop,
+    /// GDB was told about the stop
+    reported: bool,
+    /// GDB resumed the thread
+    resumed: bool,
+}
+
+// SAFETY: the frame is only accessed by the thread driving the GDB session, while its thread is parked.
+unsafe impl Send for Parked {}
+
+/// The threads parked in an exception handler by TID
+static PARKED: Mutex<BTreeMap<pid_t, Parked>> = Mutex::new(BTreeMap::new());
+
+/// Held by the thread driving the GDB session
+static SESSION: Mutex<()> = Mutex::new(());
+
+/// The original bytes of the software breakpoints by address
+static BREAKPOINTS: Mutex<BTreeMap<u64, u8>> = Mutex::new(BTreeMap::new());
+
+/// A hardware breakpoint or watchpoint in one of the debug address registers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct HwSlot {
+    addr: u64,
+    /// `None` for breakpoints
+    kind: Option<WatchKind>,
+    size: BreakpointSize,
+}
+
+/// The contents of DR0 to DR3, which are shared by all threads
+static HW_SLOTS: Mutex<[Option<HwSlot>; 4]> = Mutex::new([None; 4]);
+
+/// Incremented on every change of [`HW_SLOTS`], so threads know when to reload their debug registers
+static HW_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// `gdbstub` selects thread 1 at the start of every session, so it stands for the thread GDB
+/// was told about last and the TIDs of the keep are numbered from 2.
+const FOCUS: Tid = unsafe { Tid::new_unchecked(1) };
+
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+const INT3: u8 = 0xCC;
+
+fn gdb_tid(tid: pid_t) -> Tid {
+    Tid::new(tid as usize + 2).unwrap()
+}
+
 #[derive(Debug)]
-pub(crate) struct GdbTarget<'a> {
-    frame: &'a mut ExtendedInterruptStackFrameValue,
+pub(crate) struct GdbTarget {
+    /// The thread driving the session
+    tid: pid_t,
+    /// The thread GDB was told about last
+    focus: pid_t,
+    /// The resume actions by thread
+    actions: BTreeMap<pid_t, ResumeAction>,
 }
 
-impl<'a> GdbTarget<'a> {
-    pub(crate) fn new(frame: &'a mut ExtendedInterruptStackFrameValue) -> Self {
-        Self { frame }
+impl GdbTarget {
+    pub(crate) fn new(tid: pid_t) -> Self {
+        Self {
+            tid,
+            focus: tid,
+            actions: BTreeMap::new(),
+        }
+    }
+
+    fn thread(&self, tid: Tid) -> pid_t {
+        if tid == FOCUS {
+            self.focus
+        } else {
+            (tid.get() - 2) as pid_t
+        }
+    }
+
+    /// Returns the stack frame of a parked thread.
+    fn frame(&self, tid: Tid) -> TargetResult<NonNull<ExtendedInterruptStackFrameValue>, Self> {
+        PARKED
+            .lock()
+            .get(&self.thread(tid))
+            .map(|thread| thread.frame)
+            .ok_or(TargetError::NonFatal)
     }
 }
 
-impl Target for GdbTarget<'_> {
+impl Target for GdbTarget {
     type Arch = gdbstub_arch::x86::X86_64_SSE;
     type Error = GdbTargetError;
 
     fn base_ops(&mut self) -> BaseOps<'_, Self::Arch, Self::Error> {
-        BaseOps::SingleThread(self)
+        BaseOps::MultiThread(self)
+    }
+
+    fn breakpoints(&mut self) -> Option<BreakpointsOps<'_, Self>> {
+        Some(self)
     }
 }
 
-impl SingleThreadOps for GdbTarget<'_> {
+impl MultiThreadOps for GdbTarget {
     fn resume(
         &mut self,
-        action: ResumeAction,
+        default_resume_action: ResumeAction,
         _gdb_interrupt: GdbInterrupt<'_>,
-    ) -> Result<StopReason<<Self::Arch as Arch>::Usize>, Self::Error> {
-        match action {
-            ResumeAction::Continue => {
-                self.frame.cpu_flags &= !RFlags::TRAP_FLAG.bits();
-                Err(GdbTargetError::ResumeContinue)
-            }
-            ResumeAction::Step => {
-                self.frame.cpu_flags |= RFlags::TRAP_FLAG.bits();
-                Err(GdbTargetError::ResumeStep)
-            }
-            ResumeAction::ContinueWithSignal(_) => {
-                self.frame.cpu_flags &= !RFlags::TRAP_FLAG.bits();
-                Err(GdbTargetError::ResumeContinueWithSignal)
+    ) -> Result<ThreadStopReason<<Self::Arch as Arch>::Usize>, Self::Error> {
+        let mut parked = PARKED.lock();
+
+        // Report threads, which stopped during the session, before resuming anything.
+        if let Some((&tid, thread)) = parked.iter_mut().find(|(_, thread)| !thread.reported) {
+            thread.reported = true;
+            self.focus = tid;
+            return Ok(match thread.stop {
+                Stop::Breakpoint => ThreadStopReason::SwBreak(gdb_tid(tid)),
+                Stop::Step => ThreadStopReason::Signal(SIGTRAP),
+                Stop::HwBreakpoint => ThreadStopReason::HwBreak(gdb_tid(tid)),
+                Stop::Watchpoint { kind, addr } => ThreadStopReason::Watch {
+                    tid: gdb_tid(tid),
+                    kind,
+                    addr,
+                },
+                Stop::PageFault => ThreadStopReason::Signal(SIGSEGV),
+                Stop::InvalidOpcode => ThreadStopReason::Signal(SIGILL),
+            });
+        }
+
+        let mut own_action = default_resume_action;
+        for (&tid, thread) in parked.iter_mut() {
+            let action = self
+                .actions
+                .get(&tid)
+                .copied()
+                .unwrap_or(default_resume_action);
+
+            // SAFETY: the thread is parked until it is resumed
+            let frame = unsafe { thread.frame.as_mut() };
+            match action {
+                ResumeAction::Continue | ResumeAction::ContinueWithSignal(_) => {
+                    frame.cpu_flags &= !RFlags::TRAP_FLAG.bits()
+                }
+                ResumeAction::Step | ResumeAction::StepWithSignal(_) => {
+                    frame.cpu_flags |= RFlags::TRAP_FLAG.bits()
+                }
             }
-            ResumeAction::StepWithSignal(_) => {
-                self.frame.cpu_flags |= RFlags::TRAP_FLAG.bits();
-                Err(GdbTargetError::ResumeStepWithSignal)
+
+            if tid == self.tid {
+                own_action = action;
+            } else {
+                thread.resumed = true;
             }
         }
+
+        Err(match own_action {
+            ResumeAction::Continue => GdbTargetError::ResumeContinue,
+            ResumeAction::Step => GdbTargetError::ResumeStep,
+            ResumeAction::ContinueWithSignal(_) => GdbTargetError::ResumeContinueWithSignal,
+            ResumeAction::StepWithSignal(_) => GdbTargetError::ResumeStepWithSignal,
+        })
+    }
+
+    fn clear_resume_actions(&mut self) -> Result<(), Self::Error> {
+        self.actions.clear();
+        Ok(())
+    }
+
+    fn set_resume_action(&mut self, tid: Tid, action: ResumeAction) -> Result<(), Self::Error> {
+        self.actions.insert(self.thread(tid), action);
+        Ok(())
     }
 
     fn read_registers(
         &mut self,
         regs: &mut <Self::Arch as Arch>::Registers,
+        tid: Tid,
     ) -> TargetResult<(), Self> {
-        *regs = (self.frame as &ExtendedInterruptStackFrameValue).into();
+        // SAFETY: the thread is parked until it is resumed
+        let frame = unsafe { self.frame(tid)?.as_ref() };
+        *regs = frame.into();
         Ok(())
     }
 
     fn write_registers(
         &mut self,
         regs: &<Self::Arch as Arch>::Registers,
+        tid: Tid,
     ) -> TargetResult<(), Self> {
-        self.frame.rax = regs.regs[0];
-        self.frame.rbx = regs.regs[1];
-        self.frame.rcx = regs.regs[2];
-        self.frame.rdx = regs.regs[3];
-        self.frame.rsi = regs.regs[4];
-        self.frame.rdi = regs.regs[5];
-        self.frame.rbp = regs.regs[6];
-        self.frame.stack_pointer = unsafe { VirtAddr::new_unsafe(regs.regs[7]) };
-        self.frame.r8 = regs.regs[8];
-        self.frame.r9 = regs.regs[9];
-        self.frame.r10 = regs.regs[10];
-        self.frame.r11 = regs.regs[11];
-        self.frame.r12 = regs.regs[12];
-        self.frame.r13 = regs.regs[13];
-        self.frame.r14 = regs.regs[14];
-        self.frame.r15 = regs.regs[15];
-        self.frame.instruction_pointer = unsafe { VirtAddr::new_unsafe(regs.rip) };
-        self.frame.cpu_flags &= 0xFFFF_FFFF_0000_0000;
-        self.frame.cpu_flags |= regs.eflags as u64;
+        // SAFETY: the thread is parked until it is resumed
+        let frame = unsafe { self.frame(tid)?.as_mut() };
+        frame.rax = regs.regs[0];
+        frame.rbx = regs.regs[1];
+        frame.rcx = regs.regs[2];
+        frame.rdx = regs.regs[3];
+        frame.rsi = regs.regs[4];
+        frame.rdi = regs.regs[5];
+        frame.rbp = regs.regs[6];
+        frame.stack_pointer = unsafe { VirtAddr::new_unsafe(regs.regs[7]) };
+        frame.r8 = regs.regs[8];
+        frame.r9 = regs.regs[9];
+        frame.r10 = regs.regs[10];
+        frame.r11 = regs.regs[11];
+        frame.r12 = regs.regs[12];
+        frame.r13 = regs.regs[13];
+        frame.r14 = regs.regs[14];
+        frame.r15 = regs.regs[15];
+        frame.instruction_pointer = unsafe { VirtAddr::new_unsafe(regs.rip) };
+        frame.cpu_flags &= 0xFFFF_FFFF_0000_0000;
+        frame.cpu_flags |= regs.eflags as u64;
         Ok(())
     }
 
@@ -174,51 +341,286 @@ impl SingleThreadOps for GdbTarget<'_> {
         &mut self,
         start_addr: <Self::Arch as Arch>::Usize,
         data: &mut [u8],
+        _tid: Tid,
     ) -> TargetResult<(), Self> {
-        let ptr = start_addr as *const u8;
-
-        let _phys = SHIM_PAGETABLE
-            .read()
-            .translate_addr(VirtAddr::from_ptr(ptr))
-            .ok_or(TargetError::NonFatal)?;
-        let _phys_end = SHIM_PAGETABLE
-            .read()
-            .translate_addr(VirtAddr::from_ptr(unsafe { ptr.add(data.len()) }))
-            .ok_or(TargetError::NonFatal)?;
-
-        //eprintln!("read_addrs: {:?} size {}", ptr, data.len());
-        let src = unsafe { core::slice::from_raw_parts(ptr, data.len()) };
-        data.copy_from_slice(src);
-        Ok(())
+        read_addrs(start_addr, data)
     }
 
     fn write_addrs(
         &mut self,
         start_addr: <Self::Arch as Arch>::Usize,
         data: &[u8],
+        _tid: Tid,
     ) -> TargetResult<(), Self> {
-        let ptr = start_addr as *mut u8;
-
-        let _phys = SHIM_PAGETABLE
-            .read()
-            .translate_addr(VirtAddr::from_ptr(ptr))
-            .ok_or(TargetError::Fatal(GdbTargetError::WriteMemoryOutOfRange(
-                start_addr,
-            )))?;
-        let _phys_end = SHIM_PAGETABLE
-            .read()
-            .translate_addr(VirtAddr::from_ptr(unsafe { ptr.add(data.len()) }))
-            .ok_or(TargetError::Fatal(GdbTargetError::WriteMemoryOutOfRange(
-                start_addr,
-            )))?;
-
-        //eprintln!("write_addrs: {:?} size {}", ptr, data.len());
-        let dst = unsafe { core::slice::from_raw_parts_mut(ptr, data.len()) };
-        dst.copy_from_slice(data);
+        write_addrs(start_addr, data)
+    }
+
+    fn list_active_threads(
+        &mut self,
+        thread_is_active: &mut dyn FnMut(Tid),
+    ) -> Result<(), Self::Error> {
+        // `gdbstub` picks the first thread, if GDB asks for any thread.
+        thread_is_active(gdb_tid(self.focus));
+        PARKED
+            .lock()
+            .keys()
+            .filter(|&&tid| tid != self.focus)
+            .for_each(|&tid| thread_is_active(gdb_tid(tid)));
         Ok(())
     }
 }
 
+impl Breakpoints for GdbTarget {
+    fn sw_breakpoint(&mut self) -> Option<SwBreakpointOps<'_, Self>> {
+        Some(self)
+    }
+
+    // Under SEV-SNP, writes to DR7 are intercepted with a #VC exception the shim cannot
+    // handle, so GDB is told there are no hardware breakpoints or watchpoints.
+    fn hw_breakpoint(&mut self) -> Option<HwBreakpointOps<'_, Self>> {
+        (!snp_active()).then_some(self)
+    }
+
+    fn hw_watchpoint(&mut self) -> Option<HwWatchpointOps<'_, Self>> {
+        (!snp_active()).then_some(self)
+    }
+}
+
+impl SwBreakpoint for GdbTarget {
+    fn add_sw_breakpoint(
+        &mut self,
+        addr: <Self::Arch as Arch>::Usize,
+        _kind: <Self::Arch as Arch>::BreakpointKind,
+    ) -> TargetResult<bool, Self> {
+        if let Entry::Vacant(entry) = BREAKPOINTS.lock().entry(addr) {
+            let mut orig = [0];
+            read_addrs(addr, &mut orig)?;
+            write_addrs(addr, &[INT3])?;
+            entry.insert(orig[0]);
+        }
+        Ok(true)
+    }
+
+    fn remove_sw_breakpoint(
+        &mut self,
+        addr: <Self::Arch as Arch>::Usize,
+        _kind: <Self::Arch as Arch>::BreakpointKind,
+    ) -> TargetResult<bool, Self> {
+        match BREAKPOINTS.lock().remove(&addr) {
+            Some(orig) => write_addrs(addr, &[orig]).map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
+
+impl HwBreakpoint for GdbTarget {
+    fn add_hw_breakpoint(
+        &mut self,
+        addr: <Self::Arch as Arch>::Usize,
+        _kind: <Self::Arch as Arch>::BreakpointKind,
+    ) -> TargetResult<bool, Self> {
+        Ok(add_hw_slot(HwSlot {
+            addr,
+            kind: None,
+            size: BreakpointSize::Length1B,
+        }))
+    }
+
+    fn remove_hw_breakpoint(
+        &mut self,
+        addr: <Self::Arch as Arch>::Usize,
+        _kind: <Self::Arch as Arch>::BreakpointKind,
+    ) -> TargetResult<bool, Self> {
+        Ok(remove_hw_slot(HwSlot {
+            addr,
+            kind: None,
+            size: BreakpointSize::Length1B,
+        }))
+    }
+}
+
+impl HwWatchpoint for GdbTarget {
+    fn add_hw_watchpoint(
+        &mut self,
+        addr: <Self::Arch as Arch>::Usize,
+        kind: WatchKind,
+    ) -> TargetResult<bool, Self> {
+        Ok(add_hw_slot(watch_slot(addr, kind)))
+    }
+
+    fn remove_hw_watchpoint(
+        &mut self,
+        addr: <Self::Arch as Arch>::Usize,
+        kind: WatchKind,
+    ) -> TargetResult<bool, Self> {
+        Ok(remove_hw_slot(watch_slot(addr, kind)))
+    }
+}
+
+/// Returns the slot watching the naturally aligned word of up to 8 bytes at `addr`.
+///
+/// `gdbstub` does not pass on the length of the watched memory.
+fn watch_slot(addr: u64, kind: WatchKind) -> HwSlot {
+    let size = match addr.trailing_zeros() {
+        0 => BreakpointSize::Length1B,
+        1 => BreakpointSize::Length2B,
+        2 => BreakpointSize::Length4B,
+        _ => BreakpointSize::Length8B,
+    };
+    HwSlot {
+        addr,
+        kind: Some(kind),
+        size,
+    }
+}
+
+fn add_hw_slot(slot: HwSlot) -> bool {
+    if snp_active() {
+        return false;
+    }
+
+    let mut slots = HW_SLOTS.lock();
+    if slots.contains(&Some(slot)) {
+        return true;
+    }
+    match slots.iter_mut().find(|s| s.is_none()) {
+        Some(free) => {
+            *free = Some(slot);
+            HW_GENERATION.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+fn remove_hw_slot(slot: HwSlot) -> bool {
+    let mut slots = HW_SLOTS.lock();
+    match slots.iter_mut().find(|s| **s == Some(slot)) {
+        Some(used) => {
+            *used = None;
+            HW_GENERATION.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Loads the hardware breakpoints and watchpoints into the debug registers of the current CPU.
+///
+/// Does nothing under SEV-SNP, where the debug registers are never used.
+fn load_debug_registers() {
+    if snp_active() {
+        return;
+    }
+
+    let slots = HW_SLOTS.lock();
+    let mut dr7 = Dr7Value::from_bits_truncate(0);
+
+    for (n, slot) in slots.iter().enumerate() {
+        let num = DebugAddressRegisterNumber::new(n as u8).unwrap();
+        let addr = slot.map_or(0, |slot| slot.addr);
+        match n {
+            0 => Dr0::write(addr),
+            1 => Dr1::write(addr),
+            2 => Dr2::write(addr),
+            _ => Dr3::write(addr),
+        }
+
+        if let Some(slot) = slot {
+            let condition = match slot.kind {
+                None => BreakpointCondition::InstructionExecution,
+                Some(WatchKind::Write) => BreakpointCondition::DataWrites,
+                Some(WatchKind::Read | WatchKind::ReadWrite) => {
+                    BreakpointCondition::DataReadsWrites
+                }
+            };
+            dr7.set_condition(num, condition);
+            dr7.set_size(num, slot.size);
+            dr7.insert_flags(Dr7Flags::local_breakpoint_enable(num));
+        }
+    }
+
+    Dr7::write(dr7);
+}
+
+/// Reloads the debug registers of the current CPU, if GDB changed the hardware breakpoints or
+/// watchpoints since `generation`.
+pub(crate) fn sync_debug_registers(generation: &mut u64) {
+    let current = HW_GENERATION.load(Ordering::SeqCst);
+    if *generation != current {
+        load_debug_registers();
+        *generation = current;
+    }
+}
+
+/// Returns the reason of the current debug exception and resets DR6.
+pub(crate) fn debug_stop() -> Stop {
+    let dr6 = Dr6::read();
+
+    // DR6 is never cleared by the CPU.
+    unsafe {
+        asm!("mov dr6, {}", in(reg) 0xFFFF_0FF0u64, options(nomem, nostack, preserves_flags))
+    };
+
+    let slots = HW_SLOTS.lock();
+    let hit = (0..4u8).find_map(|n| {
+        let num = DebugAddressRegisterNumber::new(n).unwrap();
+        dr6.contains(Dr6Flags::trap(num))
+            .then(|| slots[n as usize])
+            .flatten()
+    });
+
+    match hit {
+        Some(HwSlot {
+            addr,
+            kind: Some(kind),
+            ..
+        }) => Stop::Watchpoint { kind, addr },
+        Some(HwSlot { kind: None, .. }) => Stop::HwBreakpoint,
+        None => Stop::Step,
+    }
+}
+
+fn read_addrs(start_addr: u64, data: &mut [u8]) -> TargetResult<(), GdbTarget> {
+    let ptr = start_addr as *const u8;
+
+    let _phys = SHIM_PAGETABLE
+        .read()
+        .translate_addr(VirtAddr::from_ptr(ptr))
+        .ok_or(TargetError::NonFatal)?;
+    let _phys_end = SHIM_PAGETABLE
+        .read()
+        .translate_addr(VirtAddr::from_ptr(unsafe { ptr.add(data.len()) }))
+        .ok_or(TargetError::NonFatal)?;
+
+    //eprintln!("read_addrs: {:?} size {}", ptr, data.len());
+    let src = unsafe { core::slice::from_raw_parts(ptr, data.len()) };
+    data.copy_from_slice(src);
+    Ok(())
+}
+
+fn write_addrs(start_addr: u64, data: &[u8]) -> TargetResult<(), GdbTarget> {
+    let ptr = start_addr as *mut u8;
+
+    let _phys = SHIM_PAGETABLE
+        .read()
+        .translate_addr(VirtAddr::from_ptr(ptr))
+        .ok_or(TargetError::Fatal(GdbTargetError::WriteMemoryOutOfRange(
+            start_addr,
+        )))?;
+    let _phys_end = SHIM_PAGETABLE
+        .read()
+        .translate_addr(VirtAddr::from_ptr(unsafe { ptr.add(data.len()) }))
+        .ok_or(TargetError::Fatal(GdbTargetError::WriteMemoryOutOfRange(
+            start_addr,
+        )))?;
+
+    //eprintln!("write_addrs: {:?} size {}", ptr, data.len());
+    let dst = unsafe { core::slice::from_raw_parts_mut(ptr, data.len()) };
+    dst.copy_from_slice(data);
+    Ok(())
+}
+
 impl From<&ExtendedInterruptStackFrameValue> for X86_64CoreRegs {
     fn from(frame: &ExtendedInterruptStackFrameValue) -> Self {
         let mut mxcsr: u32 = 0;
@@ -254,18 +656,83 @@ impl From<&ExtendedInterruptStackFrameValue> for X86_64CoreRegs {
     }
 }
 
-pub(crate) fn gdb_session(stack_frame: &mut ExtendedInterruptStackFrameValue) {
+/// Returns the TID of the thread interrupted with `frame`.
+fn current_tid(frame: &ExtendedInterruptStackFrameValue) -> pid_t {
+    // Unlike syscalls, exceptions from user mode do not swap in the GS base of the TCB.
+    let user = frame.code_segment & 3 == 3;
+    if user {
+        unsafe { asm!("swapgs", options(nostack, preserves_flags)) };
+    }
+    let tid = HostCall::maint().get_tid();
+    if user {
+        unsafe { asm!("swapgs", options(nostack, preserves_flags)) };
+    }
+    tid
+}
+
+/// Parks the current thread, which stopped for `stop`, until GDB resumes it.
+///
+/// Only one thread at a time drives the GDB session. GDB sees all parked threads as stopped and
+/// can inspect and resume each of them. Threads, which did not stop, keep running, because there
+/// is no way to interrupt them, and are not listed.
+pub(crate) fn gdb_session(stack_frame: &mut ExtendedInterruptStackFrameValue, stop: Stop) {
+    let tid = current_tid(stack_frame);
+
+    match stop {
+        Stop::HwBreakpoint => eprintln!("[{tid}] Hardware breakpoint"),
+        Stop::Watchpoint { kind, addr } => eprintln!("[{tid}] Watchpoint {kind:?} {addr:#x}"),
+        _ => {}
+    }
+
     let regs: X86_64CoreRegs = (stack_frame as &ExtendedInterruptStackFrameValue).into();
     regs.regs
         .iter()
         .enumerate()
-        .for_each(|(i, v)| eprintln!("r{i} = {v:#x}"));
+        .for_each(|(i, v)| eprintln!("[{tid}] r{i} = {v:#x}"));
+
+    PARKED.lock().insert(
+        tid,
+        Parked {
+            frame: NonNull::from(stack_frame),
+            stop,
+            reported: false,
+            resumed: false,
+        },
+    );
+
+    let resumed = || PARKED.lock().get(&tid).unwrap().resumed;
+    let session = loop {
+        if resumed() {
+            break None;
+        }
+        if let Some(session) = SESSION.try_lock() {
+            // Another session might have resumed the thread in the meantime.
+            break (!resumed()).then_some(session);
+        }
+        spin_loop();
+    };
 
-    let mut target = GdbTarget::new(stack_frame);
+    if let Some(_session) = session {
+        run_session(tid);
+    }
+
+    // GDB might have changed the hardware breakpoints or watchpoints while the thread was
+    // parked. The debug registers are per CPU, so every parked thread reloads its own, before
+    // it leaves the exception handler. Threads, which were not parked, reload theirs on their
+    // next syscall, see `sync_debug_registers()`.
+    load_debug_registers();
+
+    PARKED.lock().remove(&tid);
+}
+
+fn run_session(tid: pid_t) {
+    PARKED.lock().get_mut(&tid).unwrap().reported = true;
+
+    let mut target = GdbTarget::new(tid);
 
     let mut buf = [0; 4096];
 
-    eprintln!("Starting GDB session...");
+    eprintln!("[{tid}] Starting GDB session...");
 
     eprintln!("symbol-file -o {SHIM_VIRT_OFFSET:#x} <shim>");
 
@@ -312,4 +779,11 @@ pub(crate) fn gdb_session(stack_frame: &mut ExtendedInterruptStackFrameValue) {
             },
         };
     }
+
+    // Don't leave the threads GDB knows of parked, if the session ended without resuming them.
+    PARKED
+        .lock()
+        .values_mut()
+        .filter(|thread| thread.reported)
+        .for_each(|thread| thread.resumed = true);
 }
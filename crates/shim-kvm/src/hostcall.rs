@@ -5,6 +5,7 @@
 use crate::addr::ShimPhysUnencryptedAddr;
 use crate::addr::TranslateFrom;
 use crate::allocator::{PageTableAllocatorLock, ZERO_PAGE_FRAME};
+use crate::clock;
 use crate::debug::_enarx_asm_triple_fault;
 use crate::exec::{BRK_LINE, NEXT_MMAP_RWLOCK};
 use crate::paging::SHIM_PAGETABLE;
@@ -29,12 +30,12 @@ use core::sync::atomic::{AtomicU32, Ordering};
 
 use const_default::ConstDefault;
 use sallyport::guest::syscall::types::MremapFlags;
-use sallyport::guest::{Handler, Platform, ThreadLocalStorage};
+use sallyport::guest::{signal, Handler, Platform, ThreadLocalStorage};
 use sallyport::item::enarxcall::sev::TECH;
 use sallyport::item::syscall;
 use sallyport::libc::{
-    off_t, pid_t, CloneFlags, EFAULT, EINVAL, EIO, EMSGSIZE, ENOMEM, ENOTSUP, MAP_ANONYMOUS,
-    MAP_PRIVATE, PROT_EXEC, PROT_READ, PROT_WRITE,
+    clockid_t, off_t, pid_t, timespec, CloneFlags, CLOCK_MONOTONIC, EFAULT, EINVAL, EIO, EMSGSIZE,
+    ENOMEM, ENOTSUP, MAP_ANONYMOUS, MAP_PRIVATE, PROT_EXEC, PROT_READ, PROT_WRITE,
 };
 use sallyport::util::ptr::is_aligned_non_null;
 use sallyport::{libc, KVM_SYSCALL_TRIGGER_EXIT_THREAD, KVM_SYSCALL_TRIGGER_PORT};
@@ -55,6 +56,10 @@ pub const NUM_BLOCKS: usize = 2;
 
 const BLOCK_SIZE_USIZE: usize = BLOCK_SIZE / core::mem::size_of::<usize>();
 
+/// The index of the word of a sallyport block the host posts forwarded signals in,
+/// see [`sallyport::item::split_signal`]
+const SIGNAL_WORD: usize = BLOCK_SIZE_USIZE - 1;
+
 const SNP_VCEK_BUF_SIZE: usize = 4096;
 
 /// SNP VCEK buffer
@@ -224,7 +229,7 @@ impl<'a> HostCall<'a> {
     /// Get the thread id
     pub fn get_tid(&self) -> pid_t {
         match self {
-            HostCall::Maintenance(_) => TcbRefCell::from_gs_base().borrow_mut().tid,
+            HostCall::Maintenance(_) => TcbRefCell::from_gs_base().tid(),
             HostCall::Syscall(syscall) => syscall.tcb.tid,
         }
     }
@@ -367,22 +372,30 @@ impl Handler for HostCall<'_> {
             // prevent later reads from being moved before this point
             core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::Acquire);
         }
+
+        let block = match self {
+            HostCall::Maintenance(maint) => &mut maint.block.block.0,
+            HostCall::Syscall(syscall) => &mut syscall.tcb.block.block.0,
+        };
+        if signal::collect(&mut block[SIGNAL_WORD]) {
+            clock::resume();
+        }
         Ok(())
     }
 
     #[inline(always)]
     fn block(&self) -> &[usize] {
         match self {
-            HostCall::Maintenance(maint) => maint.block.block.0.as_ref(),
-            HostCall::Syscall(syscall) => syscall.tcb.block.block.0.as_ref(),
+            HostCall::Maintenance(maint) => &maint.block.block.0[..SIGNAL_WORD],
+            HostCall::Syscall(syscall) => &syscall.tcb.block.block.0[..SIGNAL_WORD],
         }
     }
 
     #[inline(always)]
     fn block_mut(&mut self) -> &mut [usize] {
         match self {
-            HostCall::Maintenance(maint) => maint.block.block.0.as_mut(),
-            HostCall::Syscall(syscall) => syscall.tcb.block.block.0.as_mut(),
+            HostCall::Maintenance(maint) => &mut maint.block.block.0[..SIGNAL_WORD],
+            HostCall::Syscall(syscall) => &mut syscall.tcb.block.block.0[..SIGNAL_WORD],
         }
     }
 
@@ -394,6 +407,18 @@ impl Handler for HostCall<'_> {
         }
     }
 
+    fn clock_gettime(&mut self, clockid: clockid_t, tp: &mut timespec) -> sallyport::Result<()> {
+        if clockid != CLOCK_MONOTONIC {
+            return self.clock_gettime_host(clockid, tp);
+        }
+        let (now, event) = clock::monotonic(|tp| self.clock_gettime_host(CLOCK_MONOTONIC, tp))?;
+        if let Some(event) = event {
+            eprintln!("clock: {event}");
+        }
+        *tp = now;
+        Ok(())
+    }
+
     fn clone(
         &mut self,
         flags: CloneFlags,
@@ -484,7 +509,7 @@ impl Handler for HostCall<'_> {
                 if let Some(addr) = tcb.clear_on_exit {
                     eprintln!("[{tid}] clear TID at {addr:p}");
                     addr.store(0, Ordering::SeqCst);
-                    let _ = self.unpark();
+                    let _ = self.unpark(addr as *const AtomicU32 as usize, u32::MAX);
                 } else {
                     eprintln!("[{tid}] no TID to clear");
                 }
@@ -366,7 +366,7 @@ declare_interrupt!(
 
             #[cfg(feature = "gdb")]
             unsafe {
-                crate::gdb::gdb_session(_stack_frame.as_mut());
+                crate::gdb::gdb_session(_stack_frame.as_mut(), crate::gdb::Stop::PageFault);
             }
 
             #[cfg(not(feature = "gdb"))]
@@ -511,7 +511,7 @@ mod debug {
 
             #[cfg(feature = "gdb")]
             unsafe {
-                crate::gdb::gdb_session(stack_frame.as_mut());
+                crate::gdb::gdb_session(stack_frame.as_mut(), crate::gdb::Stop::InvalidOpcode);
             }
 
             shim_exit(255);
@@ -536,7 +536,8 @@ mod debug {
 
             #[cfg(feature = "gdb")]
             unsafe {
-                crate::gdb::gdb_session(stack_frame.as_mut());
+                let stop = crate::gdb::debug_stop();
+                crate::gdb::gdb_session(stack_frame.as_mut(), stop);
             }
 
             // skip breakpoint
@@ -557,7 +558,7 @@ mod debug {
 
             #[cfg(feature = "gdb")]
             unsafe {
-                crate::gdb::gdb_session(stack_frame.as_mut());
+                crate::gdb::gdb_session(stack_frame.as_mut(), crate::gdb::Stop::Breakpoint);
             }
 
             #[cfg(not(feature = "gdb"))]
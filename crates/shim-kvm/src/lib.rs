@@ -36,6 +36,7 @@ cfg_if::cfg_if! {
 }
 pub mod addr;
 pub mod allocator;
+pub mod clock;
 pub mod debug;
 pub mod exec;
 pub mod gdb;
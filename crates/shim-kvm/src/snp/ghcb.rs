@@ -171,7 +171,7 @@ enum RmpPgSize {
 #[repr(C)]
 #[non_exhaustive]
 enum RmpPgOp {
-    // Private = 1,
+    Private = 1,
     Shared = 2,
     // PSmash,
     // UnSmash,
@@ -465,8 +465,6 @@ impl RwLocked<GhcbHandle<'_>> {
     /// turn physical pages to decrypted / shared
     #[cfg_attr(coverage, no_coverage)]
     pub fn set_memory_shared(&self, virt_addr: VirtAddr, npages: usize) {
-        const SVM_VMGEXIT_PSC: u64 = 0x80000010;
-
         (virt_addr.as_u64()
             ..(virt_addr + Page::<Size4KiB>::SIZE.checked_mul(npages as u64).unwrap()).as_u64())
             .step_by(Page::<Size4KiB>::SIZE as usize)
@@ -482,58 +480,95 @@ impl RwLocked<GhcbHandle<'_>> {
         )
         .unwrap();
 
-        let mut this = self.write();
+        self.page_state_change(virt_addr, npages, RmpPgOp::Shared);
+    }
+
+    /// turn private pages, which are not used anymore, to shared, so the host can release them
+    ///
+    /// The pages stay mapped encrypted and must not be accessed, until they are
+    /// [reclaimed](Self::reclaim_memory).
+    #[cfg_attr(coverage, no_coverage)]
+    pub fn release_memory(&self, virt_addr: VirtAddr, npages: usize) {
+        (0..npages as u64).for_each(|i| {
+            let virt = virt_addr + i.checked_mul(Page::<Size4KiB>::SIZE).unwrap();
+            pvalidate(virt, PvalidateSize::Size4K, false).unwrap();
+        });
 
-        // Fill in shared_buffer
-        // SnpPscDesc has the exact same size.
-        let psc_desc: &mut SnpPscDesc =
-            unsafe { &mut *(this.ghcb.shared_buffer.as_mut_ptr() as *mut SnpPscDesc) };
+        self.page_state_change(virt_addr, npages, RmpPgOp::Shared);
+    }
 
-        *psc_desc = <SnpPscDesc as ConstDefault>::DEFAULT;
+    /// turn pages released with [`release_memory`](Self::release_memory) back to private
+    #[cfg_attr(coverage, no_coverage)]
+    pub fn reclaim_memory(&self, virt_addr: VirtAddr, npages: usize) {
+        self.page_state_change(virt_addr, npages, RmpPgOp::Private);
 
-        // FIXME
-        assert!(psc_desc.entries.len() >= npages);
+        (0..npages as u64).for_each(|i| {
+            let virt = virt_addr + i.checked_mul(Page::<Size4KiB>::SIZE).unwrap();
+            pvalidate(virt, PvalidateSize::Size4K, true).unwrap();
+        });
+    }
 
-        psc_desc.cur_entry = 0;
-        psc_desc.end_entry = (npages as u16).checked_sub(1).unwrap();
+    /// change the page state of `npages` pages mapped at `virt_addr` in the shim
+    #[cfg_attr(coverage, no_coverage)]
+    fn page_state_change(&self, virt_addr: VirtAddr, npages: usize, op: RmpPgOp) {
+        const SVM_VMGEXIT_PSC: u64 = 0x80000010;
 
         let mut pa_addr = PhysAddr::new((virt_addr - SHIM_VIRT_OFFSET).as_u64());
+        let mut remaining = npages;
 
-        for i in 0..npages {
-            psc_desc.entries[i].set_entry(pa_addr.as_u64(), RmpPgOp::Shared, RmpPgSize::Size4k);
-            pa_addr += Page::<Size4KiB>::SIZE;
-        }
+        let mut this = self.write();
 
-        loop {
-            // Use `read_volatile` to be safe
-            let cur_entry = unsafe { ptr::addr_of!(psc_desc.cur_entry).read_volatile() };
-            let end_entry = unsafe { ptr::addr_of!(psc_desc.end_entry).read_volatile() };
+        while remaining > 0 {
+            // Fill in shared_buffer
+            // SnpPscDesc has the exact same size.
+            let psc_desc: &mut SnpPscDesc =
+                unsafe { &mut *(this.ghcb.shared_buffer.as_mut_ptr() as *mut SnpPscDesc) };
 
-            if cur_entry > end_entry {
-                break;
-            }
+            *psc_desc = <SnpPscDesc as ConstDefault>::DEFAULT;
 
-            this.invalidate();
+            let batch = remaining.min(psc_desc.entries.len());
 
-            let addr = ptr::addr_of!(this.ghcb.shared_buffer);
-            this.ghcb.save_area.sw_scratch = (VirtAddr::from_ptr(addr) - SHIM_VIRT_OFFSET).as_u64();
-            let offset: usize = ptr::addr_of!(this.ghcb.save_area.sw_scratch) as _;
-            this.set_offset_valid(offset);
+            psc_desc.cur_entry = 0;
+            psc_desc.end_entry = (batch as u16).checked_sub(1).unwrap();
 
-            unsafe {
-                if this.vmgexit(SVM_VMGEXIT_PSC, 0, 0).is_err() {
-                    crate::debug::_early_debug_panic(4, 0x33);
-                }
+            for entry in psc_desc.entries[..batch].iter_mut() {
+                entry.set_entry(pa_addr.as_u64(), op, RmpPgSize::Size4k);
+                pa_addr += Page::<Size4KiB>::SIZE;
             }
+            remaining = remaining.checked_sub(batch).unwrap();
 
-            if psc_desc.reserved != 0 {
-                unsafe {
-                    crate::debug::_early_debug_panic(4, 0x35);
+            loop {
+                // Use `read_volatile` to be safe
+                let cur_entry = unsafe { ptr::addr_of!(psc_desc.cur_entry).read_volatile() };
+                let end_entry = unsafe { ptr::addr_of!(psc_desc.end_entry).read_volatile() };
+
+                if cur_entry > end_entry {
+                    break;
                 }
-            }
-            if (psc_desc.end_entry > end_entry) || (cur_entry > psc_desc.cur_entry) {
+
+                this.invalidate();
+
+                let addr = ptr::addr_of!(this.ghcb.shared_buffer);
+                this.ghcb.save_area.sw_scratch =
+                    (VirtAddr::from_ptr(addr) - SHIM_VIRT_OFFSET).as_u64();
+                let offset: usize = ptr::addr_of!(this.ghcb.save_area.sw_scratch) as _;
+                this.set_offset_valid(offset);
+
                 unsafe {
-                    crate::debug::_early_debug_panic(4, 0x36);
+                    if this.vmgexit(SVM_VMGEXIT_PSC, 0, 0).is_err() {
+                        crate::debug::_early_debug_panic(4, 0x33);
+                    }
+                }
+
+                if psc_desc.reserved != 0 {
+                    unsafe {
+                        crate::debug::_early_debug_panic(4, 0x35);
+                    }
+                }
+                if (psc_desc.end_entry > end_entry) || (cur_entry > psc_desc.cur_entry) {
+                    unsafe {
+                        crate::debug::_early_debug_panic(4, 0x36);
+                    }
                 }
             }
         }
@@ -167,6 +167,10 @@ extern "sysv64" fn syscall_rust(
 ) -> X8664DoubleReturn {
     let orig_rdx: usize = c;
     let mut tcb = TcbRefCell::from_gs_base().borrow_mut();
+
+    #[cfg(feature = "gdb")]
+    crate::gdb::sync_debug_registers(&mut tcb.debug_generation);
+
     let mut h = HostCall::syscall(&mut tcb, frame);
 
     #[cfg(feature = "dbg")]
@@ -10,7 +10,7 @@ use core::arch::asm;
 use core::cell::{Cell, UnsafeCell};
 use core::marker::PhantomData;
 use core::ops::{Deref, DerefMut};
-use core::ptr::NonNull;
+use core::ptr::{self, NonNull};
 use core::sync::atomic::{AtomicI32, AtomicU32, AtomicUsize, Ordering};
 
 use const_default::ConstDefault;
@@ -155,6 +155,9 @@ pub struct Tcb {
     pub tls: ThreadLocalStorage,
     /// sallyport block,
     pub block: BlockGuard,
+    /// Generation of the hardware breakpoints loaded into the debug registers
+    #[cfg(feature = "gdb")]
+    pub debug_generation: u64,
 }
 
 /// RefCell<Tcb> variant that is stored in the gsbase
@@ -248,6 +251,8 @@ impl TcbRefCell {
                 clear_on_exit: None,
                 tls: Default::default(),
                 block,
+                #[cfg(feature = "gdb")]
+                debug_generation: 0,
             }),
             borrow: Cell::new(UNUSED),
         }
@@ -271,6 +276,15 @@ impl TcbRefCell {
         }
     }
 
+    /// Get the thread id
+    ///
+    /// Unlike [`borrow_mut`](Self::borrow_mut), this does not panic if the TCB is borrowed by a
+    /// syscall, which an exception handler interrupted.
+    pub fn tid(&self) -> pid_t {
+        // SAFETY: the TCB is per cpu and `tid` is only written outside of exception handlers.
+        unsafe { ptr::addr_of!((*self.value.get()).tid).read() }
+    }
+
     /// Get a mutable reference to the CPU local Tcb.
     pub fn from_gs_base() -> &'static TcbRefCell {
         let base = GS::read_base();
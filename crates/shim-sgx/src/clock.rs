@@ -0,0 +1,46 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! The monotonic clock of the enclave, see [`TscClock`]
+//!
+//! `rdtsc` is allowed in SGX2 enclaves, which the shim requires anyway. There is no trusted TSC
+//! frequency in SGX, so the clock is calibrated against the host clock.
+
+use core::arch::x86_64::_rdtsc;
+
+use sallyport::libc::timespec;
+use shared::no_std::clock::{self, Event, TscClock, NANOS_PER_SEC};
+use spin::Mutex;
+
+static CLOCK: Mutex<TscClock> = Mutex::new(TscClock::new(None));
+
+/// Anchors the clock again, after the keep was resumed from a snapshot.
+pub fn resume() {
+    CLOCK.lock().resume();
+}
+
+/// Returns the monotonic time, asking `host` for the monotonic time of the host,
+/// as long as the clock is not calibrated, and periodically to cross-check it.
+///
+/// Also returns the discrepancy to the host clock found by a cross-check, if any.
+pub fn monotonic(
+    host: impl FnOnce(&mut timespec) -> sallyport::Result<()>,
+) -> sallyport::Result<(timespec, Option<Event>)> {
+    let host = || -> sallyport::Result<u64> {
+        let mut tp = timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        host(&mut tp)?;
+        Ok((tp.tv_sec as u64)
+            .saturating_mul(NANOS_PER_SEC)
+            .saturating_add(tp.tv_nsec as u64))
+    };
+
+    // Safety: `rdtsc` has no side effects.
+    let (now, event) = clock::monotonic(&CLOCK, || unsafe { _rdtsc() }, host)?;
+    let tp = timespec {
+        tv_sec: (now / NANOS_PER_SEC) as _,
+        tv_nsec: (now % NANOS_PER_SEC) as _,
+    };
+    Ok((tp, event))
+}
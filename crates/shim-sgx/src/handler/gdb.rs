@@ -1,80 +1,253 @@
 // SPDX-License-Identifier: Apache-2.0
 
+//! GDB debugging
+//!
+//! The stub exposes every thread stopped in an exception handler as a GDB thread.
+
 #![cfg(feature = "gdb")]
 
 use core::arch::asm;
 use core::ffi::c_int;
+use core::hint::spin_loop;
 use core::mem::size_of;
 use core::ops::Range;
+use core::ptr::NonNull;
 
 use crate::handler::HEAP;
 use crate::heap::Access;
 use crate::{shim_address, BLOCK_SIZE, ENARX_EXEC_START};
 
 use gdbstub::arch::Arch;
-use gdbstub::target::ext::base::singlethread::SingleThreadOps;
-use gdbstub::target::ext::base::singlethread::{GdbInterrupt, ResumeAction, StopReason};
+use gdbstub::common::Tid;
+use gdbstub::target::ext::base::multithread::{
+    GdbInterrupt, MultiThreadOps, ResumeAction, ThreadStopReason,
+};
 use gdbstub::target::ext::base::BaseOps;
+use gdbstub::target::ext::breakpoints::{
+    Breakpoints, BreakpointsOps, SwBreakpoint, SwBreakpointOps,
+};
 use gdbstub::target::{Target, TargetError, TargetResult};
 use gdbstub::Connection;
 use gdbstub_arch::x86::reg::X86_64CoreRegs;
 use primordial::{Address, Offset, Page};
 use sallyport::guest::Handler;
+use sallyport::libc::pid_t;
 use sgx::ssa::StateSaveArea;
+use spin::Mutex;
 use x86_64::registers::rflags::RFlags;
 
+/// The reason a thread entered a GDB session
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Stop {
+    /// The thread executed `int3`
+    Breakpoint,
+    /// The thread accessed unmapped memory
+    PageFault,
+    /// The thread executed an invalid instruction
+    InvalidOpcode,
+}
+
+/// A thread parked in an exception handler
+#[derive(Clone, Copy)]
+struct Parked {
+    tid: pid_t,
+    ssa: NonNull<StateSaveArea>,
+    stop: Stop,
+    /// GDB was told about the stop
+    reported: bool,
+    /// GDB resumed the thread
+    resumed: bool,
+}
+
+/// The maximum number of threads parked at the same time
+const MAX_PARKED: usize = 16;
+
+/// The threads parked in an exception handler
+struct ParkedThreads([Option<Parked>; MAX_PARKED]);
+
+// SAFETY: the SSAs are only accessed by the thread driving the GDB session, while their threads are parked.
+unsafe impl Send for ParkedThreads {}
+
+impl ParkedThreads {
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut Parked> {
+        self.0.iter_mut().flatten()
+    }
+
+    fn get(&mut self, tid: pid_t) -> Option<&mut Parked> {
+        self.iter_mut().find(|thread| thread.tid == tid)
+    }
+
+    /// Parks `thread`, unless all slots are taken.
+    fn park(&mut self, thread: Parked) -> bool {
+        match self.0.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(thread);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn unpark(&mut self, tid: pid_t) {
+        if let Some(slot) = self
+            .0
+            .iter_mut()
+            .find(|slot| matches!(slot, Some(thread) if thread.tid == tid))
+        {
+            *slot = None;
+        }
+    }
+}
+
+static PARKED: Mutex<ParkedThreads> = Mutex::new(ParkedThreads([None; MAX_PARKED]));
+
+/// Held by the thread driving the GDB session
+static SESSION: Mutex<()> = Mutex::new(());
+
+/// The maximum number of software breakpoints
+const MAX_BREAKPOINTS: usize = 64;
+
+/// The addresses and original bytes of the software breakpoints
+static BREAKPOINTS: Mutex<[Option<(u64, u8)>; MAX_BREAKPOINTS]> =
+    Mutex::new([None; MAX_BREAKPOINTS]);
+
+/// `gdbstub` selects thread 1 at the start of every session, so it stands for the thread GDB
+/// was told about last and the TIDs of the keep are numbered from 2.
+const FOCUS: Tid = unsafe { Tid::new_unchecked(1) };
+
+const SIGILL: u8 = 4;
+const SIGSEGV: u8 = 11;
+
+const INT3: u8 = 0xCC;
+
+fn gdb_tid(tid: pid_t) -> Tid {
+    Tid::new(tid as usize + 2).unwrap()
+}
+
+fn registers(ssa: &StateSaveArea) -> X86_64CoreRegs {
+    let mut mxcsr: u32 = 0;
+    unsafe { asm!("stmxcsr [{}]", in(reg) &mut mxcsr, options(nostack)) };
+
+    X86_64CoreRegs {
+        /// RAX, RBX, RCX, RDX, RSI, RDI, RBP, RSP, r8-r15
+        regs: [
+            ssa.gpr.rax,
+            ssa.gpr.rbx,
+            ssa.gpr.rcx,
+            ssa.gpr.rdx,
+            ssa.gpr.rsi,
+            ssa.gpr.rdi,
+            ssa.gpr.rbp,
+            ssa.gpr.rsp,
+            ssa.gpr.r8,
+            ssa.gpr.r9,
+            ssa.gpr.r10,
+            ssa.gpr.r11,
+            ssa.gpr.r12,
+            ssa.gpr.r13,
+            ssa.gpr.r14,
+            ssa.gpr.r15,
+        ],
+        eflags: ssa.gpr.rflags as u32,
+        rip: ssa.gpr.rip,
+        segments: Default::default(),
+        /// FPU registers: ST0 through ST7
+        st: Default::default(),
+        fpu: Default::default(),
+        xmm: Default::default(),
+        mxcsr,
+    }
+}
+
+fn set_registers(ssa: &mut StateSaveArea, regs: &X86_64CoreRegs) {
+    ssa.gpr.rax = regs.regs[0];
+    ssa.gpr.rbx = regs.regs[1];
+    ssa.gpr.rcx = regs.regs[2];
+    ssa.gpr.rdx = regs.regs[3];
+    ssa.gpr.rsi = regs.regs[4];
+    ssa.gpr.rdi = regs.regs[5];
+    ssa.gpr.rbp = regs.regs[6];
+    ssa.gpr.rsp = regs.regs[7];
+    ssa.gpr.r8 = regs.regs[8];
+    ssa.gpr.r9 = regs.regs[9];
+    ssa.gpr.r10 = regs.regs[10];
+    ssa.gpr.r11 = regs.regs[11];
+    ssa.gpr.r12 = regs.regs[12];
+    ssa.gpr.r13 = regs.regs[13];
+    ssa.gpr.r14 = regs.regs[14];
+    ssa.gpr.r15 = regs.regs[15];
+    ssa.gpr.rip = regs.rip;
+    ssa.gpr.rflags &= 0xFFFF_FFFF_0000_0000;
+    ssa.gpr.rflags |= regs.eflags as u64;
+}
+
 impl<'a> super::Handler<'a> {
-    pub(crate) fn gdb_session(&mut self) {
-        use gdbstub::{DisconnectReason, GdbStubBuilder, GdbStubError};
+    /// Parks the current thread, which stopped for `stop`, until GDB resumes it.
+    ///
+    /// Only one thread at a time drives the GDB session. GDB sees all parked threads as stopped
+    /// and can inspect and resume each of them. Threads, which did not stop, keep running,
+    /// because there is no way to interrupt them, and are not listed.
+    pub(crate) fn gdb_session(&mut self, stop: Stop) {
+        let tid = self.tcb.tid;
+
+        let regs = registers(self.ssa);
+        regs.regs.iter().enumerate().for_each(|(i, v)| {
+            debugln!(self, "[{}] r{} = {:#x}", tid, i, v);
+        });
+
+        debugln!(self, "[{}] rip = {:#x}", tid, regs.rip);
 
-        let mut mxcsr: u32 = 0;
-        unsafe { asm!("stmxcsr [{}]", in(reg) &mut mxcsr, options(nostack)) };
-
-        let regs = X86_64CoreRegs {
-            /// RAX, RBX, RCX, RDX, RSI, RDI, RBP, RSP, r8-r15
-            regs: [
-                self.ssa.gpr.rax,
-                self.ssa.gpr.rbx,
-                self.ssa.gpr.rcx,
-                self.ssa.gpr.rdx,
-                self.ssa.gpr.rsi,
-                self.ssa.gpr.rdi,
-                self.ssa.gpr.rbp,
-                self.ssa.gpr.rsp,
-                self.ssa.gpr.r8,
-                self.ssa.gpr.r9,
-                self.ssa.gpr.r10,
-                self.ssa.gpr.r11,
-                self.ssa.gpr.r12,
-                self.ssa.gpr.r13,
-                self.ssa.gpr.r14,
-                self.ssa.gpr.r15,
-            ],
-            eflags: self.ssa.gpr.rflags as u32,
-            rip: self.ssa.gpr.rip,
-            segments: Default::default(),
-            /// FPU registers: ST0 through ST7
-            st: Default::default(),
-            fpu: Default::default(),
-            xmm: Default::default(),
-            mxcsr,
+        let thread = Parked {
+            tid,
+            ssa: NonNull::from(&mut *self.ssa),
+            stop,
+            reported: false,
+            resumed: false,
+        };
+        while !PARKED.lock().park(thread) {
+            spin_loop();
+        }
+
+        let resumed = || PARKED.lock().get(tid).unwrap().resumed;
+        let session = loop {
+            if resumed() {
+                break None;
+            }
+            if let Some(session) = SESSION.try_lock() {
+                // Another session might have resumed the thread in the meantime.
+                break (!resumed()).then_some(session);
+            }
+            spin_loop();
         };
 
+        if let Some(_session) = session {
+            self.run_session(tid);
+        }
+
+        PARKED.lock().unpark(tid);
+
+        let regs = registers(self.ssa);
         regs.regs.iter().enumerate().for_each(|(i, v)| {
-            debugln!(self, "r{} = {:#x}", i, v);
+            debugln!(self, "[{}] r{} = {:#x}", tid, i, v);
         });
 
-        debugln!(self, "rip = {:#x}", regs.rip);
+        debugln!(self, "[{}] rip = {:#x}", tid, regs.rip);
+    }
+
+    fn run_session(&mut self, tid: pid_t) {
+        use gdbstub::{DisconnectReason, GdbStubBuilder, GdbStubError};
+
+        PARKED.lock().get(tid).unwrap().reported = true;
 
         let block_start = self.block.as_ptr() as usize;
         let block_range = block_start..block_start + BLOCK_SIZE;
         let ssa_start = self.ssa as *const _ as usize;
         let ssa_range = ssa_start..ssa_start + size_of::<StateSaveArea>();
 
-        let mut target = GdbTarget::new(regs, block_range, ssa_range);
+        let mut target = GdbTarget::new(tid, block_range, ssa_range);
 
         let mut buf = [0; 4096];
-        debugln!(self, "Starting GDB session...");
+        debugln!(self, "[{}] Starting GDB session...", tid);
         debugln!(self, "symbol-file -o {:#x} <shim>", shim_address());
         debugln!(self, "add-symbol-file -o {:#x} <exec>", unsafe {
             &ENARX_EXEC_START as *const u8 as u64
@@ -120,32 +293,12 @@ impl<'a> super::Handler<'a> {
             };
         }
 
-        target.regs.regs.iter().enumerate().for_each(|(i, v)| {
-            debugln!(self, "r{} = {:#x}", i, v);
-        });
-
-        debugln!(self, "rip = {:#x}", target.regs.rip);
-
-        // update the registers
-        self.ssa.gpr.rax = target.regs.regs[0];
-        self.ssa.gpr.rbx = target.regs.regs[1];
-        self.ssa.gpr.rcx = target.regs.regs[2];
-        self.ssa.gpr.rdx = target.regs.regs[3];
-        self.ssa.gpr.rsi = target.regs.regs[4];
-        self.ssa.gpr.rdi = target.regs.regs[5];
-        self.ssa.gpr.rbp = target.regs.regs[6];
-        self.ssa.gpr.rip = target.regs.regs[7];
-        self.ssa.gpr.r8 = target.regs.regs[8];
-        self.ssa.gpr.r9 = target.regs.regs[9];
-        self.ssa.gpr.r10 = target.regs.regs[10];
-        self.ssa.gpr.r11 = target.regs.regs[11];
-        self.ssa.gpr.r12 = target.regs.regs[12];
-        self.ssa.gpr.r13 = target.regs.regs[13];
-        self.ssa.gpr.r14 = target.regs.regs[14];
-        self.ssa.gpr.r15 = target.regs.regs[15];
-        self.ssa.gpr.rip = target.regs.rip;
-        self.ssa.gpr.rflags &= 0xFFFF_FFFF_0000_0000;
-        self.ssa.gpr.rflags |= target.regs.eflags as u64;
+        // Don't leave the threads GDB knows of parked, if the session ended without resuming them.
+        PARKED
+            .lock()
+            .iter_mut()
+            .filter(|thread| thread.reported)
+            .for_each(|thread| thread.resumed = true);
     }
 }
 
@@ -188,19 +341,100 @@ impl<'a> gdbstub::Connection for super::Handler<'a> {
 
 #[derive(Debug)]
 pub(crate) struct GdbTarget {
-    regs: X86_64CoreRegs,
+    /// The thread driving the session
+    tid: pid_t,
+    /// The thread GDB was told about last
+    focus: pid_t,
+    /// The resume actions by thread
+    actions: [Option<(pid_t, ResumeAction)>; MAX_PARKED],
     block_range: Range<usize>,
     ssa_range: Range<usize>,
 }
 
 impl GdbTarget {
-    pub fn new(regs: X86_64CoreRegs, block_range: Range<usize>, ssa_range: Range<usize>) -> Self {
+    pub fn new(tid: pid_t, block_range: Range<usize>, ssa_range: Range<usize>) -> Self {
         Self {
-            regs,
+            tid,
+            focus: tid,
+            actions: [None; MAX_PARKED],
             block_range,
             ssa_range,
         }
     }
+
+    fn thread(&self, tid: Tid) -> pid_t {
+        if tid == FOCUS {
+            self.focus
+        } else {
+            (tid.get() - 2) as pid_t
+        }
+    }
+
+    /// Returns the SSA of a parked thread.
+    fn ssa(&self, tid: Tid) -> TargetResult<NonNull<StateSaveArea>, Self> {
+        PARKED
+            .lock()
+            .get(self.thread(tid))
+            .map(|thread| thread.ssa)
+            .ok_or(TargetError::NonFatal)
+    }
+
+    fn read(&self, start_addr: u64, data: &mut [u8]) -> TargetResult<(), Self> {
+        let start_addr = start_addr as usize;
+        let end_addr = start_addr
+            .checked_add(data.len())
+            .ok_or(TargetError::NonFatal)?;
+        let length = Offset::from_items((end_addr - start_addr) / Page::SIZE);
+        let heap = HEAP.read();
+        let readable = heap
+            .contains(Address::new(start_addr), length)
+            .map_or(false, |access| access.contains(Access::READ));
+
+        if !(readable
+            || (self.block_range.contains(&start_addr) && self.block_range.contains(&end_addr))
+            || (self.ssa_range.contains(&start_addr) && self.ssa_range.contains(&end_addr)))
+        {
+            return Err(TargetError::NonFatal);
+        }
+
+        let src = unsafe { core::slice::from_raw_parts(start_addr as *const u8, data.len()) };
+        data.copy_from_slice(src);
+        Ok(())
+    }
+
+    /// Returns whether the enclave can write the `len` bytes at `start_addr`.
+    ///
+    /// The text of the enclave is not writable, since the EPCM permissions of its pages cannot be
+    /// changed from within the enclave.
+    fn writable(&self, start_addr: u64, len: usize) -> bool {
+        let start_addr = start_addr as usize;
+        let Some(end_addr) = start_addr.checked_add(len) else {
+            return false;
+        };
+        let length = Offset::from_items((end_addr - start_addr) / Page::SIZE);
+        let heap = HEAP.read();
+        let writable = heap
+            .contains(Address::new(start_addr), length)
+            .map_or(false, |access| access.contains(Access::WRITE));
+
+        writable
+            || (self.block_range.contains(&start_addr) && self.block_range.contains(&end_addr))
+            || (self.ssa_range.contains(&start_addr) && self.ssa_range.contains(&end_addr))
+    }
+
+    fn write(&self, start_addr: u64, data: &[u8]) -> TargetResult<(), Self> {
+        if !self.writable(start_addr, data.len()) {
+            return Err(TargetError::Fatal(GdbTargetError::WriteMemoryOutOfRange(
+                start_addr,
+            )));
+        }
+
+        let ptr = start_addr as *mut u8;
+
+        let dst = unsafe { core::slice::from_raw_parts_mut(ptr, data.len()) };
+        dst.copy_from_slice(data);
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -217,49 +451,104 @@ impl Target for GdbTarget {
     type Error = GdbTargetError;
 
     fn base_ops(&mut self) -> BaseOps<'_, Self::Arch, Self::Error> {
-        BaseOps::SingleThread(self)
+        BaseOps::MultiThread(self)
+    }
+
+    fn breakpoints(&mut self) -> Option<BreakpointsOps<'_, Self>> {
+        Some(self)
     }
 }
 
-impl SingleThreadOps for GdbTarget {
+impl MultiThreadOps for GdbTarget {
     fn resume(
         &mut self,
-        action: ResumeAction,
+        default_resume_action: ResumeAction,
         _gdb_interrupt: GdbInterrupt<'_>,
-    ) -> Result<StopReason<<Self::Arch as Arch>::Usize>, Self::Error> {
-        match action {
-            ResumeAction::Continue => {
-                self.regs.eflags &= !RFlags::TRAP_FLAG.bits() as u32;
-                Err(GdbTargetError::ResumeContinue)
-            }
-            ResumeAction::Step => {
-                self.regs.eflags |= RFlags::TRAP_FLAG.bits() as u32;
-                Err(GdbTargetError::ResumeStep)
-            }
-            ResumeAction::ContinueWithSignal(_) => {
-                self.regs.eflags &= !RFlags::TRAP_FLAG.bits() as u32;
-                Err(GdbTargetError::ResumeContinueWithSignal)
+    ) -> Result<ThreadStopReason<<Self::Arch as Arch>::Usize>, Self::Error> {
+        let mut parked = PARKED.lock();
+
+        // Report threads, which stopped during the session, before resuming anything.
+        if let Some(thread) = parked.iter_mut().find(|thread| !thread.reported) {
+            thread.reported = true;
+            self.focus = thread.tid;
+            return Ok(match thread.stop {
+                Stop::Breakpoint => ThreadStopReason::SwBreak(gdb_tid(thread.tid)),
+                Stop::PageFault => ThreadStopReason::Signal(SIGSEGV),
+                Stop::InvalidOpcode => ThreadStopReason::Signal(SIGILL),
+            });
+        }
+
+        let mut own_action = default_resume_action;
+        for thread in parked.iter_mut() {
+            let action = self
+                .actions
+                .iter()
+                .flatten()
+                .find(|(tid, _)| *tid == thread.tid)
+                .map_or(default_resume_action, |(_, action)| *action);
+
+            // SAFETY: the thread is parked until it is resumed
+            let ssa = unsafe { thread.ssa.as_mut() };
+            match action {
+                ResumeAction::Continue | ResumeAction::ContinueWithSignal(_) => {
+                    ssa.gpr.rflags &= !RFlags::TRAP_FLAG.bits()
+                }
+                ResumeAction::Step | ResumeAction::StepWithSignal(_) => {
+                    ssa.gpr.rflags |= RFlags::TRAP_FLAG.bits()
+                }
             }
-            ResumeAction::StepWithSignal(_) => {
-                self.regs.eflags |= RFlags::TRAP_FLAG.bits() as u32;
-                Err(GdbTargetError::ResumeStepWithSignal)
+
+            if thread.tid == self.tid {
+                own_action = action;
+            } else {
+                thread.resumed = true;
             }
         }
+
+        Err(match own_action {
+            ResumeAction::Continue => GdbTargetError::ResumeContinue,
+            ResumeAction::Step => GdbTargetError::ResumeStep,
+            ResumeAction::ContinueWithSignal(_) => GdbTargetError::ResumeContinueWithSignal,
+            ResumeAction::StepWithSignal(_) => GdbTargetError::ResumeStepWithSignal,
+        })
+    }
+
+    fn clear_resume_actions(&mut self) -> Result<(), Self::Error> {
+        self.actions = [None; MAX_PARKED];
+        Ok(())
+    }
+
+    fn set_resume_action(&mut self, tid: Tid, action: ResumeAction) -> Result<(), Self::Error> {
+        let tid = self.thread(tid);
+        // Only parked threads can be resumed, so there is a slot for each of them.
+        let slot = self
+            .actions
+            .iter()
+            .position(|slot| matches!(slot, Some((t, _)) if *t == tid))
+            .or_else(|| self.actions.iter().position(Option::is_none));
+        if let Some(slot) = slot {
+            self.actions[slot] = Some((tid, action));
+        }
+        Ok(())
     }
 
     fn read_registers(
         &mut self,
         regs: &mut <Self::Arch as Arch>::Registers,
+        tid: Tid,
     ) -> TargetResult<(), Self> {
-        *regs = self.regs.clone();
+        // SAFETY: the thread is parked until it is resumed
+        *regs = registers(unsafe { self.ssa(tid)?.as_ref() });
         Ok(())
     }
 
     fn write_registers(
         &mut self,
         regs: &<Self::Arch as Arch>::Registers,
+        tid: Tid,
     ) -> TargetResult<(), Self> {
-        self.regs = regs.clone();
+        // SAFETY: the thread is parked until it is resumed
+        set_registers(unsafe { self.ssa(tid)?.as_mut() }, regs);
         Ok(())
     }
 
@@ -267,60 +556,86 @@ impl SingleThreadOps for GdbTarget {
         &mut self,
         start_addr: <Self::Arch as Arch>::Usize,
         data: &mut [u8],
+        _tid: Tid,
     ) -> TargetResult<(), Self> {
-        let start_addr = start_addr as usize;
-        let end_addr = start_addr
-            .checked_add(data.len())
-            .ok_or(TargetError::NonFatal)?;
-        let length = Offset::from_items((end_addr - start_addr) / Page::SIZE);
-        let heap = HEAP.read();
-        let readable = heap
-            .contains(Address::new(start_addr), length)
-            .map_or(false, |access| access.contains(Access::READ));
-
-        if !(readable
-            || (self.block_range.contains(&start_addr) && self.block_range.contains(&end_addr))
-            || (self.ssa_range.contains(&start_addr) && self.ssa_range.contains(&end_addr)))
-        {
-            return Err(TargetError::NonFatal);
-        }
-
-        let src = unsafe { core::slice::from_raw_parts(start_addr as *const u8, data.len()) };
-        data.copy_from_slice(src);
-        Ok(())
+        self.read(start_addr, data)
     }
 
     fn write_addrs(
         &mut self,
         start_addr: <Self::Arch as Arch>::Usize,
         data: &[u8],
+        _tid: Tid,
     ) -> TargetResult<(), Self> {
-        let start_addr = start_addr as usize;
-        let end_addr = start_addr
-            .checked_add(data.len())
-            .ok_or(TargetError::Fatal(GdbTargetError::WriteMemoryOutOfRange(
-                start_addr as _,
-            )))?;
-        let length = Offset::from_items((end_addr - start_addr) / Page::SIZE);
-        let heap = HEAP.read();
-        let writable = heap
-            .contains(Address::new(start_addr), length)
-            .map_or(false, |access| access.contains(Access::WRITE));
+        self.write(start_addr, data)
+    }
 
-        if !(writable
-            || (self.block_range.contains(&start_addr) && self.block_range.contains(&end_addr))
-            || (self.ssa_range.contains(&start_addr) && self.ssa_range.contains(&end_addr)))
-        {
-            return Err(TargetError::Fatal(GdbTargetError::WriteMemoryOutOfRange(
-                start_addr as _,
-            )));
+    fn list_active_threads(
+        &mut self,
+        thread_is_active: &mut dyn FnMut(Tid),
+    ) -> Result<(), Self::Error> {
+        // `gdbstub` picks the first thread, if GDB asks for any thread.
+        thread_is_active(gdb_tid(self.focus));
+        PARKED
+            .lock()
+            .iter_mut()
+            .filter(|thread| thread.tid != self.focus)
+            .for_each(|thread| thread_is_active(gdb_tid(thread.tid)));
+        Ok(())
+    }
+}
+
+// Hardware breakpoints and watchpoints are not supported: the enclave cannot write the debug
+// registers, and a debug exception in the enclave is handled by the host kernel instead of being
+// reported in the SSA. Without `hw_breakpoint()` and `hw_watchpoint()`, the stub rejects them,
+// and GDB has to use software breakpoints and single-stepping watchpoints instead.
+impl Breakpoints for GdbTarget {
+    fn sw_breakpoint(&mut self) -> Option<SwBreakpointOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl SwBreakpoint for GdbTarget {
+    fn add_sw_breakpoint(
+        &mut self,
+        addr: <Self::Arch as Arch>::Usize,
+        _kind: <Self::Arch as Arch>::BreakpointKind,
+    ) -> TargetResult<bool, Self> {
+        let mut breakpoints = BREAKPOINTS.lock();
+        if breakpoints.iter().flatten().any(|(a, _)| *a == addr) {
+            return Ok(true);
+        }
+        let Some(slot) = breakpoints.iter_mut().find(|slot| slot.is_none()) else {
+            return Ok(false);
+        };
+        // Breakpoints in the text of the enclave cannot be set, see `writable()`.
+        if !self.writable(addr, 1) {
+            return Ok(false);
         }
 
-        let ptr = start_addr as *mut u8;
+        let mut orig = [0];
+        self.read(addr, &mut orig)?;
+        self.write(addr, &[INT3])?;
+        *slot = Some((addr, orig[0]));
+        Ok(true)
+    }
 
-        let dst = unsafe { core::slice::from_raw_parts_mut(ptr, data.len()) };
-        dst.copy_from_slice(data);
-        Ok(())
+    fn remove_sw_breakpoint(
+        &mut self,
+        addr: <Self::Arch as Arch>::Usize,
+        _kind: <Self::Arch as Arch>::BreakpointKind,
+    ) -> TargetResult<bool, Self> {
+        let mut breakpoints = BREAKPOINTS.lock();
+        match breakpoints
+            .iter_mut()
+            .find(|slot| matches!(slot, Some((a, _)) if *a == addr))
+        {
+            Some(slot) => {
+                let (_, orig) = slot.take().unwrap();
+                self.write(addr, &[orig]).map(|_| true)
+            }
+            None => Ok(false),
+        }
     }
 }
 
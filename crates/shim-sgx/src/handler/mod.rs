@@ -27,6 +27,7 @@ pub(crate) mod gdb;
 pub(crate) mod key;
 pub(crate) mod usermem;
 
+use crate::clock;
 use crate::handler::usermem::UserMemScope;
 use crate::heap::{Access, Heap};
 use crate::thread::{
@@ -53,9 +54,11 @@ use primordial::{Address, Offset, Page};
 use sallyport::guest::{self, Handler as _, Platform, ThreadLocalStorage};
 use sallyport::item::enarxcall::sgx::{Report, ReportData, TargetInfo, TECH};
 use sallyport::item::enarxcall::{SYS_GETATT, SYS_GETKEY};
+use sallyport::item::split_signal;
 use sallyport::libc::{
-    off_t, pid_t, CloneFlags, SYS_clock_gettime, EACCES, EAGAIN, EINVAL, EIO, EMSGSIZE, ENOMEM,
-    ENOSYS, ENOTSUP, MAP_ANONYMOUS, MAP_PRIVATE, PROT_EXEC, PROT_READ, PROT_WRITE, STDERR_FILENO,
+    clockid_t, off_t, pid_t, timespec, CloneFlags, SYS_clock_gettime, CLOCK_MONOTONIC, EACCES,
+    EAGAIN, EINVAL, EIO, EMSGSIZE, ENOMEM, ENOSYS, ENOTSUP, MAP_ANONYMOUS, MAP_PRIVATE, PROT_EXEC,
+    PROT_READ, PROT_WRITE, STDERR_FILENO,
 };
 use sallyport::Error;
 use sgx::page::{Class, Flags};
@@ -182,6 +185,7 @@ enum MMapStrategy {
 /// Thread local storage for the current thread
 pub struct Handler<'a> {
     block: &'a mut [usize],
+    signal: &'a mut usize,
     ssa: &'a mut StateSaveArea,
     tcb: &'a mut Tcb,
     start: u64,
@@ -215,6 +219,9 @@ impl guest::Handler for Handler<'_> {
         // prevent later reads from being moved before this point
         core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::Acquire);
 
+        if guest::signal::collect(self.signal) {
+            clock::resume();
+        }
         Ok(())
     }
 
@@ -267,6 +274,18 @@ impl guest::Handler for Handler<'_> {
         Ok(addr.into_nonnull())
     }
 
+    fn clock_gettime(&mut self, clockid: clockid_t, tp: &mut timespec) -> sallyport::Result<()> {
+        if clockid != CLOCK_MONOTONIC {
+            return self.clock_gettime_host(clockid, tp);
+        }
+        let (now, event) = clock::monotonic(|tp| self.clock_gettime_host(CLOCK_MONOTONIC, tp))?;
+        if let Some(event) = event {
+            debugln!(self, "clock: {event}");
+        }
+        *tp = now;
+        Ok(())
+    }
+
     fn clone(
         &mut self,
         flags: CloneFlags,
@@ -346,7 +365,7 @@ impl guest::Handler for Handler<'_> {
         if let Some(addr) = addr {
             debugln!(self, "[{tid}] clear TID at {addr:p}");
             unsafe { (*addr.as_ptr()).store(0, Ordering::SeqCst) };
-            let _ = self.unpark();
+            let _ = self.unpark(addr.as_ptr() as usize, u32::MAX);
         } else {
             debugln!(self, "[{tid}] no TID to clear");
         }
@@ -449,9 +468,11 @@ impl<'a> Handler<'a> {
         tcb: &'a mut Tcb,
         start: u64,
     ) -> Self {
+        let (block, signal) = split_signal(block).unwrap();
         Self {
             ssa,
             block,
+            signal,
             tcb,
             start,
         }
@@ -518,7 +539,11 @@ impl<'a> Handler<'a> {
                     }
 
                     #[cfg(feature = "gdb")]
-                    h.gdb_session();
+                    h.gdb_session(if r as u8 == 0xCC {
+                        gdb::Stop::Breakpoint
+                    } else {
+                        gdb::Stop::InvalidOpcode
+                    });
 
                     if r == unsafe { read_unaligned(h.ssa.gpr.rip as _) } {
                         let _ = h.exit_group(1);
@@ -539,7 +564,7 @@ impl<'a> Handler<'a> {
                 if cfg!(feature = "gdb") {
                     h.print_ssa_stack_trace();
                     #[cfg(feature = "gdb")]
-                    h.gdb_session();
+                    h.gdb_session(gdb::Stop::PageFault);
                     let _ = h.exit_group(1);
                 } else {
                     h.attacked()
@@ -798,10 +823,14 @@ impl<'a> Handler<'a> {
             self.attacked()
         }
 
+        // The host refuses memory beyond the limit of the keep.
         if let Err(e) = self.mmap_host(addr.into_nonnull(), length.bytes(), PROT_READ | PROT_WRITE)
         {
             debugln!(self, "ERROR mmap_host() = {e:#?}");
-            self.attacked()
+            if heap.munmap(addr, length).is_err() {
+                self.attacked()
+            }
+            return Err(e);
         }
 
         self.mmap_guest(addr, length, flags_from_libc(prot));
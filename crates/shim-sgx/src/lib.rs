@@ -14,6 +14,7 @@
 #![deny(missing_docs)]
 #![warn(rust_2018_idioms)]
 
+pub mod clock;
 pub mod entry;
 pub mod handler;
 pub mod heap;
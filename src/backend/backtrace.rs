@@ -0,0 +1,358 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Symbolization of the stack traces printed by the shims.
+//!
+//! With the `dbg` feature the shims write `TRACE:` blocks of raw addresses to stderr. Every write
+//! of a keep to stderr is scanned for these blocks and each address is resolved against the debug
+//! info of the shim or the exec, like `helper/parse-trace.sh` does for saved output.
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use addr2line::Context;
+use anyhow::{anyhow, Result};
+use gimli::{Dwarf, EndianArcSlice, RunTimeEndian, SectionId};
+use goblin::elf::section_header::SHT_NOBITS;
+use goblin::elf::Elf;
+use once_cell::sync::OnceCell;
+
+use super::hook::{Call, Number};
+
+/// The symbolizer installed for this process, if any.
+static SYMBOLIZER: OnceCell<Mutex<Symbolizer>> = OnceCell::new();
+
+/// The virtual address of the KVM shim is its physical address plus this offset.
+///
+/// See `SHIM_VIRT_OFFSET` in `crates/shim-kvm/src/addr.rs`.
+const SHIM_VIRT_OFFSET: u64 = 0xFFFF_FF80_0000_0000;
+
+/// The registers of a `kvm_regs` dump, which do not hold code addresses.
+const NO_CODE_REGISTERS: &[&str] = &["rsp", "rbp", "rflags"];
+
+type Reader = EndianArcSlice<RunTimeEndian>;
+
+/// The binary an address of a stack trace belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    Shim,
+    Exec,
+}
+
+impl Origin {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Shim => "Shim",
+            Self::Exec => "Exec",
+        }
+    }
+}
+
+/// An ELF binary, whose debug info is only parsed once it is needed.
+struct DebugInfo {
+    elf: Cow<'static, [u8]>,
+    context: Option<Option<Context<Reader>>>,
+}
+
+impl DebugInfo {
+    fn new(elf: Cow<'static, [u8]>) -> Self {
+        Self { elf, context: None }
+    }
+
+    /// Returns the debug info of the binary, unless it has none.
+    fn context(&mut self) -> Option<&Context<Reader>> {
+        let elf = &self.elf;
+        self.context
+            .get_or_insert_with(|| load_context(elf).ok())
+            .as_ref()
+    }
+
+    /// Returns one line per frame at the link address `addr`, innermost first.
+    fn frames(&mut self, addr: u64) -> Vec<String> {
+        let Some(context) = self.context() else {
+            return Vec::new();
+        };
+        let Ok(mut frames) = context.find_frames(addr).skip_all_loads() else {
+            return Vec::new();
+        };
+
+        let mut lines = Vec::new();
+        while let Ok(Some(frame)) = frames.next() {
+            let function = frame
+                .function
+                .as_ref()
+                .and_then(|function| function.demangle().ok())
+                .unwrap_or(Cow::Borrowed("??"));
+            let location = frame
+                .location
+                .map(|location| {
+                    format!(
+                        "{}:{}",
+                        location.file.unwrap_or("??"),
+                        location.line.unwrap_or(0)
+                    )
+                })
+                .unwrap_or_else(|| "??:0".into());
+            let prefix = if lines.is_empty() {
+                format!("{addr:#018x}: ")
+            } else {
+                " (inlined by) ".into()
+            };
+            lines.push(format!("{prefix}{function} at {location}"));
+        }
+        lines
+    }
+}
+
+/// Loads the DWARF sections of `elf`.
+fn load_context(elf: &[u8]) -> Result<Context<Reader>> {
+    let parsed = Elf::parse(elf)?;
+    let endian = if parsed.little_endian {
+        RunTimeEndian::Little
+    } else {
+        RunTimeEndian::Big
+    };
+
+    let dwarf = Dwarf::load(|id: SectionId| -> Result<Reader> {
+        let data = parsed
+            .section_headers
+            .iter()
+            .find(|header| parsed.shdr_strtab.get_at(header.sh_name) == Some(id.name()))
+            .filter(|header| header.sh_type != SHT_NOBITS)
+            .and_then(|header| elf.get(header.file_range()?))
+            .unwrap_or_default();
+        Ok(EndianArcSlice::new(Arc::from(data), endian))
+    })?;
+
+    if dwarf.units().next()?.is_none() {
+        return Err(anyhow!("no debug info"));
+    }
+    Ok(Context::from_dwarf(dwarf)?)
+}
+
+/// Resolves the stack traces written by the keeps of this process.
+struct Symbolizer {
+    shim: DebugInfo,
+    exec: DebugInfo,
+    /// The unterminated line written last
+    line: Vec<u8>,
+    /// The lines written last were a stack trace
+    in_trace: bool,
+}
+
+impl Symbolizer {
+    fn new(shim: Cow<'static, [u8]>, exec: &'static [u8]) -> Self {
+        Self {
+            shim: DebugInfo::new(shim),
+            exec: DebugInfo::new(Cow::Borrowed(exec)),
+            line: Vec::new(),
+            in_trace: false,
+        }
+    }
+
+    fn debug_info(&mut self, origin: Origin) -> &mut DebugInfo {
+        match origin {
+            Origin::Shim => &mut self.shim,
+            Origin::Exec => &mut self.exec,
+        }
+    }
+
+    /// Consumes bytes written to stderr and returns the symbolized frames of the complete lines.
+    fn scan(&mut self, buf: &[u8]) -> Vec<String> {
+        let mut out = Vec::new();
+        for &byte in buf {
+            if byte != b'\n' {
+                self.line.push(byte);
+                continue;
+            }
+
+            let line = String::from_utf8_lossy(&self.line).into_owned();
+            self.line.clear();
+
+            if line.trim_end() == "TRACE:" {
+                self.in_trace = true;
+                continue;
+            }
+            if !self.in_trace {
+                continue;
+            }
+
+            match parse_trace_line(&line) {
+                Some((origin, addr)) => out.extend(
+                    self.debug_info(origin)
+                        .frames(addr)
+                        .into_iter()
+                        .map(|frame| format!("{}: {frame}", origin.label())),
+                ),
+                None => self.in_trace = false,
+            }
+        }
+        out
+    }
+
+    /// Returns the symbolized frames of the code addresses in the `kvm_regs` dump in `text`.
+    fn registers(&mut self, text: &str) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut in_regs = false;
+        for line in text.lines() {
+            // Only look at the general purpose registers and not the `kvm_sregs` following them.
+            match line.trim() {
+                "kvm_regs {" => in_regs = true,
+                "}," | "}" => in_regs = false,
+                _ => {}
+            }
+            let Some((name, value)) = parse_register(line).filter(|_| in_regs) else {
+                continue;
+            };
+            if NO_CODE_REGISTERS.contains(&name) {
+                continue;
+            }
+            let Some(addr) = value.checked_sub(SHIM_VIRT_OFFSET) else {
+                continue;
+            };
+            out.extend(
+                self.shim
+                    .frames(addr)
+                    .into_iter()
+                    .map(|frame| format!("Shim: {name}: {frame}")),
+            );
+        }
+        out
+    }
+}
+
+/// Parses an address line of a `TRACE:` block.
+///
+/// The shims mark addresses of the shim with `S` or no marker at all and addresses of the exec
+/// with `E`, or `P` in older shims. All addresses are relative to the start of the binary.
+fn parse_trace_line(line: &str) -> Option<(Origin, u64)> {
+    let line = line.trim();
+    let (origin, addr) = match line.split_once(' ') {
+        Some(("S", addr)) => (Origin::Shim, addr),
+        Some(("E" | "P", addr)) => (Origin::Exec, addr),
+        Some(_) => return None,
+        None => (Origin::Shim, line),
+    };
+    let addr = addr.trim().strip_prefix("0x")?;
+    u64::from_str_radix(addr, 16)
+        .ok()
+        .map(|addr| (origin, addr))
+}
+
+/// Parses a `name: 0x...,` line of a `kvm_regs` dump.
+fn parse_register(line: &str) -> Option<(&str, u64)> {
+    let (name, value) = line.trim().split_once(": ")?;
+    let value = value.trim_end_matches(',').strip_prefix("0x")?;
+    Some((name, u64::from_str_radix(value, 16).ok()?))
+}
+
+/// Writes the symbolized frames to stderr.
+fn print(frames: Vec<String>) {
+    let mut stderr = io::stderr().lock();
+    for frame in frames {
+        // Symbolization must never take down the keep, so write errors are ignored.
+        let _ = writeln!(stderr, "{frame}");
+    }
+}
+
+/// Installs a symbolizer resolving the stack traces of all keeps of this process against `shim`
+/// and `exec`.
+pub fn install(shim: Cow<'static, [u8]>, exec: &'static [u8]) -> Result<()> {
+    SYMBOLIZER
+        .set(Mutex::new(Symbolizer::new(shim, exec)))
+        .map_err(|_| anyhow!("a stack trace symbolizer is already installed"))
+}
+
+/// The stack trace frames of a write to stderr.
+///
+/// They are printed after the write was executed, so the frames follow the raw addresses.
+pub(crate) struct Frames {
+    frames: Vec<String>,
+}
+
+impl Frames {
+    pub(crate) fn print(self) {
+        print(self.frames);
+    }
+}
+
+/// Scans `call` for stack traces, if a symbolizer is installed and it writes to stderr.
+pub(crate) fn before(call: &Call<'_>) -> Option<Frames> {
+    let symbolizer = SYMBOLIZER.get()?;
+    let [fd, offset, len, ..] = call.argv;
+    if call.number != Number::Syscall(libc::SYS_write) || fd != libc::STDERR_FILENO as usize {
+        return None;
+    }
+
+    // The buffer is laid out by the guest side of sallyport, so it is an offset into `data`.
+    let buf = call.data.get(offset..offset.checked_add(len)?)?;
+    let frames = symbolizer.lock().unwrap().scan(buf);
+    (!frames.is_empty()).then_some(Frames { frames })
+}
+
+/// Writes the symbolized frames of the register dump in the error `text` to stderr, if a
+/// symbolizer is installed.
+pub fn registers(text: &str) {
+    if let Some(symbolizer) = SYMBOLIZER.get() {
+        print(symbolizer.lock().unwrap().registers(text));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trace_line() {
+        assert_eq!(
+            parse_trace_line("  0x000000000000f876"),
+            Some((Origin::Shim, 0xf876))
+        );
+        assert_eq!(
+            parse_trace_line("S 0x0000000000039d10"),
+            Some((Origin::Shim, 0x39d10))
+        );
+        assert_eq!(
+            parse_trace_line("E 0x0000000000001279"),
+            Some((Origin::Exec, 0x1279))
+        );
+        assert_eq!(
+            parse_trace_line("P 0x000000000000102c"),
+            Some((Origin::Exec, 0x102c))
+        );
+        assert_eq!(parse_trace_line("invalid rbp: 0x0000000000000008"), None);
+        assert_eq!(parse_trace_line("Hello, world!"), None);
+    }
+
+    #[test]
+    fn register() {
+        assert_eq!(
+            parse_register("        rip: 0xffffff8000230662,"),
+            Some(("rip", 0xffffff8000230662))
+        );
+        assert_eq!(parse_register("    kvm_regs {"), None);
+    }
+
+    #[test]
+    fn registers() {
+        // Neither binary has debug info, so nothing is resolved, but nothing must fail either.
+        let mut symbolizer = Symbolizer::new(Cow::Borrowed(&[]), &[]);
+        let error =
+            "KVM error: Shutdown Ok(\n    kvm_regs {\n        rip: 0xffffff8000230662,\n    },\n)";
+        assert!(symbolizer.registers(error).is_empty());
+    }
+
+    #[test]
+    fn scan() {
+        // Neither binary has debug info, so the addresses are recognized but not resolved.
+        let mut symbolizer = Symbolizer::new(Cow::Borrowed(&[]), &[]);
+        assert!(symbolizer
+            .scan(b"panicked at 'explicit panic'\nTRA")
+            .is_empty());
+        assert!(symbolizer.scan(b"CE:\n  0x000000000000f876\n").is_empty());
+        assert!(symbolizer.in_trace);
+        assert!(symbolizer.scan(b"P 0x0000000000001279\nHello\n").is_empty());
+        assert!(!symbolizer.in_trace);
+        assert!(symbolizer.line.is_empty());
+    }
+}
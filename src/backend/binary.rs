@@ -35,7 +35,7 @@ impl std::fmt::Debug for Segment<'_> {
 pub struct Binary<'a>(&'a [u8], Elf<'a>);
 
 impl<'a> Binary<'a> {
-    fn new(bytes: &'a [u8]) -> Result<Self> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self> {
         let elf = Elf::parse(bytes)?;
 
         if elf.header.e_ident[EI_CLASS] != ELFCLASS64 {
@@ -109,6 +109,33 @@ impl<'a> Binary<'a> {
         lo.unwrap_or_default()..hi.unwrap_or_default()
     }
 
+    /// Find the slot reserved for the executable in a shim.
+    ///
+    /// This also checks that the shim's sallyport version requirement is
+    /// satisfied.
+    fn exec_slot(&self) -> Result<Range<usize>> {
+        use sallyport::elf;
+
+        let slot = self
+            .headers(elf::pt::EXEC)
+            .next()
+            .ok_or_else(|| anyhow!("Shim is missing the executable slot!"))?
+            .vm_range();
+
+        // Check sallyport compatibility
+        let version = semver::Version::parse(sallyport::VERSION).unwrap();
+        let supported = self
+            .notes(elf::note::NAME, elf::note::REQUIRES)
+            .filter_map(|n| std::str::from_utf8(n).ok())
+            .filter_map(|n| semver::VersionReq::parse(n).ok())
+            .any(|req| req.matches(&version));
+        if !supported {
+            return Err(anyhow!("Unable to satisfy sallyport version requirement!"));
+        }
+
+        Ok(slot)
+    }
+
     pub fn headers(&self, kind: u32) -> impl Iterator<Item = &ProgramHeader> {
         self.1
             .program_headers
@@ -151,23 +178,23 @@ impl<'a> Binary<'a> {
 }
 
 impl<T: Mapper> Loader for T {
+    fn check(shim: impl AsRef<[u8]>) -> Result<()> {
+        let sbin = Binary::new(shim.as_ref())?;
+        sbin.exec_slot()?;
+        Self::Config::new(&sbin, None).map(drop)
+    }
+
     fn load(
         shim: impl AsRef<[u8]>,
         exec: impl AsRef<[u8]>,
         signatures: Option<Signatures>,
     ) -> Result<Self::Output> {
-        use sallyport::elf;
-
         // Parse the ELF files.
         let sbin = Binary::new(shim.as_ref())?;
         let ebin = Binary::new(exec.as_ref())?;
 
         // Find the offset for loading the code.
-        let slot = sbin
-            .headers(elf::pt::EXEC)
-            .next()
-            .ok_or_else(|| anyhow!("Shim is missing the executable slot!"))?
-            .vm_range();
+        let slot = sbin.exec_slot()?;
 
         // Check the bounds of the executable.
         let range = ebin.range();
@@ -175,19 +202,8 @@ impl<T: Mapper> Loader for T {
             return Err(anyhow!("The executable doesn't fit in the slot!"));
         }
 
-        // Check sallyport compatibility
-        let version = semver::Version::parse(sallyport::VERSION).unwrap();
-        let supported = sbin
-            .notes(elf::note::NAME, elf::note::REQUIRES)
-            .filter_map(|n| std::str::from_utf8(n).ok())
-            .filter_map(|n| semver::VersionReq::parse(n).ok())
-            .any(|req| req.matches(&version));
-        if !supported {
-            return Err(anyhow!("Unable to satisfy sallyport version requirement!"));
-        }
-
         // Parse the config and create a builder.
-        let mut loader: Self = Self::Config::new(&sbin, &ebin, signatures)?.try_into()?;
+        let mut loader: Self = Self::Config::new(&sbin, signatures)?.try_into()?;
 
         // Get an array of all final segment locations (relocated).
         let ssegs: Vec<Segment<'_>> =
@@ -236,6 +252,9 @@ pub(crate) trait Mapper: Sized + TryFrom<Self::Config, Error = Error> {
 }
 
 pub(crate) trait Loader: Mapper {
+    /// Validate a shim binary without loading an executable.
+    fn check(shim: impl AsRef<[u8]>) -> Result<()>;
+
     fn load(
         shim: impl AsRef<[u8]>,
         exec: impl AsRef<[u8]>,
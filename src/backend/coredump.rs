@@ -0,0 +1,317 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! ELF core dumps of crashed keeps.
+//!
+//! A core dump holds the memory of the keep as seen by the faulting thread and the registers of
+//! all threads, so it can be loaded into GDB together with the shim and the exec for post-mortem
+//! debugging.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::mem::size_of;
+use std::slice;
+
+use anyhow::{anyhow, Context, Result};
+use camino::Utf8PathBuf;
+use libc::user_regs_struct;
+use once_cell::sync::OnceCell;
+use tracing::error;
+
+/// The path to write the core dump to, if any.
+static PATH: OnceCell<Utf8PathBuf> = OnceCell::new();
+
+const PAGE_SIZE: u64 = 4096;
+
+const ELF_HEADER_SIZE: u64 = 64;
+const PROGRAM_HEADER_SIZE: u64 = 56;
+
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+
+/// The size of `struct elf_prstatus` on x86_64.
+const PRSTATUS_SIZE: usize = 336;
+/// The offset of `pr_pid` in `struct elf_prstatus`.
+const PRSTATUS_PID: usize = 32;
+/// The offset of `pr_reg` in `struct elf_prstatus`.
+const PRSTATUS_REG: usize = 112;
+
+/// The signal reported for the faulting thread
+const SIGSEGV: u16 = 11;
+
+/// Permissions of a [`Segment`]
+pub(crate) const PF_X: u32 = 1;
+pub(crate) const PF_W: u32 = 2;
+pub(crate) const PF_R: u32 = 4;
+
+/// A contiguous range of keep memory.
+#[derive(Debug)]
+pub(crate) struct Segment<'a> {
+    /// The guest virtual address
+    pub vaddr: u64,
+    /// The guest physical address, if known
+    pub paddr: u64,
+    /// `PF_*` flags
+    pub flags: u32,
+    pub data: &'a [u8],
+}
+
+/// A thread of the keep.
+pub(crate) struct Thread {
+    pub tid: i32,
+    pub regs: user_regs_struct,
+}
+
+/// Writes a core dump to the path given on the command line, when a keep crashes.
+pub fn install(path: Utf8PathBuf) -> Result<()> {
+    PATH.set(path)
+        .map_err(|_| anyhow!("a core dump path is already installed"))
+}
+
+/// Returns `true`, if a core dump should be written.
+pub(crate) fn enabled() -> bool {
+    PATH.get().is_some()
+}
+
+/// Writes the core dump of a crashed keep, if a path was installed.
+///
+/// Errors are only logged, since the keep is about to fail anyway.
+pub(crate) fn dump(segments: &[Segment<'_>], threads: &[Thread]) {
+    let Some(path) = PATH.get() else {
+        return;
+    };
+
+    let written = File::create(path)
+        .map(BufWriter::new)
+        .and_then(|mut file| {
+            write(&mut file, segments, threads)?;
+            file.flush()
+        })
+        .with_context(|| format!("failed to write core dump to `{path}`"));
+
+    match written {
+        Ok(()) => eprintln!("Core dump written to `{path}`"),
+        Err(e) => error!("{e:#}"),
+    }
+}
+
+/// Writes an ELF core file with one `PT_LOAD` segment per [`Segment`] and one `NT_PRSTATUS`
+/// note per [`Thread`], the faulting thread first.
+pub(crate) fn write(
+    out: &mut impl Write,
+    segments: &[Segment<'_>],
+    threads: &[Thread],
+) -> io::Result<()> {
+    let notes = notes(threads);
+    let phnum = 1 + segments.len() as u64;
+    let notes_offset = ELF_HEADER_SIZE + phnum * PROGRAM_HEADER_SIZE;
+    let data_offset = align_up(notes_offset + notes.len() as u64, PAGE_SIZE);
+
+    // ELF header
+    let mut ident = [0u8; 16];
+    ident[..4].copy_from_slice(b"\x7fELF");
+    ident[4] = 2; // ELFCLASS64
+    ident[5] = 1; // ELFDATA2LSB
+    ident[6] = 1; // EV_CURRENT
+    out.write_all(&ident)?;
+    out.write_all(&ET_CORE.to_le_bytes())?;
+    out.write_all(&EM_X86_64.to_le_bytes())?;
+    out.write_all(&1u32.to_le_bytes())?; // e_version
+    out.write_all(&0u64.to_le_bytes())?; // e_entry
+    out.write_all(&ELF_HEADER_SIZE.to_le_bytes())?; // e_phoff
+    out.write_all(&0u64.to_le_bytes())?; // e_shoff
+    out.write_all(&0u32.to_le_bytes())?; // e_flags
+    out.write_all(&(ELF_HEADER_SIZE as u16).to_le_bytes())?;
+    out.write_all(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes())?;
+    out.write_all(&(phnum as u16).to_le_bytes())?;
+    out.write_all(&0u16.to_le_bytes())?; // e_shentsize
+    out.write_all(&0u16.to_le_bytes())?; // e_shnum
+    out.write_all(&0u16.to_le_bytes())?; // e_shstrndx
+
+    // Program headers
+    program_header(out, PT_NOTE, 0, notes_offset, 0, 0, notes.len() as u64, 1)?;
+    let mut offset = data_offset;
+    for segment in segments {
+        let size = segment.data.len() as u64;
+        program_header(
+            out,
+            PT_LOAD,
+            segment.flags,
+            offset,
+            segment.vaddr,
+            segment.paddr,
+            size,
+            PAGE_SIZE,
+        )?;
+        offset += size;
+    }
+
+    out.write_all(&notes)?;
+    let padding = data_offset - notes_offset - notes.len() as u64;
+    out.write_all(&vec![0; padding as usize])?;
+
+    for segment in segments {
+        out.write_all(segment.data)?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn program_header(
+    out: &mut impl Write,
+    kind: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    size: u64,
+    align: u64,
+) -> io::Result<()> {
+    out.write_all(&kind.to_le_bytes())?;
+    out.write_all(&flags.to_le_bytes())?;
+    out.write_all(&offset.to_le_bytes())?;
+    out.write_all(&vaddr.to_le_bytes())?;
+    out.write_all(&paddr.to_le_bytes())?;
+    out.write_all(&size.to_le_bytes())?; // p_filesz
+    out.write_all(&size.to_le_bytes())?; // p_memsz
+    out.write_all(&align.to_le_bytes())
+}
+
+/// Returns the `NT_PRSTATUS` notes of `threads`.
+fn notes(threads: &[Thread]) -> Vec<u8> {
+    const NAME: &[u8; 8] = b"CORE\0\0\0\0";
+
+    let mut notes = Vec::new();
+    for (i, thread) in threads.iter().enumerate() {
+        let mut prstatus = [0u8; PRSTATUS_SIZE];
+        if i == 0 {
+            // pr_cursig
+            prstatus[12..14].copy_from_slice(&SIGSEGV.to_le_bytes());
+        }
+        prstatus[PRSTATUS_PID..][..4].copy_from_slice(&thread.tid.to_le_bytes());
+        // SAFETY: `user_regs_struct` is a plain struct of integers.
+        let regs = unsafe {
+            slice::from_raw_parts(
+                &thread.regs as *const user_regs_struct as *const u8,
+                size_of::<user_regs_struct>(),
+            )
+        };
+        prstatus[PRSTATUS_REG..][..regs.len()].copy_from_slice(regs);
+
+        notes.extend_from_slice(&5u32.to_le_bytes()); // n_namesz, including the NUL
+        notes.extend_from_slice(&(PRSTATUS_SIZE as u32).to_le_bytes());
+        notes.extend_from_slice(&NT_PRSTATUS.to_le_bytes());
+        notes.extend_from_slice(NAME);
+        notes.extend_from_slice(&prstatus);
+    }
+    notes
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    (value + align - 1) / align * align
+}
+
+/// Appends the range at `vaddr` to `segments`, merging it with the last segment, if they are
+/// contiguous in the guest and on the host.
+pub(crate) fn push<'a>(
+    segments: &mut Vec<Segment<'a>>,
+    vaddr: u64,
+    paddr: u64,
+    flags: u32,
+    data: &'a [u8],
+) {
+    if let Some(last) = segments.last_mut() {
+        let contiguous = last.vaddr + last.data.len() as u64 == vaddr
+            && last.paddr + last.data.len() as u64 == paddr
+            && last.flags == flags
+            && last.data.as_ptr_range().end == data.as_ptr();
+        if contiguous {
+            // SAFETY: both slices are adjacent parts of the same mapping.
+            last.data =
+                unsafe { slice::from_raw_parts(last.data.as_ptr(), last.data.len() + data.len()) };
+            return;
+        }
+    }
+    segments.push(Segment {
+        vaddr,
+        paddr,
+        flags,
+        data,
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use goblin::elf::note::NT_PRSTATUS as GOBLIN_NT_PRSTATUS;
+    use goblin::elf::Elf;
+
+    #[test]
+    fn prstatus_layout() {
+        assert_eq!(
+            PRSTATUS_REG + size_of::<user_regs_struct>() + 8,
+            PRSTATUS_SIZE
+        );
+    }
+
+    #[test]
+    fn core() {
+        let memory = vec![0xAAu8; 3 * PAGE_SIZE as usize];
+        let (first, rest) = memory.split_at(PAGE_SIZE as usize);
+
+        let mut segments = Vec::new();
+        push(&mut segments, 0x1000, 0x0, PF_R | PF_X, first);
+        push(
+            &mut segments,
+            0x2000,
+            0x1000,
+            PF_R | PF_X,
+            &rest[..PAGE_SIZE as usize],
+        );
+        push(
+            &mut segments,
+            0x8000,
+            0x2000,
+            PF_R | PF_W,
+            &rest[PAGE_SIZE as usize..],
+        );
+        assert_eq!(segments.len(), 2);
+
+        let mut regs: user_regs_struct = unsafe { std::mem::zeroed() };
+        regs.rip = 0x1234;
+        let threads = [Thread { tid: 1, regs }, Thread { tid: 2, regs }];
+
+        let mut core = Vec::new();
+        write(&mut core, &segments, &threads).unwrap();
+
+        let elf = Elf::parse(&core).unwrap();
+        assert_eq!(elf.header.e_type, ET_CORE);
+        assert_eq!(elf.program_headers.len(), 3);
+
+        let load = &elf.program_headers[1];
+        assert_eq!(load.p_vaddr, 0x1000);
+        assert_eq!(load.p_filesz, 2 * PAGE_SIZE);
+        assert_eq!(load.p_offset % PAGE_SIZE, 0);
+        assert_eq!(&core[load.file_range()], &memory[..2 * PAGE_SIZE as usize]);
+
+        let notes: Vec<_> = elf
+            .iter_note_headers(&core)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(notes.len(), 2);
+        for (note, tid) in notes.iter().zip(1i32..) {
+            assert_eq!(note.n_type, GOBLIN_NT_PRSTATUS);
+            assert_eq!(note.name, "CORE");
+            assert_eq!(note.desc[PRSTATUS_PID..][..4], tid.to_le_bytes());
+            let rip = PRSTATUS_REG + 16 * 8;
+            assert_eq!(note.desc[rip..][..8], 0x1234u64.to_le_bytes());
+        }
+        // Only the faulting thread has a pending signal.
+        assert_eq!(notes[0].desc[12..14], SIGSEGV.to_le_bytes());
+        assert_eq!(notes[1].desc[12..14], [0, 0]);
+    }
+}
@@ -0,0 +1,129 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Observers of the host calls of a keep.
+//!
+//! The host call tracer, the stack trace symbolizer and the metrics of the keep look at every
+//! [`Item`] once before and once after it was executed. In between, they only hold owned copies
+//! of what they need, so the item is free to be executed by the backend.
+
+use sallyport::item::{enarxcall, gdbcall, Item};
+
+use super::metrics::Metrics;
+use super::{backtrace, trace};
+
+/// The number of a host call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Number {
+    Syscall(libc::c_long),
+    Gdbcall(gdbcall::Number),
+    Enarxcall(enarxcall::Number),
+}
+
+/// A host call, as seen by its observers.
+pub(crate) struct Call<'a> {
+    pub number: Number,
+    pub argv: [usize; 6],
+    /// The first return value, which is only meaningful after the call was executed
+    pub ret: usize,
+    pub data: &'a [u8],
+}
+
+impl<'a> Call<'a> {
+    pub(crate) fn new(item: &'a Item<'_>) -> Self {
+        match item {
+            Item::Syscall(call, data) => Self {
+                number: Number::Syscall(call.num as _),
+                argv: call.argv,
+                ret: call.ret[0],
+                data,
+            },
+            Item::Gdbcall(call, data) => Self {
+                number: Number::Gdbcall(call.num),
+                argv: [call.argv[0], call.argv[1], call.argv[2], call.argv[3], 0, 0],
+                ret: call.ret,
+                data,
+            },
+            Item::Enarxcall(call, data) => Self {
+                number: Number::Enarxcall(call.num),
+                argv: [call.argv[0], call.argv[1], call.argv[2], call.argv[3], 0, 0],
+                ret: call.ret,
+                data,
+            },
+        }
+    }
+
+    /// The kind of the call.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self.number {
+            Number::Syscall(_) => "syscall",
+            Number::Gdbcall(_) => "gdbcall",
+            Number::Enarxcall(_) => "enarxcall",
+        }
+    }
+
+    /// The name of the call.
+    pub(crate) fn name(&self) -> String {
+        match self.number {
+            Number::Syscall(num) => trace::syscall_name(num),
+            Number::Gdbcall(num) => format!("{num:?}"),
+            Number::Enarxcall(num) => format!("{num:?}"),
+        }
+    }
+
+    /// Whether the call does not return to the backend.
+    pub(crate) fn is_exit(&self) -> bool {
+        matches!(
+            self.number,
+            Number::Syscall(libc::SYS_exit | libc::SYS_exit_group)
+        )
+    }
+
+    /// The errno, if the executed call failed.
+    pub(crate) fn errno(&self) -> Option<libc::c_int> {
+        let ret = self.ret as isize;
+        (-4095..0).contains(&ret).then_some(-ret as _)
+    }
+}
+
+/// What the observers took of a host call before it was executed.
+pub(crate) struct Hook {
+    trace: Option<trace::Pending>,
+    frames: Option<backtrace::Frames>,
+}
+
+impl Hook {
+    /// Observes `item` of a keep with `metrics`, before it is executed.
+    pub(crate) fn before(item: &Item<'_>, metrics: Option<&Metrics>) -> Self {
+        let call = Call::new(item);
+        if let Some(metrics) = metrics {
+            metrics.before(&call);
+        }
+        Self {
+            trace: trace::before(&call),
+            frames: backtrace::before(&call),
+        }
+    }
+
+    /// Observes `item` again, after it was executed.
+    pub(crate) fn after(self, item: &Item<'_>, metrics: Option<&Metrics>) {
+        let call = Call::new(item);
+        if let Some(metrics) = metrics {
+            metrics.after(&call);
+        }
+        if let Some(pending) = self.trace {
+            pending.after(&call);
+        }
+        if let Some(frames) = self.frames {
+            frames.print();
+        }
+    }
+}
+
+/// Reborrows `item`, so it can be executed and still be looked at afterwards.
+pub(crate) fn reborrow<'a>(item: &'a mut Item<'_>) -> Item<'a> {
+    match item {
+        Item::Syscall(call, data) => Item::Syscall(call, data),
+        Item::Gdbcall(call, data) => Item::Gdbcall(call, data),
+        Item::Enarxcall(call, data) => Item::Enarxcall(call, data),
+    }
+}
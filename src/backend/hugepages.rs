@@ -0,0 +1,196 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Huge pages backing the guest memory of KVM keeps.
+//!
+//! Guest memory backed by 2 MiB pages takes far fewer EPT entries and TLB misses than memory
+//! backed by 4 KiB pages. Only memory, whose guest address and size are aligned to a huge
+//! page, is backed by huge pages, the rest of the memory of a keep uses normal pages.
+
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::FromRawFd;
+use std::ptr;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use libc::{
+    madvise, memfd_create, mmap, munmap, MADV_HUGEPAGE, MAP_ANONYMOUS, MAP_FAILED, MAP_NORESERVE,
+    MAP_PRIVATE, MFD_CLOEXEC, MFD_HUGETLB, MFD_HUGE_2MB, PROT_NONE,
+};
+use mmarinus::{perms, Map, Private};
+use once_cell::sync::OnceCell;
+
+/// The size of a huge page
+pub const HUGE_PAGE_SIZE: usize = 1 << HUGE_PAGE_SHIFT;
+
+/// The exponent of the size of a huge page
+pub const HUGE_PAGE_SHIFT: usize = 21;
+
+/// The huge pages installed for this process.
+static MODE: OnceCell<Mode> = OnceCell::new();
+
+/// How guest memory is backed by huge pages.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Back all guest memory by normal pages
+    #[default]
+    Off,
+    /// Ask the kernel to back guest memory by transparent huge pages
+    Thp,
+    /// Back guest memory by huge pages of the hugetlbfs pool
+    Hugetlb,
+}
+
+/// Convert a str to a Mode. This is how Clap parses CLI args.
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "thp" => Ok(Self::Thp),
+            "hugetlb" => Ok(Self::Hugetlb),
+            _ => Err(anyhow!("unknown huge pages mode {:?}", s)),
+        }
+    }
+}
+
+/// Installs the huge pages mode for all keeps of this process.
+pub fn install(mode: Mode) -> Result<()> {
+    if mode == Mode::Thp {
+        let path = "/sys/kernel/mm/transparent_hugepage/enabled";
+        let enabled = fs::read_to_string(path)
+            .with_context(|| format!("failed to read `{path}`, is THP supported?"))?;
+        if enabled.contains("[never]") {
+            bail!("transparent huge pages are disabled in `{path}`");
+        }
+    }
+
+    MODE.set(mode)
+        .map_err(|_| anyhow!("huge pages are already installed"))
+}
+
+fn mode() -> Mode {
+    MODE.get().copied().unwrap_or_default()
+}
+
+/// Returns `true`, if `len` bytes of guest memory at `guest` are backed by huge pages.
+pub(crate) fn eligible(guest: u64, len: u64) -> bool {
+    let huge = HUGE_PAGE_SIZE as u64;
+    mode() != Mode::Off && len > 0 && guest % huge == 0 && len % huge == 0
+}
+
+/// Allocates `len` bytes of anonymous memory to back guest memory at `guest`.
+///
+/// The memory is backed by huge pages, if it is [`eligible`]. Private guest memory lives in the
+/// guest memfd of its slot, so the host memory allocated for it is never backed by huge pages.
+pub(crate) fn map(len: usize, guest: u64, is_private: bool) -> io::Result<Map<perms::ReadWrite>> {
+    if is_private || !eligible(guest, len as u64) {
+        return Map::bytes(len)
+            .anywhere()
+            .anonymously()
+            .with(perms::ReadWrite)
+            .map_err(|e| e.err);
+    }
+
+    match mode() {
+        Mode::Hugetlb => hugetlb(len),
+        _ => thp(len),
+    }
+}
+
+/// Moves the initial guest memory at `guest` to huge pages, if it is [`eligible`].
+pub(crate) fn back(pages: Map<perms::ReadWrite>, guest: u64) -> io::Result<Map<perms::ReadWrite>> {
+    if !eligible(guest, pages.len() as u64) {
+        return Ok(pages);
+    }
+
+    let mut huge = map(pages.len(), guest, false)?;
+    huge.copy_from_slice(&pages);
+    Ok(huge)
+}
+
+/// Maps `len` bytes of hugetlbfs pages.
+fn hugetlb(len: usize) -> io::Result<Map<perms::ReadWrite>> {
+    let name = b"enarx-guest\0";
+    let flags = MFD_CLOEXEC | MFD_HUGETLB | MFD_HUGE_2MB;
+    let fd = unsafe { memfd_create(name.as_ptr().cast(), flags) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Safety: we've just created the file descriptor.
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.set_len(len as u64)?;
+
+    // The huge pages of the private mapping are reserved, when it is created, and stay mapped
+    // after the file is closed.
+    Map::bytes(len)
+        .anywhere()
+        .from(&mut file, 0)
+        .with_kind(Private)
+        .with(perms::ReadWrite)
+        .map_err(|e| e.err)
+}
+
+/// Maps `len` bytes of memory aligned to a huge page, which the kernel backs by transparent
+/// huge pages.
+fn thp(len: usize) -> io::Result<Map<perms::ReadWrite>> {
+    // Reserve enough address space to align the mapping.
+    let size = len + HUGE_PAGE_SIZE;
+    let flags = MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE;
+    let reserved = unsafe { mmap(ptr::null_mut(), size, PROT_NONE, flags, -1, 0) };
+    if reserved == MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    let reserved = reserved as usize;
+    let start = (reserved + HUGE_PAGE_SIZE - 1) & !(HUGE_PAGE_SIZE - 1);
+
+    // Safety: the memory at `start` is part of the reservation above.
+    let map = unsafe {
+        Map::bytes(len)
+            .onto(start)
+            .anonymously()
+            .with(perms::ReadWrite)
+    };
+
+    // Return the rest of the reservation.
+    unsafe {
+        if start > reserved {
+            munmap(reserved as _, start - reserved);
+        }
+        if reserved + size > start + len {
+            munmap((start + len) as _, reserved + size - start - len);
+        }
+    }
+
+    let map = map.map_err(|e| {
+        unsafe { munmap(start as _, len) };
+        e.err
+    })?;
+
+    if unsafe { madvise(map.as_ptr() as _, len, MADV_HUGEPAGE) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn thp_alignment() {
+        let len = 2 * HUGE_PAGE_SIZE;
+        let mut map = thp(len).unwrap();
+        assert_eq!(map.addr() % HUGE_PAGE_SIZE, 0);
+        assert_eq!(map.len(), len);
+        map[len - 1] = 1;
+    }
+
+    #[test]
+    fn parse_mode() {
+        assert_eq!("off".parse::<Mode>().unwrap(), Mode::Off);
+        assert_eq!("THP".parse::<Mode>().unwrap(), Mode::Thp);
+        assert_eq!("hugetlb".parse::<Mode>().unwrap(), Mode::Hugetlb);
+        assert!("huge".parse::<Mode>().is_err());
+    }
+}
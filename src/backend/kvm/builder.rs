@@ -3,6 +3,7 @@
 use super::config::Config;
 use super::mem::{Region, Slot};
 use super::KvmKeepPersonality;
+use crate::backend::{hugepages, limits, metrics};
 
 use std::convert::TryFrom;
 use std::mem::align_of;
@@ -60,6 +61,9 @@ impl super::super::Mapper for Builder {
             );
         }
 
+        let pages = hugepages::back(pages, to as u64)
+            .context("Failed to back guest memory by huge pages")?;
+
         let slot = Slot::new(
             &mut self.vm_fd,
             self.regions.len() as u32,
@@ -105,6 +109,10 @@ impl TryFrom<Builder> for Arc<dyn super::super::Keep> {
             num_cpus: 1,
             cpu_fds: vec![vcpu_fd],
             regions: builder.regions,
+            released: Vec::new(),
+            metrics: metrics::keep(),
+            limits: limits::keep(),
+            registers: Default::default(),
             sallyport_block_size: builder.config.sallyport_block_size,
             sallyports: builder.sallyports,
             personality: KvmKeepPersonality(()),
@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Core dumps of crashed KVM keeps.
+
+use super::{Keep, KeepPersonality};
+use crate::backend::coredump::{self, Segment, Thread, PF_R, PF_W, PF_X};
+
+use std::os::fd::AsRawFd;
+
+use kvm_ioctls::VcpuFd;
+use libc::user_regs_struct;
+use tracing::error;
+
+const PAGE_SIZE: u64 = 4096;
+
+const PTE_PRESENT: u64 = 1;
+const PTE_WRITABLE: u64 = 1 << 1;
+const PTE_HUGE: u64 = 1 << 7;
+const PTE_NO_EXECUTE: u64 = 1 << 63;
+const PTE_ADDR: u64 = 0x000F_FFFF_FFFF_F000;
+
+impl<P: KeepPersonality> Keep<P> {
+    /// Returns the host memory backing `len` bytes of guest memory at `guest_phys`.
+    fn guest_memory(&self, guest_phys: u64, len: u64) -> Option<&[u8]> {
+        self.regions.iter().find_map(|region| {
+            let guest = region.as_guest();
+            let offset = guest_phys.checked_sub(guest.start.as_u64())?;
+            let end = offset.checked_add(len)?;
+            (end <= guest.count).then(|| &region.backing()[offset as usize..end as usize])
+        })
+    }
+
+    /// Appends the memory mapped by the page table at `table` of paging `level` to `segments`.
+    ///
+    /// `base` is the virtual address mapped by the first entry of the table and `flags` are the
+    /// permissions granted by the tables above.
+    fn walk<'a>(
+        &'a self,
+        table: u64,
+        level: u32,
+        base: u64,
+        flags: u32,
+        segments: &mut Vec<Segment<'a>>,
+    ) {
+        let Some(table) = self.guest_memory(table, PAGE_SIZE) else {
+            return;
+        };
+        let size = PAGE_SIZE << (9 * (level - 1));
+
+        for (i, entry) in table.chunks_exact(8).enumerate() {
+            let entry = u64::from_le_bytes(entry.try_into().unwrap());
+            if entry & PTE_PRESENT == 0 {
+                continue;
+            }
+
+            let mut vaddr = base + i as u64 * size;
+            if level == 4 && i >= 256 {
+                // Canonical upper half
+                vaddr |= 0xFFFF_0000_0000_0000;
+            }
+
+            let mut flags = flags;
+            if entry & PTE_WRITABLE == 0 {
+                flags &= !PF_W;
+            }
+            if entry & PTE_NO_EXECUTE != 0 {
+                flags &= !PF_X;
+            }
+
+            if level > 1 && entry & PTE_HUGE == 0 {
+                self.walk(entry & PTE_ADDR, level - 1, vaddr, flags, segments);
+                continue;
+            }
+
+            // Huge pages might only be partially backed by memory.
+            let paddr = entry & PTE_ADDR & !(size - 1);
+            match self.guest_memory(paddr, size) {
+                Some(data) => coredump::push(segments, vaddr, paddr, flags, data),
+                None => {
+                    for offset in (0..size).step_by(PAGE_SIZE as usize) {
+                        if let Some(data) = self.guest_memory(paddr + offset, PAGE_SIZE) {
+                            coredump::push(segments, vaddr + offset, paddr + offset, flags, data);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the registers of `vcpu_fd` in the layout of a core dump.
+fn registers(vcpu_fd: &VcpuFd) -> Result<user_regs_struct, kvm_ioctls::Error> {
+    let regs = vcpu_fd.get_regs()?;
+    let sregs = vcpu_fd.get_sregs()?;
+
+    // SAFETY: `user_regs_struct` is a plain struct of integers.
+    let mut user_regs: user_regs_struct = unsafe { std::mem::zeroed() };
+    user_regs.rax = regs.rax;
+    user_regs.rbx = regs.rbx;
+    user_regs.rcx = regs.rcx;
+    user_regs.rdx = regs.rdx;
+    user_regs.rsi = regs.rsi;
+    user_regs.rdi = regs.rdi;
+    user_regs.rsp = regs.rsp;
+    user_regs.rbp = regs.rbp;
+    user_regs.r8 = regs.r8;
+    user_regs.r9 = regs.r9;
+    user_regs.r10 = regs.r10;
+    user_regs.r11 = regs.r11;
+    user_regs.r12 = regs.r12;
+    user_regs.r13 = regs.r13;
+    user_regs.r14 = regs.r14;
+    user_regs.r15 = regs.r15;
+    user_regs.rip = regs.rip;
+    user_regs.eflags = regs.rflags;
+    user_regs.cs = sregs.cs.selector.into();
+    user_regs.ss = sregs.ss.selector.into();
+    user_regs.ds = sregs.ds.selector.into();
+    user_regs.es = sregs.es.selector.into();
+    user_regs.fs = sregs.fs.selector.into();
+    user_regs.gs = sregs.gs.selector.into();
+    user_regs.fs_base = sregs.fs.base;
+    user_regs.gs_base = sregs.gs.base;
+    Ok(user_regs)
+}
+
+/// Remembers the registers of `vcpu_fd`, which is about to execute a host call, for the core
+/// dump of another vCPU.
+///
+/// The other vCPUs cannot be stopped to read their registers, when one of them crashes, so the
+/// core dump shows the vCPUs running at the time with the registers of their last host call.
+pub(super) fn record<P: KeepPersonality>(keep: &Keep<P>, vcpu_fd: &VcpuFd) {
+    match registers(vcpu_fd) {
+        Ok(regs) => {
+            keep.registers
+                .lock()
+                .unwrap()
+                .insert(vcpu_fd.as_raw_fd(), regs);
+        }
+        Err(e) => error!("Failed to read the registers for the core dump: {e}"),
+    }
+}
+
+/// Writes a core dump of the memory mapped by `vcpu_fd` and the registers of all vCPUs, the
+/// ones of `vcpu_fd` first.
+pub(super) fn dump<P: KeepPersonality>(keep: &Keep<P>, vcpu_fd: &VcpuFd) {
+    let (regs, cr3) = match (registers(vcpu_fd), vcpu_fd.get_sregs()) {
+        (Ok(regs), Ok(sregs)) => (regs, sregs.cr3),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Failed to read the registers for the core dump: {e}");
+            return;
+        }
+    };
+
+    let mut segments = Vec::new();
+    keep.walk(cr3 & PTE_ADDR, 4, 0, PF_R | PF_W | PF_X, &mut segments);
+
+    let mut threads = vec![Thread { tid: 1, regs }];
+    let others = keep.registers.lock().unwrap();
+    threads.extend(
+        others
+            .iter()
+            .filter(|(&fd, _)| fd != vcpu_fd.as_raw_fd())
+            .zip(2..)
+            .map(|((_, &regs), tid)| Thread { tid, regs }),
+    );
+
+    coredump::dump(&segments, &threads);
+}
@@ -8,10 +8,12 @@ pub fn dev_kvm() -> Datum {
     let dev_kvm = std::path::Path::new("/dev/kvm");
 
     Datum {
+        id: "dev-kvm",
+        parent: None,
         name: "Driver".into(),
         pass: dev_kvm.exists(),
         info: Some("/dev/kvm".into()),
-        mesg: None,
+        remedy: None,
     }
 }
 
@@ -23,15 +25,19 @@ pub fn kvm_version() -> Datum {
     };
 
     Datum {
-        name: " API Version".into(),
+        id: "api-version",
+        parent: Some("dev-kvm"),
+        name: "API Version".into(),
         pass,
         info,
-        mesg: None,
+        remedy: None,
     }
 }
 
 pub const CPUIDS: &[CpuId] = &[
     CpuId {
+        id: "cpu",
+        parent: None,
         name: "CPU",
         leaf: 0x80000000,
         subl: 0x00000000,
@@ -39,14 +45,18 @@ pub const CPUIDS: &[CpuId] = &[
         vend: None,
     },
     CpuId {
-        name: " CPU supports FSGSBASE instructions",
+        id: "fsgsbase",
+        parent: Some("cpu"),
+        name: "CPU supports FSGSBASE instructions",
         leaf: 0x00000007,
         subl: 0x00000000,
         func: |res| (res.ebx & 0x1 != 0, None),
         vend: None,
     },
     CpuId {
-        name: " CPU supports RDRAND instruction",
+        id: "rdrand",
+        parent: Some("cpu"),
+        name: "CPU supports RDRAND instruction",
         leaf: 0x00000001,
         subl: 0x00000000,
         func: |res| (res.ecx & (1 << 30) != 0, None),
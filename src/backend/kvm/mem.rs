@@ -1,9 +1,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
-use std::os::fd::{AsFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+
+use crate::backend::numa;
 
 use iocuddle::{Group, Ioctl, Write, WriteRead};
 use kvm_ioctls::VmFd;
+use libc::{fallocate, madvise, FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE, MADV_DONTNEED};
 use lset::Span;
 use mmarinus::{perms, Map};
 use x86_64::{PhysAddr, VirtAddr};
@@ -17,11 +21,80 @@ const KVM_MEM_PRIVATE: u32 = 0x04;
 pub struct Region {
     slot: Slot,
     backing: Map<perms::ReadWrite>,
+    /// Bytes of the region returned by the guest with `enarxcall::DeflateMemory`
+    deflated: u64,
 }
 
 impl Region {
     pub fn new(slot: Slot, backing: Map<perms::ReadWrite>) -> Self {
-        Self { slot, backing }
+        Self {
+            slot,
+            backing,
+            deflated: 0,
+        }
+    }
+
+    /// Account for `deflated` bytes of the region already returned by the guest.
+    pub fn with_deflated(mut self, deflated: u64) -> Self {
+        self.deflated = deflated.min(self.slot.memory_size);
+        self
+    }
+
+    /// The index of the memory slot of the region.
+    pub fn slot(&self) -> u32 {
+        self.slot.slot
+    }
+
+    /// Release the memory backing `count` bytes at `offset` into the region.
+    ///
+    /// The memory reads as zeroes, when it is accessed again.
+    pub fn release(&mut self, offset: u64, count: u64, is_private: bool) -> io::Result<()> {
+        let end = offset
+            .checked_add(count)
+            .filter(|end| *end <= self.slot.memory_size)
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::EINVAL))?;
+
+        let addr = self.backing_mut()[offset as usize..end as usize].as_mut_ptr();
+        if unsafe { madvise(addr.cast(), count as usize, MADV_DONTNEED) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // The private memory was already freed, when the guest converted it to shared, but the
+        // guest might not have done so.
+        if let Some(fd) = self.restricted_fd().filter(|_| is_private) {
+            let mode = FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE;
+            if unsafe { fallocate(fd.as_raw_fd(), mode, offset as i64, count as i64) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        self.deflated = self
+            .deflated
+            .saturating_add(count)
+            .min(self.slot.memory_size);
+        Ok(())
+    }
+
+    /// Account for `count` bytes of the region reclaimed by the guest.
+    pub fn reclaim(&mut self, count: u64) {
+        self.deflated = self.deflated.saturating_sub(count);
+    }
+
+    /// Returns `true`, if all memory of the region was returned by the guest.
+    pub fn is_deflated(&self) -> bool {
+        self.deflated == self.slot.memory_size
+    }
+
+    /// The bytes of the region, which are backed by host memory.
+    pub fn in_use(&self) -> u64 {
+        self.slot.memory_size - self.deflated
+    }
+
+    /// Remove the memory slot of the region from the VM.
+    pub fn remove(mut self, vm_fd: &mut VmFd) -> io::Result<()> {
+        self.slot.memory_size = 0;
+        KVM_SET_USER_MEMORY_REGION2.ioctl(vm_fd, &self.slot)?;
+        Ok(())
     }
 
     #[allow(dead_code)]
@@ -75,6 +148,7 @@ impl Slot {
         is_private: bool,
     ) -> std::io::Result<Self> {
         let memory_size = u64::try_from(backing_memory.len()).unwrap();
+        numa::bind(backing_memory.addr(), backing_memory.len())?;
 
         // Optionally allocate private memory.
         let restricted_fd = is_private
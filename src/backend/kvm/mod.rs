@@ -4,13 +4,18 @@ use super::Loader;
 use data::{dev_kvm, kvm_version, CPUIDS};
 use mem::{Region, Slot};
 
-use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::os::fd::RawFd;
+use std::sync::{Arc, Mutex};
 
+use crate::backend::hugepages;
+use crate::backend::limits::KeepLimits;
+use crate::backend::metrics::Metrics;
 use crate::backend::Signatures;
 use anyhow::Result;
 use kvm_ioctls::Kvm;
 use kvm_ioctls::{VcpuFd, VmFd};
-use lset::Contains;
+use lset::{Contains, Line, Span};
 use mmarinus::{perms, Map};
 use sallyport::item::enarxcall::Payload;
 use sallyport::item::Item;
@@ -18,8 +23,10 @@ use x86_64::{PhysAddr, VirtAddr};
 
 pub mod builder;
 pub mod config;
+mod coredump;
 pub mod data;
 pub mod mem;
+pub mod snapshot;
 pub mod thread;
 
 pub(crate) const KVM_HC_MAP_GPA_RANGE: u64 = 12;
@@ -29,6 +36,10 @@ pub trait KeepPersonality: Send + Sync + 'static {
         Ok(())
     }
 
+    fn unmap(_vm_fd: &mut VmFd, _region: &Region) -> std::io::Result<()> {
+        Ok(())
+    }
+
     fn enarxcall<'a>(
         &mut self,
         enarxcall: &'a mut Payload,
@@ -50,6 +61,15 @@ pub struct Keep<P: KeepPersonality + Send> {
     pub sallyport_block_size: usize,
     pub sallyports: Vec<Option<VirtAddr>>,
     pub regions: Vec<Region>,
+    /// Guest memory of regions removed by `enarxcall::DeflateMemory`
+    pub released: Vec<Span<PhysAddr, u64>>,
+    /// The metrics of the keep, if they are exported
+    pub metrics: Option<Arc<Metrics>>,
+    /// The resource limits of the keep
+    pub limits: Arc<KeepLimits>,
+    /// The registers of every vCPU by its file descriptor as of its last host call, if core
+    /// dumps are enabled
+    pub registers: Mutex<BTreeMap<RawFd, libc::user_regs_struct>>,
     pub personality: P,
 }
 
@@ -61,13 +81,11 @@ impl<P: KeepPersonality> Keep<P> {
         to: usize,
         is_private: bool,
     ) -> std::io::Result<&mut Region> {
-        let slot = Slot::new(
-            &mut self.vm_fd,
-            self.regions.len() as u32,
-            &pages,
-            to as u64,
-            is_private,
-        )?;
+        // Slots of removed regions are reused.
+        let slot_index = (0..)
+            .find(|i| self.regions.iter().all(|region| region.slot() != *i))
+            .unwrap();
+        let slot = Slot::new(&mut self.vm_fd, slot_index, &pages, to as u64, is_private)?;
         let region = Region::new(slot, pages);
 
         P::map(&mut self.vm_fd, &region, is_private)?;
@@ -76,6 +94,106 @@ impl<P: KeepPersonality> Keep<P> {
 
         Ok(self.regions.last_mut().unwrap())
     }
+
+    /// The bytes of guest memory backed by host memory.
+    pub fn memory(&self) -> u64 {
+        self.regions.iter().map(Region::in_use).sum()
+    }
+
+    /// Splits `len` bytes of guest memory at `addr` into the parts in each region.
+    ///
+    /// Returns the index of the region, the offset into it and the length of every part, or
+    /// `EINVAL`, if some of the memory is in no region.
+    fn split(&self, addr: u64, len: u64) -> std::io::Result<Vec<(usize, u64, u64)>> {
+        let end = addr
+            .checked_add(len)
+            .filter(|_| len > 0)
+            .ok_or_else(|| std::io::Error::from_raw_os_error(libc::EINVAL))?;
+
+        let parts: Vec<_> = self
+            .regions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, region)| {
+                let guest = Line::from(region.as_guest());
+                let start = addr.max(guest.start.as_u64());
+                let stop = end.min(guest.end.as_u64());
+                (start < stop).then(|| (index, start - guest.start.as_u64(), stop - start))
+            })
+            .collect();
+
+        if parts.iter().map(|(_, _, count)| count).sum::<u64>() != len {
+            return Err(std::io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        Ok(parts)
+    }
+
+    /// Release `len` bytes of guest memory at `addr` for `enarxcall::DeflateMemory`.
+    ///
+    /// The memory might span several regions, e.g. if the guest ballooned it in several steps.
+    /// Regions, whose memory was released completely, are removed to free their memory slot.
+    pub fn deflate(&mut self, addr: u64, len: u64, is_private: bool) -> std::io::Result<()> {
+        let parts = self.split(addr, len)?;
+        for &(index, offset, count) in &parts {
+            self.regions[index].release(offset, count, is_private)?;
+        }
+
+        // The parts are ordered by region, so removing from the back keeps the indices valid.
+        for &(index, ..) in parts.iter().rev() {
+            if self.regions[index].is_deflated() {
+                let region = self.regions.remove(index);
+                let guest = region.as_guest();
+                P::unmap(&mut self.vm_fd, &region)?;
+                region.remove(&mut self.vm_fd)?;
+                self.released.push(guest);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reclaim `len` bytes of guest memory at `addr` released by [`Self::deflate`].
+    ///
+    /// Returns the host address of the first byte of the memory or `None`, if it was never
+    /// released.
+    pub fn reclaim(
+        &mut self,
+        addr: u64,
+        len: u64,
+        is_private: bool,
+    ) -> std::io::Result<Option<VirtAddr>> {
+        let end = addr.saturating_add(len);
+        let overlaps = |guest: &Span<PhysAddr, u64>| {
+            let guest = Line::from(*guest);
+            guest.start.as_u64() < end && addr < guest.end.as_u64()
+        };
+
+        let known = self
+            .regions
+            .iter()
+            .any(|region| overlaps(&region.as_guest()))
+            || self.released.iter().any(overlaps);
+        if !known {
+            return Ok(None);
+        }
+
+        // Map removed regions again as released completely, before taking back the reclaimed
+        // parts of all regions.
+        while let Some(index) = self.released.iter().position(overlaps) {
+            let guest = self.released.remove(index);
+            let pages = hugepages::map(guest.count as usize, guest.start.as_u64(), is_private)?;
+            let region = self.map(pages, guest.start.as_u64() as usize, is_private)?;
+            region.release(0, guest.count, false)?;
+        }
+
+        let parts = self.split(addr, len)?;
+        for &(index, _, count) in &parts {
+            self.regions[index].reclaim(count);
+        }
+
+        let (index, offset, _) = parts[0];
+        Ok(Some(self.regions[index].as_virt().start + offset))
+    }
+
     pub fn virt_from_guest_phys(&self, guest_phys: PhysAddr) -> Option<VirtAddr> {
         for region in &self.regions {
             if region.as_guest().contains(&guest_phys) {
@@ -121,6 +239,9 @@ impl crate::backend::Backend for Backend {
         exec: &[u8],
         signatures: Option<Signatures>,
     ) -> Result<Arc<dyn super::Keep>> {
+        if let Some(keep) = snapshot::restore(shim, exec)? {
+            return Ok(keep);
+        }
         builder::Builder::load(shim, exec, signatures)
     }
 
@@ -128,6 +249,16 @@ impl crate::backend::Backend for Backend {
     fn hash(&self, _shim: &[u8], _exec: &[u8]) -> Result<Vec<u8>> {
         Ok(Vec::new())
     }
+
+    #[inline]
+    fn check_shim(&self, shim: &[u8]) -> Result<()> {
+        builder::Builder::check(shim)
+    }
+
+    #[inline]
+    fn debuggable(&self, _shim: &[u8]) -> Result<bool> {
+        Ok(true)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,465 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Snapshots of plain KVM keeps.
+//!
+//! A snapshot is taken, when the exec reads its arguments from fd 3, i.e. after the shim booted
+//! and the exec initialized, but before the exec knows its workload. It holds the guest memory,
+//! the state of the only vCPU and the sallyport blocks of the keep, including the block with the
+//! pending `read`. A keep restored from it executes that `read` on fd 3 of the restoring
+//! process first, so it runs whatever package is handed to it. The keep has not opened any host
+//! files up to then, so there is no other host state to restore. The block is handed back with
+//! the [`RESUMED`](item::RESUMED) flag, so the shim anchors its clock at the host clock again.
+//!
+//! SEV keeps can not be snapshotted, since their memory is encrypted.
+
+use super::builder::kvm_new_vcpu;
+use super::mem::{Region, Slot};
+use super::{Keep, KeepPersonality, KvmKeepPersonality};
+use crate::backend::{hugepages, limits, metrics};
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::mem::size_of;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::{ptr, slice};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use camino::Utf8PathBuf;
+use kvm_bindings::{kvm_msr_entry, Msrs};
+use kvm_ioctls::{Kvm, VcpuFd};
+use lset::{Contains, Span};
+use sallyport::item::{self, Block, Item};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+use x86_64::{PhysAddr, VirtAddr};
+
+/// The magic bytes a snapshot starts with
+const MAGIC: &[u8; 8] = b"ENARXSNP";
+
+/// The version of the snapshot format
+const VERSION: u32 = 1;
+
+/// The file descriptor the exec reads its arguments from
+const ARGS_FD: usize = 3;
+
+const PAGE_SIZE: usize = 4096;
+
+/// Whether a snapshot is still to be taken.
+static ARMED: AtomicBool = AtomicBool::new(false);
+
+/// The snapshot to take, until it is taken.
+static SAVE: Mutex<Option<Target>> = Mutex::new(None);
+
+/// The snapshot to restore the keep from, until the keep is built.
+static RESTORE: Mutex<Option<Target>> = Mutex::new(None);
+
+/// The sallyport block a restored keep has to execute first.
+static RESUME: Mutex<Option<usize>> = Mutex::new(None);
+
+/// A snapshot file and the binaries the keep runs.
+struct Target {
+    path: Utf8PathBuf,
+    binaries: Binaries,
+}
+
+/// Digests of the shim and the exec, a snapshot is only restored with the same binaries.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Binaries {
+    shim: String,
+    exec: String,
+}
+
+impl Binaries {
+    fn new(shim: &[u8], exec: &[u8]) -> Self {
+        Self {
+            shim: format!("{:x}", Sha256::digest(shim)),
+            exec: format!("{:x}", Sha256::digest(exec)),
+        }
+    }
+}
+
+/// The part of a snapshot preceding the guest memory
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    binaries: Binaries,
+    regions: Vec<RegionHeader>,
+    /// Guest memory returned by the guest, as guest address and size
+    released: Vec<(u64, u64)>,
+    sallyport_block_size: usize,
+    /// The guest addresses of the sallyport blocks
+    sallyports: Vec<Option<u64>>,
+    /// The sallyport block with the pending `read` of the arguments
+    pending: usize,
+    vcpu: Vcpu,
+}
+
+/// A region of guest memory, whose bytes follow the header in order
+#[derive(Debug, Serialize, Deserialize)]
+struct RegionHeader {
+    guest: u64,
+    size: u64,
+    deflated: u64,
+}
+
+/// The state of a vCPU, as the raw KVM structures
+#[derive(Debug, Serialize, Deserialize)]
+struct Vcpu {
+    regs: Vec<u8>,
+    sregs: Vec<u8>,
+    fpu: Vec<u8>,
+    xsave: Vec<u8>,
+    xcrs: Vec<u8>,
+    mp_state: Vec<u8>,
+    events: Vec<u8>,
+    debugregs: Vec<u8>,
+    msrs: Vec<(u32, u64)>,
+}
+
+/// Takes a snapshot of the keep to `path`, once the exec reads its arguments.
+pub fn install(path: Utf8PathBuf, shim: &[u8], exec: &[u8]) -> Result<()> {
+    let mut save = SAVE.lock().unwrap();
+    if save.is_some() {
+        bail!("a snapshot is already installed");
+    }
+    *save = Some(Target {
+        path,
+        binaries: Binaries::new(shim, exec),
+    });
+    ARMED.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Restores the keep from the snapshot at `path`, instead of building it.
+pub fn install_restore(path: Utf8PathBuf, shim: &[u8], exec: &[u8]) -> Result<()> {
+    let mut restore = RESTORE.lock().unwrap();
+    if restore.is_some() {
+        bail!("a snapshot to restore is already installed");
+    }
+    *restore = Some(Target {
+        path,
+        binaries: Binaries::new(shim, exec),
+    });
+    Ok(())
+}
+
+/// Returns `true`, if a snapshot is still to be taken.
+pub(crate) fn armed() -> bool {
+    ARMED.load(Ordering::Relaxed)
+}
+
+/// Returns `true`, if `block` holds the `read` of the exec arguments.
+pub(crate) fn reads_args(block: Block<'_>) -> bool {
+    block.into_iter().any(|item| {
+        matches!(
+            item,
+            Item::Syscall(item::Syscall { num, argv: [fd, ..], .. }, ..)
+                if *num == libc::SYS_read as usize && *fd == ARGS_FD
+        )
+    })
+}
+
+/// Returns the sallyport block a restored keep has to execute before it runs, if any.
+pub(crate) fn resume() -> Option<usize> {
+    RESUME.lock().unwrap().take()
+}
+
+/// Returns the guest address of the host memory at `virt`.
+fn guest_phys<P: KeepPersonality>(keep: &Keep<P>, virt: VirtAddr) -> Option<u64> {
+    keep.regions
+        .iter()
+        .find(|region| region.as_virt().contains(&virt))
+        .map(|region| (region.as_guest().start + (virt - region.as_virt().start)).as_u64())
+}
+
+fn bytes<T>(value: &T) -> Vec<u8> {
+    // Safety: the KVM structures are plain old data.
+    unsafe { slice::from_raw_parts((value as *const T).cast::<u8>(), size_of::<T>()) }.to_vec()
+}
+
+fn value<T: Default>(bytes: &[u8]) -> Result<T> {
+    ensure!(
+        bytes.len() == size_of::<T>(),
+        "snapshot holds vCPU state of a different KVM version"
+    );
+    let mut value = T::default();
+    // Safety: the KVM structures are plain old data of the checked size.
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), (&mut value as *mut T).cast(), bytes.len()) };
+    Ok(value)
+}
+
+/// Takes the installed snapshot of `keep`, whose only vCPU exited to execute the sallyport
+/// block `pending` at `block`.
+pub(crate) fn save<P: KeepPersonality>(
+    keep: &Keep<P>,
+    vcpu_fd: &mut VcpuFd,
+    pending: usize,
+    block: VirtAddr,
+) -> Result<()> {
+    ARMED.store(false, Ordering::SeqCst);
+    let Some(Target { path, binaries }) = SAVE.lock().unwrap().take() else {
+        return Ok(());
+    };
+    ensure!(
+        keep.num_cpus == 1,
+        "the keep runs more than one vCPU, a snapshot can not be taken"
+    );
+
+    // Complete the port I/O, so the vCPU state is consistent and the vCPU continues right
+    // after it, once the block is executed.
+    vcpu_fd.set_kvm_immediate_exit(1);
+    let completed = vcpu_fd.run().map(|_| ());
+    vcpu_fd.set_kvm_immediate_exit(0);
+    match completed {
+        Err(e) if e.errno() == libc::EINTR => {}
+        Err(e) => return Err(e).context("failed to complete the pending port I/O"),
+        Ok(()) => bail!("the vCPU ran on, instead of completing the pending port I/O"),
+    }
+
+    let mut sallyports: Vec<_> = keep
+        .sallyports
+        .iter()
+        .map(|virt| virt.and_then(|virt| guest_phys(keep, virt)))
+        .collect();
+    sallyports[pending] =
+        Some(guest_phys(keep, block).context("sallyport block outside of guest memory")?);
+
+    let kvm_err = |what: &'static str| move |e| anyhow!("failed to get {what}: {e}");
+    let msr_list = keep
+        .kvm_fd
+        .get_msr_index_list()
+        .map_err(kvm_err("MSR list"))?;
+    let mut msrs = Vec::new();
+    for index in msr_list.as_slice() {
+        let entry = kvm_msr_entry {
+            index: *index,
+            ..Default::default()
+        };
+        let mut msr = Msrs::from_entries(&[entry]).map_err(|e| anyhow!("{e:?}"))?;
+        // Some MSRs are only readable with some CPU features.
+        if vcpu_fd.get_msrs(&mut msr).map_err(kvm_err("MSRs"))? == 1 {
+            msrs.push((*index, msr.as_slice()[0].data));
+        }
+    }
+
+    let vcpu = Vcpu {
+        regs: bytes(&vcpu_fd.get_regs().map_err(kvm_err("registers"))?),
+        sregs: bytes(&vcpu_fd.get_sregs().map_err(kvm_err("special registers"))?),
+        fpu: bytes(&vcpu_fd.get_fpu().map_err(kvm_err("FPU state"))?),
+        xsave: bytes(&vcpu_fd.get_xsave().map_err(kvm_err("XSAVE state"))?),
+        xcrs: bytes(&vcpu_fd.get_xcrs().map_err(kvm_err("XCRs"))?),
+        mp_state: bytes(&vcpu_fd.get_mp_state().map_err(kvm_err("MP state"))?),
+        events: bytes(&vcpu_fd.get_vcpu_events().map_err(kvm_err("vCPU events"))?),
+        debugregs: bytes(
+            &vcpu_fd
+                .get_debug_regs()
+                .map_err(kvm_err("debug registers"))?,
+        ),
+        msrs,
+    };
+
+    let header = Header {
+        version: VERSION,
+        binaries,
+        regions: keep
+            .regions
+            .iter()
+            .map(|region| RegionHeader {
+                guest: region.as_guest().start.as_u64(),
+                size: region.as_guest().count,
+                deflated: region.as_guest().count - region.in_use(),
+            })
+            .collect(),
+        released: keep
+            .released
+            .iter()
+            .map(|guest| (guest.start.as_u64(), guest.count))
+            .collect(),
+        sallyport_block_size: keep.sallyport_block_size,
+        sallyports,
+        pending,
+        vcpu,
+    };
+    let header = serde_json::to_vec(&header).context("failed to encode snapshot")?;
+
+    let file =
+        File::create(&path).with_context(|| format!("failed to create snapshot at `{path}`"))?;
+    let mut out = BufWriter::new(file);
+    out.write_all(MAGIC)
+        .and_then(|_| out.write_all(&(header.len() as u64).to_le_bytes()))
+        .and_then(|_| out.write_all(&header))
+        .and_then(|_| {
+            keep.regions
+                .iter()
+                .try_for_each(|region| out.write_all(region.backing()))
+        })
+        .and_then(|_| out.flush())
+        .with_context(|| format!("failed to write snapshot to `{path}`"))?;
+
+    info!("wrote snapshot of the keep to `{path}`");
+    Ok(())
+}
+
+/// Restores the keep from the installed snapshot, if there is one.
+pub(crate) fn restore(shim: &[u8], exec: &[u8]) -> Result<Option<Arc<dyn crate::backend::Keep>>> {
+    let Some(Target { path, binaries }) = RESTORE.lock().unwrap().take() else {
+        return Ok(None);
+    };
+
+    let file = File::open(&path).with_context(|| format!("failed to open snapshot `{path}`"))?;
+    let mut input = BufReader::new(file);
+    let mut magic = [0; MAGIC.len()];
+    let mut len = [0; size_of::<u64>()];
+    input
+        .read_exact(&mut magic)
+        .and_then(|_| input.read_exact(&mut len))
+        .with_context(|| format!("failed to read snapshot `{path}`"))?;
+    ensure!(&magic == MAGIC, "`{path}` is not a snapshot");
+
+    let mut header = vec![0; u64::from_le_bytes(len) as usize];
+    input
+        .read_exact(&mut header)
+        .with_context(|| format!("failed to read snapshot `{path}`"))?;
+    let header: Header =
+        serde_json::from_slice(&header).with_context(|| format!("invalid snapshot `{path}`"))?;
+    ensure!(
+        header.version == VERSION,
+        "snapshot `{path}` has the unsupported version {}",
+        header.version
+    );
+    ensure!(
+        header.binaries == binaries && binaries == Binaries::new(shim, exec),
+        "snapshot `{path}` was taken with a different shim or exec"
+    );
+
+    let mut kvm_fd = Kvm::new().context("Failed to open '/dev/kvm'")?;
+    let mut vm_fd = kvm_fd
+        .create_vm()
+        .context("Failed to create a virtual machine")?;
+
+    let mut regions = Vec::with_capacity(header.regions.len());
+    let mut page = [0; PAGE_SIZE];
+    for (index, region) in header.regions.iter().enumerate() {
+        let mut pages = hugepages::map(region.size as usize, region.guest, false)
+            .context("Failed to allocate guest memory")?;
+
+        // Only pages holding data are copied, to not allocate host memory for the rest.
+        for chunk in pages.chunks_mut(PAGE_SIZE) {
+            let page = &mut page[..chunk.len()];
+            input
+                .read_exact(page)
+                .with_context(|| format!("failed to read snapshot `{path}`"))?;
+            if page.iter().any(|byte| *byte != 0) {
+                chunk.copy_from_slice(page);
+            }
+        }
+
+        let slot = Slot::new(&mut vm_fd, index as u32, &pages, region.guest, false)?;
+        regions.push(Region::new(slot, pages).with_deflated(region.deflated));
+    }
+
+    let vcpu_fd = kvm_new_vcpu(&mut kvm_fd, &mut vm_fd, 0)?;
+    let vcpu = &header.vcpu;
+    let kvm_err = |what: &'static str| move |e| anyhow!("failed to set {what}: {e}");
+    vcpu_fd
+        .set_sregs(&value(&vcpu.sregs)?)
+        .map_err(kvm_err("special registers"))?;
+    vcpu_fd
+        .set_regs(&value(&vcpu.regs)?)
+        .map_err(kvm_err("registers"))?;
+    vcpu_fd
+        .set_fpu(&value(&vcpu.fpu)?)
+        .map_err(kvm_err("FPU state"))?;
+    vcpu_fd
+        .set_xsave(&value(&vcpu.xsave)?)
+        .map_err(kvm_err("XSAVE state"))?;
+    vcpu_fd
+        .set_xcrs(&value(&vcpu.xcrs)?)
+        .map_err(kvm_err("XCRs"))?;
+    let entries: Vec<_> = vcpu
+        .msrs
+        .iter()
+        .map(|(index, data)| kvm_msr_entry {
+            index: *index,
+            data: *data,
+            ..Default::default()
+        })
+        .collect();
+    let msrs = Msrs::from_entries(&entries).map_err(|e| anyhow!("{e:?}"))?;
+    let set = vcpu_fd.set_msrs(&msrs).map_err(kvm_err("MSRs"))?;
+    ensure!(
+        set == entries.len(),
+        "failed to set MSR {:#x}",
+        entries[set].index
+    );
+    vcpu_fd
+        .set_mp_state(value(&vcpu.mp_state)?)
+        .map_err(kvm_err("MP state"))?;
+    vcpu_fd
+        .set_vcpu_events(&value(&vcpu.events)?)
+        .map_err(kvm_err("vCPU events"))?;
+    vcpu_fd
+        .set_debug_regs(&value(&vcpu.debugregs)?)
+        .map_err(kvm_err("debug registers"))?;
+
+    let mut keep = Keep {
+        kvm_fd,
+        vm_fd,
+        num_cpus: 1,
+        cpu_fds: vec![vcpu_fd],
+        sallyport_block_size: header.sallyport_block_size,
+        sallyports: Vec::new(),
+        regions,
+        released: header
+            .released
+            .iter()
+            .map(|(start, count)| Span {
+                start: PhysAddr::new(*start),
+                count: *count,
+            })
+            .collect(),
+        metrics: metrics::keep(),
+        limits: limits::keep(),
+        registers: Default::default(),
+        personality: KvmKeepPersonality(()),
+    };
+    keep.sallyports = header
+        .sallyports
+        .iter()
+        .map(|guest| match guest {
+            Some(guest) => keep
+                .virt_from_guest_phys(PhysAddr::new(*guest))
+                .map(Some)
+                .context("sallyport block outside of guest memory"),
+            None => Ok(None),
+        })
+        .collect::<Result<_>>()?;
+    ensure!(
+        matches!(keep.sallyports.get(header.pending), Some(Some(_))),
+        "snapshot `{path}` has no pending sallyport block"
+    );
+
+    *RESUME.lock().unwrap() = Some(header.pending);
+    info!("restored the keep from snapshot `{path}`");
+    let keep: Arc<dyn crate::backend::Keep> = Arc::new(RwLock::new(keep));
+    Ok(Some(keep))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn raw_state() {
+        let mut regs = kvm_bindings::kvm_regs::default();
+        regs.rip = 0xffff_8000_0000_1000;
+        regs.rax = 42;
+
+        let restored: kvm_bindings::kvm_regs = value(&bytes(&regs)).unwrap();
+        assert_eq!(restored.rip, regs.rip);
+        assert_eq!(restored.rax, 42);
+        assert!(value::<kvm_bindings::kvm_sregs>(&bytes(&regs)).is_err());
+    }
+}
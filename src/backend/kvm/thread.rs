@@ -1,15 +1,20 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::super::Command;
+use super::snapshot;
 use super::Keep;
 use super::KeepPersonality;
 use super::KVM_HC_MAP_GPA_RANGE;
 #[cfg(feature = "gdb")]
 use crate::backend::execute_gdb;
+use crate::backend::hook::{self, Hook};
+use crate::backend::hugepages::{self, HUGE_PAGE_SIZE};
 use crate::backend::kvm::builder::kvm_new_vcpu;
+use crate::backend::metrics::{self, Metrics};
 use crate::backend::parking::THREAD_PARK;
 use crate::backend::sev::set_memory_attributes;
 use crate::backend::Keep as _;
+use crate::backend::{coredump, limits, numa, policy, signals};
 
 use std::io;
 use std::iter;
@@ -26,7 +31,6 @@ use libc::{
     c_int, fallocate, madvise, timespec, FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE, MADV_FREE,
 };
 use lset::Contains;
-use mmarinus::{perms, Map};
 use sallyport::host::deref_aligned;
 use sallyport::item::enarxcall::Payload;
 use sallyport::item::{Block, Item};
@@ -38,21 +42,28 @@ use x86_64::PhysAddr;
 pub struct Thread<P: KeepPersonality> {
     keep: Arc<RwLock<super::Keep<P>>>,
     vcpu_fd: Option<VcpuFd>,
-
-    #[cfg(feature = "gdb")]
-    gdb_fd: Option<std::net::TcpStream>,
+    metrics: Option<Arc<Metrics>>,
+    _limit: limits::ThreadSlot,
 }
 
 impl<P: KeepPersonality> Drop for Thread<P> {
     fn drop(&mut self) {
         let vcpu_fd = self.vcpu_fd.take().unwrap();
-        self.keep.write().unwrap().cpu_fds.push(vcpu_fd);
+        let mut keep = self.keep.write().unwrap();
+        keep.registers
+            .get_mut()
+            .unwrap()
+            .remove(&vcpu_fd.as_raw_fd());
+        keep.cpu_fds.push(vcpu_fd);
     }
 }
 
 impl<P: KeepPersonality> super::super::Keep for RwLock<super::Keep<P>> {
     fn spawn(self: Arc<Self>) -> Result<Option<Box<dyn super::super::Thread>>> {
         let mut this = self.write().unwrap();
+        let Some(limit) = this.limits.thread() else {
+            return Ok(None);
+        };
         let cpu_opt = this.cpu_fds.pop();
         let thread = match cpu_opt {
             None => {
@@ -68,17 +79,16 @@ impl<P: KeepPersonality> super::super::Keep for RwLock<super::Keep<P>> {
                     Box::new(Thread {
                         keep: self.clone(),
                         vcpu_fd: Some(vcpu_fd),
-                        #[cfg(feature = "gdb")]
-                        gdb_fd: None,
+                        metrics: this.metrics.clone(),
+                        _limit: limit,
                     }) as Box<dyn super::super::Thread>
                 })
             }
             Some(vcpu_fd) => Some(Box::new(Thread {
                 keep: self.clone(),
                 vcpu_fd: Some(vcpu_fd),
-
-                #[cfg(feature = "gdb")]
-                gdb_fd: None,
+                metrics: this.metrics.clone(),
+                _limit: limit,
             }) as Box<dyn super::super::Thread>),
         };
         Ok(thread)
@@ -100,19 +110,29 @@ impl<P: KeepPersonality> Thread<P> {
         assert!(pgsz.is_power_of_two());
 
         // Check that the page size is supported and addr is aligned
-        if size != pgsz || addr % size != 0 {
+        if (size != pgsz && size != HUGE_PAGE_SIZE) || addr % size != 0 {
             return Err(libc::EINVAL);
         }
-
-        // Allocate the new memory
-        let pages = Map::bytes(size * npgs)
-            .anywhere()
-            .anonymously()
-            .with(perms::ReadWrite)
-            .map_err(|e| e.err.raw_os_error().unwrap_or(libc::ENOTSUP))?;
+        let len = size.checked_mul(npgs).ok_or(libc::EINVAL)?;
 
         let mut keep = self.keep.write().unwrap();
 
+        if keep.limits.exceeds_memory(keep.memory(), len as _) {
+            return Err(libc::ENOMEM);
+        }
+
+        // Memory returned with `DeflateMemory` is reclaimed at its old address.
+        if let Some(vaddr) = keep
+            .reclaim(addr as _, len as _, is_private)
+            .map_err(|e| e.raw_os_error().unwrap_or(libc::ENOTSUP))?
+        {
+            return Ok(vaddr.as_u64() as _);
+        }
+
+        // Allocate the new memory
+        let pages = hugepages::map(len, addr as _, is_private)
+            .map_err(|e| e.raw_os_error().unwrap_or(libc::ENOTSUP))?;
+
         // Map the memory into the VM
         let vaddr = keep
             .map(pages, addr, is_private)
@@ -123,6 +143,34 @@ impl<P: KeepPersonality> Thread<P> {
         Ok(vaddr.as_u64() as _)
     }
 
+    pub fn deflate(
+        &mut self,
+        log2: usize,
+        npgs: usize,
+        addr: usize,
+        is_private: bool,
+    ) -> sallyport::Result<usize> {
+        let size: usize = 1 << log2; // Page Size
+
+        // Get the current page size
+        let pgsz = unsafe { libc::sysconf(libc::_SC_PAGE_SIZE) } as usize;
+        assert!(pgsz.is_power_of_two());
+
+        // Check that the page size is supported and addr is aligned
+        if (size != pgsz && size != HUGE_PAGE_SIZE) || addr % size != 0 {
+            return Err(libc::EINVAL);
+        }
+        let len = size.checked_mul(npgs).ok_or(libc::EINVAL)?;
+
+        self.keep
+            .write()
+            .unwrap()
+            .deflate(addr as _, len as _, is_private)
+            .map_err(|e| e.raw_os_error().unwrap_or(libc::ENOTSUP))?;
+
+        Ok(0)
+    }
+
     pub fn meminfo(&self) -> sallyport::Result<usize> {
         let keep = self.keep.read().unwrap();
 
@@ -160,6 +208,18 @@ impl<P: KeepPersonality> Thread<P> {
                 };
                 Ok(None)
             }
+
+            item::Enarxcall {
+                num: item::enarxcall::Number::DeflateMemory,
+                argv: [log2, npgs, addr, is_private, ..],
+                ret,
+            } => {
+                *ret = match self.deflate(*log2, *npgs, *addr, *is_private != 0) {
+                    Ok(n) => n,
+                    Err(e) => -e as usize,
+                };
+                Ok(None)
+            }
             item::Enarxcall {
                 num: item::enarxcall::Number::NewSallyport,
                 argv: [addr, index, ..],
@@ -208,7 +268,7 @@ impl<P: KeepPersonality> Thread<P> {
             }
             item::Enarxcall {
                 num: item::enarxcall::Number::Park,
-                argv: [val, timeout, ..],
+                argv: [val, timeout, key, ..],
                 ret,
                 ..
             } => {
@@ -226,7 +286,7 @@ impl<P: KeepPersonality> Thread<P> {
                 };
 
                 *ret = THREAD_PARK
-                    .park(*val as _, timeout.as_ref())
+                    .park(*key, *val as _, timeout.as_ref())
                     .map(|v| v as usize)
                     .unwrap_or_else(|e| -e as usize);
 
@@ -234,11 +294,10 @@ impl<P: KeepPersonality> Thread<P> {
             }
             item::Enarxcall {
                 num: item::enarxcall::Number::UnPark,
+                argv: [key, count, ..],
                 ret,
-                ..
             } => {
-                THREAD_PARK.unpark();
-                *ret = 0;
+                *ret = THREAD_PARK.unpark(*key, *count as _) as _;
                 Ok(None)
             }
             _ => return Ok(Some(Item::Enarxcall(enarxcall, data))),
@@ -253,23 +312,28 @@ impl<P: KeepPersonality> Thread<P> {
                         "Thread",
                         id = ?std::thread::current().id()
                     )
-                    .in_scope(|| loop {
-                        match thread.enter(&None)? {
-                            Command::Continue => (),
-                            Command::Exit(exit_code) => {
-                                return Ok::<i32, anyhow::Error>(exit_code);
+                    .in_scope(|| {
+                        numa::pin()?;
+                        loop {
+                            match thread.enter(&None)? {
+                                Command::Continue => (),
+                                Command::Exit(exit_code) => {
+                                    return Ok::<i32, anyhow::Error>(exit_code);
+                                }
                             }
                         }
                     });
 
                     if let Err(e) = ret {
                         error!("Thread failed: {e:#?}");
+                        metrics::finish();
                         std::process::exit(1);
                     }
                     ret
                 })
                 .unwrap_or_else(|e| {
                     error!("Thread panicked: {e:#?}");
+                    metrics::finish();
                     std::process::exit(1);
                 })
             });
@@ -336,102 +400,138 @@ where
     Ok(())
 }
 
-impl<P: KeepPersonality> super::super::Thread for Thread<P> {
-    fn enter(&mut self, _gdblisten: &Option<String>) -> Result<Command> {
-        let vcpu_fd = self.vcpu_fd.as_mut().unwrap();
-        match vcpu_fd.run()? {
-            VcpuExit::IoOut(port, data) if port == KVM_SYSCALL_TRIGGER_EXIT_THREAD => {
-                let status = data[0] as c_int + ((data[1] as c_int) << 8);
-                Ok(Command::Exit(status))
-            }
-            VcpuExit::IoOut(port, data) if port == KVM_SYSCALL_TRIGGER_PORT => {
-                debug_assert_eq!(data.len(), 2);
-                let block_nr = data[0] as usize + ((data[1] as usize) << 8);
-                let block_virt = self.keep.write().unwrap().sallyports[block_nr]
-                    .take()
-                    .unwrap();
+impl<P: KeepPersonality> Thread<P> {
+    /// Executes the sallyport block `block_nr`, which was pending in the snapshot the keep was
+    /// restored from, if `resumed`.
+    fn sallyport(
+        &mut self,
+        block_nr: usize,
+        resumed: bool,
+        _gdblisten: &Option<String>,
+    ) -> Result<Command> {
+        if coredump::enabled() {
+            super::coredump::record(&self.keep.read().unwrap(), self.vcpu_fd.as_ref().unwrap());
+        }
 
-                // If some other thread tried to use the same block, the above unwrap would have panicked.
-                let block: Block = unsafe {
-                    std::slice::from_raw_parts_mut(
-                        block_virt.as_mut_ptr::<usize>(),
-                        self.keep.read().unwrap().sallyport_block_size / size_of::<usize>(),
-                    )
-                }
-                .into();
-
-                for item in block {
-                    match item {
-                        Item::Gdbcall(_gdbcall, _data) => {
-                            #[cfg(feature = "gdb")]
-                            unsafe {
-                                execute_gdb(
-                                    _gdbcall,
-                                    _data,
-                                    &mut self.gdb_fd,
-                                    _gdblisten.as_ref().unwrap(),
-                                )
-                                .map_err(io::Error::from_raw_os_error)
-                                .context("execute_gdb")?;
-                            }
-                        }
+        let block_virt = self.keep.write().unwrap().sallyports[block_nr]
+            .take()
+            .unwrap();
 
-                        Item::Enarxcall(enarxcall, data) => {
-                            if let Some(Item::Enarxcall(enarxcall, data)) =
-                                self.kvm_enarxcall(enarxcall, data)?
-                            {
-                                let mut keep = self.keep.write().unwrap();
-                                sallyport::host::execute(
-                                    keep.personality.enarxcall(enarxcall, data)?.into_iter(),
-                                )
-                                .map_err(io::Error::from_raw_os_error)
-                                .context("sallyport::host::execute")?;
-                            }
-                        }
+        // If some other thread tried to use the same block, the above unwrap would have panicked.
+        let block = unsafe {
+            std::slice::from_raw_parts_mut(
+                block_virt.as_mut_ptr::<usize>(),
+                self.keep.read().unwrap().sallyport_block_size / size_of::<usize>(),
+            )
+        };
+        let (block, signal) = item::split_signal(block).context("empty sallyport block")?;
 
-                        // Catch exit_group for a clean shutdown
-                        Item::Syscall(
-                            item::Syscall {
-                                num,
-                                argv: [code, ..],
-                                ..
-                            },
-                            ..,
-                        ) if (*num == libc::SYS_exit_group as usize) => {
-                            trace!("exit_group({code})");
-                            std::process::exit(*code as _);
-                        }
+        // The snapshot is taken before the exec gets its arguments, so a restored keep reads them
+        // from the restoring process.
+        if snapshot::armed() && snapshot::reads_args(Block::from(&mut *block)) {
+            let keep = self.keep.read().unwrap();
+            snapshot::save(&keep, self.vcpu_fd.as_mut().unwrap(), block_nr, block_virt)?;
+        }
 
-                        // Catch exit and exit_group for a clean shutdown
-                        Item::Syscall(syscall, ..) if (syscall.num == libc::SYS_exit as usize) => {
-                            trace!(?syscall);
-                            panic!("unexpected exit syscall!");
-                        }
+        for mut item in Block::from(block) {
+            let hook = Hook::before(&item, self.metrics.as_deref());
+            match hook::reborrow(&mut item) {
+                Item::Gdbcall(_gdbcall, _data) => {
+                    #[cfg(feature = "gdb")]
+                    unsafe {
+                        execute_gdb(_gdbcall, _data, _gdblisten.as_ref().unwrap())
+                            .map_err(io::Error::from_raw_os_error)
+                            .context("execute_gdb")?;
+                    }
+                }
 
-                        Item::Syscall(ref _syscall, ..) => {
-                            #[cfg(feature = "dbg")]
-                            match (
-                                _syscall.num as libc::c_long,
-                                _syscall.argv[1] as libc::c_int,
-                            ) {
-                                (
-                                    libc::SYS_write | libc::SYS_read,
-                                    libc::STDIN_FILENO | libc::STDOUT_FILENO | libc::STDERR_FILENO,
-                                ) => {}
-                                _ => {
-                                    trace!(?_syscall);
-                                }
-                            }
+                Item::Enarxcall(enarxcall, data) => {
+                    if let Some(Item::Enarxcall(enarxcall, data)) =
+                        self.kvm_enarxcall(enarxcall, data)?
+                    {
+                        let mut keep = self.keep.write().unwrap();
+                        sallyport::host::execute(
+                            keep.personality.enarxcall(enarxcall, data)?.into_iter(),
+                        )
+                        .map_err(io::Error::from_raw_os_error)
+                        .context("sallyport::host::execute")?;
+                    }
+                }
+
+                // Catch exit_group for a clean shutdown
+                Item::Syscall(
+                    item::Syscall {
+                        num,
+                        argv: [code, ..],
+                        ..
+                    },
+                    ..,
+                ) if (*num == libc::SYS_exit_group as usize) => {
+                    trace!("exit_group({code})");
+                    metrics::finish();
+                    std::process::exit(*code as _);
+                }
+
+                // Catch exit and exit_group for a clean shutdown
+                Item::Syscall(syscall, ..) if (syscall.num == libc::SYS_exit as usize) => {
+                    trace!(?syscall);
+                    panic!("unexpected exit syscall!");
+                }
 
-                            sallyport::host::execute(iter::once(item))
-                                .map_err(io::Error::from_raw_os_error)
-                                .context("sallyport::host::execute")?;
+                Item::Syscall(_syscall, _data) => {
+                    #[cfg(feature = "dbg")]
+                    match (
+                        _syscall.num as libc::c_long,
+                        _syscall.argv[1] as libc::c_int,
+                    ) {
+                        (
+                            libc::SYS_write | libc::SYS_read,
+                            libc::STDIN_FILENO | libc::STDOUT_FILENO | libc::STDERR_FILENO,
+                        ) => {}
+                        _ => {
+                            trace!(?_syscall);
                         }
                     }
+
+                    policy::execute(iter::once(Item::Syscall(_syscall, _data)))
+                        .map_err(io::Error::from_raw_os_error)
+                        .context("sallyport::host::execute")?;
                 }
+            }
+            hook.after(&item, self.metrics.as_deref());
+        }
+        signals::post(signal);
+        // The shim anchors its clock again, as the host clock differs from the one snapshotted.
+        if resumed {
+            *signal |= item::RESUMED;
+        }
 
-                self.keep.write().unwrap().sallyports[block_nr].replace(block_virt);
-                Ok(Command::Continue)
+        self.keep.write().unwrap().sallyports[block_nr].replace(block_virt);
+        Ok(Command::Continue)
+    }
+}
+
+impl<P: KeepPersonality> super::super::Thread for Thread<P> {
+    fn enter(&mut self, _gdblisten: &Option<String>) -> Result<Command> {
+        // A restored keep first executes the host calls pending in its snapshot.
+        if let Some(block_nr) = snapshot::resume() {
+            return self.sallyport(block_nr, true, _gdblisten);
+        }
+
+        let vcpu_fd = self.vcpu_fd.as_mut().unwrap();
+        let exit = vcpu_fd.run()?;
+        if let Some(metrics) = &self.metrics {
+            metrics.exit(metrics::variant(&exit));
+        }
+        match exit {
+            VcpuExit::IoOut(port, data) if port == KVM_SYSCALL_TRIGGER_EXIT_THREAD => {
+                let status = data[0] as c_int + ((data[1] as c_int) << 8);
+                Ok(Command::Exit(status))
+            }
+            VcpuExit::IoOut(port, data) if port == KVM_SYSCALL_TRIGGER_PORT => {
+                debug_assert_eq!(data.len(), 2);
+                let block_nr = data[0] as usize + ((data[1] as usize) << 8);
+                self.sallyport(block_nr, false, _gdblisten)
             }
             VcpuExit::Hypercall(HypercallExit {
                 nr: KVM_HC_MAP_GPA_RANGE,
@@ -455,6 +555,9 @@ impl<P: KeepPersonality> super::super::Thread for Thread<P> {
             #[cfg(debug_assertions)]
             reason => {
                 let reason = format!("{reason:?}");
+                if coredump::enabled() {
+                    super::coredump::dump(&self.keep.read().unwrap(), vcpu_fd);
+                }
                 bail!(
                     "KVM error: {} {:#x?} {:#x?}",
                     reason,
@@ -464,7 +567,13 @@ impl<P: KeepPersonality> super::super::Thread for Thread<P> {
             }
 
             #[cfg(not(debug_assertions))]
-            reason => bail!("KVM error: {:?}", reason),
+            reason => {
+                let reason = format!("{reason:?}");
+                if coredump::enabled() {
+                    super::coredump::dump(&self.keep.read().unwrap(), vcpu_fd);
+                }
+                bail!("KVM error: {}", reason)
+            }
         }
     }
 }
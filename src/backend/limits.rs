@@ -0,0 +1,174 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-keep resource limits enforced by the host.
+//!
+//! A keep is refused memory beyond [`Limits::memory`] with `ENOMEM` and threads beyond
+//! [`Limits::threads`] with `EAGAIN`, so a single keep cannot starve a shared host.
+//!
+//! The limits of the host are installed before any keep is built. The limits of the package
+//! config can only lower them, once the package is opened, which might be after the keep was
+//! built, e.g. for a keep parked until its package arrives.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+/// The limits of the keeps of this process and the keeps they apply to.
+static INSTALLED: Mutex<Installed> = Mutex::new(Installed::new());
+
+struct Installed {
+    limits: Limits,
+    keeps: Vec<Weak<KeepLimits>>,
+}
+
+impl Installed {
+    const fn new() -> Self {
+        Self {
+            limits: Limits {
+                memory: None,
+                threads: None,
+            },
+            keeps: Vec::new(),
+        }
+    }
+
+    fn lower(&mut self, limits: Limits) {
+        self.limits = self.limits.min(limits);
+        self.keeps.retain(|keep| match keep.upgrade() {
+            Some(keep) => {
+                keep.lower(limits);
+                true
+            }
+            None => false,
+        });
+    }
+
+    fn keep(&mut self) -> Arc<KeepLimits> {
+        let keep = Arc::new(KeepLimits {
+            limits: Mutex::new(self.limits),
+            threads: AtomicUsize::new(0),
+        });
+        self.keeps.push(Arc::downgrade(&keep));
+        keep
+    }
+}
+
+/// The resource limits of a keep.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum bytes of host memory backing the keep
+    pub memory: Option<u64>,
+    /// The maximum number of threads running in the keep
+    pub threads: Option<usize>,
+}
+
+impl Limits {
+    /// Returns the lower of both limits of every resource.
+    pub fn min(self, other: Self) -> Self {
+        fn lower<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+            a.into_iter().chain(b).min()
+        }
+
+        Self {
+            memory: lower(self.memory, other.memory),
+            threads: lower(self.threads, other.threads),
+        }
+    }
+}
+
+impl From<enarx_config::Limits> for Limits {
+    fn from(limits: enarx_config::Limits) -> Self {
+        Self {
+            memory: limits.memory.map(|size| size.0),
+            threads: limits.threads.map(|threads| threads.get()),
+        }
+    }
+}
+
+/// Lowers the limits of all keeps of this process to `limits`, including the keeps already built.
+pub fn lower(limits: Limits) {
+    INSTALLED.lock().unwrap().lower(limits)
+}
+
+/// Returns the limits of a new keep.
+pub(crate) fn keep() -> Arc<KeepLimits> {
+    INSTALLED.lock().unwrap().keep()
+}
+
+/// The limits of a keep and the number of its running threads.
+#[derive(Debug)]
+pub struct KeepLimits {
+    limits: Mutex<Limits>,
+    threads: AtomicUsize,
+}
+
+impl KeepLimits {
+    fn lower(&self, limits: Limits) {
+        let mut current = self.limits.lock().unwrap();
+        *current = current.min(limits);
+    }
+
+    /// Returns `true`, if `used` bytes of memory plus `len` more exceed the memory limit.
+    pub(crate) fn exceeds_memory(&self, used: u64, len: u64) -> bool {
+        match self.limits.lock().unwrap().memory {
+            Some(max) => used.checked_add(len).map_or(true, |total| total > max),
+            None => false,
+        }
+    }
+
+    /// Reserves a slot for a new thread, or returns `None`, if the thread limit is reached.
+    pub(crate) fn thread(self: &Arc<Self>) -> Option<ThreadSlot> {
+        let max = self.limits.lock().unwrap().threads.unwrap_or(usize::MAX);
+        self.threads
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| ThreadSlot(self.clone()))
+    }
+}
+
+/// A running thread, which counts against the thread limit of its keep until it is dropped.
+pub(crate) struct ThreadSlot(Arc<KeepLimits>);
+
+impl Drop for ThreadSlot {
+    fn drop(&mut self) {
+        self.0.threads.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lower_built_keeps() {
+        let mut installed = Installed::new();
+        let keep = installed.keep();
+        assert!(!keep.exceeds_memory(u64::MAX, 0));
+
+        installed.lower(Limits {
+            memory: Some(1 << 30),
+            threads: Some(1),
+        });
+        installed.lower(Limits {
+            memory: Some(1 << 31),
+            threads: None,
+        });
+        assert!(!keep.exceeds_memory(1 << 29, 1 << 29));
+        assert!(keep.exceeds_memory(1 << 29, (1 << 29) + 1));
+        assert!(keep.exceeds_memory(u64::MAX, 1));
+
+        let slot = keep.thread().unwrap();
+        assert!(keep.thread().is_none());
+        drop(slot);
+        assert!(keep.thread().is_some());
+
+        // Keeps built later start out with the lowered limits.
+        assert!(installed.keep().exceeds_memory(1 << 30, 1));
+
+        // Dropped keeps are forgotten.
+        drop(keep);
+        installed.lower(Limits::default());
+        assert!(installed.keeps.is_empty());
+    }
+}
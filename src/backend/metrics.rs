@@ -0,0 +1,360 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-keep metrics for capacity planning.
+//!
+//! The host calls of a keep are counted by kind and name, together with the exits of its vCPUs or
+//! enclave threads, memory ballooning, thread spawns and the bytes transferred through sallyport.
+//! Each keep holds its own counters, which are served labeled by keep in the Prometheus text
+//! format on a Unix socket and can be written out once more, when the keep exits.
+
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Write as _};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use camino::Utf8PathBuf;
+use libc::c_long;
+use once_cell::sync::OnceCell;
+use sallyport::item::enarxcall;
+
+use super::hook::{Call, Number};
+
+/// The exporter of the metrics of the keeps of this process, if any.
+static EXPORTER: OnceCell<Exporter> = OnceCell::new();
+
+/// Syscalls, which return the number of bytes read by the host into the keep.
+const READS: &[c_long] = &[
+    libc::SYS_read,
+    libc::SYS_readv,
+    libc::SYS_pread64,
+    libc::SYS_recvfrom,
+    libc::SYS_recvmsg,
+];
+
+/// Syscalls, which return the number of bytes written by the host out of the keep.
+const WRITES: &[c_long] = &[
+    libc::SYS_write,
+    libc::SYS_writev,
+    libc::SYS_pwrite64,
+    libc::SYS_sendto,
+    libc::SYS_sendmsg,
+];
+
+/// A family of counters sharing a name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Family {
+    Hostcalls,
+    SallyportBytes,
+    IoBytes,
+    Exits,
+    BalloonBytes,
+    DeflateBytes,
+    ThreadSpawns,
+}
+
+impl Family {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Hostcalls => "enarx_hostcalls_total",
+            Self::SallyportBytes => "enarx_sallyport_bytes_total",
+            Self::IoBytes => "enarx_io_bytes_total",
+            Self::Exits => "enarx_exits_total",
+            Self::BalloonBytes => "enarx_balloon_bytes_total",
+            Self::DeflateBytes => "enarx_deflate_bytes_total",
+            Self::ThreadSpawns => "enarx_thread_spawns_total",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Self::Hostcalls => "Host calls of the keep by kind and name",
+            Self::SallyportBytes => "Bytes of the sallyport buffers of the host calls by kind",
+            Self::IoBytes => "Bytes read into or written out of the keep by syscalls",
+            Self::Exits => "Exits of the vCPUs or enclave threads of the keep by reason",
+            Self::BalloonBytes => "Bytes of memory ballooned into the keep",
+            Self::DeflateBytes => "Bytes of memory returned by the keep",
+            Self::ThreadSpawns => "Threads spawned by the keep",
+        }
+    }
+}
+
+/// The counters of a keep.
+#[derive(Default)]
+struct Counters {
+    /// The rendered labels of each counter in a family
+    values: BTreeMap<(Family, String), u64>,
+}
+
+impl Counters {
+    fn add(&mut self, family: Family, labels: String, value: u64) {
+        *self.values.entry((family, labels)).or_default() += value;
+    }
+
+    /// Renders all counters in the Prometheus text exposition format.
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let mut last = None;
+        for ((family, labels), value) in &self.values {
+            if last != Some(*family) {
+                let name = family.name();
+                let _ = writeln!(out, "# HELP {name} {}", family.help());
+                let _ = writeln!(out, "# TYPE {name} counter");
+                last = Some(*family);
+            }
+            if labels.is_empty() {
+                let _ = writeln!(out, "{} {value}", family.name());
+            } else {
+                let _ = writeln!(out, "{}{{{labels}}} {value}", family.name());
+            }
+        }
+        out
+    }
+}
+
+/// The metrics of a keep.
+#[derive(Default)]
+pub struct Metrics {
+    counters: Mutex<Counters>,
+}
+
+impl Metrics {
+    fn add(&self, family: Family, labels: String, value: u64) {
+        self.counters.lock().unwrap().add(family, labels, value);
+    }
+
+    /// Counts an exit of a vCPU or an enclave thread.
+    pub(crate) fn exit(&self, reason: String) {
+        self.add(Family::Exits, format!("reason=\"{reason}\""), 1);
+    }
+
+    /// Counts `call`, before it is executed.
+    pub(crate) fn before(&self, call: &Call<'_>) {
+        let kind = call.kind();
+        let mut counters = self.counters.lock().unwrap();
+        counters.add(
+            Family::Hostcalls,
+            format!("kind=\"{kind}\",name=\"{}\"", call.name()),
+            1,
+        );
+        counters.add(
+            Family::SallyportBytes,
+            format!("kind=\"{kind}\""),
+            call.data.len() as u64,
+        );
+    }
+
+    /// Counts the results of `call`, after it was executed.
+    pub(crate) fn after(&self, call: &Call<'_>) {
+        if call.errno().is_some() {
+            return;
+        }
+
+        let [log2, npgs, ..] = call.argv;
+        let pages = || (npgs as u64).checked_shl(log2 as u32).unwrap_or(0);
+        match call.number {
+            Number::Syscall(num) if READS.contains(&num) => self.add(
+                Family::IoBytes,
+                "direction=\"read\"".into(),
+                call.ret as u64,
+            ),
+            Number::Syscall(num) if WRITES.contains(&num) => self.add(
+                Family::IoBytes,
+                "direction=\"write\"".into(),
+                call.ret as u64,
+            ),
+            Number::Enarxcall(enarxcall::Number::Spawn) => {
+                self.add(Family::ThreadSpawns, String::new(), 1)
+            }
+            Number::Enarxcall(enarxcall::Number::BalloonMemory) => {
+                self.add(Family::BalloonBytes, String::new(), pages())
+            }
+            Number::Enarxcall(enarxcall::Number::DeflateMemory) => {
+                self.add(Family::DeflateBytes, String::new(), pages())
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Serves and dumps the metrics of the keeps of this process.
+struct Exporter {
+    keeps: Mutex<Vec<Arc<Metrics>>>,
+    /// The destination of the final dump
+    dump: Option<Mutex<Box<dyn Write + Send>>>,
+    dumped: AtomicBool,
+}
+
+impl Exporter {
+    /// Renders the counters of all keeps, labeled by the order the keeps were created in.
+    fn render(&self) -> String {
+        let mut all = Counters::default();
+        for (i, keep) in self.keeps.lock().unwrap().iter().enumerate() {
+            for ((family, labels), value) in &keep.counters.lock().unwrap().values {
+                let labels = match labels.is_empty() {
+                    true => format!("keep=\"{i}\""),
+                    false => format!("keep=\"{i}\",{labels}"),
+                };
+                all.add(*family, labels, *value);
+            }
+        }
+        all.render()
+    }
+}
+
+/// Answers every connection to `listener` with the current metrics.
+///
+/// The request is not looked at, but the answer is a minimal HTTP response, so the socket can be
+/// scraped with e.g. `curl --unix-socket`.
+fn serve(exporter: &'static Exporter, listener: UnixListener) {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request);
+
+        let body = exporter.render();
+        let _ = write!(
+            stream,
+            "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+    }
+}
+
+/// Installs the exporter of the metrics of all keeps of this process.
+///
+/// They are served on a Unix socket at `socket` and written to `dump` by [`finish`].
+pub fn install(socket: Option<Utf8PathBuf>, dump: Option<Box<dyn Write + Send>>) -> Result<()> {
+    // Open `/dev/null` to reserve fd 3, which `exec-wasmtime` expects the exec socket at
+    let _reserved = File::open("/dev/null").context("failed to open `/dev/null`")?;
+
+    let listener = socket
+        .map(|path| {
+            UnixListener::bind(&path)
+                .with_context(|| format!("failed to bind metrics socket at `{path}`"))
+        })
+        .transpose()?;
+
+    EXPORTER
+        .set(Exporter {
+            keeps: Default::default(),
+            dump: dump.map(Mutex::new),
+            dumped: AtomicBool::new(false),
+        })
+        .map_err(|_| anyhow!("metrics are already installed"))?;
+
+    if let Some(listener) = listener {
+        let exporter = EXPORTER.get().unwrap();
+        std::thread::Builder::new()
+            .name("metrics".into())
+            .spawn(move || serve(exporter, listener))
+            .context("failed to spawn metrics thread")?;
+    }
+    Ok(())
+}
+
+/// Writes the metrics to the destination given to [`install`], once.
+///
+/// It must be called before the keep exits the process.
+pub fn finish() {
+    let Some(exporter) = EXPORTER.get() else {
+        return;
+    };
+    let Some(dump) = &exporter.dump else {
+        return;
+    };
+    if exporter.dumped.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let mut dump = dump.lock().unwrap();
+    // The keep is exiting anyway, so write errors are ignored.
+    let _ = dump.write_all(exporter.render().as_bytes());
+    let _ = dump.flush();
+}
+
+/// Returns the metrics of a new keep, if an exporter is installed.
+pub(crate) fn keep() -> Option<Arc<Metrics>> {
+    let exporter = EXPORTER.get()?;
+    let metrics = Arc::new(Metrics::default());
+    exporter.keeps.lock().unwrap().push(metrics.clone());
+    Some(metrics)
+}
+
+/// Returns the name of the variant of an enum value, e.g. of a `VcpuExit`.
+pub(crate) fn variant(value: &impl Debug) -> String {
+    let name = format!("{value:?}");
+    let end = name
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(name.len());
+    name[..end].into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let mut counters = Counters::default();
+        counters.add(Family::Hostcalls, r#"kind="syscall",name="read""#.into(), 1);
+        counters.add(Family::Hostcalls, r#"kind="syscall",name="read""#.into(), 2);
+        counters.add(Family::ThreadSpawns, String::new(), 1);
+        assert_eq!(
+            counters.render(),
+            "# HELP enarx_hostcalls_total Host calls of the keep by kind and name\n\
+             # TYPE enarx_hostcalls_total counter\n\
+             enarx_hostcalls_total{kind=\"syscall\",name=\"read\"} 3\n\
+             # HELP enarx_thread_spawns_total Threads spawned by the keep\n\
+             # TYPE enarx_thread_spawns_total counter\n\
+             enarx_thread_spawns_total 1\n"
+        );
+    }
+
+    #[test]
+    fn render_keeps() {
+        let exporter = Exporter {
+            keeps: Default::default(),
+            dump: None,
+            dumped: AtomicBool::new(false),
+        };
+        for spawns in [1, 2] {
+            let metrics = Metrics::default();
+            metrics.add(Family::ThreadSpawns, String::new(), spawns);
+            metrics.exit("EEXIT".into());
+            exporter.keeps.lock().unwrap().push(Arc::new(metrics));
+        }
+        assert_eq!(
+            exporter.render(),
+            "# HELP enarx_exits_total Exits of the vCPUs or enclave threads of the keep by reason\n\
+             # TYPE enarx_exits_total counter\n\
+             enarx_exits_total{keep=\"0\",reason=\"EEXIT\"} 1\n\
+             enarx_exits_total{keep=\"1\",reason=\"EEXIT\"} 1\n\
+             # HELP enarx_thread_spawns_total Threads spawned by the keep\n\
+             # TYPE enarx_thread_spawns_total counter\n\
+             enarx_thread_spawns_total{keep=\"0\"} 1\n\
+             enarx_thread_spawns_total{keep=\"1\"} 2\n"
+        );
+    }
+
+    #[test]
+    fn variant_name() {
+        #[derive(Debug)]
+        #[allow(dead_code)]
+        enum Exit {
+            IoOut(u16, [u8; 2]),
+            Shutdown,
+            Hlt { rip: u64 },
+        }
+
+        assert_eq!(variant(&Exit::IoOut(0xff, [0, 1])), "IoOut");
+        assert_eq!(variant(&Exit::Shutdown), "Shutdown");
+        assert_eq!(variant(&Exit::Hlt { rip: 0 }), "Hlt");
+    }
+}
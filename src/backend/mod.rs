@@ -20,10 +20,38 @@ pub(crate) mod probe;
 #[cfg(enarx_with_shim)]
 mod parking;
 
+#[cfg(enarx_with_shim)]
+pub mod policy;
+
+#[cfg(enarx_with_shim)]
+pub mod trace;
+
+#[cfg(enarx_with_shim)]
+mod hook;
+
+#[cfg(enarx_with_shim)]
+pub mod backtrace;
+
+#[cfg(enarx_with_shim)]
+pub mod coredump;
+
+#[cfg(enarx_with_shim)]
+pub mod metrics;
+
+#[cfg(enarx_with_shim)]
+pub mod limits;
+
+#[cfg(enarx_with_shim)]
+pub mod hugepages;
+
+#[cfg(enarx_with_shim)]
+pub mod numa;
+
+#[cfg(enarx_with_shim)]
+pub mod signals;
+
 #[cfg(enarx_with_shim)]
 use binary::{Binary, Loader, Mapper};
-use serde_json::json;
-use serde_json::Value;
 
 use std::fs::File;
 use std::io::Read;
@@ -37,7 +65,6 @@ use camino::Utf8PathBuf;
 use enarx_exec_wasmtime::Args;
 use libc::c_int;
 use once_cell::sync::Lazy;
-use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 #[cfg(not(enarx_with_shim))]
 #[allow(dead_code)]
@@ -142,7 +169,7 @@ pub(crate) trait Config: Sized {
     type Flags;
 
     fn flags(flags: u32) -> Self::Flags;
-    fn new(shim: &Binary<'_>, exec: &Binary<'_>, signatures: Option<Signatures>) -> Result<Self>;
+    fn new(shim: &Binary<'_>, signatures: Option<Signatures>) -> Result<Self>;
 }
 
 pub trait Backend: Sync + Send {
@@ -169,6 +196,15 @@ pub trait Backend: Sync + Send {
     /// Hash the inputs
     fn hash(&self, shim: &[u8], exec: &[u8]) -> Result<Vec<u8>>;
 
+    /// Check that an external shim can be loaded by this backend
+    fn check_shim(&self, shim: &[u8]) -> Result<()>;
+
+    /// Whether the memory and registers of keeps with `shim` can be read by the host, e.g. to
+    /// write core dumps
+    fn debuggable(&self, _shim: &[u8]) -> Result<bool> {
+        Ok(false)
+    }
+
     /// Whether or not the platform has support for this keep type
     fn have(&self) -> bool {
         !self.data().iter().fold(false, |e, d| e | !d.pass)
@@ -184,65 +220,14 @@ pub trait Backend: Sync + Send {
     fn set_args(&self, _args: Args) {}
 }
 
-impl Serialize for dyn Backend {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        fn leading_white_spaces(str: &str) -> usize {
-            str.chars().take_while(|c| c.is_whitespace()).count()
-        }
-
-        fn rec_insert(mut val: &mut Value, datum_json: Value, counter: usize) {
-            //each unwrap will never fail
-            if counter != 1 {
-                val = val
-                    .get_mut("data")
-                    .unwrap()
-                    .as_array_mut()
-                    .unwrap()
-                    .last_mut()
-                    .unwrap();
-                rec_insert(val, datum_json, counter - 1);
-            } else {
-                val.get_mut("data")
-                    .unwrap()
-                    .as_array_mut()
-                    .unwrap()
-                    .push(datum_json);
-            }
-        }
-
-        //Logic
-        let data = self.data();
-        let mut ser = Vec::<Value>::new();
-
-        for datum in data {
-            //Count the number of whitespaces.
-            let white_spaces = leading_white_spaces(&datum.name);
-
-            let datum_json = json!({
-                "name": &datum.name.trim(),
-                "pass": &datum.pass,
-                "info": &datum.info.unwrap_or("null".into()),
-                "mesg": &datum.mesg.unwrap_or("null".into()),
-                "data": [],
-            });
-
-            if white_spaces != 0 {
-                //This unwrap will never fail
-                rec_insert(ser.last_mut().unwrap(), datum_json, white_spaces);
-            } else {
-                ser.push(datum_json);
-            }
-        }
-
-        let mut backend = serializer.serialize_struct("Backend", 2)?;
-        backend.serialize_field("backend", self.name())?;
-        backend.serialize_field("data", &ser)?;
-        backend.end()
-    }
-}
-
 #[derive(serde::Serialize, Debug)]
 pub struct Datum {
+    /// The stable identifier of this datum, unique among the data of a backend.
+    pub id: &'static str,
+
+    /// The identifier of the datum this one is nested under, if any.
+    pub parent: Option<&'static str>,
+
     /// The name of this datum.
     pub name: String,
 
@@ -252,8 +237,82 @@ pub struct Datum {
     /// Short additional information to display to the user.
     pub info: Option<String>,
 
-    /// Longer explanatory message on how to resolve problems.
-    pub mesg: Option<String>,
+    /// How to resolve the problem, if this datum does not pass.
+    pub remedy: Option<Remedy>,
+}
+
+/// A known way to resolve a failing [`Datum`].
+///
+/// The codes are stable and may be matched on by tooling consuming
+/// `enarx platform info --json`.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Remedy {
+    /// The Intel SGX cache directory is missing or unreadable.
+    SgxCacheDir,
+
+    /// The Intel CRL cache is missing or outdated.
+    SgxCacheCrl,
+
+    /// The Intel FMSPC cache is missing.
+    SgxCachePck,
+
+    /// The Intel TCB cache is missing or outdated.
+    SgxCacheTcb,
+
+    /// The AMD SEV cache directory is missing or unreadable.
+    SevCacheDir,
+
+    /// The AMD CRL cache is missing or outdated.
+    SnpCacheCrl,
+
+    /// The SEV-SNP VCEK cache is missing.
+    SnpUpdate,
+
+    /// The MEMLOCK rlimit is too small to run a keep.
+    RaiseMemlock,
+}
+
+impl Remedy {
+    /// The stable code of this remedy.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::SgxCacheDir => "sgx-cache-dir",
+            Self::SgxCacheCrl => "sgx-cache-crl",
+            Self::SgxCachePck => "sgx-cache-pck",
+            Self::SgxCacheTcb => "sgx-cache-tcb",
+            Self::SevCacheDir => "sev-cache-dir",
+            Self::SnpCacheCrl => "snp-cache-crl",
+            Self::SnpUpdate => "snp-update",
+            Self::RaiseMemlock => "raise-memlock",
+        }
+    }
+
+    /// A human-readable hint on how to resolve the problem.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::SgxCacheDir => {
+                "enarx expects the directory `/var/cache/intel-sgx` to exist and be readable"
+            }
+            Self::SgxCacheCrl => {
+                "Run `enarx platform sgx cache-crl` to generate the Intel CRL cache file"
+            }
+            Self::SgxCachePck => "Run `enarx platform sgx cache-pck`",
+            Self::SgxCacheTcb => "Run `enarx platform sgx cache-tcb`",
+            Self::SevCacheDir => {
+                "enarx expects the directory `/var/cache/amd-sev` to exist and be readable"
+            }
+            Self::SnpCacheCrl => {
+                "Run `enarx platform snp cache-crl` to generate the AMD CRL cache file."
+            }
+            Self::SnpUpdate => "Run `enarx platform snp update` to generate the cache file.",
+            Self::RaiseMemlock => {
+                "The MEMLOCK rlimit must be large enough to \
+                 accommodate the Enarx shim, exec-wasmtime, and the memory pressure \
+                 requirements of the target workloads across all deployed SEV keeps."
+            }
+        }
+    }
 }
 
 pub trait Keep {
@@ -313,6 +372,10 @@ impl Backend for NotSupportedBackend {
     fn hash(&self, _shim: &[u8], _exec: &[u8]) -> Result<Vec<u8>> {
         unimplemented!()
     }
+
+    fn check_shim(&self, _shim: &[u8]) -> Result<()> {
+        unimplemented!()
+    }
 }
 
 pub static BACKENDS: Lazy<Vec<Box<dyn Backend>>> = Lazy::new(|| {
@@ -348,11 +411,16 @@ pub fn wait_for_gdb_connection(sockaddr: &str) -> std::io::Result<std::net::TcpS
     Ok(stream) // `TcpStream` implements `gdbstub::Connection`
 }
 
+/// The GDB connection shared by all threads of the keep
+///
+/// The shim lets only one thread at a time drive the GDB session.
+#[cfg(all(feature = "gdb", target_arch = "x86_64", target_os = "linux"))]
+static GDB_FD: std::sync::Mutex<Option<std::net::TcpStream>> = std::sync::Mutex::new(None);
+
 #[cfg(all(feature = "gdb", target_arch = "x86_64", target_os = "linux"))]
 pub(super) unsafe fn execute_gdb(
     gdbcall: &mut sallyport::item::Gdbcall,
     data: &mut [u8],
-    gdb_fd: &mut Option<std::net::TcpStream>,
     sockaddr: &str,
 ) -> Result<(), c_int> {
     use gdbstub::Connection;
@@ -360,6 +428,8 @@ pub(super) unsafe fn execute_gdb(
     use sallyport::item;
     use sallyport::item::gdbcall::Number;
 
+    let gdb_fd = &mut *GDB_FD.lock().unwrap();
+
     match gdbcall {
         item::Gdbcall {
             num: Number::OnSessionStart,
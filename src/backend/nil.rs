@@ -82,6 +82,15 @@ impl crate::backend::Backend for Backend {
         Ok(Vec::new())
     }
 
+    #[inline]
+    fn check_shim(&self, shim: &[u8]) -> Result<()> {
+        if !shim.is_empty() {
+            bail!("The nil backend cannot be called with a shim!")
+        }
+
+        Ok(())
+    }
+
     #[cfg(windows)]
     fn set_args(&self, args: Args) {
         self.0.write().unwrap().replace(args);
@@ -127,5 +136,7 @@ mod test {
         assert!(backend.have());
         assert!(backend.data().is_empty());
         assert!(backend.hash(&[], &[]).unwrap().is_empty());
+        assert!(backend.check_shim(&[]).is_ok());
+        assert!(backend.check_shim(&[0]).is_err());
     }
 }
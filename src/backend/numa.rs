@@ -0,0 +1,277 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Placement of keeps on the CPUs and NUMA nodes of the host.
+//!
+//! On hosts with more than one NUMA node, a keep whose threads migrate between sockets, or whose
+//! memory lives on a remote node, sees a large variance of its latency. The threads running the
+//! vCPUs of KVM and SEV keeps and the threads entering SGX enclaves are pinned to a set of CPUs,
+//! and the guest memory of KVM and SEV keeps is bound to the NUMA node of those CPUs.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem::size_of;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use libc::{c_ulong, cpu_set_t, sched_setaffinity, syscall, SYS_mbind, CPU_SET, CPU_SETSIZE};
+use once_cell::sync::OnceCell;
+use tracing::warn;
+
+/// Move pages already allocated on other nodes, see `mbind(2)`
+const MPOL_MF_MOVE: u32 = 1 << 1;
+
+/// The placement installed for this process.
+static PLACEMENT: OnceCell<Placement> = OnceCell::new();
+
+/// A set of CPUs, written like `0-3,8,10-11` as in `/sys/devices/system/node/node0/cpulist`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CpuSet(BTreeSet<usize>);
+
+/// Convert a str to a CpuSet. This is how Clap parses CLI args.
+impl FromStr for CpuSet {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cpus = BTreeSet::new();
+        for range in s.trim().split(',') {
+            let (first, last) = range.split_once('-').unwrap_or((range, range));
+            let first: usize = first
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("invalid CPU list {:?}", s))?;
+            let last: usize = last
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("invalid CPU list {:?}", s))?;
+            if first > last {
+                bail!("invalid CPU range {:?}", range);
+            }
+            cpus.extend(first..=last);
+        }
+        Ok(Self(cpus))
+    }
+}
+
+impl fmt::Display for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut cpus = self.0.iter().copied().peekable();
+        let mut first = true;
+        while let Some(start) = cpus.next() {
+            let mut end = start;
+            while cpus.next_if_eq(&(end + 1)).is_some() {
+                end += 1;
+            }
+            if !first {
+                write!(f, ",")?;
+            }
+            first = false;
+            if end == start {
+                write!(f, "{start}")?;
+            } else {
+                write!(f, "{start}-{end}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CpuSet {
+    fn is_subset(&self, other: &Self) -> bool {
+        self.0.is_subset(&other.0)
+    }
+}
+
+/// Where the keeps of this process run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Placement {
+    /// The CPUs the keep threads are pinned to
+    cpus: CpuSet,
+    /// The NUMA node the guest memory is bound to
+    node: Option<u32>,
+}
+
+/// The NUMA nodes of the host
+const NODES: &str = "/sys/devices/system/node";
+
+/// Returns the CPUs of NUMA node `node` of the `nodes` directory, or `None` for a node, which has
+/// memory, but no CPUs.
+fn node_cpus(nodes: &Path, node: u32) -> Result<Option<CpuSet>> {
+    let path = nodes.join(format!("node{node}/cpulist"));
+    let cpulist = fs::read_to_string(&path).with_context(|| {
+        format!(
+            "failed to read `{}`, is there a NUMA node {node}?",
+            path.display()
+        )
+    })?;
+    if cpulist.trim().is_empty() {
+        return Ok(None);
+    }
+    cpulist.parse().map(Some)
+}
+
+/// Returns the NUMA node of the `nodes` directory all of `cpus` belong to, if there is one.
+fn node_of(nodes: &Path, cpus: &CpuSet) -> Result<Option<u32>> {
+    let entries = match fs::read_dir(nodes) {
+        Ok(entries) => entries,
+        // Kernels without NUMA support have no nodes, so the CPUs are on no node in particular.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("failed to read `{}`", nodes.display())),
+    };
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to read `{}`", nodes.display()))?;
+        let name = entry.file_name();
+        let Some(node) = name.to_str().and_then(|name| name.strip_prefix("node")) else {
+            continue;
+        };
+        let Ok(node) = node.parse() else {
+            continue;
+        };
+        if node_cpus(nodes, node)?.is_some_and(|node_cpus| cpus.is_subset(&node_cpus)) {
+            return Ok(Some(node));
+        }
+    }
+    Ok(None)
+}
+
+/// Installs the placement of all keeps of this process.
+///
+/// Without `cpus`, the threads are pinned to the CPUs of `node`. Without `node`, the memory is
+/// bound to the node of `cpus`, unless they span more than one node.
+pub fn install(cpus: Option<CpuSet>, node: Option<u32>) -> Result<()> {
+    let placement = match (cpus, node) {
+        (None, None) => bail!("neither CPUs nor a NUMA node to place the keep on"),
+        (None, Some(node)) => Placement {
+            cpus: node_cpus(Path::new(NODES), node)?
+                .with_context(|| format!("NUMA node {node} has no CPUs to run the keep on"))?,
+            node: Some(node),
+        },
+        (Some(cpus), Some(node)) => {
+            let on_node = node_cpus(Path::new(NODES), node)?;
+            if !on_node.is_some_and(|on_node| cpus.is_subset(&on_node)) {
+                bail!("CPUs {cpus} are not all on NUMA node {node}");
+            }
+            Placement {
+                cpus,
+                node: Some(node),
+            }
+        }
+        (Some(cpus), None) => {
+            let node = node_of(Path::new(NODES), &cpus)?;
+            if node.is_none() {
+                warn!("CPUs {cpus} are not all on one NUMA node, the guest memory is not bound");
+            }
+            Placement { cpus, node }
+        }
+    };
+
+    let online = fs::read_to_string("/sys/devices/system/cpu/online")
+        .context("failed to read `/sys/devices/system/cpu/online`")?
+        .parse()?;
+    if placement.cpus.0.is_empty() || !placement.cpus.is_subset(&online) {
+        bail!("CPUs {} are not all online", placement.cpus);
+    }
+    if placement
+        .cpus
+        .0
+        .iter()
+        .any(|cpu| *cpu >= CPU_SETSIZE as usize)
+    {
+        bail!("CPUs {} exceed {CPU_SETSIZE} CPUs", placement.cpus);
+    }
+
+    PLACEMENT
+        .set(placement)
+        .map_err(|_| anyhow!("the placement is already installed"))
+}
+
+/// Pins the calling thread to the installed CPUs.
+pub(crate) fn pin() -> Result<()> {
+    let Some(placement) = PLACEMENT.get() else {
+        return Ok(());
+    };
+
+    // SAFETY: an all-zero `cpu_set_t` is the empty set, and all CPUs are below `CPU_SETSIZE`.
+    let mut set: cpu_set_t = unsafe { std::mem::zeroed() };
+    for cpu in &placement.cpus.0 {
+        unsafe { CPU_SET(*cpu, &mut set) };
+    }
+    if unsafe { sched_setaffinity(0, size_of::<cpu_set_t>(), &set) } != 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("failed to pin thread to CPUs {}", placement.cpus));
+    }
+    Ok(())
+}
+
+/// Binds `len` bytes of guest memory at `addr` to the installed NUMA node.
+///
+/// Pages already allocated on another node are moved. Private guest memory lives in the guest
+/// memfd of its slot, so it is not bound.
+pub(crate) fn bind(addr: usize, len: usize) -> io::Result<()> {
+    let Some(node) = PLACEMENT.get().and_then(|placement| placement.node) else {
+        return Ok(());
+    };
+
+    let bits = c_ulong::BITS as usize;
+    let mut mask = vec![0 as c_ulong; node as usize / bits + 1];
+    mask[node as usize / bits] |= 1 << (node as usize % bits);
+
+    // The kernel ignores the last bit of `maxnode`.
+    let maxnode = mask.len() * bits + 1;
+    let ret = unsafe {
+        syscall(
+            SYS_mbind,
+            addr,
+            len,
+            libc::MPOL_BIND,
+            mask.as_ptr(),
+            maxnode,
+            MPOL_MF_MOVE,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_cpus() {
+        let cpus: CpuSet = "0-3,8,10-11\n".parse().unwrap();
+        assert_eq!(
+            cpus.0.into_iter().collect::<Vec<_>>(),
+            [0, 1, 2, 3, 8, 10, 11]
+        );
+        assert!("".parse::<CpuSet>().is_err());
+        assert!("3-1".parse::<CpuSet>().is_err());
+        assert!("0,a".parse::<CpuSet>().is_err());
+    }
+
+    #[test]
+    fn memory_only_node() {
+        let nodes = std::env::temp_dir().join("enarx-test-numa");
+        for (node, cpulist) in [(0, "0-3\n"), (1, "\n"), (2, "4-7\n")] {
+            let dir = nodes.join(format!("node{node}"));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("cpulist"), cpulist).unwrap();
+        }
+
+        assert_eq!(node_cpus(&nodes, 1).unwrap(), None);
+        let cpus = |cpus: &str| cpus.parse::<CpuSet>().unwrap();
+        assert_eq!(node_of(&nodes, &cpus("1-2")).unwrap(), Some(0));
+        assert_eq!(node_of(&nodes, &cpus("5")).unwrap(), Some(2));
+        assert_eq!(node_of(&nodes, &cpus("3-4")).unwrap(), None);
+    }
+
+    #[test]
+    fn display_cpus() {
+        for cpus in ["0", "0-3,8,10-11", "1,3,5"] {
+            assert_eq!(cpus.parse::<CpuSet>().unwrap().to_string(), cpus);
+        }
+    }
+}
@@ -1,97 +1,236 @@
 // SPDX-License-Identifier: Apache-2.0
 
+//! Parking of keep threads on keys, e.g. the addresses of guest futexes.
+//!
+//! Keys are hashed to a fixed number of buckets. Every bucket has a parking state, which is
+//! advanced by every unpark of a key in the bucket, and a queue of the threads parked on its keys.
+//! A thread parks, as long as the state of its bucket has the expected value, so an unpark between
+//! the guest checking its futex and parking is never lost. An unpark only wakes threads parked on
+//! its key, so a futex wake does not wake all threads of the keep.
+
 use std::io;
 use std::ptr;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
 
 use libc::timespec;
 use tracing::{instrument, trace};
 
 pub(crate) static THREAD_PARK: Parking = Parking::const_default();
 
+/// The number of buckets of parked threads
+const BUCKETS: usize = 256;
+
+/// A thread parked on a key.
+#[derive(Debug)]
+struct Waiter {
+    key: usize,
+    /// The futex the thread sleeps on, set to 1, when the thread is unparked
+    unparked: AtomicU32,
+}
+
+#[derive(Debug)]
+struct State {
+    /// The parking state of all keys of the bucket
+    value: u32,
+    /// The threads parked on the keys of the bucket in the order they were parked
+    waiters: Vec<Arc<Waiter>>,
+}
+
+#[derive(Debug)]
+struct Bucket(Mutex<State>);
+
+impl Bucket {
+    const fn new() -> Self {
+        Self(Mutex::new(State {
+            value: 0,
+            waiters: Vec::new(),
+        }))
+    }
+}
+
 #[derive(Debug)]
-pub(crate) struct Parking(AtomicU32);
+pub(crate) struct Parking([Bucket; BUCKETS]);
 
 // Notes about memory ordering:
 //
-// Memory ordering is only relevant for the relative ordering of operations
-// between different variables. Even Ordering::Relaxed guarantees a
-// monotonic/consistent order when looking at just a single atomic variable.
-//
-// So, since this parker is just a single atomic variable, we only need to look
-// at the ordering guarantees we need to provide to the 'outside world'.
+// The parking state and the queue of a bucket are only accessed with the lock of the bucket held,
+// which orders all parks and unparks of its keys.
 //
-// The only memory ordering guarantee that parking and unparking provide, is
-// that things which happened before unpark() are visible on the thread
-// returning from park() afterwards. Otherwise, it was effectively unparked
-// before unpark() was called.
-//
-// In other words, unpark() needs to synchronize with the part of park() that
-// consumes the value and returns.
-//
-// This is done with a release-acquire synchronization, by using
-// Ordering::Release when increasing the value in unpark(), and using
-// Ordering::Acquire when checking for this value in park().
+// The only memory ordering guarantee that parking and unparking provide, is that things which
+// happened before unpark() are visible on the thread returning from park() afterwards. This is
+// done with a release-acquire synchronization on the futex of the waiter, by using
+// Ordering::Release when it is unparked, and using Ordering::Acquire when checking for this value
+// in park().
 impl Parking {
     pub const fn const_default() -> Self {
-        Self(AtomicU32::new(0))
+        // The constant is only used to initialize the array.
+        #[allow(clippy::declare_interior_mutable_const)]
+        const BUCKET: Bucket = Bucket::new();
+        Self([BUCKET; BUCKETS])
     }
 
-    #[instrument(level = "trace")]
+    fn bucket(&self, key: usize) -> &Bucket {
+        // Fibonacci hashing, futexes are aligned to 4 bytes.
+        let hash = (key >> 2).wrapping_mul(0x9E37_79B9_7F4A_7C15_u64 as usize);
+        &self.0[hash >> (usize::BITS - BUCKETS.trailing_zeros())]
+    }
+
+    /// Parks the current thread on `key`, as long as the parking state of `key` is `expected`.
+    ///
+    /// Returns the current parking state of `key`.
+    #[instrument(level = "trace", skip(self))]
     pub(crate) fn park(
         &self,
+        key: usize,
         expected: u32,
         timespec: Option<&timespec>,
     ) -> sallyport::Result<u32> {
-        let timespec = timespec.map_or(0, |t| t as *const _ as usize);
-        let futex_ptr = &self.0 as *const AtomicU32;
+        let bucket = self.bucket(key);
+
+        let waiter = {
+            let mut state = bucket.0.lock().unwrap();
 
-        loop {
             // No need to wait if the value already changed.
-            let val = self.0.load(Ordering::Acquire);
-            if val != expected {
-                trace!("futex value changed to {val}");
-                return Ok(val);
+            if state.value != expected {
+                trace!("parking state changed to {}", state.value);
+                return Ok(state.value);
             }
 
-            trace!("futex: FUTEX_WAIT_BITSET {futex_ptr:p} {expected} {timespec:#?}");
-            let r = unsafe {
-                libc::syscall(
-                    libc::SYS_futex,
-                    futex_ptr,
-                    libc::FUTEX_WAIT_BITSET | libc::FUTEX_PRIVATE_FLAG,
-                    expected,
-                    timespec,
-                    ptr::null::<u32>(), // This argument is unused for FUTEX_WAIT_BITSET.
-                    !0u32,              // Wait on all bits for FUTEX_WAIT_BITSET.
-                )
-            };
-
-            if r == 0 {
-                let val = self.0.load(Ordering::Acquire);
-                trace!("futex: FUTEX_WAIT_BITSET {futex_ptr:p} {expected} {timespec:#?} = 0 (val={val})");
-                return Ok(val);
-            }
+            let waiter = Arc::new(Waiter {
+                key,
+                unparked: AtomicU32::new(0),
+            });
+            state.waiters.push(waiter.clone());
+            waiter
+        };
+
+        let ret = wait(&waiter.unparked, timespec);
+
+        let mut state = bucket.0.lock().unwrap();
+        if waiter.unparked.load(Ordering::Acquire) != 0 {
+            return Ok(state.value);
+        }
+
+        // The thread was not unparked, so it is still queued.
+        state.waiters.retain(|other| !Arc::ptr_eq(other, &waiter));
+        ret.map(|_| state.value)
+    }
 
-            let err = io::Error::last_os_error().raw_os_error().unwrap();
-            trace!("futex: FUTEX_WAIT_BITSET {futex_ptr:p} {expected} {timespec:#?} = -{err}");
+    /// Unparks up to `count` threads parked on `key`.
+    ///
+    /// Returns the number of unparked threads.
+    #[instrument(level = "trace", skip(self))]
+    pub(crate) fn unpark(&self, key: usize, count: u32) -> u32 {
+        let mut state = self.bucket(key).0.lock().unwrap();
+        state.value = state.value.wrapping_add(1);
 
-            match err {
-                libc::EINTR => continue,
-                e => return Err(e),
+        let mut woken = 0;
+        state.waiters.retain(|waiter| {
+            if waiter.key != key || woken == count {
+                return true;
             }
+            woken += 1;
+            waiter.unparked.store(1, Ordering::Release);
+            wake(&waiter.unparked);
+            false
+        });
+        trace!("unparked {woken} threads");
+        woken
+    }
+}
+
+/// Waits on the futex `futex` until it is not zero or until the CLOCK_MONOTONIC time `timespec`.
+fn wait(futex: &AtomicU32, timespec: Option<&timespec>) -> sallyport::Result<()> {
+    let timespec = timespec.map_or(0, |t| t as *const _ as usize);
+    let futex_ptr = futex as *const AtomicU32;
+
+    while futex.load(Ordering::Acquire) == 0 {
+        trace!("futex: FUTEX_WAIT_BITSET {futex_ptr:p} 0 {timespec:#?}");
+        let r = unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                futex_ptr,
+                libc::FUTEX_WAIT_BITSET | libc::FUTEX_PRIVATE_FLAG,
+                0,
+                timespec,
+                ptr::null::<u32>(), // This argument is unused for FUTEX_WAIT_BITSET.
+                !0u32,              // Wait on all bits for FUTEX_WAIT_BITSET.
+            )
+        };
+
+        if r == 0 {
+            continue;
+        }
+
+        let err = io::Error::last_os_error().raw_os_error().unwrap();
+        trace!("futex: FUTEX_WAIT_BITSET {futex_ptr:p} 0 {timespec:#?} = -{err}");
+
+        match err {
+            libc::EINTR | libc::EAGAIN => continue,
+            e => return Err(e),
         }
     }
+    Ok(())
+}
+
+/// Wakes the thread waiting on the futex `futex`.
+fn wake(futex: &AtomicU32) {
+    let futex_ptr = futex as *const AtomicU32;
+    let op = libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG;
+    trace!("futex: {futex_ptr:p} FUTEX_WAKE");
+    unsafe {
+        libc::syscall(libc::SYS_futex, futex_ptr, op, 1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::thread;
+    use std::time::Duration;
 
-    #[instrument(level = "trace")]
-    pub(crate) fn unpark(&self) {
-        self.0.fetch_add(1, Ordering::Release);
+    #[test]
+    fn unpark_key() {
+        static PARKING: Parking = Parking::const_default();
 
-        let futex_ptr = &self.0 as *const AtomicU32;
-        let op = libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG;
-        trace!("futex: {futex_ptr:p} FUTEX_WAKE");
-        unsafe {
-            libc::syscall(libc::SYS_futex, futex_ptr, op, i32::MAX);
+        let parked = |key| {
+            thread::spawn(move || {
+                let mut expected = 0;
+                loop {
+                    let value = PARKING.park(key, expected, None).unwrap();
+                    if value != expected {
+                        return value;
+                    }
+                    expected = value;
+                }
+            })
+        };
+
+        let first = parked(0x1000);
+        let second = parked(0x2000);
+        let is_parked = |key| {
+            let state = PARKING.bucket(key).0.lock().unwrap();
+            state.waiters.iter().any(|waiter| waiter.key == key)
+        };
+        while !is_parked(0x1000) || !is_parked(0x2000) {
+            thread::sleep(Duration::from_millis(1));
         }
+
+        assert_eq!(PARKING.unpark(0x1000, 1), 1);
+        first.join().unwrap();
+        assert!(!second.is_finished());
+        assert_eq!(PARKING.unpark(0x3000, u32::MAX), 0);
+
+        assert_eq!(PARKING.unpark(0x2000, u32::MAX), 1);
+        second.join().unwrap();
+    }
+
+    #[test]
+    fn changed_state() {
+        let parking = Parking::const_default();
+        parking.unpark(0x1000, 1);
+        assert_eq!(parking.park(0x1000, 0, None), Ok(1));
     }
 }
@@ -0,0 +1,455 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host-side policy restricting the host resources a keep may use.
+//!
+//! Every syscall a keep proxies to the host is checked against the installed [`Policy`]
+//! before it is executed, so a compromised workload cannot use the host as a confused deputy.
+//! Denied syscalls fail with `EACCES` and are logged.
+
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use enarx_config::{Config, ConnectFile, File, ListenFile};
+use libc::{c_int, c_long, AF_INET, AF_INET6, AF_UNIX, AT_FDCWD, STDERR_FILENO, STDIN_FILENO};
+use once_cell::sync::OnceCell;
+use sallyport::host::Access;
+use sallyport::item::Item;
+use tracing::warn;
+
+/// The policy installed for this process, if any.
+static POLICY: OnceCell<Policy> = OnceCell::new();
+
+/// Files read by the resolver of the keep to look up host names.
+const RESOLVER_FILES: &[&str] = &["/etc/hosts", "/etc/resolv.conf"];
+
+/// How the policy is applied.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Execute all syscalls
+    #[default]
+    Off,
+    /// Log syscalls the policy would deny, but execute them
+    Log,
+    /// Deny syscalls the policy does not allow
+    Enforce,
+}
+
+/// Convert a str to a Mode. This is how Clap parses CLI args.
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "log" => Ok(Self::Log),
+            "enforce" => Ok(Self::Enforce),
+            _ => Err(anyhow!("unknown policy mode {:?}", s)),
+        }
+    }
+}
+
+/// The host resources a keep may use.
+#[derive(Debug, Default)]
+pub struct Policy {
+    mode: Mode,
+
+    /// Path prefixes, which may be opened
+    paths: Vec<PathBuf>,
+
+    /// Addresses, which may be bound to
+    bind: Vec<SocketAddr>,
+
+    /// Addresses, which may be connected or sent to
+    connect: Vec<SocketAddr>,
+
+    /// File descriptors handed out to the keep
+    fds: Mutex<BTreeSet<c_int>>,
+}
+
+impl Policy {
+    /// Creates a policy, which allows nothing but the standard I/O file descriptors.
+    pub fn new(mode: Mode) -> Self {
+        Self {
+            mode,
+            fds: Mutex::new((STDIN_FILENO..=STDERR_FILENO).collect()),
+            ..Default::default()
+        }
+    }
+
+    /// Allows access to `path` and everything below it.
+    pub fn allow_path(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let path =
+            resolve(path).with_context(|| format!("failed to resolve `{}`", path.display()))?;
+        self.paths.push(path);
+        Ok(())
+    }
+
+    /// Allows binding to `addr`.
+    pub fn allow_bind(&mut self, addr: SocketAddr) {
+        self.bind.push(addr);
+    }
+
+    /// Allows connecting and sending to all addresses `addr` resolves to.
+    pub fn allow_connect(&mut self, addr: impl ToSocketAddrs + fmt::Debug) -> Result<()> {
+        let addrs = addr
+            .to_socket_addrs()
+            .with_context(|| format!("failed to resolve {addr:?}"))?;
+        self.connect.extend(addrs);
+        Ok(())
+    }
+
+    /// Allows the resolver of the keep to look up host names.
+    fn allow_resolver(&mut self) -> Result<()> {
+        for path in RESOLVER_FILES {
+            self.allow_path(path)?;
+        }
+        let conf = fs::read_to_string("/etc/resolv.conf").unwrap_or_default();
+        let nameservers = conf.lines().filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("nameserver"), Some(ip)) => ip.parse::<IpAddr>().ok(),
+                _ => None,
+            }
+        });
+        for ip in nameservers {
+            self.connect.push(SocketAddr::new(ip, 53));
+        }
+        Ok(())
+    }
+
+    /// Allows the sockets and the Steward declared in the package config.
+    pub fn allow_config(&mut self, config: &Config) -> Result<()> {
+        let mut resolver = false;
+        for file in &config.files {
+            match file {
+                File::Listen(ListenFile::Tls { addr, port, .. })
+                | File::Listen(ListenFile::Tcp { addr, port, .. }) => {
+                    let ip = addr
+                        .parse()
+                        .with_context(|| format!("invalid listen address `{addr}`"))?;
+                    self.allow_bind(SocketAddr::new(ip, *port));
+                }
+                File::Connect(ConnectFile::Tls { host, port, .. })
+                | File::Connect(ConnectFile::Tcp { host, port, .. }) => {
+                    resolver |= host.parse::<IpAddr>().is_err();
+                    self.allow_connect((host.as_str(), *port))?;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(ref steward) = config.steward {
+            resolver |= !matches!(
+                steward.host(),
+                Some(url::Host::Ipv4(_) | url::Host::Ipv6(_))
+            );
+            let addrs = steward
+                .socket_addrs(|| None)
+                .with_context(|| format!("failed to resolve Steward `{steward}`"))?;
+            self.connect.extend(addrs);
+        }
+
+        if resolver {
+            self.allow_resolver()?;
+        }
+        Ok(())
+    }
+
+    /// Hands out `fd` to the keep.
+    pub fn grant(&self, fd: c_int) {
+        self.fds.lock().unwrap().insert(fd);
+    }
+
+    fn families(&self) -> impl Iterator<Item = c_int> + '_ {
+        let unix = (!self.paths.is_empty()).then_some(AF_UNIX);
+        self.bind
+            .iter()
+            .chain(&self.connect)
+            .map(|addr| if addr.is_ipv4() { AF_INET } else { AF_INET6 })
+            .chain(unix)
+    }
+
+    fn allows_fd(&self, fd: c_int) -> bool {
+        // A file descriptor, which is not open now, might be opened by the host before the
+        // syscall is executed, so only the handed out ones are allowed.
+        self.fds.lock().unwrap().contains(&fd)
+    }
+
+    fn allows_path(&self, dirfd: c_int, path: &[u8]) -> bool {
+        let path = Path::new(OsStr::from_bytes(path));
+        let path = if path.is_absolute() || dirfd == AT_FDCWD {
+            path.to_path_buf()
+        } else {
+            match fs::read_link(format!("/proc/self/fd/{dirfd}")) {
+                Ok(dir) => dir.join(path),
+                Err(_) => return false,
+            }
+        };
+        match resolve(&path) {
+            Ok(path) => self.paths.iter().any(|prefix| path.starts_with(prefix)),
+            Err(_) => false,
+        }
+    }
+
+    fn allows_bind(&self, addr: &[u8]) -> bool {
+        match sockaddr(addr) {
+            Some(Address::Ip(addr)) => {
+                // Binding to an ephemeral port is needed by any client socket.
+                (addr.port() == 0 && addr.ip().is_unspecified())
+                    || self.bind.iter().any(|rule| {
+                        rule.port() == addr.port()
+                            && (rule.ip().is_unspecified() || rule.ip() == addr.ip())
+                    })
+            }
+            Some(Address::Unix(path)) => self.allows_path(AT_FDCWD, path),
+            None => false,
+        }
+    }
+
+    fn allows_connect(&self, addr: &[u8]) -> bool {
+        match sockaddr(addr) {
+            Some(Address::Ip(addr)) => self.connect.contains(&addr),
+            Some(Address::Unix(path)) => self.allows_path(AT_FDCWD, path),
+            None => false,
+        }
+    }
+}
+
+impl sallyport::host::Policy for Policy {
+    fn allows(&self, num: c_long, access: Access<'_>) -> bool {
+        let allowed = match access {
+            Access::Fd(fd) => self.allows_fd(fd),
+            Access::Path { dirfd, path } => self.allows_path(dirfd, path),
+            Access::Socket(family) => self.families().any(|f| f == family),
+            Access::Bind(addr) => self.allows_bind(addr),
+            Access::Connect(addr) => self.allows_connect(addr),
+        };
+        if !allowed {
+            warn!(syscall = num, access = %Described(access), mode = ?self.mode, "host policy violation");
+        }
+        allowed || self.mode != Mode::Enforce
+    }
+
+    fn opened(&self, fd: c_int) {
+        self.grant(fd);
+    }
+
+    fn closed(&self, fd: c_int) {
+        self.fds.lock().unwrap().remove(&fd);
+    }
+}
+
+/// Human readable form of an [`Access`] for the log.
+struct Described<'a>(Access<'a>);
+
+impl fmt::Display for Described<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Access::Fd(fd) => write!(f, "fd {fd}"),
+            Access::Path { dirfd, path } if dirfd == AT_FDCWD => {
+                write!(f, "path {:?}", String::from_utf8_lossy(path))
+            }
+            Access::Path { dirfd, path } => {
+                write!(f, "path {:?} at fd {dirfd}", String::from_utf8_lossy(path))
+            }
+            Access::Socket(family) => write!(f, "socket of family {family}"),
+            Access::Bind(addr) | Access::Connect(addr) => match sockaddr(addr) {
+                Some(Address::Ip(addr)) => write!(f, "address {addr}"),
+                Some(Address::Unix(path)) => {
+                    write!(f, "address {:?}", String::from_utf8_lossy(path))
+                }
+                None => write!(f, "unsupported address"),
+            },
+        }
+    }
+}
+
+/// A decoded socket address.
+pub(crate) enum Address<'a> {
+    Ip(SocketAddr),
+    Unix(&'a [u8]),
+}
+
+/// Decodes the raw socket address `addr` as passed to the kernel.
+pub(crate) fn sockaddr(addr: &[u8]) -> Option<Address<'_>> {
+    let family = u16::from_ne_bytes(addr.get(..2)?.try_into().ok()?) as c_int;
+    let port = || Some(u16::from_be_bytes(addr.get(2..4)?.try_into().ok()?));
+    match family {
+        AF_INET => {
+            let ip: [u8; 4] = addr.get(4..8)?.try_into().ok()?;
+            Some(Address::Ip(SocketAddr::new(
+                Ipv4Addr::from(ip).into(),
+                port()?,
+            )))
+        }
+        AF_INET6 => {
+            let ip: [u8; 16] = addr.get(8..24)?.try_into().ok()?;
+            Some(Address::Ip(SocketAddr::new(
+                Ipv6Addr::from(ip).into(),
+                port()?,
+            )))
+        }
+        AF_UNIX => {
+            let path = addr.get(2..)?;
+            let len = path.iter().position(|b| *b == 0).unwrap_or(path.len());
+            // Abstract socket addresses start with a nul byte and have no path.
+            (len > 0).then(|| Address::Unix(&path[..len]))
+        }
+        _ => None,
+    }
+}
+
+/// Resolves `path` to an absolute path without symbolic links.
+///
+/// Paths, which do not exist yet, are resolved through their parent directory.
+fn resolve(path: &Path) -> Result<PathBuf> {
+    if let Ok(path) = path.canonicalize() {
+        return Ok(path);
+    }
+    let name = match path.components().next_back() {
+        Some(Component::Normal(name)) => name,
+        _ => return Err(anyhow!("`{}` has no file name", path.display())),
+    };
+    let parent = match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() => Path::new("."),
+        Some(parent) => parent,
+        None => Path::new("/"),
+    };
+    Ok(parent.canonicalize()?.join(name))
+}
+
+/// Installs `policy` for all keeps of this process.
+pub fn install(policy: Policy) -> Result<()> {
+    POLICY
+        .set(policy)
+        .map_err(|_| anyhow!("a host policy is already installed"))
+}
+
+/// Hands out `fd` to the keep, if a policy is installed.
+pub fn grant(fd: c_int) {
+    if let Some(policy) = POLICY.get() {
+        policy.grant(fd)
+    }
+}
+
+/// Executes the passed `items` under the installed policy.
+pub(crate) fn execute<'a>(items: impl IntoIterator<Item = Item<'a>>) -> sallyport::Result<()> {
+    match POLICY.get() {
+        Some(policy) if policy.mode != Mode::Off => {
+            sallyport::host::execute_with_policy(items, policy)
+        }
+        _ => sallyport::host::execute(items),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use sallyport::host::Policy as _;
+
+    fn sockaddr_in(ip: [u8; 4], port: u16) -> Vec<u8> {
+        let mut addr = (AF_INET as u16).to_ne_bytes().to_vec();
+        addr.extend(port.to_be_bytes());
+        addr.extend(ip);
+        addr.extend([0; 8]);
+        addr
+    }
+
+    #[test]
+    fn config() {
+        let config: Config = toml::from_str(
+            r#"
+            [[files]]
+            kind = "listen"
+            name = "listen"
+            prot = "tcp"
+            port = 8080
+
+            [[files]]
+            kind = "connect"
+            prot = "tcp"
+            host = "127.0.0.1"
+            port = 9000
+            "#,
+        )
+        .unwrap();
+
+        let mut policy = Policy::new(Mode::Enforce);
+        policy.allow_config(&config).unwrap();
+
+        assert!(policy.allows(0, Access::Socket(AF_INET)));
+        assert!(!policy.allows(0, Access::Socket(AF_UNIX)));
+        assert!(policy.allows(0, Access::Bind(&sockaddr_in([0, 0, 0, 0], 8080))));
+        assert!(policy.allows(0, Access::Bind(&sockaddr_in([0, 0, 0, 0], 0))));
+        assert!(!policy.allows(0, Access::Bind(&sockaddr_in([0, 0, 0, 0], 22))));
+        assert!(policy.allows(0, Access::Connect(&sockaddr_in([127, 0, 0, 1], 9000))));
+        assert!(!policy.allows(0, Access::Connect(&sockaddr_in([127, 0, 0, 1], 9001))));
+        // Resolving an IP address needs no resolver.
+        assert!(policy.paths.is_empty());
+    }
+
+    #[test]
+    fn paths() {
+        let dir = std::env::temp_dir().join("enarx-test-policy");
+        fs::create_dir_all(dir.join("allowed")).unwrap();
+
+        let mut policy = Policy::new(Mode::Enforce);
+        policy.allow_path(dir.join("allowed")).unwrap();
+
+        let path = |p: &Path| p.as_os_str().as_bytes().to_vec();
+        let allows = |p: &Path| {
+            policy.allows(
+                0,
+                Access::Path {
+                    dirfd: AT_FDCWD,
+                    path: &path(p),
+                },
+            )
+        };
+        assert!(allows(&dir.join("allowed")));
+        assert!(allows(&dir.join("allowed/new")));
+        assert!(!allows(&dir.join("allowed/../other")));
+        assert!(!allows(&dir.join("allowed/missing/new")));
+        assert!(!allows(Path::new("/etc/shadow")));
+
+        let log = Policy {
+            mode: Mode::Log,
+            ..Default::default()
+        };
+        assert!(log.allows(
+            0,
+            Access::Path {
+                dirfd: AT_FDCWD,
+                path: b"/etc/shadow"
+            }
+        ));
+    }
+
+    #[test]
+    fn fds() {
+        let policy = Policy::new(Mode::Enforce);
+        let file = fs::File::open("/dev/null").unwrap();
+        let fd = std::os::unix::io::AsRawFd::as_raw_fd(&file);
+
+        assert!(policy.allows(0, Access::Fd(STDIN_FILENO)));
+        assert!(!policy.allows(0, Access::Fd(fd)));
+        policy.opened(fd);
+        assert!(policy.allows(0, Access::Fd(fd)));
+        policy.closed(fd);
+        assert!(!policy.allows(0, Access::Fd(fd)));
+
+        // File descriptors, which are not open, are denied as well.
+        drop(file);
+        assert!(!policy.allows(0, Access::Fd(fd)));
+        assert!(!policy.allows(0, Access::Fd(-1)));
+    }
+}
@@ -55,6 +55,8 @@ impl Vendor {
 }
 
 pub struct CpuId {
+    pub id: &'static str,
+    pub parent: Option<&'static str>,
     pub name: &'static str,
     pub leaf: u32,
     pub subl: u32,
@@ -121,10 +123,12 @@ impl CpuId {
 impl From<&CpuId> for Datum {
     fn from(cpuid: &CpuId) -> Datum {
         let datum = Datum {
+            id: cpuid.id,
+            parent: cpuid.parent,
             name: cpuid.name.into(),
             pass: false,
             info: None,
-            mesg: None,
+            remedy: None,
         };
 
         let this_vendor = match Vendor::get() {
@@ -145,10 +149,12 @@ impl From<&CpuId> for Datum {
         };
 
         Datum {
+            id: datum.id,
+            parent: datum.parent,
             name: datum.name,
             pass,
             info,
-            mesg: datum.mesg,
+            remedy: datum.remedy,
         }
     }
 }
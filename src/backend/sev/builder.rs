@@ -8,7 +8,7 @@ use crate::backend::kvm::builder::{kvm_new_vcpu, map_sallyports};
 use crate::backend::kvm::mem::{Region, Slot};
 use crate::backend::kvm::KVM_HC_MAP_GPA_RANGE;
 use crate::backend::sev::config::Config;
-use crate::backend::ByteSized;
+use crate::backend::{limits, metrics, ByteSized};
 
 use std::convert::TryFrom;
 use std::sync::{Arc, RwLock};
@@ -234,6 +234,10 @@ impl TryFrom<Builder> for Arc<dyn super::super::Keep> {
             num_cpus: 1,
             cpu_fds: vec![vcpu_fd],
             regions,
+            released: Vec::new(),
+            metrics: metrics::keep(),
+            limits: limits::keep(),
+            registers: Default::default(),
             sallyport_block_size,
             sallyports,
             personality: SnpKeepPersonality { _sev_fd: sev_fd },
@@ -3,7 +3,7 @@
 pub use crate::backend::kvm::data::{dev_kvm, kvm_version};
 
 use crate::backend::probe::x86_64::{CpuId, Vendor};
-use crate::backend::Datum;
+use crate::backend::{Datum, Remedy};
 use crate::caching::CrlList;
 
 use crate::backend::sev::snp::vcek::{
@@ -17,40 +17,47 @@ use std::time::SystemTime;
 use der::Decode;
 
 pub fn has_crl_cache() -> Result<Datum, Datum> {
+    const ID: &str = "crl-cache";
     const NAME: &str = "AMD CRL cache file";
-    const UPDATE_MSG: &str =
-        "Run `enarx platform snp cache-crl` to generate the AMD CRL cache file.";
 
     let (path, mut reader) = get_crl_reader_with_path().map_err(|e| Datum {
+        id: ID,
+        parent: None,
         name: NAME.to_string(),
         pass: false,
         info: Some(e.to_string()),
-        mesg: Some(UPDATE_MSG.to_string()),
+        remedy: Some(Remedy::SnpCacheCrl),
     })?;
 
     let mut crls = Vec::new();
     std::io::copy(&mut reader, &mut crls).map_err(|e| Datum {
+        id: ID,
+        parent: None,
         name: NAME.to_string(),
         pass: false,
         info: Some(e.to_string()),
-        mesg: Some(UPDATE_MSG.to_string()),
+        remedy: Some(Remedy::SnpCacheCrl),
     })?;
 
     let crls = CrlList::from_der(&crls).map_err(|e| Datum {
+        id: ID,
+        parent: None,
         name: NAME.to_string(),
         pass: false,
         info: Some(e.to_string()),
-        mesg: Some(UPDATE_MSG.to_string()),
+        remedy: Some(Remedy::SnpCacheCrl),
     })?;
 
     for (_, crl) in crls.entries() {
         if let Some(update) = crl.tbs_cert_list.next_update {
             if update.to_system_time() <= SystemTime::now() {
                 return Err(Datum {
+                    id: ID,
+                    parent: None,
                     name: NAME.to_string(),
                     pass: false,
-                    info: None,
-                    mesg: Some("CRLs expired! ".to_string() + UPDATE_MSG),
+                    info: Some("CRLs expired".into()),
+                    remedy: Some(Remedy::SnpCacheCrl),
                 });
             }
         }
@@ -58,6 +65,8 @@ pub fn has_crl_cache() -> Result<Datum, Datum> {
 
     if let Some(next_update) = crls.next_update() {
         Ok(Datum {
+            id: ID,
+            parent: None,
             name: NAME.to_string(),
             pass: true,
             info: Some(format!(
@@ -65,19 +74,22 @@ pub fn has_crl_cache() -> Result<Datum, Datum> {
                 path.to_string_lossy().into_owned(),
                 next_update
             )),
-            mesg: None,
+            remedy: None,
         })
     } else {
         Ok(Datum {
+            id: ID,
+            parent: None,
             name: NAME.to_string(),
             pass: true,
             info: path.to_string_lossy().into_owned().into(),
-            mesg: None,
+            remedy: None,
         })
     }
 }
 
 pub fn has_vcek_cache() -> Datum {
+    const ID: &str = "vcek-cache";
     let name = "SEV-SNP VCEK key cache file".to_string();
 
     let cache_dir =
@@ -85,29 +97,32 @@ pub fn has_vcek_cache() -> Datum {
             Ok(cache_dir) => cache_dir,
             Err(e) => {
                 return Datum {
+                    id: ID,
+                    parent: None,
                     name,
                     pass: false,
                     info: Some(e.to_string()),
-                    mesg: Some(
-                        "enarx expects the directory `/var/cache/amd-sev` to exist and be readable"
-                            .to_string(),
-                    ),
+                    remedy: Some(Remedy::SevCacheDir),
                 }
             }
         };
 
     match get_vcek_reader_with_path(cache_dir) {
         Ok((path, _)) => Datum {
+            id: ID,
+            parent: None,
             name,
             pass: true,
             info: path.to_string_lossy().into_owned().into(),
-            mesg: None,
+            remedy: None,
         },
         Err(e) => Datum {
+            id: ID,
+            parent: None,
             name,
             pass: false,
             info: Some(e.to_string()),
-            mesg: Some("Run `enarx platform snp update` to generate the cache file.".to_string()),
+            remedy: Some(Remedy::SnpUpdate),
         },
     }
 }
@@ -142,38 +157,37 @@ pub fn has_reasonable_memlock_rlimit() -> Datum {
         (false, Some("failed to query memlock rlimit".into()))
     };
 
-    let mesg = if !pass {
-        let mesg = "The MEMLOCK rlimit must be large enough to \
-                    accommodate the Enarx shim, exec-wasmtime, and the memory pressure \
-                    requirements of the target workloads across all deployed SEV keeps.";
-        Some(mesg.into())
-    } else {
-        None
-    };
+    let remedy = (!pass).then_some(Remedy::RaiseMemlock);
 
     Datum {
+        id: "memlock-rlimit",
+        parent: None,
         name: "MEMLOCK rlimit allows for".into(),
         pass,
         info,
-        mesg,
+        remedy,
     }
 }
 
 pub fn dev_sev() -> Datum {
     Datum {
+        id: "dev-sev",
+        parent: None,
         name: "Driver".into(),
         pass: std::path::Path::new("/dev/sev").exists(),
         info: Some("/dev/sev".into()),
-        mesg: None,
+        remedy: None,
     }
 }
 
 pub fn sev_enabled_in_kernel() -> Datum {
     let mut datum = Datum {
-        name: " SEV-SNP is enabled in host kernel".into(),
+        id: "sev-snp-enabled",
+        parent: Some("dev-sev"),
+        name: "SEV-SNP is enabled in host kernel".into(),
         pass: false,
         info: None,
-        mesg: None,
+        remedy: None,
     };
 
     let mod_param = "/sys/module/kvm_amd/parameters/sev_snp";
@@ -190,10 +204,12 @@ pub fn dev_sev_readable() -> Datum {
     let opts = OpenOptions::new().read(true).open("/dev/sev");
 
     Datum {
+        id: "dev-sev-readable",
+        parent: None,
         name: "/dev/sev is readable by user".into(),
         pass: opts.is_ok(),
         info: None,
-        mesg: None,
+        remedy: None,
     }
 }
 
@@ -201,15 +217,19 @@ pub fn dev_sev_writable() -> Datum {
     let opts = OpenOptions::new().write(true).open("/dev/sev");
 
     Datum {
+        id: "dev-sev-writable",
+        parent: None,
         name: "/dev/sev is writable by user".into(),
         pass: opts.is_ok(),
         info: None,
-        mesg: None,
+        remedy: None,
     }
 }
 
 pub const CPUIDS: &[CpuId] = &[
     CpuId {
+        id: "cpu",
+        parent: None,
         name: "CPU",
         leaf: 0x80000000,
         subl: 0x00000000,
@@ -217,7 +237,9 @@ pub const CPUIDS: &[CpuId] = &[
         vend: None,
     },
     CpuId {
-        name: " Microcode support",
+        id: "microcode",
+        parent: Some("cpu"),
+        name: "Microcode support",
         leaf: 0x80000002,
         subl: 0x00000000,
         func: |_res| {
@@ -239,14 +261,18 @@ pub const CPUIDS: &[CpuId] = &[
         vend: Some(Vendor::Amd),
     },
     CpuId {
-        name: " Secure Memory Encryption (SME)",
+        id: "sme",
+        parent: Some("cpu"),
+        name: "Secure Memory Encryption (SME)",
         leaf: 0x8000001f,
         subl: 0x00000000,
         func: |res| (res.eax & 0x1 != 0, None),
         vend: Some(Vendor::Amd),
     },
     CpuId {
-        name: "  Physical address bit reduction",
+        id: "physical-address-reduction",
+        parent: Some("sme"),
+        name: "Physical address bit reduction",
         leaf: 0x8000001f,
         subl: 0x00000000,
         func: |res| {
@@ -256,7 +282,9 @@ pub const CPUIDS: &[CpuId] = &[
         vend: Some(Vendor::Amd),
     },
     CpuId {
-        name: "  C-bit location in page table entry",
+        id: "c-bit",
+        parent: Some("sme"),
+        name: "C-bit location in page table entry",
         leaf: 0x8000001f,
         subl: 0x00000000,
         func: |res| {
@@ -266,35 +294,45 @@ pub const CPUIDS: &[CpuId] = &[
         vend: Some(Vendor::Amd),
     },
     CpuId {
-        name: " Secure Encrypted Virtualization (SEV)",
+        id: "sev",
+        parent: Some("cpu"),
+        name: "Secure Encrypted Virtualization (SEV)",
         leaf: 0x8000001f,
         subl: 0x00000000,
         func: |res| (res.eax & (1 << 1) != 0, None),
         vend: Some(Vendor::Amd),
     },
     CpuId {
-        name: "  Number of encrypted guests supported simultaneously",
+        id: "max-guests",
+        parent: Some("sev"),
+        name: "Number of encrypted guests supported simultaneously",
         leaf: 0x8000001f,
         subl: 0x00000000,
         func: |res| (true, Some(format!("{}", res.ecx))),
         vend: Some(Vendor::Amd),
     },
     CpuId {
-        name: "  Minimum ASID value for SEV-enabled, SEV-ES disabled guest",
+        id: "min-asid",
+        parent: Some("sev"),
+        name: "Minimum ASID value for SEV-enabled, SEV-ES disabled guest",
         leaf: 0x8000001f,
         subl: 0x00000000,
         func: |res| (true, Some(format!("{}", res.edx))),
         vend: Some(Vendor::Amd),
     },
     CpuId {
-        name: " Secure Encrypted Virtualization Secure Nested Paging (SEV-SNP)",
+        id: "sev-snp",
+        parent: Some("cpu"),
+        name: "Secure Encrypted Virtualization Secure Nested Paging (SEV-SNP)",
         leaf: 0x8000001f,
         subl: 0x00000000,
         func: |res| (res.eax & (1 << 4) != 0, None),
         vend: Some(Vendor::Amd),
     },
     CpuId {
-        name: " Page Flush MSR available",
+        id: "page-flush-msr",
+        parent: Some("cpu"),
+        name: "Page Flush MSR available",
         leaf: 0x8000001f,
         subl: 0x00000000,
         func: |res| (res.eax & (1 << 2) != 0, None),
@@ -61,6 +61,17 @@ impl KeepPersonality for SnpKeepPersonality {
 
         Ok(())
     }
+
+    fn unmap(vm_fd: &mut VmFd, region: &Region) -> io::Result<()> {
+        let memory_region = kvm_enc_region {
+            addr: region.backing().as_ptr() as _,
+            size: region.backing().len() as _,
+        };
+        vm_fd
+            .unregister_enc_memory_region(&memory_region)
+            .map_err(|e| io::Error::from_raw_os_error(e.errno()))
+    }
+
     fn enarxcall<'a>(
         &mut self,
         enarxcall: &'a mut Payload,
@@ -185,4 +196,9 @@ impl super::Backend for Backend {
     fn hash(&self, shim: &[u8], exec: &[u8]) -> Result<Vec<u8>> {
         hasher::Hasher::load(shim, exec, None)
     }
+
+    #[inline]
+    fn check_shim(&self, shim: &[u8]) -> Result<()> {
+        builder::Builder::check(shim)
+    }
 }
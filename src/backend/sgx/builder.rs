@@ -6,6 +6,7 @@ use super::ioctls::*;
 use std::convert::TryFrom;
 use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
+use std::sync::atomic::AtomicU64;
 use std::sync::{Arc, Mutex, RwLock};
 
 use anyhow::{anyhow, Context, Error, Result};
@@ -17,7 +18,7 @@ use sgx::signature::{Author, Hasher, Signature};
 
 use tracing::trace;
 
-use crate::backend::ByteSized;
+use crate::backend::{limits, metrics, ByteSized};
 
 pub struct Builder {
     file: File,
@@ -168,6 +169,19 @@ impl TryFrom<Builder> for Arc<dyn super::super::Keep> {
 
         // Fix up mapped permissions.
         builder.perm.sort_by_key(|x| x.0);
+
+        // Remember the pages of debug enclaves for core dumps.
+        let debug = builder.cnfg.debug();
+        let pages = if debug {
+            builder
+                .perm
+                .iter()
+                .map(|(addr, size, si)| (*addr as usize, *size, si.flags()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mapped = builder.perm.iter().map(|(_, size, _)| *size as u64).sum();
         for (addr, size, si) in builder.perm {
             trace!(
                 "remapping: {:016x}-{:016x} {}",
@@ -211,6 +225,13 @@ impl TryFrom<Builder> for Arc<dyn super::super::Keep> {
             mem: builder.mmap,
             tcs: RwLock::new(builder.tcsp),
             enclave: Mutex::new(builder.file.try_clone().unwrap()),
+            debug,
+            ssa_pages: builder.cnfg.ssap.get(),
+            pages,
+            parked: Default::default(),
+            mapped: AtomicU64::new(mapped),
+            metrics: metrics::keep(),
+            limits: limits::keep(),
         }))
     }
 }
@@ -7,7 +7,7 @@ use anyhow::{anyhow, Result};
 use goblin::elf::program_header::{PF_R, PF_W, PF_X};
 use sallyport::elf;
 use sgx::page::{Class, Flags, SecInfo};
-use sgx::parameters::{Attributes, Masked, Parameters};
+use sgx::parameters::{Attributes, Features, Masked, Parameters};
 use x86_64::registers::xcontrol::XCr0;
 
 #[derive(Debug)]
@@ -19,6 +19,17 @@ pub struct Config {
     pub sallyport_block_size: u64,
 }
 
+impl Config {
+    /// Whether the enclave is created with `ATTRIBUTES.DEBUG`, so the host can read its memory.
+    pub fn debug(&self) -> bool {
+        self.parameters
+            .attr
+            .data
+            .features()
+            .contains(Features::DEBUG)
+    }
+}
+
 impl super::super::Config for Config {
     type Flags = (SecInfo, bool);
 
@@ -48,11 +59,7 @@ impl super::super::Config for Config {
         (si, m)
     }
 
-    fn new(
-        shim: &super::super::Binary<'_>,
-        _exec: &super::super::Binary<'_>,
-        signatures: Option<Signatures>,
-    ) -> Result<Self> {
+    fn new(shim: &super::super::Binary<'_>, signatures: Option<Signatures>) -> Result<Self> {
         unsafe {
             let attr_mask: Attributes = shim
                 .note(elf::note::NAME, elf::note::sgx::ATTRMASK)
@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Core dumps of crashed SGX debug enclaves.
+
+use super::{Keep, Tcs};
+use crate::backend::coredump::{self, Segment, Thread, PF_R, PF_W, PF_X};
+
+use std::fs::File;
+use std::io;
+use std::mem::size_of;
+use std::os::unix::fs::FileExt;
+
+use libc::user_regs_struct;
+use primordial::Page;
+use sgx::page::Flags;
+use sgx::ssa::GenPurposeRegs;
+use tracing::error;
+
+/// The offset of `OSSA` in the TCS
+const TCS_OSSA: u64 = 16;
+
+/// Reads `len` bytes of enclave memory at `addr`.
+///
+/// The kernel reads the memory of debug enclaves with `EDBGRD`.
+fn read(mem: &File, addr: usize, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    mem.read_exact_at(&mut buf, addr as u64)?;
+    Ok(buf)
+}
+
+/// Reads the registers saved in SSA frame `cssa` of `tcs`.
+fn registers(keep: &Keep, mem: &File, tcs: Tcs, cssa: usize) -> io::Result<user_regs_struct> {
+    let ossa = read(mem, tcs + TCS_OSSA as usize, size_of::<u64>())?;
+    let ossa = u64::from_le_bytes(ossa.try_into().unwrap()) as usize;
+
+    let frame_size = keep.ssa_pages as usize * Page::SIZE;
+    let frame = keep.mem.addr() + ossa + cssa * frame_size;
+    let gpr = read(
+        mem,
+        frame + frame_size - size_of::<GenPurposeRegs>(),
+        size_of::<GenPurposeRegs>(),
+    )?;
+
+    // SAFETY: `GenPurposeRegs` is a plain struct of integers.
+    let gpr = unsafe { gpr.as_ptr().cast::<GenPurposeRegs>().read_unaligned() };
+
+    // SAFETY: `user_regs_struct` is a plain struct of integers.
+    let mut regs: user_regs_struct = unsafe { std::mem::zeroed() };
+    regs.rax = gpr.rax;
+    regs.rbx = gpr.rbx;
+    regs.rcx = gpr.rcx;
+    regs.rdx = gpr.rdx;
+    regs.rsi = gpr.rsi;
+    regs.rdi = gpr.rdi;
+    regs.rsp = gpr.rsp;
+    regs.rbp = gpr.rbp;
+    regs.r8 = gpr.r8;
+    regs.r9 = gpr.r9;
+    regs.r10 = gpr.r10;
+    regs.r11 = gpr.r11;
+    regs.r12 = gpr.r12;
+    regs.r13 = gpr.r13;
+    regs.r14 = gpr.r14;
+    regs.r15 = gpr.r15;
+    regs.rip = gpr.rip;
+    regs.eflags = gpr.rflags;
+    regs.fs_base = gpr.fsbase;
+    regs.gs_base = gpr.gsbase;
+    Ok(regs)
+}
+
+/// Writes a core dump of the enclave memory and of the registers saved in SSA frame `cssa`
+/// of `tcs`, followed by the registers of the threads executing a host call.
+///
+/// A thread executing a host call trapped into the shim from the exec, which saved the
+/// registers of the exec in the first SSA frame of its TCS.
+pub(super) fn dump(keep: &Keep, tcs: Tcs, cssa: usize) {
+    if !keep.debug {
+        error!("Core dumps are only written for debug enclaves");
+        return;
+    }
+
+    let mem = match File::open("/proc/self/mem") {
+        Ok(mem) => mem,
+        Err(e) => {
+            error!("Failed to open the enclave memory for the core dump: {e}");
+            return;
+        }
+    };
+
+    let mut chunks = Vec::new();
+    for &(addr, size, flags) in &keep.pages {
+        let mut pf = 0;
+        if flags.contains(Flags::READ) {
+            pf |= PF_R;
+        }
+        if flags.contains(Flags::WRITE) {
+            pf |= PF_W;
+        }
+        if flags.contains(Flags::EXECUTE) {
+            pf |= PF_X;
+        }
+
+        match read(&mem, addr, size) {
+            Ok(data) => chunks.push((addr, pf, data)),
+            // Some pages, e.g. the TCS, might not be readable at all.
+            Err(_) => chunks.extend((addr..addr + size).step_by(Page::SIZE).filter_map(|page| {
+                read(&mem, page, Page::SIZE)
+                    .ok()
+                    .map(|data| (page, pf, data))
+            })),
+        }
+    }
+
+    let mut segments: Vec<Segment<'_>> = Vec::new();
+    for (addr, flags, data) in &chunks {
+        // The enclave is mapped at the same addresses on the host.
+        coredump::push(&mut segments, *addr as u64, 0, *flags, data);
+    }
+
+    let regs = match registers(keep, &mem, tcs, cssa) {
+        Ok(regs) => regs,
+        Err(e) => {
+            error!("Failed to read the registers for the core dump: {e}");
+            return;
+        }
+    };
+
+    let mut threads = vec![Thread { tid: 1, regs }];
+    let parked = keep.parked.lock().unwrap();
+    for (&other, tid) in parked.iter().filter(|&&other| other != tcs).zip(2..) {
+        match registers(keep, &mem, other, 0) {
+            Ok(regs) => threads.push(Thread { tid, regs }),
+            Err(e) => {
+                error!("Failed to read the registers of TCS {other:#x} for the core dump: {e}")
+            }
+        }
+    }
+
+    coredump::dump(&segments, &threads);
+}
@@ -2,7 +2,7 @@
 
 use crate::backend::probe::x86_64::{CpuId, Vendor};
 use crate::backend::sgx::{sgx_cache_dir, TcbPackage, AESM_SOCKET, FMSPC_PATH, TCB_PATH};
-use crate::backend::Datum;
+use crate::backend::{Datum, Remedy};
 use crate::caching::CrlList;
 
 use sgx::parameters::{Features, MiscSelect, Xfrm};
@@ -42,6 +42,8 @@ fn humanize(mut size: f64) -> (f64, &'static str) {
 
 pub const CPUIDS: &[CpuId] = &[
     CpuId {
+        id: "cpu",
+        parent: None,
         name: "CPU",
         leaf: 0x80000000,
         subl: 0x00000000,
@@ -49,35 +51,45 @@ pub const CPUIDS: &[CpuId] = &[
         vend: None,
     },
     CpuId {
-        name: " SGX Support",
+        id: "sgx",
+        parent: Some("cpu"),
+        name: "SGX Support",
         leaf: 0x00000007,
         subl: 0x00000000,
         func: |res| (res.ebx & (1 << 2) != 0, None),
         vend: Some(Vendor::Intel),
     },
     CpuId {
-        name: "  Version 1",
+        id: "sgx1",
+        parent: Some("sgx"),
+        name: "Version 1",
         leaf: 0x00000012,
         subl: 0x00000000,
         func: |res| (res.eax & (1 << 0) != 0, None),
         vend: Some(Vendor::Intel),
     },
     CpuId {
-        name: "  Version 2",
+        id: "sgx2",
+        parent: Some("sgx"),
+        name: "Version 2",
         leaf: 0x00000012,
         subl: 0x00000000,
         func: |res| (res.eax & (1 << 1) != 0, None),
         vend: Some(Vendor::Intel),
     },
     CpuId {
-        name: "  FLC Support",
+        id: "flc",
+        parent: Some("sgx"),
+        name: "FLC Support",
         leaf: 0x00000007,
         subl: 0x00000000,
         func: |res| (res.ecx & (1 << 30) != 0, None),
         vend: Some(Vendor::Intel),
     },
     CpuId {
-        name: "  Max Size (32-bit)",
+        id: "max-size-32",
+        parent: Some("sgx"),
+        name: "Max Size (32-bit)",
         leaf: 0x00000012,
         subl: 0x00000000,
         func: |res| {
@@ -88,7 +100,9 @@ pub const CPUIDS: &[CpuId] = &[
         vend: Some(Vendor::Intel),
     },
     CpuId {
-        name: "  Max Size (64-bit)",
+        id: "max-size-64",
+        parent: Some("sgx"),
+        name: "Max Size (64-bit)",
         leaf: 0x00000012,
         subl: 0x00000000,
         func: |res| {
@@ -99,7 +113,9 @@ pub const CPUIDS: &[CpuId] = &[
         vend: Some(Vendor::Intel),
     },
     CpuId {
-        name: "  MiscSelect",
+        id: "miscselect",
+        parent: Some("sgx"),
+        name: "MiscSelect",
         leaf: 0x00000012,
         subl: 0x00000000,
         func: |res| {
@@ -109,7 +125,9 @@ pub const CPUIDS: &[CpuId] = &[
         vend: Some(Vendor::Intel),
     },
     CpuId {
-        name: "  Features",
+        id: "features",
+        parent: Some("sgx"),
+        name: "Features",
         leaf: 0x00000012,
         subl: 0x00000001,
         func: |res| {
@@ -119,7 +137,9 @@ pub const CPUIDS: &[CpuId] = &[
         vend: Some(Vendor::Intel),
     },
     CpuId {
-        name: "  Xfrm",
+        id: "xfrm",
+        parent: Some("sgx"),
+        name: "Xfrm",
         leaf: 0x00000012,
         subl: 0x00000001,
         func: |res| {
@@ -154,8 +174,10 @@ pub fn epc_size(max: u32) -> Datum {
     }
 
     Datum {
-        name: "  EPC Size".into(),
-        mesg: None,
+        id: "epc-size",
+        parent: Some("sgx"),
+        name: "EPC Size".into(),
+        remedy: None,
         pass,
         info,
     }
@@ -163,47 +185,52 @@ pub fn epc_size(max: u32) -> Datum {
 
 pub fn dev_sgx_enclave() -> Datum {
     Datum {
+        id: "dev-sgx-enclave",
+        parent: None,
         name: "Driver".into(),
         pass: File::open("/dev/sgx_enclave").is_ok(),
         info: Some("/dev/sgx_enclave".into()),
-        mesg: None,
+        remedy: None,
     }
 }
 
 pub fn aesm_socket() -> Datum {
     Datum {
+        id: "aesm-socket",
+        parent: None,
         name: "AESM Daemon Socket".into(),
         pass: cfg!(feature = "disable-sgx-attestation") || Path::new(AESM_SOCKET).exists(),
         info: Some(AESM_SOCKET.into()),
-        mesg: None,
+        remedy: None,
     }
 }
 
 pub fn intel_crl() -> Datum {
+    const ID: &str = "crl-cache";
     const NAME: &str = "Intel CRL cache file";
-    const UPDATE_MSG: &str =
-        "Run `enarx platform sgx cache-crl` to generate the Intel CRL cache file";
 
-    let crl_file =
-        match sgx_cache_dir() {
-            Ok(p) => p.join("crls.der"),
-            Err(e) => return Datum {
+    let crl_file = match sgx_cache_dir() {
+        Ok(p) => p.join("crls.der"),
+        Err(e) => {
+            return Datum {
+                id: ID,
+                parent: None,
                 name: NAME.to_string(),
                 pass: false,
                 info: Some(e.to_string()),
-                mesg: Some(
-                    "enarx expects the directory `/var/cache/intel-sgx` to exist and be readable"
-                        .into(),
-                ),
-            },
-        };
+                remedy: Some(Remedy::SgxCacheDir),
+            }
+        }
+    };
 
     if !crl_file.exists() {
         return Datum {
+            id: ID,
+            parent: None,
             name: NAME.to_string(),
             pass: false,
             info: None,
-            mesg: Some(UPDATE_MSG.to_string()),
+            remedy: Some(Remedy::SgxCacheCrl),
         };
     }
 
@@ -211,10 +238,12 @@ pub fn intel_crl() -> Datum {
         Ok(c) => c,
         Err(e) => {
             return Datum {
+                id: ID,
+                parent: None,
                 name: NAME.to_string(),
                 pass: false,
                 info: Some(e.to_string()),
-                mesg: Some(UPDATE_MSG.to_string()),
+                remedy: Some(Remedy::SgxCacheCrl),
             }
         }
     };
@@ -223,10 +252,12 @@ pub fn intel_crl() -> Datum {
         Ok(c) => c,
         Err(e) => {
             return Datum {
+                id: ID,
+                parent: None,
                 name: NAME.to_string(),
                 pass: false,
                 info: Some(e.to_string()),
-                mesg: Some(UPDATE_MSG.to_string()),
+                remedy: Some(Remedy::SgxCacheCrl),
             }
         }
     };
@@ -235,10 +266,12 @@ pub fn intel_crl() -> Datum {
         if let Some(update) = crl.tbs_cert_list.next_update {
             if update.to_system_time() <= SystemTime::now() {
                 return Datum {
+                    id: ID,
+                    parent: None,
                     name: NAME.to_string(),
                     pass: false,
                     info: None,
-                    mesg: Some(UPDATE_MSG.to_string()),
+                    remedy: Some(Remedy::SgxCacheCrl),
                 };
             }
         }
@@ -246,6 +279,8 @@ pub fn intel_crl() -> Datum {
 
     if let Some(next_update) = crls.next_update() {
         Datum {
+            id: ID,
+            parent: None,
             name: NAME.to_string(),
             pass: true,
             info: Some(format!(
@@ -253,37 +288,43 @@ pub fn intel_crl() -> Datum {
                 crl_file.to_string_lossy().into_owned(),
                 next_update
             )),
-            mesg: None,
+            remedy: None,
         }
     } else {
         Datum {
+            id: ID,
+            parent: None,
             name: NAME.to_string(),
             pass: true,
             info: crl_file.to_string_lossy().into_owned().into(),
-            mesg: None,
+            remedy: None,
         }
     }
 }
 
 pub fn tcb_fmspc_cached() -> Datum {
+    const ID: &str = "tcb-fmspc-cache";
     const NAME: &str = "TCB & FMSPC cache";
-    const TCB_INSTRUCTION: &str = "Run `enarx platform sgx cache-tcb`";
 
     if !Path::new(FMSPC_PATH).exists() {
         return Datum {
+            id: ID,
+            parent: None,
             name: NAME.to_string(),
             pass: false,
             info: Some("Missing FMSPC".into()),
-            mesg: Some("Run `enarx platform sgx cache-pck`".into()),
+            remedy: Some(Remedy::SgxCachePck),
         };
     }
 
     if !Path::new(TCB_PATH).exists() {
         return Datum {
+            id: ID,
+            parent: None,
             name: NAME.to_string(),
             pass: false,
             info: Some("Missing TCB report".into()),
-            mesg: Some(TCB_INSTRUCTION.into()),
+            remedy: Some(Remedy::SgxCacheTcb),
         };
     }
 
@@ -291,10 +332,12 @@ pub fn tcb_fmspc_cached() -> Datum {
         Ok(bytes) => bytes,
         Err(e) => {
             return Datum {
+                id: ID,
+                parent: None,
                 name: NAME.to_string(),
                 pass: false,
                 info: Some(format!("Unable to read TCB report: {e}")),
-                mesg: Some(TCB_INSTRUCTION.into()),
+                remedy: Some(Remedy::SgxCacheTcb),
             };
         }
     };
@@ -303,10 +346,12 @@ pub fn tcb_fmspc_cached() -> Datum {
         Ok(t) => t,
         Err(e) => {
             return Datum {
+                id: ID,
+                parent: None,
                 name: NAME.to_string(),
                 pass: false,
                 info: Some(format!("Unable to decode TCB report: {e}")),
-                mesg: Some(TCB_INSTRUCTION.into()),
+                remedy: Some(Remedy::SgxCacheTcb),
             };
         }
     };
@@ -315,10 +360,12 @@ pub fn tcb_fmspc_cached() -> Datum {
         Ok(s) => s,
         Err(e) => {
             return Datum {
+                id: ID,
+                parent: None,
                 name: NAME.to_string(),
                 pass: false,
                 info: Some(format!("Unable to decode JSON TCB report: {e}")),
-                mesg: Some(TCB_INSTRUCTION.into()),
+                remedy: Some(Remedy::SgxCacheTcb),
             };
         }
     };
@@ -327,10 +374,12 @@ pub fn tcb_fmspc_cached() -> Datum {
         Ok(j) => j,
         Err(e) => {
             return Datum {
+                id: ID,
+                parent: None,
                 name: NAME.to_string(),
                 pass: false,
                 info: Some(format!("Unable to decode JSON TCB report: {e}")),
-                mesg: Some(TCB_INSTRUCTION.into()),
+                remedy: Some(Remedy::SgxCacheTcb),
             };
         }
     };
@@ -339,10 +388,12 @@ pub fn tcb_fmspc_cached() -> Datum {
         Some(t) => t,
         None => {
             return Datum {
+                id: ID,
+                parent: None,
                 name: NAME.to_string(),
                 pass: false,
                 info: Some("Unable to decode JSON TCB report, missing `tcbInfo` field".into()),
-                mesg: Some(TCB_INSTRUCTION.into()),
+                remedy: Some(Remedy::SgxCacheTcb),
             };
         }
     };
@@ -351,12 +402,14 @@ pub fn tcb_fmspc_cached() -> Datum {
         Some(t) => t,
         None => {
             return Datum {
+                id: ID,
+                parent: None,
                 name: NAME.to_string(),
                 pass: false,
                 info: Some(
                     "Unable to decode JSON TCB report, missing `tcbInfo.nextUpdate` field".into(),
                 ),
-                mesg: Some(TCB_INSTRUCTION.into()),
+                remedy: Some(Remedy::SgxCacheTcb),
             };
         }
     };
@@ -365,13 +418,15 @@ pub fn tcb_fmspc_cached() -> Datum {
         Some(u) => u,
         None => {
             return Datum {
+                id: ID,
+                parent: None,
                 name: NAME.to_string(),
                 pass: false,
                 info: Some(
                     "Unable to decode JSON TCB report, unable to decode `tcbInfo.nextUpdate` field"
                         .into(),
                 ),
-                mesg: Some(TCB_INSTRUCTION.into()),
+                remedy: Some(Remedy::SgxCacheTcb),
             };
         }
     };
@@ -380,12 +435,14 @@ pub fn tcb_fmspc_cached() -> Datum {
         Ok(d) => d,
         Err(e) => {
             return Datum {
+                id: ID,
+                parent: None,
                 name: NAME.to_string(),
                 pass: false,
                 info: Some(format!(
                     "Unable to decode timestamp in JSON TCB report: {e}"
                 )),
-                mesg: Some(TCB_INSTRUCTION.into()),
+                remedy: Some(Remedy::SgxCacheTcb),
             };
         }
     };
@@ -406,17 +463,21 @@ pub fn tcb_fmspc_cached() -> Datum {
             }
         };
         return Datum {
+            id: ID,
+            parent: None,
             name: NAME.to_string(),
             pass: false,
             info: Some(format!("Intel TCB expired on {next_update}, {elapsed} ago")),
-            mesg: Some(TCB_INSTRUCTION.into()),
+            remedy: Some(Remedy::SgxCacheTcb),
         };
     }
 
     Datum {
+        id: ID,
+        parent: None,
         name: NAME.to_string(),
         pass: true,
         info: Some(format!("Next update: {next_update}")),
-        mesg: None,
+        remedy: None,
     }
 }
@@ -6,15 +6,16 @@ use crate::backend::sgx::attestation::{
     get_target_info,
 };
 use crate::backend::sgx::ioctls::{ModifyTypes, RemovePages, RestrictPermissions};
-use crate::backend::{Command, Keep};
+use crate::backend::{metrics, numa, Command, Keep};
 
 use std::arch::x86_64::CpuidResult;
 use std::io;
 use std::mem::{forget, size_of, MaybeUninit};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 use anyhow::Context;
-use libc::{timespec, EAGAIN, EINVAL, PROT_READ};
+use libc::{timespec, EAGAIN, EINVAL, ENOMEM, PROT_READ};
 use mmarinus::{perms, Map, Shared};
 use sallyport::host::{deref_aligned, deref_slice};
 use sallyport::item;
@@ -64,23 +65,28 @@ pub(crate) fn sgx_enarxcall<'a>(
                                 "Thread",
                                 id = ?std::thread::current().id()
                             )
-                            .in_scope(|| loop {
-                                match thread.enter(&None)? {
-                                    Command::Continue => (),
-                                    Command::Exit(exit_code) => {
-                                        drop(thread);
-                                        return Ok::<i32, anyhow::Error>(exit_code);
+                            .in_scope(|| {
+                                numa::pin()?;
+                                loop {
+                                    match thread.enter(&None)? {
+                                        Command::Continue => (),
+                                        Command::Exit(exit_code) => {
+                                            drop(thread);
+                                            return Ok::<i32, anyhow::Error>(exit_code);
+                                        }
                                     }
                                 }
                             });
                             if let Err(e) = ret {
                                 error!("Thread failed: {e:#?}");
+                                metrics::finish();
                                 std::process::exit(1);
                             }
                             ret
                         })
                         .unwrap_or_else(|e| {
                             error!("Thread panicked: {e:#?}");
+                            metrics::finish();
                             std::process::exit(1);
                         })
                     });
@@ -95,7 +101,7 @@ pub(crate) fn sgx_enarxcall<'a>(
         }
         item::Enarxcall {
             num: item::enarxcall::Number::Park,
-            argv: [val, timeout, ..],
+            argv: [val, timeout, key, ..],
             ret,
             ..
         } => {
@@ -113,7 +119,7 @@ pub(crate) fn sgx_enarxcall<'a>(
             };
 
             *ret = THREAD_PARK
-                .park(*val as _, timeout.as_ref())
+                .park(*key, *val as _, timeout.as_ref())
                 .map(|v| v as usize)
                 .unwrap_or_else(|e| -e as usize);
 
@@ -121,11 +127,10 @@ pub(crate) fn sgx_enarxcall<'a>(
         }
         item::Enarxcall {
             num: item::enarxcall::Number::UnPark,
+            argv: [key, count, ..],
             ret,
-            ..
         } => {
-            THREAD_PARK.unpark();
-            *ret = 0;
+            *ret = THREAD_PARK.unpark(*key, *count as _) as _;
             Ok(None)
         }
         item::Enarxcall {
@@ -230,6 +235,14 @@ pub(crate) fn sgx_enarxcall<'a>(
             ..
         } => {
             let mut fd_locked = keep.enclave.lock().unwrap();
+
+            // The lock of the enclave serializes the accounting of its memory.
+            let mapped = keep.mapped.load(Ordering::SeqCst);
+            if keep.limits.exceeds_memory(mapped, *len as _) {
+                *ret = -ENOMEM as usize;
+                return Ok(None);
+            }
+
             // Safety: an `mmap()` call is pointed to a file descriptor of the
             // created enclave, and can therefore only affect the memory
             // mappings within the address range given to ENCLAVE_CREATE.
@@ -243,6 +256,7 @@ pub(crate) fn sgx_enarxcall<'a>(
                 Ok(map) => {
                     // Skip `drop()`. The VMA's ownership has been moved to the shim.
                     forget(map);
+                    keep.mapped.fetch_add(*len as _, Ordering::SeqCst);
                     *ret = 0;
                 }
                 Err(err) => {
@@ -302,6 +316,11 @@ pub(crate) fn sgx_enarxcall<'a>(
             // Safety: the parameters have been sanity checked before, that only
             // enclave memory is unmapped.
             unsafe { libc::munmap(*addr as *mut _, *len) };
+            let _ = keep
+                .mapped
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |mapped| {
+                    Some(mapped.saturating_sub(*len as _))
+                });
 
             let remove_pages = RemovePages::new(*addr - keep.mem.addr(), *len);
             remove_pages
@@ -3,6 +3,7 @@
 mod attestation;
 mod builder;
 mod config;
+mod coredump;
 mod data;
 mod enarxcall;
 mod hasher;
@@ -14,14 +15,19 @@ use super::Loader;
 use anyhow::{Context, Result};
 use mmarinus::{perms, Map};
 
+use crate::backend::limits::KeepLimits;
+use crate::backend::metrics::Metrics;
 use crate::backend::Signatures;
 use std::arch::x86_64::__cpuid_count;
+use std::collections::BTreeSet;
 use std::fs::File;
 use std::io::{self, ErrorKind};
 use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
 use std::sync::{Arc, Mutex, RwLock};
 
 use der::Sequence;
+use sgx::page::Flags;
 use x509_cert::Certificate;
 
 pub const AESM_SOCKET: &str = "/var/run/aesmd/aesm.socket";
@@ -42,6 +48,20 @@ pub(crate) struct Keep {
     mem: Map<perms::Unknown>,
     tcs: RwLock<Vec<Tcs>>,
     enclave: Mutex<File>,
+    /// The enclave was created with `ATTRIBUTES.DEBUG`
+    debug: bool,
+    /// The number of pages of an SSA frame
+    ssa_pages: u32,
+    /// The address, size and permissions of the pages added to a debug enclave
+    pages: Vec<(usize, usize, Flags)>,
+    /// The TCSs of the threads executing a host call, if core dumps are enabled
+    parked: Mutex<BTreeSet<Tcs>>,
+    /// The bytes of enclave memory mapped by the host
+    mapped: AtomicU64,
+    /// The metrics of the keep, if they are exported
+    metrics: Option<Arc<Metrics>>,
+    /// The resource limits of the keep
+    limits: Arc<KeepLimits>,
 }
 
 impl Keep {
@@ -99,6 +119,18 @@ impl crate::backend::Backend for Backend {
     fn hash(&self, shim: &[u8], exec: &[u8]) -> Result<Vec<u8>> {
         hasher::Hasher::load(shim, exec, None)
     }
+
+    #[inline]
+    fn check_shim(&self, shim: &[u8]) -> Result<()> {
+        builder::Builder::check(shim)
+    }
+
+    fn debuggable(&self, shim: &[u8]) -> Result<bool> {
+        use super::Config as _;
+
+        let shim = super::Binary::new(shim)?;
+        Ok(config::Config::new(&shim, None)?.debug())
+    }
 }
 
 /// Returns the "system-level" search path for the SGX
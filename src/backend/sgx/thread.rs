@@ -3,13 +3,14 @@
 use super::enarxcall::sgx_enarxcall;
 #[cfg(feature = "gdb")]
 use crate::backend::execute_gdb;
+use crate::backend::hook::{self, Hook};
+use crate::backend::metrics::{self, Metrics};
 use crate::backend::Command;
+use crate::backend::{coredump, limits, policy, signals};
 
 use std::arch::asm;
 use std::iter;
 use std::mem::{size_of, MaybeUninit};
-#[cfg(feature = "gdb")]
-use std::net::TcpStream;
 use std::sync::Arc;
 use std::{io, ptr};
 
@@ -28,8 +29,8 @@ pub struct Thread {
     block: [Vec<usize>; 2],
     cssa: usize,
     how: usize,
-    #[cfg(feature = "gdb")]
-    gdb_fd: Option<TcpStream>,
+    metrics: Option<Arc<Metrics>>,
+    _limit: limits::ThreadSlot,
 }
 
 impl Drop for Thread {
@@ -46,6 +47,10 @@ impl super::super::Keep for super::Keep {
             .lookup("__vdso_sgx_enter_enclave")
             .expect("__vdso_sgx_enter_enclave not found");
 
+        let Some(limit) = self.limits.thread() else {
+            return Ok(None);
+        };
+
         let tcs = self.tcs.write().unwrap().pop();
 
         let tcs = match tcs {
@@ -59,14 +64,14 @@ impl super::super::Keep for super::Keep {
         ];
 
         Ok(Some(Box::new(Thread {
+            metrics: self.metrics.clone(),
             keep: self,
             vdso,
             tcs,
             block,
             cssa: usize::default(),
             how: EENTER,
-            #[cfg(feature = "gdb")]
-            gdb_fd: None,
+            _limit: limit,
         })))
     }
 }
@@ -129,6 +134,13 @@ impl super::super::Thread for Thread {
 
         let exit_status = exit_status as i32;
 
+        if let Some(metrics) = &self.metrics {
+            metrics.exit(match run.function as usize {
+                EEXIT => "EEXIT".into(),
+                _ => metrics::variant(&run.vector),
+            });
+        }
+
         self.how = match run.function as usize {
             EENTER | ERESUME if run.vector == Vector::InvalidOpcode => EENTER,
             EENTER | ERESUME if run.vector == Vector::Page => {
@@ -147,6 +159,10 @@ impl super::super::Thread for Thread {
             }
 
             _ => {
+                if coredump::enabled() {
+                    super::coredump::dump(&self.keep, self.tcs, self.cssa);
+                }
+
                 if cfg!(feature = "dbg") {
                     error!(
                         ?run.vector,
@@ -183,20 +199,21 @@ impl super::super::Thread for Thread {
         // Handle some potential sallyport contents
         if self.cssa == 1 || self.cssa == 2 {
             if let (EENTER, ERESUME) = (how, self.how) {
-                let block: Block = self.block[self.cssa - 1].as_mut_slice().into();
-                for item in block {
-                    match item {
+                if coredump::enabled() {
+                    self.keep.parked.lock().unwrap().insert(self.tcs);
+                }
+
+                let (block, signal) = item::split_signal(&mut self.block[self.cssa - 1])
+                    .context("empty sallyport block")?;
+                for mut item in Block::from(block) {
+                    let hook = Hook::before(&item, self.metrics.as_deref());
+                    match hook::reborrow(&mut item) {
                         Item::Gdbcall(_gdbcall, _data) => {
                             #[cfg(feature = "gdb")]
                             unsafe {
-                                execute_gdb(
-                                    _gdbcall,
-                                    _data,
-                                    &mut self.gdb_fd,
-                                    _gdblisten.as_ref().unwrap(),
-                                )
-                                .map_err(io::Error::from_raw_os_error)
-                                .context("execute_gdb")?;
+                                execute_gdb(_gdbcall, _data, _gdblisten.as_ref().unwrap())
+                                    .map_err(io::Error::from_raw_os_error)
+                                    .context("execute_gdb")?;
                             }
                         }
 
@@ -235,10 +252,11 @@ impl super::super::Thread for Thread {
                             ..,
                         ) if (*num == libc::SYS_exit_group as usize) => {
                             trace!("exit_group({code})");
+                            metrics::finish();
                             std::process::exit(*code as _);
                         }
 
-                        Item::Syscall(ref _syscall, ..) => {
+                        Item::Syscall(_syscall, _data) => {
                             #[cfg(feature = "dbg")]
                             match (
                                 _syscall.num as libc::c_long,
@@ -254,11 +272,17 @@ impl super::super::Thread for Thread {
                                 }
                             }
                             // println!("Syscall: {:?}", _syscall);
-                            sallyport::host::execute(iter::once(item))
+                            policy::execute(iter::once(Item::Syscall(_syscall, _data)))
                                 .map_err(io::Error::from_raw_os_error)
                                 .context("sallyport::host::execute")?;
                         }
                     }
+                    hook.after(&item, self.metrics.as_deref());
+                }
+                signals::post(signal);
+
+                if coredump::enabled() {
+                    self.keep.parked.lock().unwrap().remove(&self.tcs);
                 }
             }
         }
@@ -0,0 +1,200 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Forwarding of signals of the host process into the keep.
+//!
+//! [`FORWARDED`] signals do not kill the host process anymore. They are queued for the keep
+//! instead and [`post`]ed in the signal word of the sallyport block on the next exit of any keep
+//! thread to the host, so no thread of the keep has to wait for them. If the keep has not exited
+//! within the grace period after the first forwarded signal, or the same signal arrives twice,
+//! the host process tears down the keep and exits.
+
+use super::metrics;
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use std::{mem, ptr, slice, thread};
+
+use anyhow::{anyhow, Context, Result};
+use libc::{c_int, SIGHUP, SIGINT, SIGTERM};
+use once_cell::sync::OnceCell;
+use tracing::{debug, error};
+
+/// The signals forwarded into the keep
+pub const FORWARDED: [c_int; 3] = [SIGINT, SIGTERM, SIGHUP];
+
+/// The signals queued for the keep, once forwarding is installed.
+static SIGNALS: OnceCell<Signals> = OnceCell::new();
+
+/// The write end of the pipe the signal handler passes signals on.
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+#[derive(Debug, Default)]
+struct Signals {
+    pending: Mutex<VecDeque<c_int>>,
+    /// Whether `pending` is not empty, checked on every exit without taking the lock
+    queued: AtomicBool,
+}
+
+impl Signals {
+    fn push(&self, signum: c_int) {
+        self.pending.lock().unwrap().push_back(signum);
+        self.queued.store(true, Ordering::SeqCst);
+    }
+
+    /// Posts the next pending signal in the signal word `signal` of a sallyport block,
+    /// unless the keep has not collected the signal posted before.
+    fn post(&self, signal: &mut usize) {
+        if *signal != 0 || !self.queued.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(signum) = pending.pop_front() {
+            *signal = signum as _;
+        }
+        self.queued.store(!pending.is_empty(), Ordering::SeqCst);
+    }
+}
+
+/// Installs the forwarding of [`FORWARDED`] signals into the keep of this process.
+///
+/// The keep is torn down `grace` after the first forwarded signal.
+pub fn install(grace: Duration) -> Result<()> {
+    SIGNALS
+        .set(Signals::default())
+        .map_err(|_| anyhow!("signal forwarding is already installed"))?;
+
+    // Open `/dev/null` to reserve fd 3, which `exec-wasmtime` expects the exec socket at
+    let _reserved = File::open("/dev/null").context("failed to open `/dev/null`")?;
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } != 0 {
+        return Err(io::Error::last_os_error()).context("failed to create signal pipe");
+    }
+    // The read end blocks, only the signal handler must never block.
+    if unsafe { libc::fcntl(fds[0], libc::F_SETFL, 0) } != 0 {
+        return Err(io::Error::last_os_error()).context("failed to set up signal pipe");
+    }
+    PIPE.store(fds[1], Ordering::SeqCst);
+
+    // Safety: we've just created the file descriptor.
+    let pipe = unsafe { File::from_raw_fd(fds[0]) };
+    thread::Builder::new()
+        .name("signals".into())
+        .spawn(move || forward(pipe, grace))
+        .context("failed to spawn signal thread")?;
+
+    for signum in FORWARDED {
+        let mut action: libc::sigaction = unsafe { mem::zeroed() };
+        action.sa_sigaction = handle as extern "C" fn(c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        if unsafe { libc::sigaction(signum, &action, ptr::null_mut()) } != 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("failed to install handler for signal {signum}"));
+        }
+    }
+    Ok(())
+}
+
+/// Passes `signum` on to the signal thread, only doing what is async-signal-safe.
+extern "C" fn handle(signum: c_int) {
+    let byte = signum as u8;
+    unsafe { libc::write(PIPE.load(Ordering::Relaxed), (&byte as *const u8).cast(), 1) };
+}
+
+/// Queues the signals read from `pipe` for the keep and tears the keep down `grace` after the
+/// first one.
+fn forward(mut pipe: File, grace: Duration) {
+    let signals = SIGNALS.get().unwrap();
+    let mut first: Option<(c_int, Instant)> = None;
+    let mut forwarded = Vec::new();
+
+    loop {
+        let timeout = match first {
+            Some((_, at)) => (at + grace)
+                .saturating_duration_since(Instant::now())
+                .as_millis()
+                .try_into()
+                .unwrap_or(c_int::MAX),
+            None => -1,
+        };
+
+        let mut fd = libc::pollfd {
+            fd: pipe.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut fd, 1, timeout) } {
+            0 => {
+                let (signum, _) = first.unwrap();
+                error!("keep did not exit within {grace:?} after signal {signum}");
+                teardown(signum);
+            }
+            n if n < 0 => continue,
+            _ => {}
+        }
+
+        let mut byte = 0u8;
+        if pipe.read_exact(slice::from_mut(&mut byte)).is_err() {
+            continue;
+        }
+        let signum = byte as c_int;
+
+        if forwarded.contains(&signum) {
+            error!("signal {signum} received again, tearing down the keep");
+            teardown(signum);
+        }
+        debug!("forwarding signal {signum} to the keep");
+        forwarded.push(signum);
+        first.get_or_insert((signum, Instant::now()));
+        signals.push(signum);
+    }
+}
+
+/// Exits the host process like the default action of `signum` would.
+fn teardown(signum: c_int) -> ! {
+    metrics::finish();
+    std::process::exit(128 + signum)
+}
+
+/// Posts the next signal forwarded to the keep in the signal word `signal` of a sallyport block,
+/// see [`sallyport::item::split_signal`].
+///
+/// Called on every exit of a keep thread to the host, once the block is executed.
+pub(crate) fn post(signal: &mut usize) {
+    if let Some(signals) = SIGNALS.get() {
+        signals.post(signal);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn queue() {
+        let queue = Signals::default();
+        let mut signal = 0;
+        queue.post(&mut signal);
+        assert_eq!(signal, 0);
+
+        queue.push(SIGTERM);
+        queue.push(SIGINT);
+        queue.post(&mut signal);
+        assert_eq!(signal, SIGTERM as usize);
+
+        // The keep has not collected the last signal yet.
+        queue.post(&mut signal);
+        assert_eq!(signal, SIGTERM as usize);
+
+        signal = 0;
+        queue.post(&mut signal);
+        assert_eq!(signal, SIGINT as usize);
+        assert!(!queue.queued.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,588 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! strace-like tracing of the host calls of a keep.
+//!
+//! Every [`Item`](sallyport::item::Item) a keep passes to the host is decoded before it is
+//! executed and written out together with its result and latency, once it was executed.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::mem;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use libc::{c_int, c_long, pollfd, AT_FDCWD};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+use super::hook::{Call, Number};
+use super::policy::{sockaddr, Address};
+
+/// The tracer installed for this process, if any.
+static TRACER: OnceCell<Tracer> = OnceCell::new();
+
+/// The maximum number of bytes of a buffer written out.
+const MAX_BYTES: usize = 32;
+
+/// The format of the trace.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One strace-like line per host call
+    #[default]
+    Text,
+    /// One JSON object per line and host call
+    Json,
+}
+
+/// Convert a str to a Format. This is how Clap parses CLI args.
+impl FromStr for Format {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!("unknown trace format {:?}", s)),
+        }
+    }
+}
+
+/// Writes decoded host calls to `out`.
+struct Tracer {
+    format: Format,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Tracer {
+    fn write(&self, record: &Record) {
+        let line = match self.format {
+            Format::Text => record.to_string(),
+            Format::Json => serde_json::to_string(record).expect("failed to serialize trace"),
+        };
+        let mut out = self.out.lock().unwrap();
+        // Tracing must never take down the keep, so write errors are ignored.
+        let _ = writeln!(out, "{line}");
+    }
+}
+
+/// A decoded host call.
+#[derive(Debug, Default, Serialize)]
+struct Record {
+    tid: c_long,
+    kind: &'static str,
+    name: String,
+    args: Vec<String>,
+
+    /// The returned value, unless the call did not return
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ret: Option<isize>,
+
+    /// The errno, if the call failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errno: Option<String>,
+
+    /// Data returned by the call
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    out: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ns: Option<u128>,
+}
+
+impl std::fmt::Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] ", self.tid)?;
+        if self.kind != "syscall" {
+            write!(f, "{}:", self.kind)?;
+        }
+        write!(f, "{}({})", self.name, self.args.join(", "))?;
+        match (self.ret, &self.errno) {
+            (None, _) => write!(f, " = ?")?,
+            (Some(_), Some(errno)) => write!(f, " = -1 {errno}")?,
+            (Some(ret), None) => write!(f, " = {ret}")?,
+        }
+        if !self.out.is_empty() {
+            write!(f, " => {}", self.out.join(", "))?;
+        }
+        if let Some(latency) = self.latency_ns {
+            write!(
+                f,
+                " <{}.{:09}>",
+                latency / 1_000_000_000,
+                latency % 1_000_000_000
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A host call being executed, which is written out after it was executed.
+pub(crate) struct Pending {
+    tracer: &'static Tracer,
+    record: Record,
+    start: Instant,
+}
+
+impl Pending {
+    /// Writes out the executed `call`.
+    pub(crate) fn after(mut self, call: &Call<'_>) {
+        self.record.latency_ns = Some(self.start.elapsed().as_nanos());
+        self.record.ret = Some(call.ret as isize);
+        if let Some(errno) = call.errno() {
+            self.record.errno = Some(self::errno(errno));
+        } else if let Number::Syscall(num) = call.number {
+            self.record.out = syscall_out(num, &call.argv, call.ret as _, call.data);
+        }
+        self.tracer.write(&self.record);
+    }
+}
+
+/// Installs a tracer writing all host calls of all keeps of this process to `out`.
+pub fn install(format: Format, out: Box<dyn Write + Send>) -> Result<()> {
+    TRACER
+        .set(Tracer {
+            format,
+            out: Mutex::new(out),
+        })
+        .map_err(|_| anyhow!("a host call tracer is already installed"))
+}
+
+/// Starts tracing `call` before it is executed, if a tracer is installed.
+pub(crate) fn before(call: &Call<'_>) -> Option<Pending> {
+    let tracer = TRACER.get()?;
+    let (name, args) = match call.number {
+        Number::Syscall(num) => syscall(num, &call.argv, call.data),
+        _ => (
+            call.name(),
+            call.argv[..4].iter().map(|arg| hex(*arg)).collect(),
+        ),
+    };
+    let record = Record {
+        tid: unsafe { libc::syscall(libc::SYS_gettid) },
+        kind: call.kind(),
+        name,
+        args,
+        ..Default::default()
+    };
+
+    // `exit` and `exit_group` do not return to the backend, so write them out right away.
+    if call.is_exit() {
+        tracer.write(&record);
+        return None;
+    }
+
+    Some(Pending {
+        tracer,
+        record,
+        start: Instant::now(),
+    })
+}
+
+/// Returns the name and the decoded arguments of syscall `num`.
+///
+/// The arguments are decoded as laid out by the guest side of sallyport, so buffers are
+/// offsets into `data`.
+fn syscall(num: c_long, argv: &[usize; 6], data: &[u8]) -> (String, Vec<String>) {
+    let [a0, a1, a2, a3, a4, a5] = *argv;
+    let args = match num {
+        libc::SYS_accept | libc::SYS_getsockname => vec![fd(a0), ptr(a1), ptr(a2)],
+        libc::SYS_accept4 => vec![fd(a0), ptr(a1), ptr(a2), hex(a3)],
+        libc::SYS_bind | libc::SYS_connect => vec![fd(a0), addr(data, a1, a2), int(a2)],
+        libc::SYS_clock_getres | libc::SYS_clock_gettime => vec![int(a0), ptr(a1)],
+        libc::SYS_close
+        | libc::SYS_dup
+        | libc::SYS_fdatasync
+        | libc::SYS_fsync
+        | libc::SYS_exit
+        | libc::SYS_exit_group => vec![int(a0)],
+        libc::SYS_dup2 => vec![fd(a0), fd(a1)],
+        libc::SYS_dup3 => vec![fd(a0), fd(a1), hex(a2)],
+        libc::SYS_epoll_create1 => vec![hex(a0)],
+        libc::SYS_epoll_ctl => vec![fd(a0), int(a1), fd(a2), ptr(a3)],
+        libc::SYS_epoll_pwait => vec![fd(a0), ptr(a1), int(a2), int(a3), ptr(a4)],
+        libc::SYS_epoll_wait => vec![fd(a0), ptr(a1), int(a2), int(a3)],
+        libc::SYS_eventfd2 => vec![int(a0), hex(a1)],
+        libc::SYS_fcntl => vec![fd(a0), int(a1), hex(a2)],
+        libc::SYS_ftruncate => vec![fd(a0), int(a1)],
+        libc::SYS_getdents64 => vec![fd(a0), ptr(a1), int(a2)],
+        libc::SYS_ioctl => vec![fd(a0), hex(a1), ptr(a2)],
+        libc::SYS_listen => vec![fd(a0), int(a1)],
+        libc::SYS_lseek => vec![fd(a0), int(a1), int(a2)],
+        libc::SYS_mkdirat => vec![dirfd(a0), path(data, a1, a2), oct(a3)],
+        libc::SYS_nanosleep => vec![ptr(a0), ptr(a1)],
+        libc::SYS_newfstatat => vec![dirfd(a0), path(data, a1, a2), ptr(a3), hex(a4)],
+        libc::SYS_open => vec![path(data, a0, a1), hex(a2), oct(a3)],
+        libc::SYS_openat => vec![dirfd(a0), path(data, a1, a2), hex(a3), oct(a4)],
+        libc::SYS_poll => vec![pollfds(data, a0, a1), int(a1), int(a2)],
+        libc::SYS_pread64 => vec![fd(a0), ptr(a1), int(a2), int(a3)],
+        libc::SYS_pwrite64 => vec![fd(a0), bytes(data, a1, a2), int(a2), int(a3)],
+        libc::SYS_read => vec![fd(a0), ptr(a1), int(a2)],
+        libc::SYS_recvfrom => vec![fd(a0), ptr(a1), int(a2), hex(a3), ptr(a4), ptr(a5)],
+        libc::SYS_renameat2 => vec![
+            dirfd(a0),
+            path(data, a1, a2),
+            dirfd(a3),
+            path(data, a4, a5 >> 32),
+            hex(a5 & u32::MAX as usize),
+        ],
+        libc::SYS_sendto => vec![
+            fd(a0),
+            bytes(data, a1, a2),
+            int(a2),
+            hex(a3),
+            addr(data, a4, a5),
+            int(a5),
+        ],
+        libc::SYS_setsockopt => vec![fd(a0), int(a1), int(a2), bytes(data, a3, a4), int(a4)],
+        libc::SYS_socket => vec![int(a0), hex(a1), int(a2)],
+        libc::SYS_sync => vec![],
+        libc::SYS_unlinkat => vec![dirfd(a0), path(data, a1, a2), hex(a3)],
+        libc::SYS_write => vec![fd(a0), bytes(data, a1, a2), int(a2)],
+        _ => argv.iter().map(|arg| hex(*arg)).collect(),
+    };
+    (syscall_name(num), args)
+}
+
+/// Decodes the data returned by syscall `num`, which returned `ret`.
+fn syscall_out(num: c_long, argv: &[usize; 6], ret: isize, data: &[u8]) -> Vec<String> {
+    let [_, a1, a2, _, a4, a5] = *argv;
+    let len = ret as usize;
+    match num {
+        libc::SYS_read | libc::SYS_pread64 => vec![bytes(data, a1, len)],
+        libc::SYS_recvfrom if a4 == sallyport::NULL => vec![bytes(data, a1, len)],
+        libc::SYS_recvfrom => vec![bytes(data, a1, len), addr_out(data, a4, a5)],
+        libc::SYS_accept | libc::SYS_accept4 | libc::SYS_getsockname if a1 != sallyport::NULL => {
+            vec![addr_out(data, a1, a2)]
+        }
+        _ => vec![],
+    }
+}
+
+/// Returns the name of syscall `num`.
+pub(super) fn syscall_name(num: c_long) -> String {
+    macro_rules! names {
+        ($($name:ident),* $(,)?) => {
+            match num {
+                $(libc::$name => stringify!($name)["SYS_".len()..].into(),)*
+                _ => format!("syscall_{num}"),
+            }
+        };
+    }
+    names!(
+        SYS_accept,
+        SYS_accept4,
+        SYS_arch_prctl,
+        SYS_bind,
+        SYS_brk,
+        SYS_clock_getres,
+        SYS_clock_gettime,
+        SYS_close,
+        SYS_connect,
+        SYS_dup,
+        SYS_dup2,
+        SYS_dup3,
+        SYS_epoll_create1,
+        SYS_epoll_ctl,
+        SYS_epoll_pwait,
+        SYS_epoll_wait,
+        SYS_eventfd2,
+        SYS_exit,
+        SYS_exit_group,
+        SYS_fcntl,
+        SYS_fdatasync,
+        SYS_fstat,
+        SYS_fsync,
+        SYS_ftruncate,
+        SYS_getdents64,
+        SYS_getegid,
+        SYS_geteuid,
+        SYS_getgid,
+        SYS_getpid,
+        SYS_getrandom,
+        SYS_getsockname,
+        SYS_getuid,
+        SYS_ioctl,
+        SYS_listen,
+        SYS_lseek,
+        SYS_madvise,
+        SYS_mkdirat,
+        SYS_mmap,
+        SYS_mprotect,
+        SYS_munmap,
+        SYS_nanosleep,
+        SYS_newfstatat,
+        SYS_open,
+        SYS_openat,
+        SYS_pipe2,
+        SYS_poll,
+        SYS_pread64,
+        SYS_pwrite64,
+        SYS_read,
+        SYS_readlink,
+        SYS_readv,
+        SYS_recvfrom,
+        SYS_renameat2,
+        SYS_rt_sigaction,
+        SYS_rt_sigprocmask,
+        SYS_sendto,
+        SYS_set_tid_address,
+        SYS_setsockopt,
+        SYS_sigaltstack,
+        SYS_socket,
+        SYS_sync,
+        SYS_uname,
+        SYS_unlinkat,
+        SYS_write,
+        SYS_writev,
+    )
+}
+
+/// Returns the name and description of errno `e`.
+fn errno(e: c_int) -> String {
+    macro_rules! names {
+        ($($name:ident),* $(,)?) => {
+            match e {
+                $(libc::$name => stringify!($name).into(),)*
+                _ => e.to_string(),
+            }
+        };
+    }
+    let name: String = names!(
+        EPERM,
+        ENOENT,
+        ESRCH,
+        EINTR,
+        EIO,
+        ENXIO,
+        E2BIG,
+        EBADF,
+        EAGAIN,
+        ENOMEM,
+        EACCES,
+        EFAULT,
+        EBUSY,
+        EEXIST,
+        EXDEV,
+        ENODEV,
+        ENOTDIR,
+        EISDIR,
+        EINVAL,
+        ENFILE,
+        EMFILE,
+        ENOTTY,
+        EFBIG,
+        ENOSPC,
+        ESPIPE,
+        EROFS,
+        EPIPE,
+        ERANGE,
+        ENAMETOOLONG,
+        ENOSYS,
+        ENOTEMPTY,
+        ELOOP,
+        EOVERFLOW,
+        ENOTSOCK,
+        EDESTADDRREQ,
+        EMSGSIZE,
+        EPROTOTYPE,
+        ENOPROTOOPT,
+        EPROTONOSUPPORT,
+        EOPNOTSUPP,
+        EAFNOSUPPORT,
+        EADDRINUSE,
+        EADDRNOTAVAIL,
+        ENETDOWN,
+        ENETUNREACH,
+        ECONNABORTED,
+        ECONNRESET,
+        ENOBUFS,
+        EISCONN,
+        ENOTCONN,
+        ETIMEDOUT,
+        ECONNREFUSED,
+        EHOSTUNREACH,
+        EALREADY,
+        EINPROGRESS,
+    );
+    let msg = io::Error::from_raw_os_error(e).to_string();
+    let msg = msg.split(" (os error").next().unwrap_or_default();
+    format!("{name} ({msg})")
+}
+
+fn int(arg: usize) -> String {
+    (arg as isize).to_string()
+}
+
+fn fd(arg: usize) -> String {
+    (arg as c_int).to_string()
+}
+
+fn dirfd(arg: usize) -> String {
+    match arg as c_int {
+        AT_FDCWD => "AT_FDCWD".into(),
+        fd => fd.to_string(),
+    }
+}
+
+fn hex(arg: usize) -> String {
+    format!("{arg:#x}")
+}
+
+fn oct(arg: usize) -> String {
+    format!("{arg:#o}")
+}
+
+/// Formats an offset into the block data.
+fn ptr(arg: usize) -> String {
+    if arg == sallyport::NULL {
+        "NULL".into()
+    } else {
+        format!("&data[{arg:#x}]")
+    }
+}
+
+/// Returns the `len` bytes at `offset` in `data`, if they are in bounds.
+fn slice(data: &[u8], offset: usize, len: usize) -> Option<&[u8]> {
+    data.get(offset..offset.checked_add(len)?)
+}
+
+/// Formats at most [`MAX_BYTES`] of the `len` bytes at `offset` as an escaped string.
+fn bytes(data: &[u8], offset: usize, len: usize) -> String {
+    let Some(buf) = slice(data, offset, len) else {
+        return ptr(offset);
+    };
+    let mut s = String::from("\"");
+    for b in buf.iter().take(MAX_BYTES) {
+        match b {
+            b'"' => s.push_str("\\\""),
+            b'\\' => s.push_str("\\\\"),
+            b'\n' => s.push_str("\\n"),
+            b'\r' => s.push_str("\\r"),
+            b'\t' => s.push_str("\\t"),
+            0x20..=0x7e => s.push(*b as char),
+            _ => write!(s, "\\x{b:02x}").unwrap(),
+        }
+    }
+    s.push('"');
+    if buf.len() > MAX_BYTES {
+        s.push_str("...");
+    }
+    s
+}
+
+/// Formats the nul-terminated path of `len` bytes at `offset`.
+fn path(data: &[u8], offset: usize, len: usize) -> String {
+    match slice(data, offset, len) {
+        Some([path @ .., 0]) => format!("{:?}", String::from_utf8_lossy(path)),
+        _ => ptr(offset),
+    }
+}
+
+/// Formats the socket address of `len` bytes at `offset`.
+fn addr(data: &[u8], offset: usize, len: usize) -> String {
+    if offset == sallyport::NULL {
+        return ptr(offset);
+    }
+    match slice(data, offset, len).and_then(sockaddr) {
+        Some(Address::Ip(addr)) => format!("{{{addr}}}"),
+        Some(Address::Unix(path)) => format!("{{{:?}}}", String::from_utf8_lossy(path)),
+        None => ptr(offset),
+    }
+}
+
+/// Formats the socket address at `offset`, whose length is stored at `len_offset`.
+fn addr_out(data: &[u8], offset: usize, len_offset: usize) -> String {
+    let len = slice(data, len_offset, mem::size_of::<libc::socklen_t>())
+        .and_then(|len| Some(libc::socklen_t::from_ne_bytes(len.try_into().ok()?)));
+    match len {
+        Some(len) => addr(data, offset, len as _),
+        None => ptr(offset),
+    }
+}
+
+/// Formats the `nfds` file descriptors to poll at `offset`.
+fn pollfds(data: &[u8], offset: usize, nfds: usize) -> String {
+    let size = mem::size_of::<pollfd>();
+    let Some(buf) = nfds.checked_mul(size).and_then(|len| slice(data, offset, len)) else {
+        return ptr(offset);
+    };
+    let fds: Vec<_> = buf
+        .chunks_exact(size)
+        .map(|pfd| {
+            let fd = c_int::from_ne_bytes(pfd[..4].try_into().unwrap());
+            let events = i16::from_ne_bytes(pfd[4..6].try_into().unwrap());
+            format!("{{fd={fd}, events={events:#x}}}")
+        })
+        .collect();
+    format!("[{}]", fds.join(", "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        let mut data = b"/etc/hosts\0hello\n".to_vec();
+        data.extend((libc::AF_INET as u16).to_ne_bytes());
+        data.extend(53u16.to_be_bytes());
+        data.extend([10, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let open = [0, 11, libc::O_RDONLY as _, 0, 0, 0];
+        assert_eq!(
+            syscall(libc::SYS_open, &open, &data),
+            (
+                "open".into(),
+                vec!["\"/etc/hosts\"".into(), "0x0".into(), "0o0".into()]
+            )
+        );
+
+        let sendto = [3, 11, 6, 0, 17, 16];
+        assert_eq!(
+            syscall(libc::SYS_sendto, &sendto, &data).1,
+            ["3", "\"hello\\n\"", "6", "0x0", "{10.0.0.1:53}", "16"]
+        );
+
+        // Out of bounds buffers are not dereferenced.
+        let write = [1, 11, 64, 0, 0, 0];
+        assert_eq!(
+            syscall(libc::SYS_write, &write, &data).1,
+            ["1", "&data[0xb]", "64"]
+        );
+
+        assert_eq!(syscall_name(1000), "syscall_1000");
+    }
+
+    #[test]
+    fn format() {
+        let record = Record {
+            tid: 42,
+            kind: "syscall",
+            name: "read".into(),
+            args: vec!["0".into(), "&data[0x0]".into(), "16".into()],
+            ret: Some(-1),
+            errno: Some(errno(libc::EAGAIN)),
+            latency_ns: Some(1_500),
+            ..Default::default()
+        };
+        assert_eq!(
+            record.to_string(),
+            "[42] read(0, &data[0x0], 16) = -1 EAGAIN (Resource temporarily unavailable) <0.000001500>"
+        );
+
+        let record = Record {
+            tid: 42,
+            kind: "enarxcall",
+            name: "BalloonMemory".into(),
+            args: vec!["0x1".into()],
+            ret: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(record.to_string(), "[42] enarxcall:BalloonMemory(0x1) = 0");
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"tid":42,"kind":"enarxcall","name":"BalloonMemory","args":["0x1"],"ret":0}"#
+        );
+    }
+}
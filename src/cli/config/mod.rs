@@ -1,6 +1,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 mod init;
+mod schema;
+mod validate;
 
 use std::process::ExitCode;
 
@@ -10,12 +12,16 @@ use clap::Subcommand;
 #[derive(Subcommand, Debug)]
 pub enum Subcommands {
     Init(init::Options),
+    Validate(validate::Options),
+    Schema(schema::Options),
 }
 
 impl Subcommands {
     pub fn dispatch(self) -> anyhow::Result<ExitCode> {
         match self {
             Self::Init(cmd) => cmd.execute(),
+            Self::Validate(cmd) => cmd.execute(),
+            Self::Schema(cmd) => cmd.execute(),
         }
     }
 }
@@ -0,0 +1,33 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::stdout;
+use std::process::ExitCode;
+
+use camino::Utf8PathBuf;
+use clap::Args;
+use enarx_config::Config;
+
+/// Print the JSON Schema of `Enarx.toml`, e.g. for editor integration
+#[derive(Args, Debug)]
+pub struct Options {
+    /// File path to write the schema
+    #[clap(long)]
+    out: Option<Utf8PathBuf>,
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<ExitCode> {
+        let schema = serde_json::to_string_pretty(&Config::schema())?;
+
+        if let Some(path) = self.out {
+            let mut file = File::create(path)?;
+            file.write_all(schema.as_bytes())?;
+        } else {
+            stdout().write_all(schema.as_bytes())?;
+        }
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
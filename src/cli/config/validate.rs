@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs;
+use std::process::ExitCode;
+
+use anyhow::Context;
+use camino::Utf8PathBuf;
+use clap::Args;
+use enarx_config::Config;
+
+/// Validate an `Enarx.toml` configuration file
+///
+/// Reports syntax errors with their line and column, as well as
+/// semantic problems, which would only surface when running the keep.
+#[derive(Args, Debug)]
+pub struct Options {
+    /// Path of the configuration file
+    #[clap(value_name = "FILE", default_value = "Enarx.toml")]
+    pub path: Utf8PathBuf,
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<ExitCode> {
+        let path = self.path;
+        let config = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read configuration at `{path}`"))?;
+
+        let config: Config = match toml::from_str(&config) {
+            Ok(config) => config,
+            Err(e) => {
+                match e.line_col() {
+                    // `line_col` is zero-based
+                    Some((line, col)) => eprintln!("{path}:{}:{}: {e}", line + 1, col + 1),
+                    None => eprintln!("{path}: {e}"),
+                }
+                return Ok(ExitCode::FAILURE);
+            }
+        };
+
+        let problems = config.problems();
+        for problem in &problems {
+            eprintln!("{path}: {problem}");
+        }
+
+        if problems.is_empty() {
+            println!("{path} is valid");
+            Ok(ExitCode::SUCCESS)
+        } else {
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
@@ -0,0 +1,223 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! The JSON API of the daemon.
+//!
+//! Clients write one [`Request`] per line to the socket of the daemon
+//! and read one [`Response`] per line back, e.g.:
+//!
+//! ```text
+//! {"op":"create","name":"web","module":"/srv/web.wasm","restart":"on-failure"}
+//! {"ok":null}
+//! {"op":"start","name":"web"}
+//! {"ok":null}
+//! {"op":"list"}
+//! {"keeps":[{"name":"web","state":"running","pid":4242,"restarts":0,"exit":null}]}
+//! ```
+
+use camino::Utf8PathBuf;
+use enarx_config::Limits;
+use serde::{Deserialize, Serialize};
+
+/// A request to the daemon
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum Request {
+    /// Define a new keep, without starting it
+    Create(Spec),
+
+    /// Start a created or exited keep
+    Start { name: String },
+
+    /// Stop a running keep, which may exit gracefully within the grace period
+    Stop { name: String },
+
+    /// Forget an exited keep and its logs
+    Remove { name: String },
+
+    /// List all keeps
+    List,
+
+    /// Get the last `lines` lines of the output of a keep
+    Logs {
+        name: String,
+        #[serde(default)]
+        lines: Option<usize>,
+    },
+
+    /// Get the state and exit status of a keep
+    Status { name: String },
+}
+
+/// The definition of a keep
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Spec {
+    /// The unique name of the keep
+    pub name: String,
+
+    /// Path of the WebAssembly module to run
+    pub module: Utf8PathBuf,
+
+    /// Path of the package config
+    #[serde(default)]
+    pub wasmcfgfile: Option<Utf8PathBuf>,
+
+    /// The backend to use, instead of the best one available
+    #[serde(default)]
+    pub backend: Option<String>,
+
+    /// The CPUs to pin the threads of the keep to, e.g. `0-3,8`
+    #[serde(default)]
+    pub cpus: Option<String>,
+
+    /// The NUMA node to bind the guest memory of the keep to
+    #[serde(default)]
+    pub numa_node: Option<u32>,
+
+    /// The resource limits of the keep
+    #[serde(default)]
+    pub limits: Limits,
+
+    /// When to restart the keep after it exited
+    #[serde(default)]
+    pub restart: Restart,
+
+    /// The maximum number of restarts, unlimited if not given
+    #[serde(default)]
+    pub max_restarts: Option<u32>,
+}
+
+/// When to restart a keep after it exited
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Restart {
+    /// Never restart the keep
+    #[default]
+    Never,
+    /// Restart the keep, if it exited with a non-zero status
+    OnFailure,
+    /// Always restart the keep, unless it was stopped
+    Always,
+}
+
+impl Restart {
+    /// Returns `true`, if a keep, which exited `successfully` and was restarted `restarts` times
+    /// already, is restarted again.
+    pub fn applies(self, successfully: bool, restarts: u32, max_restarts: Option<u32>) -> bool {
+        let wanted = match self {
+            Self::Never => false,
+            Self::OnFailure => !successfully,
+            Self::Always => true,
+        };
+        wanted && !matches!(max_restarts, Some(max) if restarts >= max)
+    }
+}
+
+/// The state of a keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum State {
+    /// The keep was created, but never started
+    Created,
+    /// The keep is running
+    Running,
+    /// The keep was asked to stop and has not exited yet
+    Stopping,
+    /// The keep exited and waits to be restarted
+    Restarting,
+    /// The keep exited
+    Exited,
+}
+
+/// How a keep exited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ExitStatus {
+    /// The exit code, if the keep exited on its own
+    pub code: Option<i32>,
+    /// The signal, which killed the keep
+    pub signal: Option<i32>,
+}
+
+/// The state of a keep as reported by the daemon
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct KeepInfo {
+    pub name: String,
+    pub state: State,
+    /// The process id of the `enarx run` process running the keep
+    pub pid: Option<u32>,
+    pub restarts: u32,
+    /// How the keep exited the last time
+    pub exit: Option<ExitStatus>,
+}
+
+/// A response of the daemon
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Response {
+    /// The request succeeded
+    Ok(()),
+    /// The keeps of a `list` request
+    Keeps(Vec<KeepInfo>),
+    /// The keep of a `status` request
+    Keep(KeepInfo),
+    /// The output of a `logs` request
+    Logs(String),
+    /// The request failed
+    Error(String),
+}
+
+impl From<anyhow::Result<Response>> for Response {
+    fn from(result: anyhow::Result<Response>) -> Self {
+        result.unwrap_or_else(|e| Self::Error(format!("{e:#}")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        let create = r#"{"op":"create","name":"web","module":"/srv/web.wasm",
+            "limits":{"memory":"1GiB","threads":4},"restart":"on-failure","max_restarts":3,
+            "cpus":"0-3","numa_node":0}"#;
+        let Request::Create(spec) = serde_json::from_str(create).unwrap() else {
+            panic!("not a create request");
+        };
+        assert_eq!(spec.name, "web");
+        assert_eq!(spec.limits.memory.map(|size| size.0), Some(1 << 30));
+        assert_eq!(spec.restart, Restart::OnFailure);
+        assert_eq!(spec.max_restarts, Some(3));
+        assert_eq!(spec.cpus.as_deref(), Some("0-3"));
+        assert_eq!(spec.numa_node, Some(0));
+
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"op":"logs","name":"web"}"#).unwrap(),
+            Request::Logs {
+                name: "web".into(),
+                lines: None
+            }
+        );
+        assert!(serde_json::from_str::<Request>(r#"{"op":"restart","name":"web"}"#).is_err());
+    }
+
+    #[test]
+    fn responses() {
+        assert_eq!(
+            serde_json::to_string(&Response::Ok(())).unwrap(),
+            r#"{"ok":null}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Response::Error("no keep `web`".into())).unwrap(),
+            r#"{"error":"no keep `web`"}"#
+        );
+    }
+
+    #[test]
+    fn restart() {
+        assert!(!Restart::Never.applies(false, 0, None));
+        assert!(Restart::OnFailure.applies(false, 0, None));
+        assert!(!Restart::OnFailure.applies(true, 0, None));
+        assert!(Restart::Always.applies(true, 2, Some(3)));
+        assert!(!Restart::Always.applies(true, 3, Some(3)));
+    }
+}
@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: Apache-2.0
+
+mod api;
+mod supervisor;
+
+use api::{Request, Response};
+use supervisor::Supervisor;
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{mem, ptr, thread};
+
+use anyhow::{bail, Context};
+use camino::Utf8PathBuf;
+use clap::Args;
+use tracing::{debug, info};
+
+/// The signals shutting the daemon down
+const SHUTDOWN: [libc::c_int; 2] = [libc::SIGTERM, libc::SIGINT];
+
+/// Supervise many Enarx Keeps behind a local control API.
+///
+/// Clients connect to the Unix socket and send one JSON request per line,
+/// e.g. `{"op":"create","name":"web","module":"/srv/web.wasm","restart":"on-failure"}`,
+/// followed by `{"op":"start","name":"web"}`. Every request is answered by one JSON line.
+///
+/// Requests: create, start, stop, remove, list, logs and status.
+///
+/// Each keep runs in its own `enarx run` process, its output is appended to
+/// `<LOG_DIR>/<NAME>.log`. On SIGTERM or SIGINT, the daemon stops all keeps
+/// and exits, once they exited or were killed after the grace period.
+#[derive(Args, Debug)]
+pub struct Options {
+    /// Path of the Unix socket to serve the control API on
+    #[clap(
+        long,
+        value_name = "PATH",
+        env = "ENARX_DAEMON_SOCKET",
+        default_value = "/run/enarx/daemon.sock"
+    )]
+    pub socket: Utf8PathBuf,
+
+    /// Directory to write the output of the keeps to
+    #[clap(long, value_name = "DIR", default_value = "/var/log/enarx")]
+    pub log_dir: Utf8PathBuf,
+
+    /// Seconds a keep has to exit after it was asked to stop, before it is torn down
+    #[clap(long, value_name = "SECONDS", default_value_t = 10)]
+    pub grace_period: u64,
+
+    /// Number of keeps to build ahead of time and park, until a keep is started.
+    /// Keeps asking for a specific backend, CPUs or NUMA node are not served from the pool.
+    #[clap(long, value_name = "N", default_value_t = 0)]
+    pub pool: usize,
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<ExitCode> {
+        let Self {
+            socket,
+            log_dir,
+            grace_period,
+            pool,
+        } = self;
+
+        // Blocked before any thread is spawned, so only the signal thread receives them.
+        let signals = shutdown_signals();
+        match unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &signals, ptr::null_mut()) } {
+            0 => {}
+            e => return Err(io::Error::from_raw_os_error(e)).context("failed to block signals"),
+        }
+
+        fs::create_dir_all(&log_dir)
+            .with_context(|| format!("failed to create log directory at `{log_dir}`"))?;
+        let listener = bind(&socket)?;
+        info!("serving the control API on `{socket}`");
+
+        let supervisor = Supervisor::new(log_dir, Duration::from_secs(grace_period), pool)?;
+        {
+            let supervisor = supervisor.clone();
+            thread::Builder::new()
+                .name("signals".into())
+                .spawn(move || shutdown(signals, &supervisor, &socket))
+                .context("failed to spawn signal thread")?;
+        }
+        supervisor.fill();
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!("failed to accept client: {e}");
+                    continue;
+                }
+            };
+            let supervisor = supervisor.clone();
+            thread::Builder::new()
+                .name("client".into())
+                .spawn(move || {
+                    if let Err(e) = serve(&supervisor, stream) {
+                        debug!("client disconnected: {e}");
+                    }
+                })
+                .context("failed to spawn client thread")?;
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// Returns the set of the [`SHUTDOWN`] signals.
+pub(super) fn shutdown_signals() -> libc::sigset_t {
+    // Safety: the set is initialized by `sigemptyset`.
+    unsafe {
+        let mut set = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for signum in SHUTDOWN {
+            libc::sigaddset(&mut set, signum);
+        }
+        set
+    }
+}
+
+/// Waits for one of the blocked `signals`, then stops all keeps, removes the socket at `socket`
+/// and exits the daemon.
+fn shutdown(signals: libc::sigset_t, supervisor: &Supervisor, socket: &Utf8PathBuf) -> ! {
+    let mut signum = 0;
+    while unsafe { libc::sigwait(&signals, &mut signum) } != 0 {}
+
+    info!("received signal {signum}, shutting down");
+    supervisor.shutdown();
+    let _ = fs::remove_file(socket);
+    std::process::exit(0)
+}
+
+/// Binds the socket of the control API at `path`, replacing a stale one.
+fn bind(path: &Utf8PathBuf) -> anyhow::Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            bail!("another daemon is already serving on `{path}`");
+        }
+        fs::remove_file(path).with_context(|| format!("failed to remove stale socket `{path}`"))?;
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create socket directory at `{dir}`"))?;
+    }
+
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("failed to bind socket at `{path}`"))?;
+    // Anyone able to connect can run keeps as the user of the daemon.
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("failed to restrict access to socket at `{path}`"))?;
+    Ok(listener)
+}
+
+/// Answers the requests of a client until it disconnects.
+fn serve(supervisor: &Arc<Supervisor>, stream: UnixStream) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => handle(supervisor, request),
+            Err(e) => Response::Error(format!("invalid request: {e}")),
+        };
+        serde_json::to_writer(&mut writer, &response)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+fn handle(supervisor: &Arc<Supervisor>, request: Request) -> Response {
+    debug!("request: {request:?}");
+    match request {
+        Request::Create(spec) => supervisor.create(spec),
+        Request::Start { name } => supervisor.start(&name),
+        Request::Stop { name } => supervisor.stop(&name),
+        Request::Remove { name } => supervisor.remove(&name),
+        Request::List => supervisor.list(),
+        Request::Logs { name, lines } => supervisor.logs(&name, lines),
+        Request::Status { name } => supervisor.status(&name),
+    }
+    .into()
+}
@@ -0,0 +1,627 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! The keeps managed by the daemon.
+//!
+//! Every keep runs in a child `enarx run` process, which drives it with `Backend::keep`,
+//! `Keep::spawn` and `Thread::enter` like any other run. The exec reads its arguments from fd 3,
+//! and the host policy, the limits and the signal forwarding are installed once per process, so
+//! keeps can not share a process. It also keeps a crashing keep from taking the daemon and all
+//! other keeps down with it.
+//!
+//! With a pool, keeps are built ahead of time by `enarx run --package-stdin`, which parks them
+//! where the exec waits for its arguments. A keep without a specific backend is started by
+//! handing its package to one of them, which cuts the time to build the keep and to load the
+//! shim and the exec from its start.
+//!
+//! The children get SIGTERM, when the daemon dies, see `PR_SET_PDEATHSIG`, which `enarx run`
+//! forwards to the keep like any other SIGTERM. As the signal is sent on the death of the thread
+//! that spawned the child, all children are spawned by a thread living as long as the daemon.
+
+use super::api::{ExitStatus, KeepInfo, Response, Spec, State};
+use crate::cli::run::Handoff;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{ptr, thread};
+
+use anyhow::{bail, Context, Result};
+use camino::Utf8PathBuf;
+use tracing::{debug, error, info};
+
+/// The delay before the first restart of a keep, doubled with every further restart
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// The maximum delay before a restart of a keep
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// The time a keep gets on top of the grace period to exit, before it is killed
+const KILL_DELAY: Duration = Duration::from_secs(5);
+
+/// The number of lines returned by a `logs` request by default
+const DEFAULT_LOG_LINES: usize = 100;
+
+/// The interval the keeps are checked in for having exited on shutdown
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+/// A command to spawn and where to send the spawned child to
+type SpawnRequest = (Command, mpsc::Sender<io::Result<Child>>);
+
+/// A keep managed by the daemon
+#[derive(Debug)]
+struct Keep {
+    spec: Spec,
+    state: State,
+    pid: Option<u32>,
+    restarts: u32,
+    exit: Option<ExitStatus>,
+    /// Counts the starts of the keep, so stale restarts and kills can tell they are stale.
+    generation: u64,
+}
+
+impl Keep {
+    fn info(&self) -> KeepInfo {
+        KeepInfo {
+            name: self.spec.name.clone(),
+            state: self.state,
+            pid: self.pid,
+            restarts: self.restarts,
+            exit: self.exit,
+        }
+    }
+}
+
+/// Runs and monitors the keeps of the daemon
+#[derive(Debug)]
+pub struct Supervisor {
+    log_dir: Utf8PathBuf,
+    grace: Duration,
+    keeps: Mutex<BTreeMap<String, Keep>>,
+    pool_size: usize,
+    /// The `enarx run` processes of the parked keeps
+    pool: Mutex<Vec<Child>>,
+    /// The thread spawning all children
+    spawner: Mutex<mpsc::Sender<SpawnRequest>>,
+    /// Set once the daemon shuts down, so no keeps are started anymore
+    shutdown: AtomicBool,
+}
+
+impl Supervisor {
+    /// Creates a supervisor writing the output of the keeps to `log_dir`, which grants keeps
+    /// `grace` to exit after they were asked to stop and keeps `pool_size` keeps parked.
+    pub fn new(log_dir: Utf8PathBuf, grace: Duration, pool_size: usize) -> Result<Arc<Self>> {
+        let (spawner, requests) = mpsc::channel::<SpawnRequest>();
+        thread::Builder::new()
+            .name("spawner".into())
+            .spawn(move || {
+                for (mut cmd, child) in requests {
+                    let _ = child.send(cmd.spawn());
+                }
+            })
+            .context("failed to spawn spawner thread")?;
+
+        Ok(Arc::new(Self {
+            log_dir,
+            grace,
+            keeps: Default::default(),
+            pool_size,
+            pool: Default::default(),
+            spawner: Mutex::new(spawner),
+            shutdown: AtomicBool::new(false),
+        }))
+    }
+
+    pub fn create(&self, spec: Spec) -> Result<Response> {
+        check_name(&spec.name)?;
+
+        let mut keeps = self.keeps.lock().unwrap();
+        if keeps.contains_key(&spec.name) {
+            bail!("keep `{}` already exists", spec.name);
+        }
+        info!("created keep `{}`", spec.name);
+        keeps.insert(
+            spec.name.clone(),
+            Keep {
+                spec,
+                state: State::Created,
+                pid: None,
+                restarts: 0,
+                exit: None,
+                generation: 0,
+            },
+        );
+        Ok(Response::Ok(()))
+    }
+
+    pub fn start(self: &Arc<Self>, name: &str) -> Result<Response> {
+        {
+            let mut keeps = self.keeps.lock().unwrap();
+            let keep = get(&mut keeps, name)?;
+            if matches!(keep.state, State::Running | State::Stopping) {
+                bail!("keep `{name}` is already running");
+            }
+            keep.restarts = 0;
+            self.spawn(keep)?;
+        }
+
+        // Building keeps takes a while, so the pool is refilled without blocking other requests.
+        self.fill();
+        Ok(Response::Ok(()))
+    }
+
+    pub fn stop(self: &Arc<Self>, name: &str) -> Result<Response> {
+        let mut keeps = self.keeps.lock().unwrap();
+        let keep = get(&mut keeps, name)?;
+        match keep.state {
+            State::Running => {}
+            // Cancels the pending restart.
+            State::Restarting => {
+                keep.state = State::Exited;
+                return Ok(Response::Ok(()));
+            }
+            State::Stopping => return Ok(Response::Ok(())),
+            State::Created | State::Exited => bail!("keep `{name}` is not running"),
+        }
+
+        // `enarx run` forwards the signal into the keep and tears it down after the grace period.
+        let pid = keep.pid.context("running keep without process")?;
+        signal(pid, libc::SIGTERM).with_context(|| format!("failed to stop keep `{name}`"))?;
+        keep.state = State::Stopping;
+        info!("stopping keep `{name}`");
+
+        let supervisor = self.clone();
+        let (name, generation) = (name.to_string(), keep.generation);
+        thread::Builder::new()
+            .name("kill".into())
+            .spawn(move || {
+                thread::sleep(supervisor.grace + KILL_DELAY);
+                let mut keeps = supervisor.keeps.lock().unwrap();
+                let Some(keep) = keeps.get_mut(&name) else {
+                    return;
+                };
+                if keep.generation == generation && keep.state == State::Stopping {
+                    error!("keep `{name}` did not stop, killing it");
+                    if let Some(pid) = keep.pid {
+                        let _ = signal(pid, libc::SIGKILL);
+                    }
+                }
+            })
+            .context("failed to spawn kill thread")?;
+        Ok(Response::Ok(()))
+    }
+
+    pub fn remove(&self, name: &str) -> Result<Response> {
+        let mut keeps = self.keeps.lock().unwrap();
+        let keep = get(&mut keeps, name)?;
+        if matches!(keep.state, State::Running | State::Stopping) {
+            bail!("keep `{name}` is still running");
+        }
+        keeps.remove(name);
+        info!("removed keep `{name}`");
+
+        let log = self.log(name);
+        match fs::remove_file(&log) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("failed to remove log at `{log}`"))
+            }
+            _ => Ok(Response::Ok(())),
+        }
+    }
+
+    pub fn list(&self) -> Result<Response> {
+        let keeps = self.keeps.lock().unwrap();
+        Ok(Response::Keeps(keeps.values().map(Keep::info).collect()))
+    }
+
+    pub fn status(&self, name: &str) -> Result<Response> {
+        let mut keeps = self.keeps.lock().unwrap();
+        Ok(Response::Keep(get(&mut keeps, name)?.info()))
+    }
+
+    pub fn logs(&self, name: &str, lines: Option<usize>) -> Result<Response> {
+        get(&mut self.keeps.lock().unwrap(), name)?;
+
+        let log = self.log(name);
+        let text = match fs::read(&log) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("failed to read log at `{log}`")),
+        };
+        Ok(Response::Logs(
+            tail(&text, lines.unwrap_or(DEFAULT_LOG_LINES)).into(),
+        ))
+    }
+
+    /// Returns the path of the log of the keep `name`.
+    fn log(&self, name: &str) -> Utf8PathBuf {
+        self.log_dir.join(format!("{name}.log"))
+    }
+
+    /// Stops all keeps and kills the parked ones, before the daemon exits.
+    ///
+    /// Keeps, which did not exit within the grace period, are killed.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+
+        for mut child in self.pool.lock().unwrap().drain(..) {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+
+        for (name, keep) in self.keeps.lock().unwrap().iter_mut() {
+            match keep.state {
+                State::Running => {
+                    if let Some(pid) = keep.pid {
+                        info!("stopping keep `{name}`");
+                        let _ = signal(pid, libc::SIGTERM);
+                    }
+                    keep.state = State::Stopping;
+                }
+                // Cancels the pending restart.
+                State::Restarting => keep.state = State::Exited,
+                State::Created | State::Stopping | State::Exited => {}
+            }
+        }
+
+        // The monitor threads reap the keeps and mark them exited.
+        let deadline = Instant::now() + self.grace + KILL_DELAY;
+        let mut killed = false;
+        loop {
+            let keeps = self.keeps.lock().unwrap();
+            let mut stopping = keeps
+                .iter()
+                .filter(|(_, keep)| keep.state == State::Stopping)
+                .peekable();
+            if stopping.peek().is_none() {
+                return;
+            }
+            if !killed && Instant::now() >= deadline {
+                for (name, keep) in stopping {
+                    error!("keep `{name}` did not stop, killing it");
+                    if let Some(pid) = keep.pid {
+                        let _ = signal(pid, libc::SIGKILL);
+                    }
+                }
+                killed = true;
+            }
+            drop(keeps);
+            thread::sleep(SHUTDOWN_POLL);
+        }
+    }
+
+    /// Returns an `enarx run` command for a keep.
+    fn enarx_run(&self) -> Result<Command> {
+        let exe = std::env::current_exe().context("failed to locate the enarx executable")?;
+        let mut cmd = Command::new(exe);
+        cmd.arg("run")
+            .arg("--grace-period")
+            .arg(self.grace.as_secs().to_string());
+
+        let daemon = std::process::id();
+        let signals = super::shutdown_signals();
+        // Safety: only async-signal-safe functions are called between fork and exec.
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // The daemon died before the death signal was set up.
+                if libc::getppid() as u32 != daemon {
+                    return Err(io::Error::from_raw_os_error(libc::ESRCH));
+                }
+                // The signals blocked by the daemon are forwarded to the keep by `enarx run`.
+                match libc::pthread_sigmask(libc::SIG_UNBLOCK, &signals, ptr::null_mut()) {
+                    0 => Ok(()),
+                    e => Err(io::Error::from_raw_os_error(e)),
+                }
+            });
+        }
+        Ok(cmd)
+    }
+
+    /// Spawns `cmd` on the spawner thread.
+    fn spawn_child(&self, cmd: Command) -> io::Result<Child> {
+        let gone = || io::Error::new(io::ErrorKind::Other, "the spawner thread is gone");
+        let (child, spawned) = mpsc::channel();
+        self.spawner
+            .lock()
+            .unwrap()
+            .send((cmd, child))
+            .map_err(|_| gone())?;
+        spawned.recv().map_err(|_| gone())?
+    }
+
+    /// Fills the pool up with parked keeps.
+    pub fn fill(&self) {
+        if self.shutdown.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let mut pool = self.pool.lock().unwrap();
+        while pool.len() < self.pool_size {
+            let parked = self.enarx_run().and_then(|mut cmd| {
+                cmd.arg("--package-stdin")
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped());
+                self.spawn_child(cmd).context("failed to park a keep")
+            });
+            match parked {
+                Ok(child) => {
+                    debug!("parked a keep as process {}", child.id());
+                    pool.push(child);
+                }
+                Err(e) => {
+                    error!("{e:#}");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Hands the package of `spec` to a parked keep, which writes its output to `log`.
+    ///
+    /// Returns `None`, if no parked keep is left or `spec` asks for a specific backend or placement,
+    /// which a parked keep was built without.
+    fn unpark(&self, spec: &Spec, log: &File) -> Option<Child> {
+        if spec.backend.is_some() || spec.cpus.is_some() || spec.numa_node.is_some() {
+            return None;
+        }
+
+        let handoff = Handoff {
+            module: spec.module.clone(),
+            wasmcfgfile: spec.wasmcfgfile.clone(),
+            limits: spec.limits.clone(),
+        };
+        let mut line = serde_json::to_vec(&handoff).ok()?;
+        line.push(b'\n');
+
+        let mut pool = self.pool.lock().unwrap();
+        while let Some(mut child) = pool.pop() {
+            // The parked keep failed to come up.
+            if !matches!(child.try_wait(), Ok(None)) {
+                let _ = child.kill();
+                let _ = child.wait();
+                continue;
+            }
+
+            let handed = match child.stdin.take() {
+                Some(mut stdin) => stdin.write_all(&line).is_ok(),
+                None => false,
+            };
+            let copied = [
+                child
+                    .stdout
+                    .take()
+                    .map(|out| Box::new(out) as Box<dyn Read + Send>),
+                child
+                    .stderr
+                    .take()
+                    .map(|err| Box::new(err) as Box<dyn Read + Send>),
+            ]
+            .into_iter()
+            .flatten()
+            .all(|output| copy(output, log));
+            if !handed || !copied {
+                let _ = child.kill();
+                let _ = child.wait();
+                continue;
+            }
+            return Some(child);
+        }
+        None
+    }
+
+    /// Starts `keep` in a child `enarx run` process and monitors it.
+    ///
+    /// A parked keep of the pool is used, if there is one, so the keep does not have to be built.
+    fn spawn(self: &Arc<Self>, keep: &mut Keep) -> Result<()> {
+        if self.shutdown.load(Ordering::SeqCst) {
+            bail!("the daemon is shutting down");
+        }
+
+        let Spec {
+            name,
+            module,
+            wasmcfgfile,
+            backend,
+            cpus,
+            numa_node,
+            limits,
+            ..
+        } = &keep.spec;
+
+        let log = self.log(name);
+        let output = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log)
+            .with_context(|| format!("failed to open log at `{log}`"))?;
+
+        let child = match self.unpark(&keep.spec, &output) {
+            Some(child) => child,
+            None => {
+                let errors = output
+                    .try_clone()
+                    .with_context(|| format!("failed to open log at `{log}`"))?;
+
+                let mut cmd = self.enarx_run()?;
+                if let Some(backend) = backend {
+                    cmd.arg("--backend").arg(backend);
+                }
+                if let Some(wasmcfgfile) = wasmcfgfile {
+                    cmd.arg("--wasmcfgfile").arg(wasmcfgfile);
+                }
+                if let Some(cpus) = cpus {
+                    cmd.arg("--cpus").arg(cpus);
+                }
+                if let Some(numa_node) = numa_node {
+                    cmd.arg("--numa-node").arg(numa_node.to_string());
+                }
+                if let Some(memory) = limits.memory {
+                    cmd.arg("--max-memory").arg(memory.to_string());
+                }
+                if let Some(threads) = limits.threads {
+                    cmd.arg("--max-threads").arg(threads.to_string());
+                }
+                cmd.arg(module)
+                    .stdin(Stdio::null())
+                    .stdout(output)
+                    .stderr(errors);
+                self.spawn_child(cmd)
+                    .with_context(|| format!("failed to start keep `{name}`"))?
+            }
+        };
+        info!("started keep `{name}` as process {}", child.id());
+
+        keep.generation += 1;
+        keep.state = State::Running;
+        keep.pid = Some(child.id());
+
+        let supervisor = self.clone();
+        let (name, generation) = (name.clone(), keep.generation);
+        thread::Builder::new()
+            .name("monitor".into())
+            .spawn(move || supervisor.monitor(name, generation, child))
+            .context("failed to spawn monitor thread")?;
+        Ok(())
+    }
+
+    /// Waits for the process of the keep `name` to exit and restarts it, if its policy says so.
+    fn monitor(self: Arc<Self>, name: String, generation: u64, mut child: Child) {
+        let status = child.wait();
+
+        let mut keeps = self.keeps.lock().unwrap();
+        let Some(keep) = keeps.get_mut(&name) else {
+            return;
+        };
+        let stopped = keep.state == State::Stopping;
+        keep.pid = None;
+        keep.state = State::Exited;
+
+        let status = match status {
+            Ok(status) => status,
+            Err(e) => {
+                error!("failed to wait for keep `{name}`: {e}");
+                return;
+            }
+        };
+        keep.exit = Some(ExitStatus {
+            code: status.code(),
+            signal: status.signal(),
+        });
+        info!("keep `{name}` exited with {status}");
+
+        let Spec {
+            restart,
+            max_restarts,
+            ..
+        } = keep.spec;
+        if stopped || !restart.applies(status.success(), keep.restarts, max_restarts) {
+            return;
+        }
+
+        keep.state = State::Restarting;
+        let delay = RESTART_DELAY
+            .saturating_mul(1 << keep.restarts.min(16))
+            .min(MAX_RESTART_DELAY);
+        drop(keeps);
+        info!("restarting keep `{name}` in {delay:?}");
+        thread::sleep(delay);
+
+        let mut keeps = self.keeps.lock().unwrap();
+        let Some(keep) = keeps.get_mut(&name) else {
+            return;
+        };
+        // The keep was stopped or started again in the meantime.
+        if keep.generation != generation || keep.state != State::Restarting {
+            return;
+        }
+        keep.restarts += 1;
+        if let Err(e) = self.spawn(keep) {
+            error!("failed to restart keep `{name}`: {e:#}");
+            keep.state = State::Exited;
+        }
+        drop(keeps);
+        self.fill();
+    }
+}
+
+/// Returns the keep `name` of `keeps`.
+fn get<'a>(keeps: &'a mut BTreeMap<String, Keep>, name: &str) -> Result<&'a mut Keep> {
+    keeps
+        .get_mut(name)
+        .with_context(|| format!("no keep `{name}`"))
+}
+
+/// Appends `output` of a keep to its `log` in the background.
+fn copy(mut output: Box<dyn Read + Send>, log: &File) -> bool {
+    let Ok(mut log) = log.try_clone() else {
+        return false;
+    };
+    thread::Builder::new()
+        .name("output".into())
+        .spawn(move || io::copy(&mut output, &mut log))
+        .is_ok()
+}
+
+/// Sends `signum` to the process `pid`.
+fn signal(pid: u32, signum: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::kill(pid as _, signum) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Checks `name` is usable as a keep name and as part of the name of its log.
+fn check_name(name: &str) -> Result<()> {
+    let valid = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if name.is_empty() || name.starts_with('.') || !valid {
+        bail!("invalid keep name `{name}`, only letters, digits, `-`, `_` and `.` are allowed");
+    }
+    Ok(())
+}
+
+/// Returns the last `lines` lines of `text`.
+fn tail(text: &str, lines: usize) -> &str {
+    if lines == 0 {
+        return "";
+    }
+    let trimmed = text.strip_suffix('\n').unwrap_or(text);
+    match trimmed.rmatch_indices('\n').nth(lines - 1) {
+        Some((at, _)) => &text[at + 1..],
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert!(check_name("web-1.prod_a").is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name(".hidden").is_err());
+        assert!(check_name("../etc/passwd").is_err());
+        assert!(check_name("a b").is_err());
+    }
+
+    #[test]
+    fn logs() {
+        let text = "one\ntwo\nthree\n";
+        assert_eq!(tail(text, 0), "");
+        assert_eq!(tail(text, 1), "three\n");
+        assert_eq!(tail(text, 2), "two\nthree\n");
+        assert_eq!(tail(text, 5), text);
+        assert_eq!(tail("one\ntwo", 1), "two");
+        assert_eq!(tail("", 3), "");
+    }
+}
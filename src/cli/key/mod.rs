@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 pub mod sev;
-mod sgx;
+pub mod sgx;
 
 use std::process::ExitCode;
 
@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 mod create;
-mod digest;
+pub mod digest;
 
 use std::process::ExitCode;
 
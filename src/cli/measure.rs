@@ -0,0 +1,239 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::backend::sev::snp::launch::IdBlock;
+use crate::backend::ByteSized;
+use crate::backend::{Backend, BACKENDS};
+use crate::cli::key::sgx::digest::sgx_key_digest;
+use crate::exec::EXECS;
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::stdout;
+use std::ops::Deref;
+use std::process::ExitCode;
+
+use anyhow::{anyhow, Context, Result};
+use camino::Utf8PathBuf;
+use clap::Args;
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::RsaPrivateKey;
+use serde::Serialize;
+use sgx::signature::Body;
+use sha2::{Digest, Sha384};
+
+/// Print the measurements a verifier should expect for each keep backend.
+///
+/// The output is a JSON object keyed by backend name. Backends which do not
+/// produce a measurement (e.g. `kvm` or `nil`) are omitted.
+#[derive(Args, Debug)]
+pub struct Options {
+    /// Binary to measure, which would be loaded and run inside the keep.
+    /// Defaults to the compiled-in exec.
+    #[clap(value_name = "BINARY")]
+    pub binpath: Option<Utf8PathBuf>,
+
+    /// SGX RSA private key in PEM form, used to compute MRSIGNER
+    #[clap(long)]
+    sgx_key: Option<Utf8PathBuf>,
+
+    /// File path to write the measurements
+    #[clap(long)]
+    out: Option<Utf8PathBuf>,
+}
+
+/// Expected SGX enclave identity.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub(crate) struct SgxMeasurement {
+    /// Hex encoded MRENCLAVE
+    mrenclave: String,
+    /// Hex encoded MRSIGNER, if a signing key was given
+    mrsigner: Option<String>,
+}
+
+/// Expected SEV-SNP launch parameters.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub(crate) struct SevMeasurement {
+    /// Hex encoded SNP launch measurement
+    measurement: String,
+    /// Hex encoded SHA-384 digest of the ID block
+    id_block_digest: String,
+    /// Hex encoded family ID from the ID block
+    family_id: String,
+    /// Hex encoded image ID from the ID block
+    image_id: String,
+    /// Guest policy from the ID block
+    policy: u64,
+    /// Guest SVN from the ID block
+    guest_svn: u32,
+}
+
+/// Measurement of a single backend.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub(crate) enum Measurement {
+    Sgx(SgxMeasurement),
+    Sev(SevMeasurement),
+}
+
+fn measure_sgx(body_bytes: &[u8], sgx_key: Option<&RsaPrivateKey>) -> Result<SgxMeasurement> {
+    let body = Body::from_bytes(body_bytes).ok_or_else(|| anyhow!("Invalid SGX input data"))?;
+    let mrsigner = sgx_key.map(sgx_key_digest).transpose()?.map(hex::encode);
+
+    Ok(SgxMeasurement {
+        mrenclave: hex::encode(body.mrenclave()),
+        mrsigner,
+    })
+}
+
+fn measure_sev(id_block_bytes: &[u8]) -> Result<SevMeasurement> {
+    let id_block =
+        IdBlock::from_bytes(id_block_bytes).ok_or_else(|| anyhow!("Invalid SEV input data"))?;
+
+    Ok(SevMeasurement {
+        measurement: hex::encode(id_block.launch_digest),
+        id_block_digest: hex::encode(Sha384::digest(id_block_bytes)),
+        family_id: hex::encode(id_block.family_id),
+        image_id: hex::encode(id_block.image_id),
+        policy: id_block.policy,
+        guest_svn: id_block.guest_svn,
+    })
+}
+
+/// Measures `shim` and `exec` as the given backend would load them.
+///
+/// Returns `None` for backends which do not produce a measurement.
+pub(crate) fn measure(
+    backend: &dyn Backend,
+    shim: &[u8],
+    exec: &[u8],
+    sgx_key: Option<&RsaPrivateKey>,
+) -> Result<Option<Measurement>> {
+    if shim.is_empty() || exec.is_empty() {
+        return Ok(None);
+    }
+
+    let measurement = match backend.name() {
+        "sgx" => Measurement::Sgx(measure_sgx(&backend.hash(shim, exec)?, sgx_key)?),
+        "sev" => Measurement::Sev(measure_sev(&backend.hash(shim, exec)?)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(measurement))
+}
+
+impl Options {
+    fn load_sgx_key(&self) -> Result<Option<RsaPrivateKey>> {
+        let Some(ref path) = self.sgx_key else {
+            return Ok(None);
+        };
+
+        let mut sgx_key_file = File::open(path).context("Failed to open SGX key file")?;
+        let mut buffer = String::new();
+        sgx_key_file.read_to_string(&mut buffer)?;
+        let sgx_key = RsaPrivateKey::from_pkcs1_pem(&buffer).context("Failed to parse SGX key")?;
+        Ok(Some(sgx_key))
+    }
+
+    pub fn execute(self) -> anyhow::Result<ExitCode> {
+        use mmarinus::{perms, Map, Private};
+
+        let binary = if let Some(ref path) = self.binpath {
+            Some(Map::load(path, Private, perms::Read)?)
+        } else {
+            None
+        };
+        let sgx_key = self.load_sgx_key()?;
+
+        let mut measurements = BTreeMap::new();
+
+        for backend in BACKENDS.deref().iter() {
+            let backend: &dyn Backend = backend.deref();
+
+            let exec = if let Some(ref e) = binary {
+                e.as_ref()
+            } else if let Some(e) = EXECS.iter().find(|w| w.with_backend(backend)) {
+                e.exec()
+            } else {
+                continue;
+            };
+
+            if let Some(measurement) = measure(backend, backend.shim(), exec, sgx_key.as_ref())? {
+                measurements.insert(backend.name(), measurement);
+            }
+        }
+
+        let out = serde_json::to_string_pretty(&measurements)?;
+        if let Some(path) = self.out {
+            let mut file = File::create(path)?;
+            file.write_all(out.as_bytes())?;
+        } else {
+            stdout().write_all(out.as_bytes())?;
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{measure_sev, measure_sgx};
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+    use rsa::RsaPrivateKey;
+
+    const SGX_KEY: &str = include_str!("../../tests/data/sgx-test.key");
+
+    const SEV_IN: [u8; 96] = [
+        255, 165, 145, 93, 184, 17, 227, 134, 166, 124, 80, 99, 74, 210, 44, 73, 78, 253, 225, 255,
+        236, 152, 189, 138, 194, 109, 162, 157, 70, 219, 81, 136, 79, 24, 70, 89, 190, 39, 116,
+        121, 93, 236, 54, 214, 57, 223, 252, 236, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 3, 0, 0, 0,
+        0, 0,
+    ];
+
+    const SGX_IN: [u8; 128] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 0,
+        0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0,
+        124, 255, 195, 180, 246, 57, 219, 115, 59, 98, 240, 212, 175, 143, 166, 98, 40, 238, 160,
+        47, 140, 230, 9, 180, 243, 246, 196, 110, 169, 159, 112, 127, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 234, 1, 0,
+    ];
+
+    #[test]
+    fn test_sgx_measurement() {
+        let key = RsaPrivateKey::from_pkcs1_pem(SGX_KEY).unwrap();
+
+        let unsigned = measure_sgx(SGX_IN.as_slice(), None).unwrap();
+        assert_eq!(
+            unsigned.mrenclave,
+            "7cffc3b4f639db733b62f0d4af8fa66228eea02f8ce609b4f3f6c46ea99f707f"
+        );
+        assert_eq!(unsigned.mrsigner, None);
+
+        let signed = measure_sgx(SGX_IN.as_slice(), Some(&key)).unwrap();
+        assert_eq!(signed.mrenclave, unsigned.mrenclave);
+        assert_eq!(
+            signed.mrsigner.as_deref(),
+            Some("298037d88782e022e019b3020745b78aa40ed95c77da4bf7f3253d3a44c4fd7e")
+        );
+    }
+
+    #[test]
+    fn test_sev_measurement() {
+        let measurement = measure_sev(SEV_IN.as_slice()).unwrap();
+        assert_eq!(
+            measurement.measurement,
+            "ffa5915db811e386a67c50634ad22c494efde1ffec98bd8ac26da29d46db51884f184659be2774795dec36d639dffcec"
+        );
+        assert_eq!(measurement.id_block_digest, "34a8b925c201943405044259971e8a6dd32a1a530734eb1a98895f7b617056eb15fb6fb423c01cce1111b7d0b23e6730");
+        assert_eq!(measurement.family_id, "01000000000000000000000000000000");
+        assert_eq!(measurement.image_id, "01000000000000000000000000000000");
+        assert_eq!(measurement.policy, 0x30000);
+        assert_eq!(measurement.guest_svn, 1);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(measure_sgx(&[0u8; 12], None).is_err());
+        assert!(measure_sev(&[0u8; 12]).is_err());
+    }
+}
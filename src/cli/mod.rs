@@ -2,7 +2,11 @@
 
 mod config;
 #[cfg(enarx_with_shim)]
+mod daemon;
+#[cfg(enarx_with_shim)]
 mod key;
+#[cfg(enarx_with_shim)]
+mod measure;
 mod platform;
 mod run;
 #[cfg(enarx_with_shim)]
@@ -14,12 +18,15 @@ mod unstable;
 use crate::backend::probe::x86_64::Vendor;
 use crate::backend::{Backend, BACKENDS};
 
+use std::borrow::Cow;
+use std::fs;
 use std::io;
 use std::ops::Deref;
 use std::process::ExitCode;
 use std::str::FromStr;
 
-use anyhow::{anyhow, bail};
+use anyhow::{anyhow, bail, Context};
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::{Args, Parser, Subcommand};
 use tracing::info;
 use tracing_subscriber::filter::{filter_fn, FilterExt};
@@ -125,6 +132,8 @@ impl Options {
                 profile,
             ),
             Subcommands::Config(cmd) => cmd.dispatch(),
+            #[cfg(enarx_with_shim)]
+            Subcommands::Daemon(cmd) => cmd.execute(),
             // Subcommands::Deploy(cmd) => cmd.execute(
             //     #[cfg(unix)]
             //     log_level,
@@ -133,6 +142,8 @@ impl Options {
             // ),
             #[cfg(enarx_with_shim)]
             Subcommands::Key(cmd) => cmd.dispatch(),
+            #[cfg(enarx_with_shim)]
+            Subcommands::Measure(cmd) => cmd.execute(),
             Subcommands::Platform(cmd) => cmd.dispatch(),
             #[cfg(enarx_with_shim)]
             Subcommands::Sign(cmd) => cmd.execute(),
@@ -185,8 +196,12 @@ enum Subcommands {
     #[clap(subcommand)]
     Config(config::Subcommands),
     #[cfg(enarx_with_shim)]
+    Daemon(daemon::Options),
+    #[cfg(enarx_with_shim)]
     #[clap(subcommand)]
     Key(key::Subcommands),
+    #[cfg(enarx_with_shim)]
+    Measure(measure::Options),
     #[clap(subcommand)]
     Platform(platform::Subcommands),
     #[cfg(enarx_with_shim)]
@@ -210,8 +225,10 @@ pub struct BackendOptions {
     /// Set which backend to use
     #[clap(long, env = "ENARX_BACKEND")]
     backend: Option<String>,
-    // TODO: Path to an external shim binary?
-    //shim: Option<PathBuf>,
+
+    /// Path to an external shim binary to use instead of the builtin one
+    #[clap(long, env = "ENARX_SHIM")]
+    shim: Option<Utf8PathBuf>,
 }
 
 impl BackendOptions {
@@ -240,6 +257,31 @@ impl BackendOptions {
         }
         .map(|b| &**b)
     }
+
+    /// Returns the external shim, if one was given, or the builtin shim of `backend`.
+    pub fn shim(&self, backend: &dyn Backend) -> anyhow::Result<Cow<'static, [u8]>> {
+        load_shim(self.shim.as_deref(), backend)
+    }
+}
+
+/// Loads and validates an external shim for `backend`,
+/// falling back to the builtin shim if no path is given.
+pub fn load_shim(
+    path: Option<&Utf8Path>,
+    backend: &dyn Backend,
+) -> anyhow::Result<Cow<'static, [u8]>> {
+    let Some(path) = path else {
+        return Ok(Cow::Borrowed(backend.shim()));
+    };
+
+    let shim = fs::read(path).with_context(|| format!("failed to read shim at `{path}`"))?;
+    backend.check_shim(&shim).with_context(|| {
+        format!(
+            "`{path}` is not a valid shim for the {:?} backend",
+            backend.name()
+        )
+    })?;
+    Ok(Cow::Owned(shim))
 }
 
 /// Common logging / output options
@@ -1,19 +1,32 @@
 // SPDX-License-Identifier: Apache-2.0
-use crate::backend::{Backend, BACKENDS};
+use crate::backend::{Backend, Datum, BACKENDS};
+use anyhow::{bail, Context};
+use camino::Utf8PathBuf;
 use clap::Args;
 #[cfg(unix)]
 use libc::{uname, utsname};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt::{self, Formatter};
+use std::fs;
 use std::ops::Deref;
 use std::process::ExitCode;
-/// Show details about backend support on this system
 
+/// Version of the JSON schema emitted by `enarx platform info --json`.
+///
+/// This must be bumped on any incompatible change to [`Info`].
+const SCHEMA_VERSION: u32 = 2;
+
+/// Show details about backend support on this system
 #[derive(Args, Debug)]
 pub struct Options {
     #[clap(short, long)]
     /// Emit JSON rather than human-readable output
     json: bool,
+
+    /// Compare against the JSON output of a previous run and report what changed
+    #[clap(long, value_name = "FILE")]
+    compare: Option<Utf8PathBuf>,
 }
 
 impl Options {
@@ -58,11 +71,33 @@ impl Options {
         }
 
         let info = Info {
-            version: env!("CARGO_PKG_VERSION"),
+            schema: SCHEMA_VERSION,
+            version: env!("CARGO_PKG_VERSION").into(),
             system_info: get_system_info(),
-            backends,
+            backends: backends
+                .iter()
+                .map(|b| BackendInfo::new(b.deref()))
+                .collect(),
         };
-        if self.json {
+
+        if let Some(path) = self.compare {
+            let old = fs::read(&path).with_context(|| format!("failed to read `{path}`"))?;
+            let old: Info = serde_json::from_slice(&old)
+                .with_context(|| format!("failed to parse `{path}`"))?;
+            if old.schema != SCHEMA_VERSION {
+                bail!(
+                    "`{path}` has schema version {}, but only version {SCHEMA_VERSION} is supported",
+                    old.schema
+                );
+            }
+
+            let comparison = Comparison::new(&old, &info);
+            if self.json {
+                println!("{}", serde_json::to_string_pretty(&comparison)?);
+            } else {
+                print!("{comparison}");
+            }
+        } else if self.json {
             println!("{}", serde_json::to_string_pretty(&info)?);
         } else {
             println!("{info}");
@@ -72,59 +107,308 @@ impl Options {
     }
 }
 
-#[derive(Serialize)]
-struct Info<'a> {
-    version: &'static str,
+fn get_icon(is_atty: bool, pass: bool) -> String {
+    use colorful::*;
+
+    match is_atty {
+        true => match pass {
+            true => "✔".green().to_string(),
+            false => "✗".red().to_string(),
+        },
+        false => match pass {
+            true => "✔".into(),
+            false => "✗".into(),
+        },
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct Info {
+    schema: u32,
+    version: String,
     system_info: String,
-    backends: &'a Vec<Box<dyn Backend>>,
+    backends: Vec<BackendInfo>,
 }
 
-impl fmt::Display for Info<'_> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        use colorful::*;
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct BackendInfo {
+    name: String,
+    have: bool,
+    configured: bool,
+    data: Vec<DatumInfo>,
+    config: Vec<DatumInfo>,
+}
 
-        fn get_icon(is_atty: bool, pass: bool) -> String {
-            match is_atty {
-                true => match pass {
-                    true => "✔".green().to_string(),
-                    false => "✗".red().to_string(),
-                },
-                false => match pass {
-                    true => "✔".into(),
-                    false => "✗".into(),
-                },
+impl BackendInfo {
+    fn new(backend: &dyn Backend) -> Self {
+        let name = backend.name();
+        Self {
+            name: name.into(),
+            have: backend.have(),
+            configured: backend.configured(),
+            data: DatumInfo::tree(name, backend.data()),
+            config: DatumInfo::tree(&format!("{name}.config"), backend.config()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct DatumInfo {
+    /// Stable ID, made of the backend name and the ID of the datum
+    id: String,
+    name: String,
+    pass: bool,
+    info: Option<String>,
+    remedy: Option<RemedyInfo>,
+    data: Vec<DatumInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct RemedyInfo {
+    code: String,
+    hint: String,
+}
+
+impl DatumInfo {
+    /// Builds a tree of data, where every datum is nested under the datum named by its `parent`.
+    ///
+    /// The ID of a datum does not depend on its name or its place in the tree, so it stays the
+    /// same, if either changes.
+    fn tree(prefix: &str, data: Vec<Datum>) -> Vec<Self> {
+        let mut tree = Vec::<Self>::new();
+
+        for datum in data {
+            let info = Self {
+                id: format!("{prefix}.{}", datum.id),
+                name: datum.name,
+                pass: datum.pass,
+                info: datum.info,
+                remedy: datum.remedy.map(|r| RemedyInfo {
+                    code: r.code().into(),
+                    hint: r.hint().into(),
+                }),
+                data: vec![],
+            };
+
+            let parent = datum
+                .parent
+                .and_then(|parent| Self::find(&mut tree, &format!("{prefix}.{parent}")));
+            match parent {
+                Some(parent) => parent.data.push(info),
+                None => tree.push(info),
+            }
+        }
+
+        tree
+    }
+
+    /// Finds the datum with `id` in `data` and its children.
+    fn find<'a>(data: &'a mut [Self], id: &str) -> Option<&'a mut Self> {
+        for datum in data {
+            if datum.id == id {
+                return Some(datum);
             }
+            if let Some(found) = Self::find(&mut datum.data, id) {
+                return Some(found);
+            }
+        }
+        None
+    }
+
+    fn fmt(&self, f: &mut Formatter<'_>, is_atty: bool, depth: usize) -> fmt::Result {
+        let icon = get_icon(is_atty, self.pass);
+        write!(f, "  {} {:depth$}{}", icon, "", self.name)?;
+
+        if let Some(ref info) = self.info {
+            write!(f, ": {info}")?;
+        }
+        writeln!(f)?;
+
+        self.data
+            .iter()
+            .try_for_each(|d| d.fmt(f, is_atty, depth + 1))
+    }
+
+    fn flatten<'a>(&'a self, all: &mut BTreeMap<&'a str, &'a DatumInfo>) {
+        all.insert(&self.id, self);
+        self.data.iter().for_each(|d| d.flatten(all));
+    }
+
+    fn remedies<'a>(&'a self, all: &mut Vec<&'a RemedyInfo>) {
+        all.extend(self.remedy.iter());
+        self.data.iter().for_each(|d| d.remedies(all));
+    }
+}
+
+impl Info {
+    /// All data of all backends by ID.
+    fn flatten(&self) -> BTreeMap<&str, &DatumInfo> {
+        let mut all = BTreeMap::new();
+        for backend in &self.backends {
+            backend
+                .data
+                .iter()
+                .chain(backend.config.iter())
+                .for_each(|d| d.flatten(&mut all));
         }
+        all
+    }
+}
 
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let is_atty = atty::is(atty::Stream::Stdout);
-        let backends = self.backends;
 
         writeln!(f, "Enarx version {}", self.version)?;
         writeln!(f, "System Info: {}", self.system_info)?;
 
-        for backend in backends {
-            let mut data = backend.data();
-            data.extend(backend.config());
-            let pass = backend.have() && backend.configured();
-            let icon = get_icon(is_atty, pass);
+        for backend in &self.backends {
+            let icon = get_icon(is_atty, backend.have && backend.configured);
 
-            writeln!(f, "{} Backend: {}", icon, backend.name())?;
+            writeln!(f, "{} Backend: {}", icon, backend.name)?;
 
-            for datum in &data {
-                let icon = get_icon(is_atty, datum.pass);
-                write!(f, "  {} {}", icon, datum.name)?;
+            let data = backend.data.iter().chain(backend.config.iter());
+            for datum in data.clone() {
+                datum.fmt(f, is_atty, 0)?;
+            }
 
-                if let Some(ref info) = datum.info {
-                    write!(f, ": {info}")?;
+            let mut remedies = vec![];
+            data.for_each(|d| d.remedies(&mut remedies));
+            for remedy in remedies {
+                writeln!(f, "\n  {} [{}]\n", remedy.hint, remedy.code)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The observable state of a datum.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+struct State {
+    pass: bool,
+    info: Option<String>,
+}
+
+impl From<&DatumInfo> for State {
+    fn from(datum: &DatumInfo) -> Self {
+        Self {
+            pass: datum.pass,
+            info: datum.info.clone(),
+        }
+    }
+}
+
+/// A difference of a single datum between two runs.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "change", rename_all = "kebab-case")]
+enum Change {
+    Added {
+        id: String,
+        after: State,
+    },
+    Removed {
+        id: String,
+        before: State,
+    },
+    Changed {
+        id: String,
+        before: State,
+        after: State,
+    },
+}
+
+/// The differences between a previous and the current `platform info`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+struct Comparison {
+    schema: u32,
+    version: [String; 2],
+    system_info: [String; 2],
+    changes: Vec<Change>,
+}
+
+impl Comparison {
+    fn new(old: &Info, new: &Info) -> Self {
+        let before = old.flatten();
+        let after = new.flatten();
+
+        let mut changes = vec![];
+        for (id, old) in &before {
+            match after.get(id) {
+                None => changes.push(Change::Removed {
+                    id: id.to_string(),
+                    before: State::from(*old),
+                }),
+                Some(new) if old.pass != new.pass || old.info != new.info => {
+                    changes.push(Change::Changed {
+                        id: id.to_string(),
+                        before: State::from(*old),
+                        after: State::from(*new),
+                    })
                 }
-                writeln!(f)?;
+                Some(_) => (),
+            }
+        }
+        for (id, new) in &after {
+            if !before.contains_key(id) {
+                changes.push(Change::Added {
+                    id: id.to_string(),
+                    after: State::from(*new),
+                });
+            }
+        }
+
+        Self {
+            schema: SCHEMA_VERSION,
+            version: [old.version.clone(), new.version.clone()],
+            system_info: [old.system_info.clone(), new.system_info.clone()],
+            changes,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let is_atty = atty::is(atty::Stream::Stdout);
+
+        fn state(f: &mut Formatter<'_>, is_atty: bool, state: &State) -> fmt::Result {
+            write!(f, "{}", get_icon(is_atty, state.pass))?;
+            if let Some(ref info) = state.info {
+                write!(f, " {info}")?;
             }
+            Ok(())
+        }
+
+        let [ref old, ref new] = self.version;
+        if old != new {
+            writeln!(f, "Enarx version: {old} -> {new}")?;
+        }
+        let [ref old, ref new] = self.system_info;
+        if old != new {
+            writeln!(f, "System Info: {old} -> {new}")?;
+        }
 
-            for datum in &data {
-                if let Some(mesg) = datum.mesg.as_ref() {
-                    writeln!(f, "\n  {mesg}\n")?;
+        if self.changes.is_empty() {
+            return writeln!(f, "No changes");
+        }
+
+        for change in &self.changes {
+            match change {
+                Change::Added { id, after } => {
+                    write!(f, "+ {id}: ")?;
+                    state(f, is_atty, after)?;
+                }
+                Change::Removed { id, before } => {
+                    write!(f, "- {id}: ")?;
+                    state(f, is_atty, before)?;
+                }
+                Change::Changed { id, before, after } => {
+                    write!(f, "~ {id}: ")?;
+                    state(f, is_atty, before)?;
+                    write!(f, " -> ")?;
+                    state(f, is_atty, after)?;
                 }
             }
+            writeln!(f)?;
         }
         Ok(())
     }
@@ -132,133 +416,179 @@ impl fmt::Display for Info<'_> {
 
 #[cfg(test)]
 mod test {
-    use super::Options;
-    use crate::backend::{Backend, Datum, Keep, Signatures};
+    use super::{BackendInfo, Change, Comparison, Info, Options, State, SCHEMA_VERSION};
+    use crate::backend::{Backend, Datum, Keep, Remedy, Signatures, BACKENDS};
     use anyhow::bail;
     use serde_json::json;
-    use std::sync::Lazy;
-    use std::{ops::Deref, sync::Arc};
+    use std::collections::HashSet;
+    use std::ops::Deref;
+    use std::sync::Arc;
 
-    #[test]
-    fn test_info() {
-        Options { json: true }.execute().unwrap();
-        Options { json: false }.execute().unwrap();
-    }
+    pub struct Dummy;
 
-    #[test]
-    fn test_info_json() {
-        pub struct Dummy;
+    impl Backend for Dummy {
+        #[inline]
+        fn name(&self) -> &'static str {
+            "dummy"
+        }
 
-        impl crate::backend::Backend for Dummy {
-            #[inline]
-            fn name(&self) -> &'static str {
-                "dummy"
-            }
+        #[inline]
+        fn shim(&self) -> &'static [u8] {
+            &[]
+        }
 
-            #[inline]
-            fn shim(&self) -> &'static [u8] {
-                &[]
-            }
+        fn data(&self) -> Vec<Datum> {
+            vec![
+                Datum {
+                    id: "driver",
+                    parent: None,
+                    name: "Driver".into(),
+                    pass: true,
+                    info: Some("/dev/dummy".into()),
+                    remedy: None,
+                },
+                Datum {
+                    id: "dummy-driver",
+                    parent: Some("driver"),
+                    name: "Dummy Driver".into(),
+                    pass: false,
+                    info: Some("driver".into()),
+                    remedy: None,
+                },
+                Datum {
+                    id: "backend-1",
+                    parent: Some("driver"),
+                    name: "Dummy Backend".into(),
+                    pass: false,
+                    info: None,
+                    remedy: None,
+                },
+                Datum {
+                    id: "backend-2",
+                    parent: Some("backend-1"),
+                    name: "Dummy Backend".into(),
+                    pass: false,
+                    info: None,
+                    remedy: None,
+                },
+                Datum {
+                    id: "backend-3",
+                    parent: Some("backend-2"),
+                    name: "Dummy Backend".into(),
+                    pass: false,
+                    info: None,
+                    remedy: None,
+                },
+                Datum {
+                    id: "backend-4",
+                    parent: None,
+                    name: "Dummy Backend".into(),
+                    pass: false,
+                    info: None,
+                    remedy: None,
+                },
+                Datum {
+                    id: "backend-5",
+                    parent: None,
+                    name: "Dummy Backend".into(),
+                    pass: false,
+                    info: None,
+                    remedy: None,
+                },
+            ]
+        }
 
-            fn data(&self) -> Vec<Datum> {
-                //Here I will do all the main work.
-                vec![
-                    Datum {
-                        name: "Driver".into(),
-                        pass: true,
-                        info: Some("/dev/dummy".into()),
-                        mesg: None,
-                    },
-                    Datum {
-                        name: " Dummy Driver".into(),
-                        pass: false,
-                        info: Some("driver".into()),
-                        mesg: None,
-                    },
-                    Datum {
-                        name: " Dummy Backend".into(),
-                        pass: false,
-                        info: None,
-                        mesg: None,
-                    },
-                    Datum {
-                        name: "  Dummy Backend".into(),
-                        pass: false,
-                        info: None,
-                        mesg: None,
-                    },
-                    Datum {
-                        name: "   Dummy Backend".into(),
-                        pass: false,
-                        info: None,
-                        mesg: None,
-                    },
-                    Datum {
-                        name: "Dummy Backend".into(),
-                        pass: false,
-                        info: None,
-                        mesg: None,
-                    },
-                    Datum {
-                        name: "Dummy Backend".into(),
-                        pass: false,
-                        info: None,
-                        mesg: None,
-                    },
-                ]
-            }
+        fn config(&self) -> Vec<Datum> {
+            vec![Datum {
+                id: "memlock-rlimit",
+                parent: None,
+                name: "MEMLOCK rlimit".into(),
+                pass: false,
+                info: None,
+                remedy: Some(Remedy::RaiseMemlock),
+            }]
+        }
 
-            fn config(&self) -> Vec<Datum> {
-                vec![]
-            }
+        fn keep(&self, _: &[u8], _: &[u8], _: Option<Signatures>) -> anyhow::Result<Arc<dyn Keep>> {
+            bail!("This is a dummy backend")
+        }
 
-            fn keep(
-                &self,
-                _: &[u8],
-                _: &[u8],
-                _: Option<Signatures>,
-            ) -> anyhow::Result<Arc<dyn Keep>> {
-                bail!("This is a dummy backend")
-            }
+        fn hash(&self, _: &[u8], _: &[u8]) -> anyhow::Result<Vec<u8>> {
+            Ok(Vec::<u8>::new())
+        }
 
-            fn hash(&self, _: &[u8], _: &[u8]) -> anyhow::Result<Vec<u8>> {
-                Ok(Vec::<u8>::new())
-            }
+        fn check_shim(&self, _: &[u8]) -> anyhow::Result<()> {
+            bail!("This is a dummy backend")
         }
+    }
 
-        let expected_json_output = json! ({
-          "backend": "dummy",
+    fn info() -> Info {
+        Info {
+            schema: SCHEMA_VERSION,
+            version: "0.0.0".into(),
+            system_info: "Dummy".into(),
+            backends: vec![BackendInfo::new(&Dummy)],
+        }
+    }
+
+    #[test]
+    fn test_info() {
+        Options {
+            json: true,
+            compare: None,
+        }
+        .execute()
+        .unwrap();
+        Options {
+            json: false,
+            compare: None,
+        }
+        .execute()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_info_json() {
+        let expected_json_output = json!({
+          "name": "dummy",
+          "have": false,
+          "configured": false,
           "data": [
             {
+              "id": "dummy.driver",
               "name": "Driver",
               "pass": true,
               "info": "/dev/dummy",
-              "mesg": "null",
+              "remedy": null,
               "data": [
                 {
+                  "id": "dummy.dummy-driver",
                   "name": "Dummy Driver",
                   "pass": false,
                   "info": "driver",
-                  "mesg": "null",
+                  "remedy": null,
                   "data": []
                 },
                 {
+                  "id": "dummy.backend-1",
                   "name": "Dummy Backend",
                   "pass": false,
-                  "info": "null",
-                  "mesg": "null",
+                  "info": null,
+                  "remedy": null,
                   "data": [
                     {
+                      "id": "dummy.backend-2",
                       "name": "Dummy Backend",
                       "pass": false,
-                      "info": "null",
-                      "mesg": "null",
+                      "info": null,
+                      "remedy": null,
                       "data": [
                         {
+                          "id": "dummy.backend-3",
                           "name": "Dummy Backend",
                           "pass": false,
-                          "info": "null",
-                          "mesg": "null",
+                          "info": null,
+                          "remedy": null,
                           "data": []
                         }
                       ]
@@ -268,28 +598,115 @@ mod test {
               ]
             },
             {
+              "id": "dummy.backend-4",
               "name": "Dummy Backend",
               "pass": false,
-              "info": "null",
-              "mesg": "null",
+              "info": null,
+              "remedy": null,
               "data": []
             },
             {
+              "id": "dummy.backend-5",
               "name": "Dummy Backend",
               "pass": false,
-              "info": "null",
-              "mesg": "null",
+              "info": null,
+              "remedy": null,
+              "data": []
+            }
+          ],
+          "config": [
+            {
+              "id": "dummy.config.memlock-rlimit",
+              "name": "MEMLOCK rlimit",
+              "pass": false,
+              "info": null,
+              "remedy": {
+                "code": "raise-memlock",
+                "hint": Remedy::RaiseMemlock.hint()
+              },
               "data": []
             }
           ]
         });
 
-        let dummy_instance: Lazy<Box<dyn Backend>> = Lazy::new(|| Box::new(Dummy {}));
-
         assert_eq!(
-            serde_json::to_string_pretty(&dummy_instance.deref()).unwrap(),
-            serde_json::to_string_pretty(&expected_json_output).unwrap(),
+            serde_json::to_value(BackendInfo::new(&Dummy)).unwrap(),
+            expected_json_output,
             "Platform info json output test failed"
         );
+
+        let info = info();
+        let json = serde_json::to_string(&info).unwrap();
+        assert_eq!(serde_json::from_str::<Info>(&json).unwrap(), info);
+    }
+
+    #[test]
+    fn test_ids() {
+        for backend in BACKENDS.deref() {
+            for data in [backend.data(), backend.config()] {
+                let mut ids = HashSet::new();
+                for datum in data {
+                    if let Some(parent) = datum.parent {
+                        assert!(
+                            ids.contains(parent),
+                            "{}: parent `{parent}` of `{}` is not listed before it",
+                            backend.name(),
+                            datum.id
+                        );
+                    }
+                    assert!(
+                        ids.insert(datum.id),
+                        "{}: duplicate ID `{}`",
+                        backend.name(),
+                        datum.id
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let old = info();
+        assert!(Comparison::new(&old, &old).changes.is_empty());
+
+        let mut new = info();
+        let backend = &mut new.backends[0];
+        backend.data[0].data[0].pass = true;
+        backend.data.pop();
+        let mut child = backend.config[0].clone();
+        child.id = "dummy.config.new".into();
+        backend.config[0].data.push(child);
+
+        assert_eq!(
+            Comparison::new(&old, &new).changes,
+            vec![
+                Change::Changed {
+                    id: "dummy.dummy-driver".into(),
+                    before: State {
+                        pass: false,
+                        info: Some("driver".into())
+                    },
+                    after: State {
+                        pass: true,
+                        info: Some("driver".into())
+                    },
+                },
+                Change::Removed {
+                    id: "dummy.backend-5".into(),
+                    before: State {
+                        pass: false,
+                        info: None
+                    },
+                },
+                Change::Added {
+                    id: "dummy.config.new".into(),
+                    after: State {
+                        pass: false,
+                        info: None
+                    },
+                },
+            ]
+        );
     }
 }
@@ -1,20 +1,58 @@
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::backend::Signatures;
+#[cfg(enarx_with_shim)]
+use crate::backend::kvm::snapshot;
+#[cfg(enarx_with_shim)]
+use crate::backend::policy::{self, Mode, Policy};
+#[cfg(enarx_with_shim)]
+use crate::backend::{backtrace, coredump, hugepages, limits, metrics, numa, signals, trace};
+use crate::backend::{Backend, Signatures};
 use crate::cli::BackendOptions;
-use crate::exec::{open_package, run_package, EXECS};
+use crate::exec::{open_package, run_package, Outcome, EXECS};
 
 use std::fmt::Debug;
+#[cfg(enarx_with_shim)]
+use std::fs;
+use std::fs::File;
+use std::io::{self, Write};
+#[cfg(enarx_with_shim)]
+use std::net::SocketAddr;
+#[cfg(enarx_with_shim)]
+use std::num::NonZeroUsize;
 #[cfg(unix)]
-use std::os::unix::io::IntoRawFd;
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
 use std::process::ExitCode;
+use std::str::FromStr;
+#[cfg(enarx_with_shim)]
+use std::time::Duration;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, Context};
 use camino::Utf8PathBuf;
 use clap::Args;
-use enarx_exec_wasmtime::Package;
+use enarx_exec_wasmtime::{Attestation, Package};
+use serde::{Deserialize, Serialize};
+
+/// Version of the report format written by `--report`.
+const REPORT_VERSION: u32 = 1;
+
+/// Exit code used when the keep could not be set up or failed outside of the workload.
+const EXIT_KEEP_FAILURE: u8 = 125;
+
+/// Exit code used when the attestation of the keep failed.
+const EXIT_ATTESTATION_FAILURE: u8 = 126;
 
 /// Run a WebAssembly module inside an Enarx Keep.
+///
+/// Exit codes:
+///
+///   0-255  the exit status of the workload
+///
+///   125    the keep could not be set up, or failed outside of the workload
+///
+///   126    the attestation of the keep failed
+///
+/// A workload may itself exit with 125 or 126, use `--report` to tell
+/// those cases apart.
 #[derive(Args, Debug)]
 pub struct Options {
     #[clap(flatten)]
@@ -25,7 +63,9 @@ pub struct Options {
 
     /// Path of the WebAssembly module to run
     #[clap(value_name = "MODULE")]
-    pub module: Utf8PathBuf,
+    #[cfg_attr(enarx_with_shim, clap(required_unless_present = "package_stdin"))]
+    #[cfg_attr(not(enarx_with_shim), clap(required = true))]
+    pub module: Option<Utf8PathBuf>,
 
     /// Start an unsigned Keep
     #[clap(long)]
@@ -35,12 +75,341 @@ pub struct Options {
     #[clap(long, value_name = "SIGNATURES")]
     pub signatures: Option<Utf8PathBuf>,
 
+    /// Write a machine-readable report of the run at exit.
+    /// Possible values: json. Written to stderr unless `--report-file` or `--report-fd` is given.
+    #[clap(long, value_name = "FORMAT")]
+    pub report: Option<ReportFormat>,
+
+    /// Path of the file to write the report to
+    #[clap(long, value_name = "FILE", requires = "report")]
+    pub report_file: Option<Utf8PathBuf>,
+
+    /// Open file descriptor to write the report to
+    #[cfg(unix)]
+    #[clap(
+        long,
+        value_name = "FD",
+        requires = "report",
+        conflicts_with = "report_file"
+    )]
+    pub report_fd: Option<RawFd>,
+
+    /// Restrict the host resources the keep may use.
+    /// Possible values: off, log, enforce.
+    /// With `log`, violations are only logged, with `enforce` the syscall also fails with `EACCES`.
+    /// The sockets and the Steward of the package config are allowed.
+    #[cfg(enarx_with_shim)]
+    #[clap(
+        long,
+        env = "ENARX_HOST_POLICY",
+        value_name = "MODE",
+        default_value = "off"
+    )]
+    pub host_policy: Mode,
+
+    /// Allow the keep to use PATH and everything below it
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "PATH")]
+    pub allow_path: Vec<Utf8PathBuf>,
+
+    /// Allow the keep to bind to ADDR
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "ADDR")]
+    pub allow_bind: Vec<SocketAddr>,
+
+    /// Allow the keep to connect to HOST:PORT
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "HOST:PORT")]
+    pub allow_connect: Vec<String>,
+
+    /// Trace every host call of the keep to FILE, or to stderr if no FILE is given
+    #[cfg(enarx_with_shim)]
+    #[clap(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "-"
+    )]
+    pub trace_hostcalls: Option<Utf8PathBuf>,
+
+    /// Format of the host call trace.
+    /// Possible values: text, json.
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "FORMAT", default_value = "text")]
+    pub trace_format: trace::Format,
+
+    /// Resolve the stack traces and register dumps printed by the shims against the debug info
+    /// of the shim and the exec. Always on in builds with the `dbg` feature.
+    #[cfg(enarx_with_shim)]
+    #[clap(long)]
+    pub symbolize: bool,
+
+    /// Write an ELF core file of the keep to PATH, if it crashes.
+    /// Only available for debug keeps, i.e. KVM and SGX debug enclaves.
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "PATH")]
+    pub core_dump: Option<Utf8PathBuf>,
+
+    /// Write a snapshot of the keep to FILE, once the shim booted and the exec is about to read
+    /// its arguments. Only available for KVM keeps.
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "FILE", conflicts_with = "restore")]
+    pub snapshot_after_init: Option<Utf8PathBuf>,
+
+    /// Resume the keep from a snapshot written by `--snapshot-after-init`, instead of booting it.
+    /// The snapshot must have been taken with the same shim and exec.
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "FILE")]
+    pub restore: Option<Utf8PathBuf>,
+
+    /// Serve metrics of the keep in the Prometheus text format on a Unix socket at PATH
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "PATH")]
+    pub metrics_socket: Option<Utf8PathBuf>,
+
+    /// Write the metrics of the keep to FILE when it exits, or to stderr if no FILE is given
+    #[cfg(enarx_with_shim)]
+    #[clap(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "-"
+    )]
+    pub metrics_dump: Option<Utf8PathBuf>,
+
+    /// Refuse the keep more than SIZE of host memory, e.g. `512MiB`.
+    /// The `limits.memory` key of the package config can only lower it.
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "SIZE")]
+    pub max_memory: Option<enarx_config::MemorySize>,
+
+    /// Refuse the keep more than N running threads.
+    /// The `limits.threads` key of the package config can only lower it.
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "N")]
+    pub max_threads: Option<NonZeroUsize>,
+
+    /// Back the guest memory of KVM keeps by huge pages.
+    /// Possible values are `off`, `thp` for transparent huge pages and `hugetlb` for
+    /// huge pages reserved in `/proc/sys/vm/nr_hugepages`.
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "MODE", default_value = "off")]
+    pub huge_pages: hugepages::Mode,
+
+    /// Pin the threads running the keep to the CPUs in LIST, e.g. `0-3,8`.
+    /// The guest memory of KVM and SEV keeps is bound to the NUMA node of those CPUs,
+    /// if they are all on one node.
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "LIST")]
+    pub cpus: Option<numa::CpuSet>,
+
+    /// Bind the guest memory of KVM and SEV keeps to NUMA node N.
+    /// Without `--cpus`, the threads running the keep are pinned to the CPUs of the node.
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "N")]
+    pub numa_node: Option<u32>,
+
+    /// Seconds the keep has to exit after SIGINT, SIGTERM or SIGHUP was forwarded to it,
+    /// before it is torn down. The same signal arriving twice tears the keep down right away.
+    #[cfg(enarx_with_shim)]
+    #[clap(long, value_name = "SECONDS", default_value_t = 10)]
+    pub grace_period: u64,
+
+    /// Build the keep right away and park it, until a JSON object with the `module` path,
+    /// the optional `wasmcfgfile` path and the `limits` of the package to run arrives on stdin.
+    /// Used by the keep pool of `enarx daemon`, not available with a host policy.
+    #[cfg(enarx_with_shim)]
+    #[clap(
+        long,
+        hide = true,
+        conflicts_with_all = ["module", "wasmcfgfile", "max_memory", "max_threads", "host_policy"]
+    )]
+    pub package_stdin: bool,
+
     /// gdb options
     #[cfg(feature = "gdb")]
     #[clap(long, default_value = "localhost:23456")]
     pub gdblisten: String,
 }
 
+/// Represents the format of the run report.
+#[derive(Debug, Clone, Copy)]
+pub enum ReportFormat {
+    Json,
+}
+
+/// Convert a str to a ReportFormat. This is how Clap parses CLI args.
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!("unknown report format {:?}", s)),
+        }
+    }
+}
+
+/// What made `enarx run` exit.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum ExitClass {
+    /// The workload ran and exited on its own
+    Workload,
+    /// The keep could not be set up or failed outside of the workload
+    Keep,
+    /// The attestation of the keep failed
+    Attestation,
+}
+
+/// Exit status of `enarx run`.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct Exit {
+    status: u8,
+    class: ExitClass,
+}
+
+impl Exit {
+    fn new(status: Option<u8>, attestation: Option<&Attestation>) -> Self {
+        match (status, attestation) {
+            (_, Some(Attestation::Failed { .. })) => Self {
+                status: EXIT_ATTESTATION_FAILURE,
+                class: ExitClass::Attestation,
+            },
+            (Some(status), _) => Self {
+                status,
+                class: ExitClass::Workload,
+            },
+            (None, _) => Self {
+                status: EXIT_KEEP_FAILURE,
+                class: ExitClass::Keep,
+            },
+        }
+    }
+}
+
+/// Machine-readable summary of a run.
+#[derive(Serialize, Debug)]
+struct Report {
+    version: u32,
+    backend: Option<&'static str>,
+    #[cfg(enarx_with_shim)]
+    measurement: Option<super::measure::Measurement>,
+    attestation: Option<Attestation>,
+    exit: Exit,
+    errors: Vec<String>,
+}
+
+/// Reads the package config at `path`.
+#[cfg(enarx_with_shim)]
+fn read_config(path: &Utf8PathBuf) -> anyhow::Result<enarx_config::Config> {
+    let config = fs::read_to_string(path)
+        .with_context(|| format!("failed to read package config at `{path}`"))?;
+    toml::from_str(&config).with_context(|| format!("failed to parse package config at `{path}`"))
+}
+
+/// Builds the host policy from the package config and the `--allow-*` flags.
+#[cfg(enarx_with_shim)]
+fn load_policy(
+    mode: Mode,
+    wasmcfgfile: Option<&Utf8PathBuf>,
+    paths: &[Utf8PathBuf],
+    bind: &[SocketAddr],
+    connect: &[String],
+) -> anyhow::Result<Policy> {
+    let mut policy = Policy::new(mode);
+    if let Some(path) = wasmcfgfile {
+        policy.allow_config(&read_config(path)?)?;
+    }
+    for path in paths {
+        policy.allow_path(path)?;
+    }
+    for addr in bind {
+        policy.allow_bind(*addr);
+    }
+    for addr in connect {
+        policy.allow_connect(addr.as_str())?;
+    }
+    Ok(policy)
+}
+
+/// Lowers the resource limits of the keep to the `limits` of the package config at
+/// `wasmcfgfile`, which is handed to the keep.
+///
+/// The package config can only lower the limits of the host, never raise them.
+#[cfg(enarx_with_shim)]
+fn lower_limits(wasmcfgfile: Option<&Utf8PathBuf>) -> anyhow::Result<()> {
+    if let Some(path) = wasmcfgfile {
+        limits::lower(read_config(path)?.limits.into());
+    }
+    Ok(())
+}
+
+/// The package handed to a keep parked with `--package-stdin`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Handoff {
+    pub module: Utf8PathBuf,
+    #[serde(default)]
+    pub wasmcfgfile: Option<Utf8PathBuf>,
+    #[serde(default)]
+    pub limits: enarx_config::Limits,
+}
+
+/// Waits for the package of a parked keep on stdin and lowers its limits to the ones handed with it.
+///
+/// The keep has not run any workload yet, so the limits apply to all of its memory and threads.
+fn receive_package() -> anyhow::Result<(Utf8PathBuf, Option<Utf8PathBuf>)> {
+    let mut handoff = String::new();
+    io::stdin()
+        .read_line(&mut handoff)
+        .context("failed to read package from stdin")?;
+    let handoff: Handoff =
+        serde_json::from_str(&handoff).context("failed to decode package from stdin")?;
+
+    #[cfg(enarx_with_shim)]
+    limits::lower(handoff.limits.into());
+
+    Ok((handoff.module, handoff.wasmcfgfile))
+}
+
+/// Opens the destination of the host call trace or the metrics dump, named `what` in errors.
+#[cfg(enarx_with_shim)]
+fn output_writer(path: Utf8PathBuf, what: &str) -> anyhow::Result<Box<dyn Write + Send>> {
+    if path == "-" {
+        return Ok(Box::new(io::stderr()));
+    }
+
+    // Open `/dev/null` to reserve fd 3 on Unix, which `exec-wasmtime` will expect to read config from
+    #[cfg(unix)]
+    let _reserved = File::open("/dev/null").context("failed to open `/dev/null`")?;
+
+    let file =
+        File::create(&path).with_context(|| format!("failed to create {what} at `{path}`"))?;
+    // The keep may exit the process at any time, so every line is flushed right away.
+    Ok(Box::new(io::LineWriter::new(file)))
+}
+
+/// Opens the destination of the report.
+fn report_writer(
+    path: Option<Utf8PathBuf>,
+    #[cfg(unix)] fd: Option<RawFd>,
+) -> anyhow::Result<Box<dyn Write>> {
+    if let Some(path) = path {
+        let file = File::create(&path)
+            .with_context(|| format!("failed to create report file at `{path}`"))?;
+        return Ok(Box::new(file));
+    }
+
+    #[cfg(unix)]
+    if let Some(fd) = fd {
+        // SAFETY: the file descriptor was handed to us on the command line to write the report to.
+        return Ok(Box::new(unsafe { File::from_raw_fd(fd) }));
+    }
+
+    Ok(Box::new(io::stderr()))
+}
+
 impl Options {
     pub fn execute(
         self,
@@ -48,55 +417,290 @@ impl Options {
         #[cfg(all(unix, feature = "bench"))] profile: Option<impl IntoRawFd>,
     ) -> anyhow::Result<ExitCode> {
         let Self {
-            backend,
+            backend: backend_options,
             wasmcfgfile,
             module,
             unsigned,
             signatures,
+            report,
+            report_file,
+            #[cfg(unix)]
+            report_fd,
+            #[cfg(enarx_with_shim)]
+            host_policy,
+            #[cfg(enarx_with_shim)]
+            allow_path,
+            #[cfg(enarx_with_shim)]
+            allow_bind,
+            #[cfg(enarx_with_shim)]
+            allow_connect,
+            #[cfg(enarx_with_shim)]
+            trace_hostcalls,
+            #[cfg(enarx_with_shim)]
+            trace_format,
+            #[cfg(enarx_with_shim)]
+            symbolize,
+            #[cfg(enarx_with_shim)]
+            core_dump,
+            #[cfg(enarx_with_shim)]
+            snapshot_after_init,
+            #[cfg(enarx_with_shim)]
+            restore,
+            #[cfg(enarx_with_shim)]
+            metrics_socket,
+            #[cfg(enarx_with_shim)]
+            metrics_dump,
+            #[cfg(enarx_with_shim)]
+            max_memory,
+            #[cfg(enarx_with_shim)]
+            max_threads,
+            #[cfg(enarx_with_shim)]
+            huge_pages,
+            #[cfg(enarx_with_shim)]
+            cpus,
+            #[cfg(enarx_with_shim)]
+            numa_node,
+            #[cfg(enarx_with_shim)]
+            grace_period,
+            #[cfg(enarx_with_shim)]
+            package_stdin,
             #[cfg(feature = "gdb")]
             gdblisten,
         } = self;
-        let backend = backend.pick()?;
-        let exec = EXECS
-            .iter()
-            .find(|w| w.with_backend(backend))
-            .ok_or_else(|| anyhow!("no supported exec found"))
-            .map(|b| b.exec())?;
-
-        let signatures = if unsigned {
-            None
-        } else {
-            Signatures::load(signatures)?
+
+        let mut picked: Option<&dyn Backend> = None;
+        #[cfg(enarx_with_shim)]
+        let mut measurement = None;
+
+        let outcome = (|| -> anyhow::Result<Outcome> {
+            let backend = backend_options.pick()?;
+            picked = Some(backend);
+            let shim = backend_options.shim(backend)?;
+            let exec = EXECS
+                .iter()
+                .find(|w| w.with_backend(backend))
+                .ok_or_else(|| anyhow!("no supported exec found"))
+                .map(|b| b.exec())?;
+
+            #[cfg(enarx_with_shim)]
+            if report.is_some() {
+                measurement = super::measure::measure(backend, &shim, exec, None)?;
+            }
+
+            #[cfg(enarx_with_shim)]
+            if host_policy != Mode::Off {
+                policy::install(load_policy(
+                    host_policy,
+                    wasmcfgfile.as_ref(),
+                    &allow_path,
+                    &allow_bind,
+                    &allow_connect,
+                )?)?;
+            } else if !(allow_path.is_empty() && allow_bind.is_empty() && allow_connect.is_empty())
+            {
+                bail!("`--allow-*` flags require `--host-policy log` or `--host-policy enforce`");
+            }
+
+            #[cfg(enarx_with_shim)]
+            if let Some(path) = trace_hostcalls {
+                trace::install(trace_format, output_writer(path, "host call trace")?)?;
+            }
+
+            #[cfg(enarx_with_shim)]
+            if metrics_socket.is_some() || metrics_dump.is_some() {
+                let dump = metrics_dump
+                    .map(|path| output_writer(path, "metrics dump"))
+                    .transpose()?;
+                metrics::install(metrics_socket, dump)?;
+            }
+
+            #[cfg(enarx_with_shim)]
+            limits::lower(limits::Limits {
+                memory: max_memory.map(|size| size.0),
+                threads: max_threads.map(NonZeroUsize::get),
+            });
+
+            #[cfg(enarx_with_shim)]
+            if huge_pages != hugepages::Mode::Off {
+                // The private memory of SEV keeps lives in guest memfds, which are not backed by
+                // huge pages.
+                if backend.name() != "kvm" {
+                    bail!("huge pages are only available for KVM keeps");
+                }
+                hugepages::install(huge_pages)?;
+            }
+
+            #[cfg(enarx_with_shim)]
+            if cpus.is_some() || numa_node.is_some() {
+                numa::install(cpus, numa_node)?;
+            }
+
+            #[cfg(enarx_with_shim)]
+            if let Some(path) = core_dump {
+                if !backend.debuggable(&shim)? {
+                    bail!("core dumps are only available for KVM keeps and SGX debug enclaves");
+                }
+                coredump::install(path)?;
+            }
+
+            #[cfg(enarx_with_shim)]
+            if snapshot_after_init.is_some() || restore.is_some() {
+                if backend.name() != "kvm" {
+                    bail!("snapshots are only available for KVM keeps");
+                }
+                if let Some(path) = snapshot_after_init {
+                    snapshot::install(path, &shim, exec)?;
+                }
+                if let Some(path) = restore {
+                    snapshot::install_restore(path, &shim, exec)?;
+                }
+            }
+
+            // The nil backend runs the workload in this process, which has no way to wait for
+            // forwarded signals.
+            #[cfg(enarx_with_shim)]
+            if backend.name() != "nil" {
+                signals::install(Duration::from_secs(grace_period))?;
+            }
+
+            // Only the shims built with the `dbg` feature print stack traces, so every stderr
+            // write of the keep is only scanned for them on request.
+            #[cfg(enarx_with_shim)]
+            if symbolize || cfg!(feature = "dbg") {
+                backtrace::install(shim.clone(), exec)?;
+            }
+
+            #[cfg(enarx_with_shim)]
+            if package_stdin {
+                tracing::info!("parking the keep until its package arrives on stdin");
+            }
+
+            let signatures = if unsigned {
+                None
+            } else {
+                Signatures::load(signatures)?
+            };
+
+            let get_pkg = move || {
+                let (module, wasmcfgfile) = match module {
+                    Some(module) => (module, wasmcfgfile),
+                    None => receive_package()?,
+                };
+                #[cfg(enarx_with_shim)]
+                lower_limits(wasmcfgfile.as_ref())?;
+                let (wasm, conf) = open_package(module, wasmcfgfile)?;
+
+                #[cfg(unix)]
+                let pkg = Package::Local {
+                    wasm: wasm.into_raw_fd(),
+                    conf: conf.map(|conf| conf.into_raw_fd()),
+                };
+
+                #[cfg(windows)]
+                let pkg = Package::Local { wasm, conf };
+
+                Ok(pkg)
+            };
+
+            run_package(
+                backend,
+                shim,
+                exec,
+                signatures,
+                #[cfg(not(feature = "gdb"))]
+                None,
+                #[cfg(feature = "gdb")]
+                Some(gdblisten),
+                get_pkg,
+                #[cfg(unix)]
+                log_level,
+                #[cfg(all(unix, feature = "bench"))]
+                profile,
+            )
+        })();
+
+        let (status, attestation, error) = match outcome {
+            Ok(Outcome { exit, attestation }) => match (exit, attestation) {
+                (Ok(status), Ok(attestation)) => (Some(status), attestation, None),
+                // The exit status of the keep is reported next to the failed I/O with the exec.
+                (Ok(status), Err(e)) => (Some(status), None, Some(e)),
+                // The I/O with the exec fails, if the keep does, so the keep error is reported.
+                (Err(e), attestation) => (None, attestation.ok().flatten(), Some(e)),
+            },
+            Err(e) => (None, None, Some(e)),
         };
+        let exit = Exit::new(status, attestation.as_ref());
 
-        let get_pkg = || {
-            let (wasm, conf) = open_package(module, wasmcfgfile)?;
+        #[cfg(enarx_with_shim)]
+        metrics::finish();
 
-            #[cfg(unix)]
-            let pkg = Package::Local {
-                wasm: wasm.into_raw_fd(),
-                conf: conf.map(|conf| conf.into_raw_fd()),
+        if let Some(ref e) = error {
+            eprintln!("Error: {e:?}");
+            #[cfg(enarx_with_shim)]
+            backtrace::registers(&format!("{e:?}"));
+        }
+
+        if let Some(ReportFormat::Json) = report {
+            let report = Report {
+                version: REPORT_VERSION,
+                backend: picked.map(|b| b.name()),
+                #[cfg(enarx_with_shim)]
+                measurement,
+                attestation,
+                exit,
+                errors: error
+                    .iter()
+                    .flat_map(|e| e.chain())
+                    .map(ToString::to_string)
+                    .collect(),
             };
 
-            #[cfg(windows)]
-            let pkg = Package::Local { wasm, conf };
+            // The report is only opened at exit, since `run_package` expects fd 3 to be free.
+            let mut out = report_writer(
+                report_file,
+                #[cfg(unix)]
+                report_fd,
+            )?;
+            serde_json::to_writer_pretty(&mut out, &report).context("failed to write report")?;
+            writeln!(out).context("failed to write report")?;
+        }
 
-            Ok(pkg)
+        Ok(ExitCode::from(exit.status))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Exit, ExitClass, EXIT_ATTESTATION_FAILURE, EXIT_KEEP_FAILURE};
+    use enarx_exec_wasmtime::Attestation;
+
+    #[test]
+    fn exit_class() {
+        let failed = Attestation::Failed {
+            url: "https://steward.example.com".into(),
+            error: "connection refused".into(),
         };
 
-        run_package(
-            backend,
-            exec,
-            signatures,
-            #[cfg(not(feature = "gdb"))]
-            None,
-            #[cfg(feature = "gdb")]
-            Some(gdblisten),
-            get_pkg,
-            #[cfg(unix)]
-            log_level,
-            #[cfg(all(unix, feature = "bench"))]
-            profile,
-        )
+        assert_eq!(
+            Exit::new(Some(3), Some(&Attestation::SelfSigned)),
+            Exit {
+                status: 3,
+                class: ExitClass::Workload
+            }
+        );
+        assert_eq!(
+            Exit::new(None, None),
+            Exit {
+                status: EXIT_KEEP_FAILURE,
+                class: ExitClass::Keep
+            }
+        );
+        assert_eq!(
+            Exit::new(Some(1), Some(&failed)),
+            Exit {
+                status: EXIT_ATTESTATION_FAILURE,
+                class: ExitClass::Attestation
+            }
+        );
     }
 }
@@ -4,6 +4,7 @@ use crate::backend::sev::snp::launch::{IdAuth, IdBlock};
 use crate::backend::sev::snp::sign::Signature as IdSignature;
 use crate::backend::ByteSized;
 use crate::backend::{Backend, SevSignature, Signatures, BACKENDS};
+use crate::cli::load_shim;
 use crate::exec::EXECS;
 
 use std::fmt::Debug;
@@ -43,6 +44,10 @@ pub struct Options {
     #[clap(long)]
     sgx_key: Utf8PathBuf,
 
+    /// Path to an external shim binary to sign instead of the builtin one
+    #[clap(long, env = "ENARX_SHIM")]
+    shim: Option<Utf8PathBuf>,
+
     /// File path to write the signature
     #[clap(long)]
     out: Option<Utf8PathBuf>,
@@ -142,10 +147,11 @@ impl Options {
                 continue;
             }
 
-            let blob = backend.hash(backend.shim().as_ref(), exec.as_ref())?;
-
             match backend.name() {
                 "sgx" => {
+                    let shim = load_shim(self.shim.as_deref(), backend)?;
+                    let blob = backend.hash(shim.as_ref(), exec.as_ref())?;
+
                     println!("Signing with SGX key");
                     let signature = sign_sgx(&blob, &self.load_sgx_key()?)?;
                     signatures.sgx = signature;
@@ -44,7 +44,7 @@ impl Options {
         use crate::backend::Signatures;
 
         let Self {
-            backend,
+            backend: backend_options,
             binpath,
             unsigned,
             signatures,
@@ -55,7 +55,8 @@ impl Options {
         use crate::exec::keep_exec;
         use mmarinus::{perms, Map, Private};
 
-        let backend = backend.pick()?;
+        let backend = backend_options.pick()?;
+        let shim = backend_options.shim(backend)?;
         let binary = Map::load(binpath, Private, perms::Read)?;
 
         let signatures = if unsigned {
@@ -70,6 +71,6 @@ impl Options {
         #[cfg(feature = "gdb")]
         let gdblisten = Some(gdblisten);
 
-        keep_exec(backend, backend.shim(), binary, signatures, gdblisten)
+        keep_exec(backend, shim, binary, signatures, gdblisten)
     }
 }
@@ -24,6 +24,8 @@
 #[cfg(enarx_with_shim)]
 pub mod exec_wasmtime;
 
+#[cfg(enarx_with_shim)]
+use crate::backend::numa;
 use crate::backend::{Backend, Command, Signatures};
 
 use std::convert::Into;
@@ -38,7 +40,7 @@ use std::process::ExitCode;
 use std::time::Duration;
 
 use anyhow::{bail, Context, Result};
-use enarx_exec_wasmtime::{Args as ExecArgs, Package};
+use enarx_exec_wasmtime::{Args as ExecArgs, Attestation, Package};
 use tracing::trace_span;
 
 /// Write timeout for writing the arguments to exec-wasmtime.
@@ -94,10 +96,23 @@ pub fn keep_exec(
     shim: impl AsRef<[u8]>,
     exec: impl AsRef<[u8]>,
     signatures: Option<Signatures>,
-    _gdblisten: Option<String>,
+    gdblisten: Option<String>,
 ) -> anyhow::Result<ExitCode> {
+    keep_status(backend, shim, exec, signatures, gdblisten).map(ExitCode::from)
+}
+
+/// Runs the keep to completion and returns its exit status.
+fn keep_status(
+    backend: &dyn Backend,
+    shim: impl AsRef<[u8]>,
+    exec: impl AsRef<[u8]>,
+    signatures: Option<Signatures>,
+    _gdblisten: Option<String>,
+) -> anyhow::Result<u8> {
     let keep = backend.keep(shim.as_ref(), exec.as_ref(), signatures)?;
     let mut thread = keep.spawn()?.unwrap();
+    #[cfg(enarx_with_shim)]
+    numa::pin()?;
     trace_span!(
         "Thread",
         id = ?std::thread::current().id()
@@ -105,7 +120,7 @@ pub fn keep_exec(
     .in_scope(|| loop {
         match thread.enter(&_gdblisten)? {
             Command::Continue => (),
-            Command::Exit(code @ 0..=0xff) => return Ok(ExitCode::from(code as u8)),
+            Command::Exit(code @ 0..=0xff) => return Ok(code as u8),
             Command::Exit(code) => {
                 bail!("keep exited with a non-portable exit code `{code}`, which exceeds 255")
             }
@@ -113,6 +128,17 @@ pub fn keep_exec(
     })
 }
 
+/// The outcome of running a package in a keep.
+#[derive(Debug)]
+pub struct Outcome {
+    /// The exit status of the keep, or the error which made it fail
+    pub exit: Result<u8>,
+
+    /// The attestation outcome reported by the exec, if any, or the error which made passing the
+    /// package to the exec or reading the outcome back fail
+    pub attestation: Result<Option<Attestation>>,
+}
+
 pub fn open_package(
     wasm: impl Into<PathBuf>,
     conf: Option<impl Into<PathBuf>>,
@@ -137,15 +163,19 @@ pub fn open_package(
 #[cfg(windows)]
 pub fn run_package(
     backend: &dyn Backend,
+    shim: impl AsRef<[u8]>,
     exec: impl AsRef<[u8]>,
     _signatures: Option<Signatures>,
     gdblisten: Option<String>,
     package: impl FnOnce() -> Result<Package>,
-) -> Result<ExitCode> {
+) -> Result<Outcome> {
     let package = package()?;
     let args = ExecArgs { package };
     backend.set_args(args);
-    keep_exec(backend, backend.shim(), exec, None, gdblisten)
+    Ok(Outcome {
+        exit: keep_status(backend, shim, exec, None, gdblisten),
+        attestation: Ok(None),
+    })
 }
 
 /// Runs a package.
@@ -155,14 +185,15 @@ pub fn run_package(
 #[cfg(unix)]
 pub fn run_package(
     backend: &dyn Backend,
+    shim: impl AsRef<[u8]>,
     exec: impl AsRef<[u8]>,
     signatures: Option<Signatures>,
     gdblisten: Option<String>,
-    package: impl FnOnce() -> Result<Package>,
+    package: impl FnOnce() -> Result<Package> + Send + 'static,
     log_level: Option<enarx_exec_wasmtime::LogLevel>,
     #[cfg(feature = "bench")] profile: Option<impl IntoRawFd>,
-) -> Result<ExitCode> {
-    use std::io::Write;
+) -> Result<Outcome> {
+    use std::io::{Read, Write};
     use std::net::Shutdown;
     use std::os::unix::net::UnixStream;
     use std::thread;
@@ -176,14 +207,10 @@ pub fn run_package(
         "exec-wasmtime expects the Unix socket to be at FD 3"
     );
 
-    let package = package()?;
-    let args = toml::to_vec(&ExecArgs {
-        package,
-        log_level,
-        #[cfg(feature = "bench")]
-        profile: profile.map(IntoRawFd::into_raw_fd),
-    })
-    .context("failed to encode exec-wasmtime arguments")?;
+    #[cfg(enarx_with_shim)]
+    crate::backend::policy::grant(exec_sock.as_raw_fd());
+    #[cfg(feature = "bench")]
+    let profile = profile.map(IntoRawFd::into_raw_fd);
 
     host_sock
         .set_nonblocking(true)
@@ -192,24 +219,62 @@ pub fn run_package(
         .set_write_timeout(Some(ARG_WRITE_TIMEOUT))
         .context("failed to set timeout on host socket")?;
 
+    // The exec only needs the package, once it reads its arguments, so the keep is built while
+    // the package is opened, or while a parked keep waits for its package to arrive.
     let exec_io = thread::spawn(move || {
+        let package = package()?;
+
+        // Hand out the file descriptors the exec is told about to the keep.
+        #[cfg(enarx_with_shim)]
+        {
+            use crate::backend::policy;
+
+            let Package::Local { wasm, conf } = package;
+            policy::grant(wasm);
+            conf.into_iter().for_each(policy::grant);
+            #[cfg(feature = "bench")]
+            profile.into_iter().for_each(policy::grant);
+        }
+
+        let args = toml::to_vec(&ExecArgs {
+            package,
+            log_level,
+            #[cfg(feature = "bench")]
+            profile,
+        })
+        .context("failed to encode exec-wasmtime arguments")?;
+
         host_sock
             .write_all(&args)
             .context("failed to write arguments to `wasmtime-exec`")?;
         host_sock
             .shutdown(Shutdown::Write)
             .context("failed to shutdown write half of host's socket")?;
-        // TODO: Read exec-wasmtime output from the socket
+
+        // Read the attestation outcome until the exec socket is closed after the keep exits.
+        host_sock
+            .set_nonblocking(false)
+            .context("failed to set host socket to blocking")?;
+        let mut status = String::new();
         host_sock
-            .shutdown(Shutdown::Read)
-            .context("failed to shutdown read half of host's socket")
+            .read_to_string(&mut status)
+            .context("failed to read attestation outcome from `wasmtime-exec`")?;
+        if status.is_empty() {
+            return Ok(None);
+        }
+        toml::from_str(&status)
+            .context("failed to decode attestation outcome")
+            .map(Some)
     });
 
-    let exit_code = keep_exec(backend, backend.shim(), exec, signatures, gdblisten)?;
-    exec_io
+    // The keep has been waited on at this point, so its exit status is kept, even if the I/O
+    // with the exec failed.
+    let exit = keep_status(backend, shim, exec, signatures, gdblisten);
+    drop(exec_sock);
+    let attestation = exec_io
         .join()
-        .expect("failed to join exec-wasmtime I/O thread")?;
-    Ok(exit_code)
+        .expect("failed to join exec-wasmtime I/O thread");
+    Ok(Outcome { exit, attestation })
 }
 
 #[cfg(test)]
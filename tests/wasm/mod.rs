@@ -182,6 +182,46 @@ fn hello_wasi_snapshot1() {
     check_output(&enarx_run(&wasm, None, None), 0, OUTPUT, None);
 }
 
+#[cfg(enarx_with_shim)]
+#[test]
+fn metrics_socket() {
+    // The metrics socket is bound before the keep starts, which must leave fd 3 to the exec socket.
+    let wasm = compile("hello_wasi_snapshot1.wasm");
+    let dir = tempdir().expect("failed to create temporary directory");
+    let output = enarx(
+        |cmd| {
+            let cmd = cmd
+                .arg("run")
+                .arg("--metrics-socket")
+                .arg(dir.path().join("metrics.sock"))
+                .arg(&wasm);
+            with_signatures(cmd)
+        },
+        None,
+    );
+    check_output(&output, 0, b"Hello, world!\n".as_slice(), None);
+}
+
+#[cfg(enarx_with_shim)]
+#[test]
+fn signals_installed() {
+    if super::is_nil() {
+        eprintln!("Signals are not forwarded on the nil backend, ignoring");
+        return;
+    }
+    // The pipe of the forwarded signals is created before the keep starts, which must leave fd 3
+    // to the exec socket.
+    let wasm = compile("hello_wasi_snapshot1.wasm");
+    let output = enarx(
+        |cmd| {
+            let cmd = cmd.arg("run").arg("--grace-period").arg("1").arg(&wasm);
+            with_signatures(cmd)
+        },
+        None,
+    );
+    check_output(&output, 0, b"Hello, world!\n".as_slice(), None);
+}
+
 #[test]
 fn no_export() {
     // This module has no exported functions, so we get an error.